@@ -0,0 +1,175 @@
+//! Handling of `dmm bench`
+//!
+//! Decodes tracks from the store as fast as possible (without playing them), to check that every
+//! file is still intact (for example after copying the music directory to another machine),
+//! and to measure how fast this machine reads from the store and decodes each format.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::Cursor,
+    time::Instant,
+};
+
+use color_eyre::eyre::{bail, Result};
+use symphonia::core::probe;
+
+use crate::{
+    cache::Hash,
+    player2::{AudioDecoder, Decoded},
+    playlist,
+    resolver::Resolver,
+    schema::Playlist,
+};
+
+/// What is known about a store entry from the playlists that use it
+struct Entry {
+    /// name of a track using this entry
+    name: String,
+    /// format of the source it was downloaded from
+    format: String,
+}
+
+struct Stats {
+    bytes: usize,
+    /// seconds of audio decoded
+    audio: f64,
+    read_time: f64,
+    decode_time: f64,
+}
+
+/// Benchmarks every downloaded track in `playlist`, or the entire store
+pub fn bench(res: &Resolver, playlist: Option<&Playlist>) -> Result<()> {
+    let mut entries = BTreeMap::<Hash, Entry>::new();
+    // cover art is not audio, so it is left out
+    let mut covers = BTreeSet::new();
+    for pl in &res.out().playlists {
+        for track in &pl.tracks {
+            if let Some(url) = &track.meta.cover {
+                covers.insert(Hash::cover(url));
+            }
+            let Some(source) = pl.find_source(&track.src) else {
+                continue;
+            };
+            entries
+                .entry(Hash::track(source, track))
+                .or_insert_with(|| Entry {
+                    name: track.meta.name.clone(),
+                    format: source.stored_format().to_string(),
+                });
+        }
+    }
+    let hashes = match playlist {
+        Some(pl) => playlist::track_hashes(pl)?
+            .into_keys()
+            .filter(|hash| res.out().cache.find(*hash).is_some())
+            .collect::<Vec<_>>(),
+        None => res
+            .out()
+            .cache
+            .list()?
+            .into_iter()
+            .filter(|hash| !covers.contains(hash))
+            .collect(),
+    };
+    if hashes.is_empty() {
+        warn!("nothing to benchmark (no downloaded tracks)");
+        return Ok(());
+    }
+
+    let mut total = Stats {
+        bytes: 0,
+        audio: 0.0,
+        read_time: 0.0,
+        decode_time: 0.0,
+    };
+    let mut failed = vec![];
+    for (i, hash) in hashes.iter().enumerate() {
+        let entry = entries.get(hash);
+        let name = entry
+            .map(|e| e.name.clone())
+            .unwrap_or_else(|| format!("<unreferenced {hash}>"));
+        let format = entry.map(|e| e.format.as_str());
+        match bench_one(res, *hash, format) {
+            Ok(stats) => {
+                info!(
+                    "[{}/{}] {name}: {:.1}s of audio decoded in {:.3}s ({:.0}x realtime)",
+                    i + 1,
+                    hashes.len(),
+                    stats.audio,
+                    stats.decode_time,
+                    stats.audio / stats.decode_time.max(f64::EPSILON),
+                );
+                total.bytes += stats.bytes;
+                total.audio += stats.audio;
+                total.read_time += stats.read_time;
+                total.decode_time += stats.decode_time;
+            }
+            Err(e) => {
+                error!("[{}/{}] {name}: failed to decode: {e}", i + 1, hashes.len());
+                failed.push((name, *hash));
+            }
+        }
+    }
+
+    info!(
+        "read {:.1} MiB from the store in {:.3}s ({:.1} MiB/s)",
+        total.bytes as f64 / (1024.0 * 1024.0),
+        total.read_time,
+        total.bytes as f64 / (1024.0 * 1024.0) / total.read_time.max(f64::EPSILON),
+    );
+    info!(
+        "decoded {:.1} minutes of audio in {:.3}s ({:.0}x realtime)",
+        total.audio / 60.0,
+        total.decode_time,
+        total.audio / total.decode_time.max(f64::EPSILON),
+    );
+    if !failed.is_empty() {
+        error!(
+            "{} of {} tracks failed to decode:",
+            failed.len(),
+            hashes.len()
+        );
+        for (name, hash) in &failed {
+            error!("  {name} ({hash})");
+        }
+        info!("Try re-downloading them (delete the files from the store, and run `dmm download`)");
+        bail!("benchmark found broken tracks");
+    }
+    Ok(())
+}
+
+fn bench_one(res: &Resolver, hash: Hash, format: Option<&str>) -> Result<Stats> {
+    let path = res.out().cache.create(hash);
+
+    // read the file up front, so reading and decoding can be timed separately
+    let start = Instant::now();
+    let data = fs::read(path)?;
+    let read_time = start.elapsed().as_secs_f64();
+    let bytes = data.len();
+
+    let start = Instant::now();
+    let mut hint = probe::Hint::new();
+    if let Some(format) = format {
+        hint.with_extension(format);
+    }
+    let mut decoder = AudioDecoder::new(Cursor::new(data), hint)?;
+    let mut audio = 0.0;
+    loop {
+        match decoder.decode_next()? {
+            Decoded::StreamEnd => break,
+            Decoded::Retry => continue,
+            Decoded::Decoded(_, buffer) => {
+                audio += buffer.frames() as f64 / buffer.spec().rate as f64;
+            }
+        }
+    }
+    let decode_time = start.elapsed().as_secs_f64();
+
+    Ok(Stats {
+        bytes,
+        audio,
+        read_time,
+        decode_time,
+    })
+}
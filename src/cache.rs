@@ -1,9 +1,9 @@
-use std::{hash::Hash as _, path::PathBuf, str::FromStr};
+use std::{ffi::OsStr, fmt, hash::Hash as _, io, path::PathBuf, str::FromStr};
 
 use base64::Engine;
 use highway::{HighwayHash, HighwayHasher};
 
-use crate::schema::Source;
+use crate::schema::{Source, Track};
 
 #[derive(Default)]
 pub struct CacheDir {
@@ -23,32 +23,155 @@ impl CacheDir {
     pub fn create(&self, hash: Hash) -> PathBuf {
         self.dir.join(hash.to_string())
     }
+
+    /// Path of a sidecar file (extra data stored alongside a download, such as its waveform)
+    pub fn sidecar(&self, hash: Hash, ext: &str) -> PathBuf {
+        self.dir.join(format!("{hash}.{ext}"))
+    }
+
+    /// Moves an entry of the store (along with its sidecar files) to another hash
+    pub fn rename(&self, from: Hash, to: Hash) -> io::Result<()> {
+        let (from_name, to_name) = (from.to_string(), to.to_string());
+        for entry in self.dir.read_dir()? {
+            let entry = entry?;
+            if entry_hash(&entry.file_name()) == Some(from) {
+                let name = entry
+                    .file_name()
+                    .to_string_lossy()
+                    .replacen(&from_name, &to_name, 1);
+                std::fs::rename(entry.path(), self.dir.join(name))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Deletes an entry from the store, along with its sidecar files
+    pub fn remove(&self, hash: Hash) -> io::Result<()> {
+        for entry in self.dir.read_dir()? {
+            let entry = entry?;
+            if entry_hash(&entry.file_name()) == Some(hash) {
+                std::fs::remove_file(entry.path())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Lists the hashes of every entry in the store, skipping (and warning about) any files
+    /// whose names are not valid hashes
+    pub fn list(&self) -> io::Result<Vec<Hash>> {
+        let mut hashes = vec![];
+        for entry in self.dir.read_dir()? {
+            let entry = entry?;
+            let name = entry.file_name();
+            match name.to_str().map(str::parse::<Hash>) {
+                Some(Ok(hash)) => hashes.push(hash),
+                _ if entry_hash(&name).is_some() => { /* sidecar file */ }
+                _ => warn!("ignoring unknown file in store: {name:?}"),
+            }
+        }
+        Ok(hashes)
+    }
 }
 
-/// Hash of source + input
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Returns the hash that a file in the store belongs to (either the download itself, or one of its sidecar files)
+pub fn entry_hash(file_name: &OsStr) -> Option<Hash> {
+    file_name.to_str()?.split('.').next()?.parse().ok()
+}
+
+/// Hash of source + input (or of the URL, for cover art)
+///
+/// Sources with an `identity` are hashed by it, others by their kind (the command or yt-dlp options they download with)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Hash {
     hash: [u8; 32],
 }
 
 impl Hash {
     pub fn generate(source: &Source, input: &ron::Value) -> Self {
+        Self::with_overrides(source, input, None)
+    }
+
+    /// Hash of the download of `track` from `source` (its input, and overrides if it has any)
+    pub fn track(source: &Source, track: &Track) -> Self {
+        Self::with_overrides(source, &track.input, track.overrides.as_ref())
+    }
+
+    fn with_overrides(source: &Source, input: &ron::Value, overrides: Option<&ron::Value>) -> Self {
+        match &source.identity {
+            Some(identity) => {
+                let mut hasher = HighwayHasher::default();
+                // tagged, so that it is not the same as the hash of a source without an identity
+                "identity".hash(&mut hasher);
+                identity.hash(&mut hasher);
+                source.stored_format().hash(&mut hasher);
+                input.hash(&mut hasher);
+                // (only hashed if there are any, so tracks without them keep the hash they had before overrides existed)
+                if let Some(overrides) = overrides {
+                    overrides.hash(&mut hasher);
+                }
+                Self::from_hasher(hasher)
+            }
+            None => Self::kind_with_overrides(source, input, overrides),
+        }
+    }
+
+    /// Hash of the download of `track` from `source`, using the kind of the source even if it has an identity
+    /// (what the hash was before the identity was set)
+    pub fn of_kind(source: &Source, track: &Track) -> Self {
+        Self::kind_with_overrides(source, &track.input, track.overrides.as_ref())
+    }
+
+    fn kind_with_overrides(
+        source: &Source,
+        input: &ron::Value,
+        overrides: Option<&ron::Value>,
+    ) -> Self {
         let mut hasher = HighwayHasher::default();
         // ignore the name of the source, only the input and kind (if the name changes, it wont need to update)
         let Source {
+            file_path: _,
             name: _,
             format,
             kind,
+            identity: _,
+            postprocess,
+            convert,
         } = source;
         format.hash(&mut hasher);
         kind.hash(&mut hasher);
         input.hash(&mut hasher);
+        if let Some(overrides) = overrides {
+            overrides.hash(&mut hasher);
+        }
+        // (only hashed if there are any, like overrides. the shell is not hashed, as with `Shell` sources)
+        for step in postprocess {
+            "postprocess".hash(&mut hasher);
+            step.cmd.hash(&mut hasher);
+            step.args.hash(&mut hasher);
+            step.optional.hash(&mut hasher);
+        }
+        if let Some(convert) = convert {
+            "convert".hash(&mut hasher);
+            convert.hash(&mut hasher);
+        }
+        Self::from_hasher(hasher)
+    }
+
+    /// Hash of the cover art downloaded from `url`
+    pub fn cover(url: &str) -> Self {
+        let mut hasher = HighwayHasher::default();
+        // tagged, so that it is not the same as the hash of a track whose input is the URL
+        "cover art".hash(&mut hasher);
+        url.hash(&mut hasher);
+        Self::from_hasher(hasher)
+    }
+
+    fn from_hasher(hasher: HighwayHasher) -> Self {
         let out = hasher.finalize256();
         Self {
             hash: out
                 .into_iter()
-                .map(|x| x.to_be_bytes())
-                .flatten()
+                .flat_map(|x| x.to_be_bytes())
                 .collect::<Vec<_>>()
                 .try_into()
                 .unwrap(),
@@ -56,9 +179,9 @@ impl Hash {
     }
 }
 
-impl ToString for Hash {
-    fn to_string(&self) -> String {
-        base64::engine::general_purpose::URL_SAFE.encode(&self.hash)
+impl fmt::Display for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&base64::engine::general_purpose::URL_SAFE.encode(self.hash))
     }
 }
 
@@ -1,19 +1,448 @@
 use std::{collections::HashMap, path::PathBuf};
 
 use color_eyre::eyre::Result;
-use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MediaKeyCode};
 use derive_deref::{Deref, DerefMut};
-use serde::{de::Deserializer, Deserialize};
+use ratatui::style::Color;
+use serde::{
+    de::{self, Deserializer},
+    Deserialize,
+};
 
-use crate::ui::{action::Action, mode::Mode};
+use crate::{
+    player2::Buffering,
+    ui::{action::Action, mode::Mode},
+};
 
 const CONFIG: &str = include_str!("../assets/dmm.default.ron");
 
 #[derive(Clone, Debug, Default, Deserialize)]
 pub struct Config {
     pub play_on_start: bool,
+    /// open the playlist that was playing when the player was last closed, continuing from the same track and position
+    #[serde(default)]
+    pub resume_on_start: bool,
+    /// enable mouse support
+    #[serde(default)]
+    pub mouse: bool,
+    /// keep the system from going to sleep while playing
+    #[serde(default)]
+    pub prevent_sleep: bool,
+    #[serde(default)]
+    pub audio: AudioConfig,
+    #[serde(default)]
+    pub git: GitConfig,
+    #[serde(default)]
+    pub radio: RadioConfig,
+    #[serde(default)]
+    pub accessibility: AccessibilityConfig,
+    #[serde(default)]
+    pub mpris: MprisConfig,
+    #[serde(default)]
+    pub remote: RemoteConfig,
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+    #[serde(default)]
+    pub cover_art: CoverArtConfig,
+    #[serde(default)]
+    pub scrobble: ScrobbleConfig,
+    #[serde(default)]
+    pub sync: SyncConfig,
+    #[serde(default)]
+    pub lyrics: LyricsConfig,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    #[serde(default)]
+    pub log: LogConfig,
+    #[serde(default)]
+    pub theme: Theme,
     #[serde(default)]
     pub keybinds: KeyBindings,
+    /// system-wide hotkeys (requires the `global-hotkeys` feature)
+    #[serde(default)]
+    pub global_hotkeys: GlobalHotkeys,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct GitConfig {
+    /// commit changes DMM makes to playlists and sources (if the music directory is a git repository)
+    pub auto_commit: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct MprisConfig {
+    /// allow the player to be controlled over MPRIS (linux only)
+    pub enabled: bool,
+}
+
+impl Default for MprisConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct RemoteConfig {
+    /// serve a web page (and JSON API) for controlling the player
+    pub enabled: bool,
+    /// address (and port) to serve it on
+    pub address: String,
+    /// if set, requests must pass it as `?token=...`
+    pub token: Option<String>,
+}
+
+impl Default for RemoteConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: "127.0.0.1:8420".to_string(),
+            token: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct NotificationsConfig {
+    /// show desktop notifications at all
+    pub enabled: bool,
+    /// show one when the player moves on to the next track
+    pub on_track_change: bool,
+    /// show one when the player reaches the end of the playlist, and stops
+    pub on_playlist_complete: bool,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            on_track_change: true,
+            on_playlist_complete: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct CoverArtConfig {
+    /// download the cover art of tracks that have one
+    pub download: bool,
+    /// show the cover art of the current track in the player
+    pub show: bool,
+}
+
+impl Default for CoverArtConfig {
+    fn default() -> Self {
+        Self {
+            download: true,
+            show: true,
+        }
+    }
+}
+
+/// Services to scrobble to. A service is used if its credentials are set
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ScrobbleConfig {
+    pub lastfm: LastfmConfig,
+    pub listenbrainz: ListenBrainzConfig,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct LastfmConfig {
+    /// API account credentials (from https://www.last.fm/api/account/create)
+    pub api_key: String,
+    pub api_secret: String,
+    /// session key of the account to scrobble to (from `dmm scrobble lastfm-login`)
+    pub session_key: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct ListenBrainzConfig {
+    /// user token (from https://listenbrainz.org/settings/)
+    pub token: String,
+    /// API root, for self-hosted instances
+    pub url: String,
+}
+
+impl Default for ListenBrainzConfig {
+    fn default() -> Self {
+        Self {
+            token: String::new(),
+            url: "https://api.listenbrainz.org".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SyncConfig {
+    /// git repository or HTTP(S) URL that `dmm sync fetch` gets shared playlists and sources from
+    pub index: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct LyricsConfig {
+    /// command that prints the lyrics of a track (plain or LRC), run by `dmm download` for tracks without lyrics
+    pub cmd: Option<String>,
+    /// arguments of the command. `${artist}`, `${name}`, and `${album}` are replaced with the track's
+    pub args: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// largest the store may grow to, in bytes (written as a size such as `"20GB"`).
+    /// the least recently played downloads are removed to stay under it
+    #[serde(deserialize_with = "deserialize_size")]
+    pub max_size: Option<u64>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    /// file the player logs to, relative to the music directory (None for `run/dmm.log`)
+    pub file: Option<PathBuf>,
+    /// start a new log file once it grows to this size (written as a size such as `"10MB"`, None to let it grow)
+    #[serde(deserialize_with = "deserialize_size")]
+    pub max_size: Option<u64>,
+    /// also start a new log file every day (at midnight UTC)
+    pub daily: bool,
+    /// number of previous log files to keep (as `dmm.log.1`, `dmm.log.2`, ...)
+    pub keep: usize,
+    /// what to log: a level, or levels per module (such as `"dmm=info,dmm::player2=debug"`).
+    /// the `RUST_LOG` environment variable takes priority over this
+    pub level: String,
+    /// show warnings and errors logged while the player is open as toasts
+    pub toasts: bool,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            file: None,
+            max_size: Some(10_000_000),
+            daily: false,
+            keep: 3,
+            level: format!("{}=info", env!("CARGO_CRATE_NAME")),
+            toasts: true,
+        }
+    }
+}
+
+fn deserialize_size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    let Some(text) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    parse_size(&text).map(Some).ok_or_else(|| {
+        de::Error::custom(format!(
+            "invalid size {text:?} (expected a size such as \"20GB\" or \"500MiB\")"
+        ))
+    })
+}
+
+/// Parses a size such as `20GB`, `1.5 GiB`, or `500M` into bytes
+fn parse_size(text: &str) -> Option<u64> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number = number.parse::<f64>().ok()?;
+    let multiplier = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1u64,
+        "k" | "kb" => 1000,
+        "kib" => 1 << 10,
+        "m" | "mb" => 1_000_000,
+        "mib" => 1 << 20,
+        "g" | "gb" => 1_000_000_000,
+        "gib" => 1 << 30,
+        "t" | "tb" => 1_000_000_000_000,
+        "tib" => 1 << 40,
+        _ => return None,
+    };
+    Some((number * multiplier as f64) as u64)
+}
+
+/// Colors of the player, as set in `dmm.ron` (colors that are not set come from the preset)
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+struct ThemeConfig {
+    preset: ThemePreset,
+    accent: Option<Color>,
+    highlight: Option<Color>,
+    playing: Option<Color>,
+    dim: Option<Color>,
+    border: Option<Color>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum ThemePreset {
+    /// yellow borders, light green for what is playing
+    #[default]
+    Default,
+    /// blue borders, light cyan for what is playing
+    Ocean,
+    /// only shades of gray
+    Monochrome,
+}
+
+/// Colors of the player
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(from = "ThemeConfig")]
+pub struct Theme {
+    /// headings, and other text that stands out
+    pub accent: Color,
+    /// the selected entry of a list
+    pub highlight: Color,
+    /// what is playing (the current track and playlist, and the played part of the progress bar)
+    pub playing: Color,
+    /// things that are turned off or not reached yet
+    pub dim: Color,
+    /// borders around the parts of the player
+    pub border: Color,
+}
+
+impl ThemePreset {
+    fn theme(self) -> Theme {
+        match self {
+            Self::Default => Theme {
+                accent: Color::Yellow,
+                highlight: Color::LightCyan,
+                playing: Color::LightGreen,
+                dim: Color::DarkGray,
+                border: Color::Yellow,
+            },
+            Self::Ocean => Theme {
+                accent: Color::LightBlue,
+                highlight: Color::LightMagenta,
+                playing: Color::LightCyan,
+                dim: Color::DarkGray,
+                border: Color::Blue,
+            },
+            Self::Monochrome => Theme {
+                accent: Color::White,
+                highlight: Color::White,
+                playing: Color::White,
+                dim: Color::DarkGray,
+                border: Color::Gray,
+            },
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        ThemePreset::default().theme()
+    }
+}
+
+impl From<ThemeConfig> for Theme {
+    fn from(cfg: ThemeConfig) -> Self {
+        let preset = cfg.preset.theme();
+        Self {
+            accent: cfg.accent.unwrap_or(preset.accent),
+            highlight: cfg.highlight.unwrap_or(preset.highlight),
+            playing: cfg.playing.unwrap_or(preset.playing),
+            dim: cfg.dim.unwrap_or(preset.dim),
+            border: cfg.border.unwrap_or(preset.border),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct AccessibilityConfig {
+    /// use text labels instead of symbols and colors, and show changes in state on a dedicated line
+    pub enabled: bool,
+    /// also speak changes in state (using `spd-say`)
+    pub speak: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct RadioConfig {
+    /// how tracks similar to the current one are found
+    pub heuristic: RadioHeuristic,
+    /// number of recently played tracks that radio mode will not pick again
+    pub avoid_recent: usize,
+}
+
+impl Default for RadioConfig {
+    fn default() -> Self {
+        Self {
+            heuristic: RadioHeuristic::Mixed,
+            avoid_recent: 20,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum RadioHeuristic {
+    /// tracks by the same artist
+    Artist,
+    /// tracks that share playlists with the current one
+    Playlists,
+    /// both of the above (same artist is weighted more heavily)
+    Mixed,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct AudioConfig {
+    /// gain (in dB) applied to all audio before it is output
+    pub preamp: f32,
+    /// skip silence at the start and end of tracks (can be overriden per-playlist)
+    pub trim_silence: bool,
+    /// level (in dBFS) below which audio is considered silent
+    pub silence_threshold: f32,
+    /// prepare the next track while the current one plays, so there is no gap between them
+    pub gapless: bool,
+    /// length (in seconds) of the crossfade between tracks (0 to not crossfade). requires `gapless`
+    pub crossfade_secs: f32,
+    /// name of the output device to use (None for the system default)
+    pub device_name: Option<String>,
+    /// move on to the next track when one can not be played (such as a broken file)
+    pub skip_on_error: bool,
+    /// move on to the next track when one is not downloaded (instead of stopping)
+    pub skip_not_downloaded: bool,
+    /// length (in milliseconds) of the audio buffered between decoding and the output device
+    pub latency_ms: u32,
+    /// frames the output device is asked to take at once (None for its default)
+    pub buffer_size: Option<u32>,
+}
+
+impl AudioConfig {
+    /// How the player should buffer its output
+    pub fn buffering(&self) -> Buffering {
+        Buffering {
+            latency_ms: self.latency_ms,
+            buffer_size: self.buffer_size,
+        }
+    }
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            preamp: 0.0,
+            trim_silence: false,
+            silence_threshold: -60.0,
+            gapless: true,
+            crossfade_secs: 0.0,
+            device_name: None,
+            skip_on_error: true,
+            skip_not_downloaded: true,
+            latency_ms: Buffering::default().latency_ms,
+            buffer_size: None,
+        }
+    }
 }
 
 impl Config {
@@ -69,6 +498,32 @@ impl<'de> Deserialize<'de> for KeyBindings {
     }
 }
 
+/// Hotkeys that work even when the terminal is not focused. Each binding must be a single key (with modifiers)
+#[derive(Clone, Debug, Default, Deref, DerefMut)]
+pub struct GlobalHotkeys(pub HashMap<KeyEvent, Action>);
+
+impl<'de> Deserialize<'de> for GlobalHotkeys {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let parsed_map = HashMap::<String, Action>::deserialize(deserializer)?;
+
+        let hotkeys = parsed_map
+            .into_iter()
+            .map(|(key_str, cmd)| match parse_key_sequence(&key_str) {
+                Ok(keys) if keys.len() == 1 => Ok((keys[0], cmd)),
+                Ok(_) => Err(de::Error::custom(format!(
+                    "global hotkey `{key_str}` must be a single key"
+                ))),
+                Err(e) => Err(de::Error::custom(e)),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(GlobalHotkeys(hotkeys))
+    }
+}
+
 fn parse_key_event(raw: &str) -> Result<KeyEvent, String> {
     let raw_lower = raw.to_ascii_lowercase();
     let (remaining, modifiers) = extract_modifiers(&raw_lower);
@@ -138,6 +593,12 @@ fn parse_key_code_with_modifiers(
         "hyphen" => KeyCode::Char('-'),
         "minus" => KeyCode::Char('-'),
         "tab" => KeyCode::Tab,
+        "mediaplaypause" => KeyCode::Media(MediaKeyCode::PlayPause),
+        "mediaplay" => KeyCode::Media(MediaKeyCode::Play),
+        "mediapause" => KeyCode::Media(MediaKeyCode::Pause),
+        "mediastop" => KeyCode::Media(MediaKeyCode::Stop),
+        "medianext" => KeyCode::Media(MediaKeyCode::TrackNext),
+        "mediaprev" => KeyCode::Media(MediaKeyCode::TrackPrevious),
         c if c.len() == 1 => {
             let mut c = c.chars().next().unwrap();
             if modifiers.contains(KeyModifiers::SHIFT) {
@@ -171,7 +632,7 @@ pub fn key_event_to_string(key_event: &KeyEvent) -> String {
             char = format!("f({c})");
             &char
         }
-        KeyCode::Char(c) if c == ' ' => "space",
+        KeyCode::Char(' ') => "space",
         KeyCode::Char(c) => {
             char = c.to_string();
             &char
@@ -181,6 +642,12 @@ pub fn key_event_to_string(key_event: &KeyEvent) -> String {
         KeyCode::CapsLock => "",
         KeyCode::Menu => "",
         KeyCode::ScrollLock => "",
+        KeyCode::Media(MediaKeyCode::PlayPause) => "mediaplaypause",
+        KeyCode::Media(MediaKeyCode::Play) => "mediaplay",
+        KeyCode::Media(MediaKeyCode::Pause) => "mediapause",
+        KeyCode::Media(MediaKeyCode::Stop) => "mediastop",
+        KeyCode::Media(MediaKeyCode::TrackNext) => "medianext",
+        KeyCode::Media(MediaKeyCode::TrackPrevious) => "mediaprev",
         KeyCode::Media(_) => "",
         KeyCode::NumLock => "",
         KeyCode::PrintScreen => "",
@@ -0,0 +1,63 @@
+//! Cover art of tracks
+//!
+//! Covers are downloaded (with `curl`) into the store as entries of their own, named by the hash of their URL
+//! (see [`Hash::cover`](crate::cache::Hash::cover)), so tracks from the same album share one file.
+
+use std::{
+    fs,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use color_eyre::eyre::{anyhow, bail, Result};
+use image::{imageops::FilterType, RgbImage};
+
+/// Covers are shrunk to at most this many pixels wide/tall when loaded, as they are only shown in a terminal
+const MAX_SIZE: u32 = 128;
+
+/// Downloads the cover art at `url` to `output`
+pub fn download(url: &str, output: &Path) -> Result<()> {
+    let out = Command::new("curl")
+        .args([
+            "--fail",
+            "--silent",
+            "--show-error",
+            "--location",
+            "--output",
+        ])
+        .arg(output)
+        .arg("--")
+        .arg(url)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| anyhow!("failed to run curl (is it installed?): {e}"))?;
+    if !out.status.success() {
+        let _ = fs::remove_file(output);
+        bail!(
+            "curl failed ({}): {}",
+            out.status,
+            String::from_utf8_lossy(&out.stderr).trim_end()
+        );
+    }
+    if let Some(problem) = check(&fs::read(output)?) {
+        let _ = fs::remove_file(output);
+        bail!("{url} is not a usable image: {problem}");
+    }
+    Ok(())
+}
+
+/// Checks that `data` is an image that can be shown, returning a description of the problem if it is not
+pub fn check(data: &[u8]) -> Option<String> {
+    image::load_from_memory(data).err().map(|e| e.to_string())
+}
+
+/// Loads the cover art at `path`, shrinking it if it is larger than needed
+pub fn load(path: &Path) -> Result<RgbImage> {
+    let image = image::load_from_memory(&fs::read(path)?)?;
+    Ok(if image.width() > MAX_SIZE || image.height() > MAX_SIZE {
+        image.resize(MAX_SIZE, MAX_SIZE, FilterType::Triangle)
+    } else {
+        image
+    }
+    .into_rgb8())
+}
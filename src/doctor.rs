@@ -0,0 +1,460 @@
+//! Handling of `dmm doctor`: checks that everything DMM needs is set up, and reports what is not.
+//! `dmm check` does only the part of this that checks the files in the music directory.
+//!
+//! Unlike the other commands, nothing stops at the first problem, so one run finds everything that needs fixing.
+//! Nothing in the music directory is changed.
+
+use std::{
+    collections::HashMap,
+    env,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::{bail, Result};
+use cpal::traits::DeviceTrait;
+use crossterm::style::Stylize;
+
+use crate::{
+    cfg::Config,
+    error::DmmError,
+    player2,
+    resolver::{self, Directories},
+    schema::{Album, Import, Playlist, Source, SourceKind, Track},
+    ytdlp,
+};
+
+#[derive(Default)]
+struct Report {
+    failed: usize,
+    warnings: usize,
+}
+
+impl Report {
+    fn section(&self, name: &str) {
+        println!("{}", name.bold());
+    }
+
+    fn pass(&self, msg: impl Display) {
+        println!("  {} {msg}", "✓".green());
+    }
+
+    fn warn(&mut self, msg: impl Display) {
+        self.warnings += 1;
+        println!("  {} {msg}", "!".yellow());
+    }
+
+    fn fail(&mut self, msg: impl Display) {
+        self.failed += 1;
+        println!("  {} {msg}", "✗".red());
+    }
+}
+
+/// Finds `cmd` the way running it would (in PATH, unless it is a path itself)
+fn find_command(cmd: &str) -> Option<PathBuf> {
+    if cmd.chars().any(std::path::is_separator) {
+        return Path::new(cmd).is_file().then(|| cmd.into());
+    }
+    // on Windows, commands are found without their extension (`.exe`, `.bat`, ...)
+    let extensions = match cfg!(windows) {
+        true => env::var("PATHEXT").unwrap_or_default(),
+        false => String::new(),
+    };
+    env::split_paths(&env::var_os("PATH")?)
+        .flat_map(|dir| {
+            let with_extensions = extensions
+                .split(';')
+                .filter(|ext| !ext.is_empty())
+                .map(|ext| dir.join(format!("{cmd}{ext}")))
+                .collect::<Vec<_>>();
+            [dir.join(cmd)].into_iter().chain(with_extensions)
+        })
+        .find(|path| path.is_file())
+}
+
+/// Reads each of `files` as a `T`, reporting the ones that fail to parse
+fn parse_files<T: serde::de::DeserializeOwned>(
+    report: &mut Report,
+    files: Vec<PathBuf>,
+) -> Vec<(PathBuf, T)> {
+    let mut parsed = vec![];
+    for path in files {
+        // (the error includes the path, and where in the file the problem is)
+        match resolver::read_ron::<T>(&path) {
+            Ok(value) => parsed.push((path, value)),
+            Err(e) => report.fail(e),
+        }
+    }
+    parsed
+}
+
+fn check_dirs(report: &mut Report, dirs: &Directories) -> bool {
+    report.section(&format!("Music directory ({})", dirs.root.display()));
+    let mut ok = true;
+    if dirs.root.join("dmm.ron").is_file() {
+        report.pass("dmm.ron");
+    } else {
+        report.warn("dmm.ron is missing (the default config is used)");
+    }
+    for (name, dir) in [("sources/", &dirs.sources), ("playlists/", &dirs.playlists)] {
+        if dir.is_dir() {
+            report.pass(name);
+        } else {
+            report.fail(format!("{name} is missing"));
+            ok = false;
+        }
+    }
+    if dirs.cache.is_dir() {
+        // DMM writes temporary files into the store, then renames them into place
+        let probe = dirs.cache.join(".dmm-doctor");
+        match fs::write(&probe, []).and_then(|()| fs::remove_file(&probe)) {
+            Ok(()) => report.pass("cache/ is writable"),
+            Err(e) => report.fail(format!("cache/ is not writable: {e}")),
+        }
+    } else {
+        report.warn("cache/ is missing (it is created by the next download)");
+    }
+    ok
+}
+
+/// Reads every file in sources/, reporting the ones that fail to parse, and names used by more than one source
+fn read_sources(report: &mut Report, dirs: &Directories) -> Vec<(PathBuf, Source)> {
+    let mut files = match fs::read_dir(&dirs.sources) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .collect::<Vec<_>>(),
+        Err(e) => {
+            report.fail(format!("failed to read sources/: {e}"));
+            return vec![];
+        }
+    };
+    files.sort();
+    let sources = parse_files::<Source>(report, files);
+    let mut seen = HashMap::<&str, &Path>::new();
+    for (path, source) in &sources {
+        if let Some(first) = seen.insert(&source.name, path) {
+            report.fail(format!(
+                "source {} is defined in both {} and {}",
+                source.name,
+                first.display(),
+                path.display()
+            ));
+        }
+    }
+    sources
+}
+
+fn check_sources(report: &mut Report, dirs: &Directories) -> Vec<Source> {
+    report.section("Sources");
+    let sources = read_sources(report, dirs)
+        .into_iter()
+        .map(|(_, source)| source)
+        .collect::<Vec<_>>();
+    for source in &sources {
+        match &source.kind {
+            SourceKind::Shell {
+                cmd, shell: None, ..
+            } => match find_command(cmd) {
+                Some(path) => report.pass(format!("{}: runs {}", source.name, path.display())),
+                None => report.fail(format!("{}: `{cmd}` was not found in PATH", source.name)),
+            },
+            // (the command can be a builtin of the shell, so only the shell itself is looked for)
+            SourceKind::Shell {
+                shell: Some(shell), ..
+            } => match find_command(shell.program()) {
+                Some(path) => report.pass(format!("{}: runs in {}", source.name, path.display())),
+                None => report.fail(format!(
+                    "{}: the shell `{}` was not found in PATH",
+                    source.name,
+                    shell.program()
+                )),
+            },
+            SourceKind::YtDlp { .. } => match ytdlp::version() {
+                Ok(version) => report.pass(format!("{}: yt-dlp {version}", source.name)),
+                Err(e) => report.fail(format!("{}: {e}", source.name)),
+            },
+        }
+    }
+    if sources.is_empty() {
+        report.warn("no sources are defined in sources/ (playlists can still define their own)");
+    }
+    sources
+}
+
+/// Problems with the sources of a playlist or album (a `what`), and of its tracks
+fn source_problems(
+    what: &str,
+    import: &[Import],
+    own: &[Source],
+    default_source: Option<&String>,
+    tracks: &[Track],
+    sources: &[Source],
+) -> Vec<String> {
+    let mut problems = vec![];
+    for (i, source) in own.iter().enumerate() {
+        if own[..i].iter().any(|other| other.name == source.name) {
+            problems.push(format!("source {} is defined more than once", source.name));
+        }
+        if import.contains(&Import::Source(source.name.clone())) {
+            problems.push(format!(
+                "source {} is both imported and defined in the {what}",
+                source.name
+            ));
+        }
+    }
+    let mut available = own.to_vec();
+    for source in import.iter().filter_map(Import::source) {
+        match sources.iter().find(|src| &src.name == source) {
+            Some(source) => available.push(source.clone()),
+            None => problems.push(format!("imports source {source}, which does not exist")),
+        }
+    }
+    if let Some(default) = default_source {
+        if !available.iter().any(|src| &src.name == default) {
+            problems.push(format!(
+                "default source {default} is not defined or imported"
+            ));
+        }
+    }
+    for track in tracks {
+        let (start, end) = (track.start.map(|s| s.0), track.end.map(|e| e.0));
+        if [start, end]
+            .into_iter()
+            .flatten()
+            .any(|time| time.is_nan() || time < 0.0)
+        {
+            problems.push(format!(
+                "track {}: start and end must be times (in seconds) of at least 0",
+                track.meta.name
+            ));
+        } else if let (Some(start), Some(end)) = (start, end) {
+            if start >= end {
+                problems.push(format!(
+                    "track {} ends (at {end}s) before it starts (at {start}s)",
+                    track.meta.name
+                ));
+            }
+        }
+        let src = match track.src.is_empty() {
+            true => default_source,
+            false => Some(&track.src),
+        };
+        match src {
+            None => problems.push(format!(
+                "track {} has no source (set its `src`, or the {what}'s `default_source`)",
+                track.meta.name
+            )),
+            Some(src) => match available.iter().find(|s| &s.name == src) {
+                // (a missing default source is reported once, above)
+                None if track.src.is_empty() => {}
+                None => problems.push(format!(
+                    "track {} uses source {src}, which is not defined or imported",
+                    track.meta.name
+                )),
+                Some(source) => {
+                    if let Err(e) = source.overrides(track.overrides.as_ref()) {
+                        problems.push(format!("track {}: {e}", track.meta.name));
+                    }
+                    if let Err(e) = source.input(&track.input) {
+                        problems.push(format!("track {}: {e}", track.meta.name));
+                    }
+                }
+            },
+        }
+    }
+    problems
+}
+
+/// Checks the albums, returning the names of the ones that loaded
+fn check_albums(report: &mut Report, dirs: &Directories, sources: &[Source]) -> Vec<String> {
+    let mut files = match fs::read_dir(&dirs.albums) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .collect::<Vec<_>>(),
+        // (albums are optional)
+        Err(_) => return vec![],
+    };
+    report.section("Albums");
+    files.sort();
+    let albums = parse_files::<Album>(report, files);
+    for (path, album) in &albums {
+        let name = format!("{} ({})", album.name, path.display());
+        let mut problems = source_problems(
+            "album",
+            &album.import,
+            &album.sources,
+            album.default_source.as_ref(),
+            &album.tracks,
+            sources,
+        );
+        if album.import.iter().any(|import| import.album().is_some()) {
+            problems.push("albums can only import sources".to_string());
+        }
+        if albums
+            .iter()
+            .filter(|(_, other)| other.name == album.name)
+            .count()
+            > 1
+        {
+            problems.push(format!("there is more than one album named {}", album.name));
+        }
+        if problems.is_empty() {
+            report.pass(format!("{name}: {} track(s)", album.tracks.len()));
+        } else {
+            report.fail(format!("{name}:"));
+            for problem in problems {
+                println!("      {problem}");
+            }
+        }
+    }
+    albums.into_iter().map(|(_, album)| album.name).collect()
+}
+
+fn check_playlists(report: &mut Report, dirs: &Directories, sources: &[Source], albums: &[String]) {
+    report.section("Playlists");
+    let mut files = vec![];
+    if let Err(e) = resolver::find_playlists(&dirs.playlists, None, &mut files) {
+        report.fail(format!("failed to read playlists/: {e}"));
+        return;
+    }
+    let playlists =
+        parse_files::<Playlist>(report, files.into_iter().map(|(path, _)| path).collect());
+    for (path, pl) in &playlists {
+        let name = format!("{} ({})", pl.name, path.display());
+        let mut problems = source_problems(
+            "playlist",
+            &pl.import,
+            &pl.sources,
+            pl.default_source.as_ref(),
+            &pl.tracks,
+            sources,
+        );
+        for album in pl.import.iter().filter_map(Import::album) {
+            if !albums.contains(album) {
+                problems.push(format!("imports album {album}, which does not exist"));
+            }
+        }
+        if problems.is_empty() {
+            let albums = match pl.import.iter().filter_map(Import::album).count() {
+                0 => String::new(),
+                n => format!(" (and {n} album(s))"),
+            };
+            report.pass(format!("{name}: {} track(s){albums}", pl.tracks.len()));
+        } else {
+            report.fail(format!("{name}:"));
+            for problem in problems {
+                println!("      {problem}");
+            }
+        }
+        // the same download more than once is allowed (the track is played twice), but usually a mistake
+        let mut inputs = HashMap::new();
+        for track in &pl.tracks {
+            let Some(src) = pl.track_source(track) else {
+                continue;
+            };
+            if let Some(first) =
+                inputs.insert((src, &track.input, &track.overrides), &track.meta.name)
+            {
+                report.warn(format!(
+                    "{name}: tracks {first} and {} have the same source and input",
+                    track.meta.name
+                ));
+            }
+        }
+    }
+    if playlists.is_empty() {
+        report.warn("there are no playlists");
+    }
+}
+
+fn check_audio(report: &mut Report, config: Option<&Config>) {
+    report.section("Audio");
+    let device_name = config.and_then(|cfg| cfg.audio.device_name.as_deref());
+    match player2::find_device(device_name) {
+        Ok((device, config)) => report.pass(format!(
+            "output device: {} ({}Hz, {} channel(s))",
+            device.name().unwrap_or("unnamed".to_string()),
+            config.sample_rate().0,
+            config.channels()
+        )),
+        Err(e) => report.fail(format!("no output device: {e}")),
+    }
+}
+
+/// Handling of `dmm doctor`. `root` is the music directory, or the error from looking for it
+pub fn run(root: Result<PathBuf>) -> Result<()> {
+    let mut report = Report::default();
+    let root = match root {
+        Ok(root) => Some(root),
+        Err(e) => {
+            report.section("Music directory");
+            report.fail(e);
+            println!("      run `dmm init` to create one, or use `--in` to point to it");
+            None
+        }
+    };
+    let mut config = None;
+    if let Some(root) = root {
+        let dirs = Directories::from_root(root);
+        if check_dirs(&mut report, &dirs) {
+            match Config::new(dirs.root.clone()) {
+                Ok(cfg) => {
+                    report.pass("dmm.ron is valid");
+                    config = Some(cfg);
+                }
+                Err(e) => report.fail(format!("dmm.ron: {e}")),
+            }
+            let sources = check_sources(&mut report, &dirs);
+            let albums = check_albums(&mut report, &dirs, &sources);
+            check_playlists(&mut report, &dirs, &sources, &albums);
+        }
+    }
+    check_audio(&mut report, config.as_ref());
+    summary(&report)
+}
+
+/// Handling of `dmm check`: checks the config, sources, and playlists in the music directory `root`
+/// (without checking for the tools and audio output they need, like `dmm doctor` does)
+pub fn check(root: PathBuf) -> Result<()> {
+    let mut report = Report::default();
+    let dirs = Directories::from_root(root);
+    report.section(&format!("Music directory ({})", dirs.root.display()));
+    match Config::new(dirs.root.clone()) {
+        Ok(_) => report.pass("dmm.ron is valid"),
+        Err(e) => report.fail(format!("dmm.ron: {e}")),
+    }
+    report.section("Sources");
+    let sources = read_sources(&mut report, &dirs);
+    for (path, source) in &sources {
+        report.pass(format!("{} ({})", source.name, path.display()));
+    }
+    let sources = sources
+        .into_iter()
+        .map(|(_, source)| source)
+        .collect::<Vec<_>>();
+    let albums = check_albums(&mut report, &dirs, &sources);
+    check_playlists(&mut report, &dirs, &sources, &albums);
+    summary(&report)
+}
+
+/// Fails if any problems were found
+fn summary(report: &Report) -> Result<()> {
+    println!();
+    if report.failed > 0 {
+        bail!(DmmError::CheckFailed {
+            problems: report.failed,
+            warnings: report.warnings,
+        });
+    }
+    println!(
+        "{} ({} warning(s))",
+        "Everything looks good".green(),
+        report.warnings
+    );
+    Ok(())
+}
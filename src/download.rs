@@ -0,0 +1,519 @@
+//! Handling of `dmm download`
+//!
+//! Tracks are downloaded by a pool of worker threads, each running one source command at a time.
+//! A failed download does not stop the others, failures are reported together once everything is done.
+//!
+//! Failed downloads are also saved to `run/download-state.ron`, so that `dmm download retry` can re-attempt
+//! only those (instead of going through every playlist again).
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    thread,
+};
+
+use color_eyre::eyre::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cache::Hash,
+    cfg::LyricsConfig,
+    cover, duration,
+    error::DmmError,
+    lyrics,
+    resolver::Resolver,
+    schema::{self, Meta, Playlist, Progress, Source},
+    store, waveform,
+};
+
+mod progress;
+
+use progress::Display;
+
+/// Name of the download state file (in the `run` directory)
+const STATE_FILE: &str = "download-state.ron";
+/// Sidecar extension of a download that is kept while it is downloaded again
+const BACKUP_EXT: &str = "old";
+
+/// Downloads that failed the last time they were attempted
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    failed: Vec<FailedDownload>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FailedDownload {
+    /// store hash of the track
+    hash: String,
+    /// name of the track (and its playlist)
+    name: String,
+    error: String,
+}
+
+impl State {
+    fn path(res: &Resolver) -> PathBuf {
+        res.dirs().run.join(STATE_FILE)
+    }
+
+    fn load(res: &Resolver) -> Result<Self> {
+        let path = Self::path(res);
+        if !path.try_exists()? {
+            return Ok(Self::default());
+        }
+        match ron::from_str(&fs::read_to_string(&path)?) {
+            Ok(state) => Ok(state),
+            Err(e) => {
+                error!("Failed to read the download state ({path:?}): {e}");
+                info!("Deleting the file will reset it (forgetting which downloads failed)");
+                bail!("invalid download state");
+            }
+        }
+    }
+
+    fn save(&self, res: &Resolver) -> Result<()> {
+        let path = Self::path(res);
+        if self.failed.is_empty() {
+            if path.try_exists()? {
+                fs::remove_file(path)?;
+            }
+            return Ok(());
+        }
+        fs::write(path, schema::to_ron_pretty(self)?)?;
+        Ok(())
+    }
+
+    fn hashes(&self) -> HashSet<Hash> {
+        self.failed
+            .iter()
+            .filter_map(|failed| failed.hash.parse().ok())
+            .collect()
+    }
+}
+
+struct Job {
+    hash: Hash,
+    /// name of the track (and its playlist), for progress reports
+    name: String,
+    path: PathBuf,
+    checksum: PathBuf,
+    task: Task,
+}
+
+enum Task {
+    Track {
+        source: Box<Source>,
+        input: ron::Value,
+        overrides: Option<ron::Value>,
+        peaks: PathBuf,
+        duration: PathBuf,
+        /// metadata of the track and the file to write its lyrics to, if the lyrics command is set and the track has none yet
+        lyrics: Option<Box<(Meta, PathBuf)>>,
+        /// if the track still needs to be downloaded (otherwise, only the waveform, duration, or lyrics are missing)
+        download: bool,
+    },
+    Cover {
+        url: String,
+    },
+}
+
+/// Messages sent from the workers
+enum Update {
+    Started(usize),
+    Progress(usize, Progress),
+    Done(usize, Result<()>),
+}
+
+struct Failure {
+    /// None if the track could not be attempted at all (it has no source)
+    hash: Option<Hash>,
+    name: String,
+    error: String,
+}
+
+/// Downloads to run, see [`plan`]
+struct Plan {
+    jobs: Vec<Job>,
+    /// hashes of every track that was looked at, downloaded or not
+    seen: HashSet<Hash>,
+    /// number of tracks that are already downloaded
+    cached: usize,
+    failed: Vec<Failure>,
+}
+
+/// Work to do for the tracks in `playlists` that `filter` accepts (and their cover art and lyrics, if enabled)
+fn plan(res: &Resolver, playlists: &[Playlist], filter: impl Fn(Hash) -> bool) -> Plan {
+    let cache = &res.out().cache;
+    let config = &res.out().config;
+    let covers = config.cover_art.download;
+    let mut plan = Plan {
+        jobs: vec![],
+        seen: HashSet::new(),
+        cached: 0,
+        failed: vec![],
+    };
+    for playlist in playlists {
+        for track in playlist.tracks.iter().filter(|track| !track.disabled) {
+            let name = format!("{} ({})", track.meta.name, playlist.name);
+            if let Some(url) = track.meta.cover.as_ref().filter(|_| covers) {
+                let hash = Hash::cover(url);
+                if filter(hash) && plan.seen.insert(hash) {
+                    if cache.find(hash).is_some() {
+                        plan.cached += 1;
+                    } else {
+                        plan.jobs.push(Job {
+                            hash,
+                            name: format!("cover of {name}"),
+                            path: cache.create(hash),
+                            checksum: cache.sidecar(hash, store::CHECKSUM_EXT),
+                            task: Task::Cover { url: url.clone() },
+                        });
+                    }
+                }
+            }
+            let Some(source) = playlist.find_source(&track.src) else {
+                error!("Could not find source {} for track {name}", track.src);
+                plan.failed.push(Failure {
+                    hash: None,
+                    name,
+                    error: format!("could not find source {}", track.src),
+                });
+                continue;
+            };
+            let hash = Hash::track(source, track);
+            // the same track may be in multiple playlists (it is named after the first one)
+            if !filter(hash) || !plan.seen.insert(hash) {
+                continue;
+            }
+            let name = match res.out().playlists_with(hash) {
+                0 | 1 => name,
+                n => format!("{} ({} +{} more)", track.meta.name, playlist.name, n - 1),
+            };
+            let peaks = cache.sidecar(hash, waveform::SIDECAR_EXT);
+            let duration = cache.sidecar(hash, duration::SIDECAR_EXT);
+            let lyrics = Some(cache.sidecar(hash, lyrics::SIDECAR_EXT))
+                .filter(|sidecar| {
+                    config.lyrics.cmd.is_some() && track.lyrics.is_none() && !sidecar.exists()
+                })
+                .map(|sidecar| Box::new((track.meta.clone(), sidecar)));
+            let download = cache.find(hash).is_none();
+            if !download {
+                debug!("{name} exists in cache [skipping]");
+                plan.cached += 1;
+                if peaks.exists() && duration.exists() && lyrics.is_none() {
+                    continue;
+                }
+            }
+            plan.jobs.push(Job {
+                hash,
+                name,
+                path: cache.create(hash),
+                checksum: cache.sidecar(hash, store::CHECKSUM_EXT),
+                task: Task::Track {
+                    source: Box::new(source.clone()),
+                    input: track.input.clone(),
+                    overrides: track.overrides.clone(),
+                    peaks,
+                    duration,
+                    lyrics,
+                    download,
+                },
+            });
+        }
+    }
+    plan
+}
+
+/// Downloads every track in `playlists` that is not already in the store, running up to `jobs` downloads at once
+pub fn download(res: &Resolver, playlists: &[Playlist], jobs: usize) -> Result<()> {
+    for playlist in playlists {
+        info!("queueing tracks in playlist {}", playlist.name);
+    }
+    let plan = plan(res, playlists, |_| true);
+    let shared = plan
+        .seen
+        .iter()
+        .filter(|&&hash| res.out().playlists_with(hash) > 1)
+        .count();
+    if shared != 0 {
+        info!("{shared} track(s) are in more than one playlist, and are only downloaded once");
+    }
+    run(res, plan, jobs, None)
+}
+
+/// Re-attempts the downloads that failed last time
+pub fn retry(res: &Resolver, jobs: usize) -> Result<()> {
+    let state = State::load(res)?;
+    if state.failed.is_empty() {
+        info!("No failed downloads to retry");
+        return Ok(());
+    }
+    download_hashes(res, &state.hashes(), jobs, None)
+}
+
+/// Called as downloads finish when running quietly, with how many are done out of the total
+pub type Report<'a> = &'a dyn Fn(usize, usize);
+
+/// Downloads the tracks (or cover art) with the given store hashes (whichever playlists they are in).
+///
+/// With `quiet`, nothing is written to the terminal (so this can run while the player is open), and progress is reported to it instead
+pub fn download_hashes(
+    res: &Resolver,
+    hashes: &HashSet<Hash>,
+    jobs: usize,
+    quiet: Option<Report>,
+) -> Result<()> {
+    let mut plan = plan(res, &res.out().playlists, |hash| hashes.contains(&hash));
+    // tracks without a source can not have any of the hashes (and are not what was asked for)
+    plan.failed.clear();
+    let gone = hashes.difference(&plan.seen).count();
+    if gone != 0 {
+        // if these were failed downloads, they are forgotten when the state is saved, as they are not in `plan.seen`
+        warn!("{gone} track(s) are no longer in any playlist [skipping]");
+    }
+    run(res, plan, jobs, quiet)
+}
+
+/// Downloads the tracks with the given store hashes again, replacing what is in the store.
+/// If downloading a track fails, its previous download is kept.
+///
+/// With `quiet`, progress is only logged and reported to it (nothing is written to the terminal), so this can run while the player is open
+pub fn redownload(
+    res: &Resolver,
+    hashes: &HashSet<Hash>,
+    jobs: usize,
+    quiet: Option<Report>,
+) -> Result<()> {
+    let cache = &res.out().cache;
+    let mut kept = vec![];
+    for hash in hashes {
+        if let Some(path) = cache.find(*hash) {
+            let backup = cache.sidecar(*hash, BACKUP_EXT);
+            fs::rename(&path, &backup)?;
+            kept.push((path, backup));
+        }
+    }
+    let mut plan = plan(res, &res.out().playlists, |hash| hashes.contains(&hash));
+    plan.failed.clear();
+    let result = run(res, plan, jobs, quiet);
+    for (path, backup) in kept {
+        if path.exists() {
+            fs::remove_file(backup)?;
+        } else {
+            warn!("keeping the previous download of {path:?}");
+            fs::rename(backup, path)?;
+        }
+    }
+    result
+}
+
+/// Runs the downloads in `plan`. With `quiet`, progress and the output of source commands is not shown
+/// (progress is reported to it instead)
+fn run(res: &Resolver, plan: Plan, jobs: usize, quiet: Option<Report>) -> Result<()> {
+    let Plan {
+        jobs: work,
+        seen,
+        cached,
+        mut failed,
+    } = plan;
+    let total = work.len();
+    let jobs = jobs.clamp(1, total.max(1));
+    // check for missing programs once, instead of failing every download that needs them
+    let mut checked = HashSet::new();
+    for job in &work {
+        if let Task::Track {
+            source,
+            download: true,
+            ..
+        } = &job.task
+        {
+            if checked.insert(std::mem::discriminant(&source.kind)) {
+                source.check_available()?;
+            }
+        }
+    }
+    info!("{total} file(s) to download ({cached} already downloaded), using {jobs} worker(s)");
+    let index = Mutex::new(store::ContentIndex::load(&res.out().cache)?);
+    let names = work
+        .iter()
+        .map(|job| (job.hash, job.name.clone()))
+        .collect::<Vec<_>>();
+    let (job_tx, job_rx) = flume::unbounded::<(usize, Job)>();
+    let (update_tx, update_rx) = flume::unbounded::<Update>();
+    for job in work.into_iter().enumerate() {
+        job_tx.send(job).unwrap();
+    }
+    drop(job_tx);
+
+    let mut display = Display::new(total, quiet.is_some());
+    // the output of concurrent commands would be interleaved (and so would progress bars), so it is only shown when running one at a time
+    let show_output = jobs == 1 && !display.is_bars() && quiet.is_none();
+
+    thread::scope(|s| {
+        for _ in 0..jobs {
+            let job_rx = job_rx.clone();
+            let update_tx = update_tx.clone();
+            let index = &index;
+            let lyrics = &res.out().config.lyrics;
+            s.spawn(move || {
+                for (id, job) in job_rx.iter() {
+                    let _ = update_tx.send(Update::Started(id));
+                    let report = |progress| {
+                        let _ = update_tx.send(Update::Progress(id, progress));
+                    };
+                    let result = run_job(&job, show_output, index, lyrics, &report);
+                    let _ = update_tx.send(Update::Done(id, result));
+                }
+            });
+        }
+        drop(update_tx);
+
+        let mut done = 0;
+        for update in update_rx.iter() {
+            let (id, result) = match update {
+                Update::Started(id) => {
+                    display.started(&names, id);
+                    continue;
+                }
+                Update::Progress(id, progress) => {
+                    display.progress(&names, id, progress);
+                    continue;
+                }
+                Update::Done(id, result) => (id, result),
+            };
+            done += 1;
+            display.done(&names, id, (done, total), &result);
+            if let Some(report) = quiet {
+                report(done, total);
+            }
+            if let Err(e) = result {
+                let (hash, name) = names[id].clone();
+                failed.push(Failure {
+                    hash: Some(hash),
+                    name,
+                    error: e.to_string(),
+                });
+            }
+        }
+    });
+    display.finish();
+
+    // tracks that were attempted this time replace their previous result
+    let mut state = State::load(res)?;
+    state
+        .failed
+        .retain(|old| !old.hash.parse().is_ok_and(|hash| seen.contains(&hash)));
+    state.failed.extend(failed.iter().filter_map(|failure| {
+        Some(FailedDownload {
+            hash: failure.hash?.to_string(),
+            name: failure.name.clone(),
+            error: failure.error.clone(),
+        })
+    }));
+    state.save(res)?;
+
+    if res.out().config.cache.max_size.is_some() && total > 0 {
+        if let Err(e) = store::prune(res, false, false) {
+            warn!("Failed to prune the store: {e}");
+        }
+    }
+
+    if !failed.is_empty() {
+        error!("{} download(s) failed:", failed.len());
+        for Failure { name, error, .. } in &failed {
+            error!("  {name}: {}", error.lines().next().unwrap_or_default());
+        }
+        if !state.failed.is_empty() {
+            info!("Run `dmm download retry` to try these again");
+        }
+        bail!(DmmError::DownloadFailed);
+    }
+    info!("Done!");
+    Ok(())
+}
+
+fn run_job(
+    job: &Job,
+    show_output: bool,
+    index: &Mutex<store::ContentIndex>,
+    lyrics_config: &LyricsConfig,
+    progress: &dyn Fn(Progress),
+) -> Result<()> {
+    match &job.task {
+        Task::Track {
+            source,
+            input,
+            overrides,
+            peaks,
+            duration,
+            lyrics,
+            download,
+        } => {
+            if *download {
+                debug!("downloading {}", job.name);
+                let downloaded = source
+                    .execute(
+                        input.clone(),
+                        overrides.as_ref(),
+                        &job.path,
+                        show_output,
+                        progress,
+                    )
+                    .and_then(|()| source.postprocess(&job.path, show_output))
+                    .and_then(|()| source.transcode(&job.path, show_output));
+                if let Err(e) = downloaded {
+                    // do not leave a partial download in the store, it would be mistaken for a complete one
+                    let _ = fs::remove_file(&job.path);
+                    return Err(e);
+                }
+                debug!("download complete");
+                let sum = store::write_checksum(&job.path, &job.checksum)?;
+                dedup(index, job, &sum);
+            }
+            if *download || !peaks.exists() {
+                generate_waveform(&job.path, source.stored_format(), peaks);
+            }
+            if *download || !duration.exists() {
+                debug!("finding duration");
+                if let Err(e) = duration::write(&job.path, source.stored_format(), duration) {
+                    warn!("Failed to find the duration of the track: {e}");
+                }
+            }
+            if let Some((meta, sidecar)) = lyrics.as_deref() {
+                debug!("fetching lyrics");
+                if let Err(e) = lyrics::fetch(lyrics_config, meta, sidecar) {
+                    warn!("Failed to fetch the lyrics of the track: {e}");
+                }
+            }
+        }
+        Task::Cover { url } => {
+            debug!("downloading {}", job.name);
+            cover::download(url, &job.path)?;
+            let sum = store::write_checksum(&job.path, &job.checksum)?;
+            dedup(index, job, &sum);
+        }
+    }
+    Ok(())
+}
+
+/// Links a new download to an identical file already in the store (if there is one).
+/// failing to do so is not fatal to the download
+fn dedup(index: &Mutex<store::ContentIndex>, job: &Job, sum: &str) {
+    match index.lock().unwrap().dedup(&job.path, sum) {
+        Ok(0) => {}
+        Ok(_) => info!(
+            "{} is identical to a file already in the store, linked it to the existing copy",
+            job.name
+        ),
+        Err(e) => warn!("Failed to check the store for duplicates: {e}"),
+    }
+}
+
+/// generates the waveform shown in the player. failing to do so is not fatal to the download
+fn generate_waveform(path: &Path, format: &str, sidecar: &Path) {
+    debug!("generating waveform");
+    if let Err(e) = waveform::write(path, format, sidecar) {
+        warn!("Failed to generate waveform: {e}");
+    }
+}
@@ -0,0 +1,189 @@
+//! Showing the progress of a download run
+//!
+//! In a terminal, this is an overall progress bar with a line for each running download under it.
+//! Otherwise (for example when the output goes to a file), progress is logged every few seconds instead
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, IsTerminal},
+    time::{Duration, Instant},
+};
+
+use color_eyre::eyre::Result;
+use crossterm::style::Stylize;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+
+use crate::{cache::Hash, log, schema::Progress};
+
+/// Minimum time between progress reports (when logging)
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(2);
+/// How often the spinners of running downloads move
+const TICK_INTERVAL: Duration = Duration::from_millis(100);
+
+pub(super) enum Display {
+    Log {
+        /// latest progress of each running download that reports it
+        running: BTreeMap<usize, Progress>,
+        last_report: Instant,
+    },
+    Bars {
+        multi: MultiProgress,
+        overall: ProgressBar,
+        /// line of each running download
+        running: HashMap<usize, ProgressBar>,
+    },
+}
+
+fn style(template: &str) -> ProgressStyle {
+    ProgressStyle::with_template(template)
+        .expect("invalid progress template")
+        .progress_chars("=> ")
+}
+
+impl Display {
+    /// Progress bars if stderr is a terminal (and not `quiet`), log lines otherwise
+    pub fn new(total: usize, quiet: bool) -> Self {
+        if quiet || !io::stderr().is_terminal() {
+            return Self::Log {
+                running: BTreeMap::new(),
+                last_report: Instant::now(),
+            };
+        }
+        let multi = MultiProgress::new();
+        let overall = multi.add(
+            ProgressBar::new(total as u64)
+                .with_style(style(
+                    "{prefix:.bold} [{bar:40.cyan/blue}] {pos}/{len} ({elapsed})",
+                ))
+                .with_prefix("downloading"),
+        );
+        overall.tick();
+        log::set_progress(Some(multi.clone()));
+        Self::Bars {
+            multi,
+            overall,
+            running: HashMap::new(),
+        }
+    }
+
+    /// If progress bars are shown (the output of source commands would break them up)
+    pub fn is_bars(&self) -> bool {
+        matches!(self, Self::Bars { .. })
+    }
+
+    pub fn started(&mut self, names: &[(Hash, String)], id: usize) {
+        if let Self::Bars { multi, running, .. } = self {
+            let bar = multi.add(
+                ProgressBar::no_length()
+                    .with_style(style("  {spinner} {msg}"))
+                    .with_message(names[id].1.clone()),
+            );
+            bar.enable_steady_tick(TICK_INTERVAL);
+            running.insert(id, bar);
+        }
+    }
+
+    pub fn progress(&mut self, names: &[(Hash, String)], id: usize, progress: Progress) {
+        match self {
+            Self::Log {
+                running,
+                last_report,
+            } => {
+                running.insert(id, progress);
+                if last_report.elapsed() >= PROGRESS_INTERVAL {
+                    *last_report = Instant::now();
+                    report_progress(names, running);
+                }
+            }
+            Self::Bars { running, .. } => {
+                let Some(bar) = running.get(&id) else {
+                    return;
+                };
+                match progress.total {
+                    Some(total) if total != 0 => {
+                        if bar.length() != Some(total) {
+                            bar.set_style(style(
+                                "  {spinner} {msg} [{bar:20}] {bytes}/{total_bytes} ({bytes_per_sec})",
+                            ));
+                            bar.set_length(total);
+                        }
+                    }
+                    _ => {
+                        if bar.length().is_some() {
+                            bar.set_style(style("  {spinner} {msg} {bytes} ({bytes_per_sec})"));
+                            bar.unset_length();
+                        }
+                    }
+                }
+                bar.set_position(progress.downloaded);
+            }
+        }
+    }
+
+    /// Reports the result of download `id`, `done` of `total` downloads in
+    pub fn done(
+        &mut self,
+        names: &[(Hash, String)],
+        id: usize,
+        (done, total): (usize, usize),
+        result: &Result<()>,
+    ) {
+        let name = &names[id].1;
+        match self {
+            Self::Log { running, .. } => {
+                running.remove(&id);
+                match result {
+                    Ok(()) => info!("[{done}/{total}] finished {name}"),
+                    Err(e) => error!("[{done}/{total}] failed to download {name}: {e}"),
+                }
+            }
+            Self::Bars {
+                multi,
+                overall,
+                running,
+            } => {
+                if let Some(bar) = running.remove(&id) {
+                    bar.finish_and_clear();
+                    multi.remove(&bar);
+                }
+                overall.inc(1);
+                let line = match result {
+                    Ok(()) => format!("{} {name}", "✓".green()),
+                    Err(e) => format!(
+                        "{} {name}: {}",
+                        "✗".red(),
+                        e.to_string().lines().next().unwrap_or_default()
+                    ),
+                };
+                let _ = multi.println(line);
+            }
+        }
+    }
+
+    /// Removes the progress bars once everything is done
+    pub fn finish(self) {
+        if let Self::Bars { multi, .. } = self {
+            log::set_progress(None);
+            let _ = multi.clear();
+        }
+    }
+}
+
+/// Logs the progress of the running downloads (that report it)
+fn report_progress(names: &[(Hash, String)], running: &BTreeMap<usize, Progress>) {
+    let report = running
+        .iter()
+        .map(|(id, progress)| {
+            let name = &names[*id].1;
+            match progress.total {
+                Some(total) if total != 0 => {
+                    format!("{name} {}%", progress.downloaded * 100 / total)
+                }
+                _ => format!("{name} {}KiB", progress.downloaded / 1024),
+            }
+        })
+        .collect::<Vec<_>>();
+    if !report.is_empty() {
+        info!("downloading: {}", report.join(", "));
+    }
+}
@@ -0,0 +1,57 @@
+//! Durations of downloaded tracks, shown in the player
+//!
+//! The duration is stored next to the download in the store, as a `<hash>.duration` sidecar file
+//! containing the length of the track in (whole) seconds.
+
+use std::{fs, path::Path};
+
+use color_eyre::eyre::Result;
+use symphonia::core::probe;
+
+use crate::player2::{AudioDecoder, Decoded};
+
+/// Extension of the sidecar files in the store
+pub const SIDECAR_EXT: &str = "duration";
+
+/// Finds the duration (in seconds) of the track at `path`
+pub fn probe(path: &Path, format: &str) -> Result<u64> {
+    let mut hint = probe::Hint::new();
+    hint.with_extension(format);
+    let mut decoder = AudioDecoder::new(fs::File::open(path)?, hint)?;
+    if let Some(duration) = decoder.duration() {
+        return Ok(duration.seconds);
+    }
+    // not stated in the file, so the whole track is decoded to count its frames
+    let (mut frames, mut rate) = (0u64, 0u64);
+    loop {
+        match decoder.decode_next()? {
+            Decoded::StreamEnd => break,
+            Decoded::Retry => continue,
+            Decoded::Decoded(_, buffer) => {
+                frames += buffer.frames() as u64;
+                rate = buffer.spec().rate as u64;
+            }
+        }
+    }
+    Ok(frames.checked_div(rate).unwrap_or(0))
+}
+
+/// Finds the duration of the track at `path` and writes it to `sidecar`
+pub fn write(path: &Path, format: &str, sidecar: &Path) -> Result<()> {
+    fs::write(sidecar, probe(path, format)?.to_string())?;
+    Ok(())
+}
+
+/// Reads a previously found duration, if it exists
+pub fn read(sidecar: &Path) -> Option<u64> {
+    fs::read_to_string(sidecar).ok()?.trim().parse().ok()
+}
+
+/// Formats `secs` as `m:ss` (or `h:mm:ss` if it is an hour or longer)
+pub fn format(secs: u64) -> String {
+    if secs >= 3600 {
+        format!("{}:{:0>2}:{:0>2}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:0>2}", secs / 60, secs % 60)
+    }
+}
@@ -0,0 +1,646 @@
+//! Changes to playlist files made from the player
+//!
+//! Playlist files are written by hand, so instead of serializing the whole playlist again (which would lose
+//! comments and formatting), only the part of the file that changed is rewritten where possible
+
+use std::{fs, ops::Range};
+
+use color_eyre::eyre::{anyhow, bail, Result};
+use serde::Serialize;
+
+use crate::{
+    cache::Hash,
+    git, playlist,
+    resolver::Resolver,
+    schema::{self, Import, Meta, Playlist, Source},
+    source,
+};
+
+/// If `text[i..]` starts a string, char, or comment, returns where it ends
+fn skip_literal(text: &str, i: usize) -> Option<usize> {
+    let rest = &text[i..];
+    let closing = |from: usize, quote: u8| {
+        let bytes = rest.as_bytes();
+        let mut j = from;
+        while j < bytes.len() {
+            match bytes[j] {
+                b'\\' => j += 2,
+                c if c == quote => return Some(i + j + 1),
+                _ => j += 1,
+            }
+        }
+        None
+    };
+    if rest.starts_with("//") {
+        Some(rest.find('\n').map_or(text.len(), |end| i + end))
+    } else if rest.starts_with("/*") {
+        // block comments can be nested
+        let mut depth = 0;
+        let mut j = 0;
+        while j < rest.len() {
+            if rest[j..].starts_with("/*") {
+                depth += 1;
+                j += 2;
+            } else if rest[j..].starts_with("*/") {
+                depth -= 1;
+                j += 2;
+                if depth == 0 {
+                    return Some(i + j);
+                }
+            } else {
+                j += 1;
+            }
+        }
+        None
+    } else if rest.starts_with('"') {
+        closing(1, b'"')
+    } else if rest.starts_with('\'') {
+        closing(1, b'\'')
+    } else if let Some(raw) = rest.strip_prefix('r').filter(|raw| {
+        // raw strings: r"..." or r#"..."#
+        raw.trim_start_matches('#').starts_with('"')
+            && (i == 0 || !is_ident(text.as_bytes()[i - 1]))
+    }) {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+        let end = format!("\"{}", "#".repeat(hashes));
+        let start = 1 + hashes + 1;
+        rest[start..]
+            .find(&end)
+            .map(|end_at| i + start + end_at + end.len())
+    } else {
+        None
+    }
+}
+
+fn is_ident(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_'
+}
+
+/// Skips whitespace and comments
+fn skip_blank(text: &str, mut i: usize) -> usize {
+    loop {
+        let rest = &text[i..];
+        let trimmed = rest.trim_start();
+        i += rest.len() - trimmed.len();
+        match skip_literal(text, i) {
+            Some(end) if trimmed.starts_with("//") || trimmed.starts_with("/*") => i = end,
+            _ => return i,
+        }
+    }
+}
+
+/// Where the entries of the `tracks` list are in the text of a playlist file.
+///
+/// Each entry includes the comments above it, and the comma after it (and a comment after that, on the same line).
+/// The last entry may not have a comma
+struct TrackList {
+    entries: Vec<Range<usize>>,
+    missing_comma: bool,
+}
+
+/// Finds the field `name` of the struct in `text[range]` (the struct the range starts in, not the ones nested in it),
+/// returning where its value starts
+fn find_field(text: &str, range: Range<usize>, name: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut i = range.start;
+    let mut depth = 0;
+    while i < range.end {
+        if let Some(end) = skip_literal(text, i) {
+            i = end;
+            continue;
+        }
+        match bytes[i] {
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' => depth -= 1,
+            _ if depth == 1
+                && text[i..].starts_with(name)
+                && (i == 0 || !is_ident(bytes[i - 1]))
+                && !bytes.get(i + name.len()).copied().is_some_and(is_ident) =>
+            {
+                let colon = skip_blank(text, i + name.len());
+                if bytes.get(colon) == Some(&b':') {
+                    return Some(skip_blank(text, colon + 1));
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Returns where the value starting at `start` ends
+fn value_end(text: &str, start: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut depth = 0;
+    let mut i = start;
+    while i < bytes.len() {
+        if let Some(end) = skip_literal(text, i) {
+            if depth == 0 {
+                return Some(end);
+            }
+            i = end;
+            continue;
+        }
+        match bytes[i] {
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' if depth == 0 => return Some(i),
+            b')' | b']' | b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            b',' if depth == 0 => return Some(i),
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+fn find_track_list(text: &str) -> Option<TrackList> {
+    let bytes = text.as_bytes();
+    // find the start of the list, `tracks: [` in the playlist itself
+    let open = find_field(text, 0..text.len(), "tracks")?;
+    if bytes.get(open) != Some(&b'[') {
+        return None;
+    }
+    let start = open + 1;
+
+    let mut entries = vec![];
+    let mut entry_start = start;
+    // end of the last thing in the current entry that is not whitespace or a comment
+    let mut content_end = None;
+    let mut depth = 0;
+    let mut i = start;
+    loop {
+        if i >= bytes.len() {
+            return None;
+        }
+        if let Some(end) = skip_literal(text, i) {
+            if !(text[i..].starts_with("//") || text[i..].starts_with("/*")) {
+                content_end = Some(end);
+            }
+            i = end;
+            continue;
+        }
+        match bytes[i] {
+            b'(' | b'[' | b'{' => {
+                depth += 1;
+                content_end = Some(i + 1);
+            }
+            b']' if depth == 0 => {
+                // anything after the last comma is either the last entry, or only comments
+                if let Some(end) = content_end {
+                    entries.push(entry_start..end);
+                }
+                return Some(TrackList {
+                    entries,
+                    missing_comma: content_end.is_some(),
+                });
+            }
+            b')' | b']' | b'}' => {
+                depth -= 1;
+                content_end = Some(i + 1);
+            }
+            b',' if depth == 0 => {
+                content_end.take()?;
+                // a comment on the same line as the comma belongs to this entry
+                let line_rest = &text[i + 1..];
+                let spaces = line_rest.len() - line_rest.trim_start_matches([' ', '\t']).len();
+                let mut end = i + 1;
+                if line_rest[spaces..].starts_with("//") {
+                    end = skip_literal(text, i + 1 + spaces)?;
+                }
+                entries.push(entry_start..end);
+                entry_start = end;
+                i = end;
+                continue;
+            }
+            c if !c.is_ascii_whitespace() => content_end = Some(i + 1),
+            _ => {}
+        }
+        i += 1;
+    }
+}
+
+/// Rewrites the `tracks` list of a playlist file in `order` (the indices of the tracks in their current order).
+/// Tracks that are not in `order` are left out, `len` is the number of tracks in the file.
+///
+/// Returns None if the file could not be understood (then it is better to serialize the playlist again)
+fn reorder_text(text: &str, order: &[usize], len: usize) -> Option<String> {
+    let list = find_track_list(text)?;
+    if list.entries.len() != len {
+        return None;
+    }
+    let entry = |i: usize| {
+        let mut entry = text[list.entries[i].clone()].to_string();
+        // the last entry gets a comma, as it may not be last anymore
+        if list.missing_comma && i == list.entries.len() - 1 {
+            entry.push(',');
+        }
+        entry
+    };
+    let first = list.entries.first()?.start;
+    let last = list.entries.last()?.end;
+    let mut out = text[..first].to_string();
+    for &i in order {
+        out += &entry(i);
+    }
+    out += &text[last..];
+    Some(out)
+}
+
+/// Rewrites the metadata of the tracks in `changes` (their index, and their metadata before and after).
+/// Only empty names and artists, and missing albums, can be filled in.
+///
+/// Returns None if the file could not be understood
+fn fill_meta_text(text: &str, changes: &[(usize, &Meta, &Meta)]) -> Option<String> {
+    let list = find_track_list(text)?;
+    let mut edits = vec![];
+    for &(i, old, new) in changes {
+        let start = find_field(text, list.entries.get(i)?.clone(), "meta")?;
+        let end = value_end(text, start)?;
+        for (field, old, new) in [
+            ("name", &old.name, &new.name),
+            ("artist", &old.artist, &new.artist),
+        ] {
+            if old != new {
+                let value = find_field(text, start..end, field)?;
+                edits.push((value..value_end(text, value)?, ron::to_string(new).ok()?));
+            }
+        }
+        if let (None, Some(album)) = (&old.album, &new.album) {
+            // added as the last field
+            let closing = text[..end].strip_suffix(')')?;
+            let at = closing.trim_end().len();
+            let album = ron::to_string(&Some(album)).ok()?;
+            let field = match text[..at].ends_with(',') {
+                true => format!(" album: {album},"),
+                false => format!(", album: {album}"),
+            };
+            edits.push((at..at, field));
+        }
+    }
+    edits.sort_by_key(|(range, _)| range.start);
+    let mut out = text.to_string();
+    for (range, value) in edits.into_iter().rev() {
+        out.replace_range(range, &value);
+    }
+    Some(out)
+}
+
+/// Reads the file of `playlist`, making sure its tracks were not changed since it was loaded.
+/// Playlists importing albums are not edited, as their tracks are not all in the file
+fn read_unchanged(playlist: &Playlist) -> Result<(String, Playlist)> {
+    let path = &playlist.file_path;
+    if playlist
+        .import
+        .iter()
+        .any(|import| import.album().is_some())
+    {
+        error!("{path:?} imports albums, edit it (or the albums) by hand instead");
+        bail!("playlist imports albums");
+    }
+    let text = fs::read_to_string(path)?;
+    let file = ron::from_str::<Playlist>(&text)?;
+    let mut loaded = file.clone();
+    loaded.fill_default_source()?;
+    if loaded.tracks != playlist.tracks {
+        error!("{path:?} was changed since it was loaded");
+        bail!("playlist changed");
+    }
+    Ok((text, file))
+}
+
+/// Saves `playlist` with its tracks in `order` (the indices of the tracks in their current order)
+pub fn reorder_tracks(res: &Resolver, playlist: &Playlist, order: &[usize]) -> Result<()> {
+    let path = &playlist.file_path;
+    let (text, mut file) = read_unchanged(playlist)?;
+    let tracks = order
+        .iter()
+        .map(|&i| file.tracks.get(i).cloned())
+        .collect::<Option<Vec<_>>>()
+        .ok_or(anyhow!("track order does not match the playlist"))?;
+    // the edited text is only used if it reads back as the same playlist, with the tracks in the new order
+    let edited = reorder_text(&text, order, file.tracks.len()).filter(|edited| {
+        ron::from_str::<Playlist>(edited).is_ok_and(|pl| {
+            pl == Playlist {
+                tracks: tracks.clone(),
+                ..file.clone()
+            }
+        })
+    });
+    let content = match edited {
+        Some(edited) => edited,
+        None => {
+            warn!(
+                "Could not edit {path:?} in place, writing it again (comments in it are not kept)"
+            );
+            file.tracks = tracks;
+            schema::to_ron_pretty(&file)?
+        }
+    };
+    source::write_all_or_nothing(&[(path.clone(), content)])?;
+    git::commit_changes(
+        res,
+        std::slice::from_ref(path),
+        &format!("reorder tracks of playlist {:?}", playlist.name),
+    );
+    info!("saved the new track order of {:?}", playlist.name);
+    Ok(())
+}
+
+/// New content of the file of `playlist`, without the tracks in `remove` (their indices)
+fn without_tracks(playlist: &Playlist, remove: &[usize]) -> Result<String> {
+    let path = &playlist.file_path;
+    let (text, mut file) = read_unchanged(playlist)?;
+    let keep = (0..file.tracks.len())
+        .filter(|i| !remove.contains(i))
+        .collect::<Vec<_>>();
+    let tracks = keep
+        .iter()
+        .map(|&i| file.tracks[i].clone())
+        .collect::<Vec<_>>();
+    let edited = reorder_text(&text, &keep, file.tracks.len()).filter(|edited| {
+        ron::from_str::<Playlist>(edited).is_ok_and(|pl| {
+            pl == Playlist {
+                tracks: tracks.clone(),
+                ..file.clone()
+            }
+        })
+    });
+    Ok(match edited {
+        Some(edited) => edited,
+        None => {
+            warn!(
+                "Could not edit {path:?} in place, writing it again (comments in it are not kept)"
+            );
+            file.tracks = tracks;
+            schema::to_ron_pretty(&file)?
+        }
+    })
+}
+
+/// Saves `playlist` without the tracks in `remove` (their indices)
+pub fn remove_tracks(res: &Resolver, playlist: &Playlist, remove: &[usize]) -> Result<()> {
+    let path = &playlist.file_path;
+    let content = without_tracks(playlist, remove)?;
+    let removed = (0..playlist.tracks.len())
+        .filter(|i| remove.contains(i))
+        .count();
+    source::write_all_or_nothing(&[(path.clone(), content)])?;
+    git::commit_changes(
+        res,
+        std::slice::from_ref(path),
+        &format!(
+            "remove {removed} track(s) from playlist {:?}",
+            playlist.name
+        ),
+    );
+    info!("removed {removed} track(s) from {:?}", playlist.name);
+    Ok(())
+}
+
+/// Adds `items` (RON values) to the end of the list in the field `name` of the playlist in `text`,
+/// one per line, indented one level more than the field.
+///
+/// Returns None if the file could not be understood
+fn append_text(text: &str, name: &str, items: &[String]) -> Option<String> {
+    if items.is_empty() {
+        return Some(text.to_string());
+    }
+    let open = find_field(text, 0..text.len(), name)?;
+    if text.as_bytes().get(open) != Some(&b'[') {
+        return None;
+    }
+    let close = value_end(text, open)? - 1;
+    let line_start = text[..open].rfind('\n').map_or(0, |i| i + 1);
+    let field_indent = &text
+        [line_start..line_start + text[line_start..].len() - text[line_start..].trim_start().len()];
+    let indent = format!("{field_indent}    ");
+    // after the last entry (and its comma), keeping what follows it (such as the line break before `]`)
+    let at = skip_blank_back(text, open + 1, close);
+    let mut insert = String::new();
+    if at > open + 1 && !text[..at].ends_with(',') {
+        insert.push(',');
+    }
+    // lists written on one line are kept on one line
+    if !text[open..close].contains('\n') {
+        if at > open + 1 {
+            insert.push(' ');
+        }
+        insert += &items.join(", ");
+        let mut out = text.to_string();
+        out.insert_str(at, &insert);
+        return Some(out);
+    }
+    for item in items {
+        insert += &format!("\n{indent}{},", item.replace('\n', &format!("\n{indent}")));
+    }
+    if at == close {
+        insert += &format!("\n{field_indent}");
+    }
+    let mut out = text.to_string();
+    out.insert_str(at, &insert);
+    Some(out)
+}
+
+/// Where the content of `text[start..end]` ends, not counting whitespace and comments after it
+fn skip_blank_back(text: &str, start: usize, end: usize) -> usize {
+    let mut content_end = start;
+    let mut i = start;
+    while i < end {
+        let next = skip_blank(text, i);
+        if next >= end {
+            break;
+        }
+        i = match skip_literal(text, next) {
+            Some(literal_end) => literal_end,
+            None => next + text[next..].chars().next().map_or(1, char::len_utf8),
+        };
+        content_end = i;
+    }
+    content_end
+}
+
+/// Copies the tracks `tracks` (their indices) of `from` to the end of `to`, also removing them from `from` if `remove`.
+///
+/// If `to` does not have the source a track is downloaded with, it is imported (for sources in the `sources`
+/// directory) or copied into it (for sources declared in `from`)
+pub fn copy_tracks(
+    res: &Resolver,
+    from: &Playlist,
+    tracks: &[usize],
+    to: &Playlist,
+    remove: bool,
+) -> Result<()> {
+    if from.file_path == to.file_path {
+        bail!("tracks can not be copied to the playlist they are in");
+    }
+    let (text, mut file) = read_unchanged(to)?;
+    // tracks that are already in `to` are not added again (moving them still removes them from `from`)
+    let existing = playlist::track_hashes(to)?;
+    let (mut new_tracks, mut imports) = (vec![], vec![]);
+    let mut sources = Vec::<&Source>::new();
+    for &i in tracks {
+        let mut track = from
+            .tracks
+            .get(i)
+            .cloned()
+            .ok_or(anyhow!("track {i} is not in the playlist"))?;
+        let source = from.find_source(&track.src).ok_or(anyhow!(
+            "Could not find source {} for track {}",
+            track.src,
+            track.meta.name
+        ))?;
+        if existing.contains_key(&Hash::track(source, &track)) {
+            info!(
+                "{:?} is already in {:?} [skipping]",
+                track.meta.name, to.name
+            );
+            continue;
+        }
+        let in_target = to
+            .find_source(&track.src)
+            .or(sources.iter().copied().find(|src| src.name == track.src));
+        match in_target {
+            Some(existing) if existing == source => {}
+            Some(_) => {
+                bail!(
+                    "playlist {:?} has a different source named {:?}",
+                    to.name,
+                    track.src
+                );
+            }
+            None if res.out().sources.contains(source) => {
+                if !imports.contains(&track.src) {
+                    imports.push(track.src.clone());
+                }
+            }
+            None => sources.push(source),
+        }
+        if to.default_source.as_deref() == Some(track.src.as_str()) {
+            track.src = String::new();
+        }
+        new_tracks.push(track);
+    }
+
+    file.tracks.extend(new_tracks.iter().cloned());
+    file.import
+        .extend(imports.iter().cloned().map(Import::Source));
+    file.sources.extend(sources.iter().map(|&src| src.clone()));
+    // the edited text is only used if it reads back as the same playlist, with the tracks (and sources) added
+    let edited = (|| {
+        let text = append_text(
+            &text,
+            "import",
+            &imports
+                .iter()
+                .map(|name| format!("Source({name:?})"))
+                .collect::<Vec<_>>(),
+        )?;
+        let text = append_text(
+            &text,
+            "sources",
+            &sources
+                .iter()
+                .map(pretty_value)
+                .collect::<Option<Vec<_>>>()?,
+        )?;
+        append_text(
+            &text,
+            "tracks",
+            &new_tracks
+                .iter()
+                .map(pretty_value)
+                .collect::<Option<Vec<_>>>()?,
+        )
+    })()
+    .filter(|edited| ron::from_str::<Playlist>(edited).is_ok_and(|pl| pl == file));
+    let content = match edited {
+        Some(edited) => edited,
+        None => {
+            warn!(
+                "Could not edit {:?} in place, writing it again (comments in it are not kept)",
+                to.file_path
+            );
+            schema::to_ron_pretty(&file)?
+        }
+    };
+
+    let mut files = vec![(to.file_path.clone(), content)];
+    if remove {
+        files.push((from.file_path.clone(), without_tracks(from, tracks)?));
+    }
+    source::write_all_or_nothing(&files)?;
+    let (verb, done) = if remove {
+        ("move", "moved")
+    } else {
+        ("copy", "copied")
+    };
+    git::commit_changes(
+        res,
+        &files
+            .iter()
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>(),
+        &format!(
+            "{verb} {} track(s) from playlist {:?} to {:?}",
+            tracks.len(),
+            from.name,
+            to.name
+        ),
+    );
+    info!(
+        "{done} {} track(s) from {:?} to {:?}",
+        tracks.len(),
+        from.name,
+        to.name
+    );
+    Ok(())
+}
+
+/// `value` in the layout of the hand-written files (without a trailing line break)
+fn pretty_value<T: Serialize>(value: &T) -> Option<String> {
+    schema::to_ron_pretty(value)
+        .ok()
+        .map(|text| text.trim_end().to_string())
+}
+
+/// Saves `playlist` with the metadata of some of its tracks filled in (the index of each track, and its new metadata)
+pub fn fill_track_meta(res: &Resolver, playlist: &Playlist, metas: &[(usize, Meta)]) -> Result<()> {
+    let path = &playlist.file_path;
+    let (text, file) = read_unchanged(playlist)?;
+    let mut filled = file.clone();
+    for (i, meta) in metas {
+        let track = filled
+            .tracks
+            .get_mut(*i)
+            .ok_or(anyhow!("track {i} is not in the playlist"))?;
+        track.meta = meta.clone();
+    }
+    let changes = metas
+        .iter()
+        .map(|(i, meta)| (*i, &file.tracks[*i].meta, meta))
+        .collect::<Vec<_>>();
+    let edited = fill_meta_text(&text, &changes)
+        .filter(|edited| ron::from_str::<Playlist>(edited).is_ok_and(|pl| pl == filled));
+    let content = match edited {
+        Some(edited) => edited,
+        None => {
+            warn!(
+                "Could not edit {path:?} in place, writing it again (comments in it are not kept)"
+            );
+            schema::to_ron_pretty(&filled)?
+        }
+    };
+    source::write_all_or_nothing(&[(path.clone(), content)])?;
+    git::commit_changes(
+        res,
+        std::slice::from_ref(path),
+        &format!("fill in track metadata of playlist {:?}", playlist.name),
+    );
+    Ok(())
+}
@@ -0,0 +1,168 @@
+//! Handling of `dmm playlist enrich`: fills in metadata that is missing from a playlist, using the tags in the
+//! downloaded files (the artist does not have to be typed in by hand for every download)
+
+use std::{fs, path::Path};
+
+use color_eyre::eyre::{anyhow, Result};
+use symphonia::core::{
+    formats::FormatOptions,
+    io::{MediaSourceStream, MediaSourceStreamOptions},
+    meta::{MetadataOptions, MetadataRevision, StandardTagKey},
+    probe::Hint,
+};
+
+use crate::{
+    cache::Hash,
+    duration, edit,
+    resolver::Resolver,
+    schema::{Meta, Playlist},
+};
+
+/// Tags read from a downloaded file
+#[derive(Debug, Default)]
+struct FileTags {
+    title: Option<String>,
+    artist: Option<String>,
+    album_artist: Option<String>,
+    album: Option<String>,
+}
+
+impl FileTags {
+    fn add(&mut self, revision: &MetadataRevision) {
+        for tag in revision.tags() {
+            // (strings in RIFF INFO chunks keep their nul terminator)
+            let value = tag.value.to_string();
+            let value = value.trim_matches(|c: char| c.is_whitespace() || c == '\0');
+            if value.is_empty() {
+                continue;
+            }
+            let field = match tag.std_key {
+                Some(StandardTagKey::TrackTitle) => &mut self.title,
+                Some(StandardTagKey::Artist) => &mut self.artist,
+                Some(StandardTagKey::AlbumArtist) => &mut self.album_artist,
+                Some(StandardTagKey::Album) => &mut self.album,
+                _ => continue,
+            };
+            field.get_or_insert_with(|| value.to_string());
+        }
+    }
+
+    /// Fills in the empty fields of `meta`, returning if anything changed
+    fn fill(self, meta: &mut Meta) -> bool {
+        let mut changed = false;
+        if meta.name.is_empty() {
+            if let Some(title) = self.title {
+                meta.name = title;
+                changed = true;
+            }
+        }
+        if meta.artist.is_empty() {
+            if let Some(artist) = self.artist.or(self.album_artist) {
+                meta.artist = artist;
+                changed = true;
+            }
+        }
+        if meta.album.is_none() && self.album.is_some() {
+            meta.album = self.album;
+            changed = true;
+        }
+        changed
+    }
+}
+
+/// Reads the tags of the track at `path`
+fn read_tags(path: &Path, format: &str) -> Result<FileTags> {
+    let mut hint = Hint::new();
+    hint.with_extension(format);
+    let source = MediaSourceStream::new(
+        Box::new(fs::File::open(path)?),
+        MediaSourceStreamOptions::default(),
+    );
+    let mut probed = symphonia::default::get_probe().format(
+        &hint,
+        source,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+    let mut tags = FileTags::default();
+    // tags in the container itself come first, then the ones found before it (such as ID3 tags in front of an mp3)
+    if let Some(revision) = probed.format.metadata().skip_to_latest() {
+        tags.add(revision);
+    }
+    if let Some(revision) = probed
+        .metadata
+        .get()
+        .as_mut()
+        .and_then(|m| m.skip_to_latest())
+    {
+        tags.add(revision);
+    }
+    Ok(tags)
+}
+
+/// Fills in the name, artist, and album of tracks in `playlist` that do not have them, from the tags of their downloads.
+/// Also finds the durations of downloads that do not have them yet
+pub fn enrich(res: &Resolver, playlist: &Playlist, dry_run: bool) -> Result<()> {
+    let cache = &res.out().cache;
+    let mut filled = vec![];
+    let mut not_downloaded = 0;
+    for (i, track) in playlist.tracks.iter().enumerate() {
+        let source = playlist.find_source(&track.src).ok_or(anyhow!(
+            "Could not find source {} for track {}",
+            track.src,
+            track.meta.name
+        ))?;
+        let hash = Hash::track(source, track);
+        let Some(path) = cache.find(hash) else {
+            not_downloaded += 1;
+            continue;
+        };
+        let sidecar = cache.sidecar(hash, duration::SIDECAR_EXT);
+        if !dry_run && !sidecar.exists() {
+            if let Err(e) = duration::write(&path, source.stored_format(), &sidecar) {
+                warn!("Failed to find the duration of {}: {e}", track.meta.name);
+            }
+        }
+        if !(track.meta.name.is_empty()
+            || track.meta.artist.is_empty()
+            || track.meta.album.is_none())
+        {
+            continue;
+        }
+        let tags = match read_tags(&path, source.stored_format()) {
+            Ok(tags) => tags,
+            Err(e) => {
+                warn!("Failed to read the tags of {}: {e}", track.meta.name);
+                continue;
+            }
+        };
+        let mut meta = track.meta.clone();
+        if tags.fill(&mut meta) {
+            info!(
+                "{:?} by {:?}{}",
+                meta.name,
+                meta.artist,
+                meta.album
+                    .as_ref()
+                    .map_or(String::new(), |album| format!(" (album {album:?})"))
+            );
+            filled.push((i, meta));
+        }
+    }
+    if not_downloaded > 0 {
+        warn!("{not_downloaded} track(s) are not downloaded, and were skipped (run `dmm download` first)");
+    }
+    if filled.is_empty() {
+        info!("nothing to fill in for {:?}", playlist.name);
+    } else if dry_run {
+        info!(
+            "would fill in {} track(s) of {:?} (without --dry-run)",
+            filled.len(),
+            playlist.name
+        );
+    } else {
+        edit::fill_track_meta(res, playlist, &filled)?;
+        info!("filled in {} track(s) of {:?}", filled.len(), playlist.name);
+    }
+    Ok(())
+}
@@ -0,0 +1,52 @@
+//! Kinds of errors that commands fail with, each with its own exit code, so scripts running DMM can tell them apart
+//! (such as a playlist that does not exist, and a download that failed)
+
+use std::process::ExitCode;
+
+use color_eyre::eyre::Report;
+
+/// An error that decides the exit code. These are returned like any other error (`bail!(DmmError::NotFound)`),
+/// or added to one with `wrap_err`. Errors that are none of these exit with 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum DmmError {
+    /// a playlist, track, source, or download that was asked for does not exist (what it was is logged)
+    #[error("query failed")]
+    NotFound,
+    /// the music directory could not be found, or its config, playlists, or sources could not be loaded
+    #[error("failed to load the music directory")]
+    ResolveError,
+    /// one or more downloads failed
+    #[error("some downloads failed")]
+    DownloadFailed,
+    /// the audio output could not be opened
+    #[error("failed to initialize audio backend")]
+    AudioBackend,
+    /// `dmm check` (or `dmm doctor`) found problems
+    #[error("{problems} problem(s) found, and {warnings} warning(s)")]
+    CheckFailed { problems: usize, warnings: usize },
+    /// copying to or from another machine failed
+    #[error("sync failed")]
+    SyncFailed,
+}
+
+impl DmmError {
+    /// Exit code for this kind of error (2 is left for invalid arguments, which clap exits with)
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Self::NotFound => 3,
+            Self::ResolveError => 4,
+            Self::DownloadFailed => 5,
+            Self::AudioBackend => 6,
+            Self::CheckFailed { .. } => 7,
+            Self::SyncFailed => 8,
+        }
+    }
+}
+
+/// Exit code to fail with for `report`: that of the [`DmmError`] in it, or 1 if there is none
+pub fn exit_code(report: &Report) -> ExitCode {
+    match report.downcast_ref::<DmmError>() {
+        Some(e) => ExitCode::from(e.exit_code()),
+        None => ExitCode::FAILURE,
+    }
+}
@@ -0,0 +1,124 @@
+//! Integration with git, for music directories that are git repositories
+//!
+//! When `git.auto_commit` is enabled, changes DMM makes to playlists and sources are committed
+//! automatically. These commits are marked with a `dmm: ` prefix, which is how `dmm undo` knows
+//! that it is safe to revert them.
+
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use color_eyre::eyre::{anyhow, bail, Result};
+
+use crate::resolver::Resolver;
+
+/// Prefix of the messages of commits made by DMM
+const COMMIT_PREFIX: &str = "dmm: ";
+
+fn git<S: AsRef<OsStr>>(root: &Path, args: &[S]) -> Result<Output> {
+    Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(args)
+        .output()
+        .map_err(|e| anyhow!("Failed to run git: {e}"))
+}
+
+fn git_ok<S: AsRef<OsStr>>(root: &Path, args: &[S]) -> Result<String> {
+    let out = git(root, args)?;
+    if !out.status.success() {
+        bail!(
+            "`git {}` failed: {}",
+            args.iter()
+                .map(|arg| arg.as_ref().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(" "),
+            String::from_utf8_lossy(&out.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).trim().to_string())
+}
+
+fn is_repo(root: &Path) -> bool {
+    git(root, &["rev-parse", "--is-inside-work-tree"]).is_ok_and(|out| out.status.success())
+}
+
+/// Makes `root` a git repository (`dmm init --git`), unless it already is one
+pub fn init(root: &Path) -> Result<()> {
+    if is_repo(root) {
+        info!("{root:?} is already a git repository");
+        return Ok(());
+    }
+    git_ok(root, &["init", "--quiet"])?;
+    info!("initialized a git repository, set `git: (auto_commit: true)` in dmm.ron to commit changes DMM makes");
+    Ok(())
+}
+
+/// Commits the changes DMM made to `paths`, if auto-commit is enabled.
+///
+/// The changes have already been made by the time this is called, so failing to commit
+/// them is only reported as a warning.
+pub fn commit_changes(res: &Resolver, paths: &[PathBuf], message: &str) {
+    let root = &res.dirs().root;
+    if !res.out().config.git.auto_commit || paths.is_empty() {
+        return;
+    }
+    if !is_repo(root) {
+        warn!("git.auto_commit is enabled, but the music directory is not a git repository [not committing]");
+        return;
+    }
+    // (paths are passed as they are, as they do not have to be valid UTF-8)
+    let message = format!("{COMMIT_PREFIX}{message}");
+    let mut add = vec![OsStr::new("add"), OsStr::new("--")];
+    add.extend(paths.iter().map(|p| p.as_os_str()));
+    let mut commit = ["commit", "-q", "-m", &message, "--"]
+        .map(OsStr::new)
+        .to_vec();
+    commit.extend(paths.iter().map(|p| p.as_os_str()));
+    let result = git_ok(root, &add).and_then(|_| git_ok(root, &commit));
+    match result {
+        Ok(_) => info!("committed changes: {message:?}"),
+        Err(e) => warn!("Failed to commit changes: {e}"),
+    }
+}
+
+/// Reverts the last change DMM committed (`dmm undo`).
+///
+/// Only works if the most recent commit was made by DMM, so that manual commits are never undone by accident.
+pub fn undo(res: &Resolver) -> Result<()> {
+    let root = &res.dirs().root;
+    if !is_repo(root) {
+        error!("The music directory is not a git repository, so there is nothing to undo");
+        bail!("undo failed");
+    }
+    let subject = git_ok(root, &["log", "-1", "--format=%s"])?;
+    let Some(change) = subject.strip_prefix(COMMIT_PREFIX) else {
+        error!("The last commit ({subject:?}) was not made by DMM, refusing to undo it");
+        bail!("undo failed");
+    };
+    git_ok(root, &["revert", "--no-edit", "HEAD"])?;
+    info!("Undid {change:?}");
+    Ok(())
+}
+
+/// Makes `dir` a shallow checkout of the latest commit of the repository at `url` (cloning it if needed)
+pub fn mirror(url: &str, dir: &Path) -> Result<()> {
+    let same_remote = is_repo(dir)
+        && git_ok(dir, &["remote", "get-url", "origin"]).is_ok_and(|origin| origin == url);
+    if same_remote {
+        git_ok(dir, &["fetch", "--quiet", "--depth", "1", "origin"])?;
+        git_ok(dir, &["reset", "--quiet", "--hard", "FETCH_HEAD"])?;
+        return Ok(());
+    }
+    if dir.exists() {
+        std::fs::remove_dir_all(dir)?;
+    }
+    let parent = dir
+        .parent()
+        .ok_or(anyhow!("{dir:?} has no parent directory"))?;
+    let args = ["clone", "--quiet", "--depth", "1", url].map(OsStr::new);
+    git_ok(parent, &[&args[..], &[dir.as_os_str()]].concat())?;
+    Ok(())
+}
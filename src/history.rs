@@ -0,0 +1,80 @@
+//! Handling of `dmm history`
+
+use color_eyre::eyre::Result;
+use crossterm::style::Stylize;
+use serde::Serialize;
+
+use crate::{
+    duration,
+    output::{self, Format},
+    plays,
+    resolver::Resolver,
+};
+
+#[derive(Debug, Serialize)]
+struct Entry {
+    /// when playback started (seconds since the unix epoch)
+    time: u64,
+    hash: String,
+    /// none if the track is no longer in any playlist
+    name: Option<String>,
+    artist: Option<String>,
+    playlist: Option<String>,
+    /// seconds
+    listened: Option<u64>,
+}
+
+/// Prints the `limit` most recent plays (most recent first), optionally only the ones played from `playlist`
+pub fn print(res: &Resolver, limit: usize, playlist: Option<String>, format: Format) -> Result<()> {
+    let entries = plays::read(res)?
+        .into_iter()
+        .rev()
+        .filter(|play| match (&playlist, &play.playlist) {
+            (None, _) => true,
+            (Some(filter), Some(name)) => filter.eq_ignore_ascii_case(name),
+            (Some(_), None) => false,
+        })
+        .take(limit)
+        .map(|play| {
+            let track = res.out().find_track(play.hash);
+            Entry {
+                time: play.time,
+                hash: play.hash.to_string(),
+                name: track.map(|(track, _)| track.meta.name.clone()),
+                artist: track.map(|(track, _)| track.meta.artist.clone()),
+                // plays recorded by older versions do not know which playlist it was
+                playlist: play
+                    .playlist
+                    .or_else(|| track.map(|(_, playlist)| playlist.name.clone())),
+                listened: play.listened,
+            }
+        })
+        .collect::<Vec<_>>();
+    match format {
+        Format::Json => output::print_json(&entries)?,
+        Format::Text => {
+            if entries.is_empty() {
+                println!("{}", "No tracks have been played yet".bold());
+            }
+            for entry in &entries {
+                let track = match (&entry.name, &entry.artist) {
+                    (Some(name), Some(artist)) => format!("{artist} - {name}"),
+                    _ => format!("unknown track {}", entry.hash).dim().to_string(),
+                };
+                let listened = match entry.listened {
+                    Some(listened) => duration::format(listened),
+                    None => "?".to_string(),
+                };
+                let playlist = match &entry.playlist {
+                    Some(playlist) => format!(" ({playlist})"),
+                    None => String::new(),
+                };
+                println!(
+                    "{} {listened:>6}  {track}{playlist}",
+                    plays::format_time(entry.time).dim()
+                );
+            }
+        }
+    }
+    Ok(())
+}
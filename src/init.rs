@@ -1,6 +1,10 @@
 //! Handling of `dmm init`
+//!
+//! Running it again in a music directory is safe: files that already exist are kept as they are,
+//! and only the missing ones are created.
 
 use std::{
+    env,
     fs::{self, OpenOptions},
     io::{self, BufRead, Write},
     path::Path,
@@ -8,33 +12,46 @@ use std::{
 
 use color_eyre::eyre::{bail, Result};
 
+use crate::git;
+
 const GITIGNORE: &str = include_str!("../assets/gitignore");
 const DMM_DOT_RON: &str = include_str!("../assets/dmm.minimal.ron");
 const YT_DLP: &str = include_str!("../examples/sources/yt-dlp.ron");
 const EX_PLAYLIST: &str = include_str!("../assets/example-playlist.ron");
 
+/// Writes `content` to `path`, unless it already exists
 fn write_file(path: impl AsRef<Path>, content: impl AsRef<[u8]>) -> Result<()> {
-    OpenOptions::new()
-        .write(true)
-        .create_new(true)
-        .open(path)?
-        .write_all(content.as_ref())?;
+    let path = path.as_ref();
+    match OpenOptions::new().write(true).create_new(true).open(path) {
+        Ok(mut file) => file.write_all(content.as_ref())?,
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            info!("keeping the existing {path:?}");
+        }
+        Err(e) => return Err(e.into()),
+    }
     Ok(())
 }
 
-pub fn dmm_init() -> Result<()> {
-    crate::log::initialize_logging(None)?;
+/// Sets up a music directory in the current directory.
+///
+/// With `bare`, the example playlist and source are left out. With `git`, the directory is also made a git repository
+pub fn dmm_init(bare: bool, git: bool) -> Result<()> {
+    let (examples, folders) = match bare {
+        true => ("", "\t├─ sources\n\t├─ playlists\n"),
+        false => (
+            "This includes creating an example playlist, and source to download from youtube.\n",
+            "\t├─ sources\n\t│  └─ yt-dlp.ron\n\t├─ playlists\n\t│  └─ example.ron\n",
+        ),
+    };
     info!(
         "Initializing a music directory. This will create the following folder structure\n\
-                This includes creating an example playlist, and source to download from youtube.\n\
+                {examples}\
+                Files that already exist are kept as they are.\n\
                 IT IS RECOMMENDED TO DO THIS IN AN EMPTY DIRECTORY\n\n\
                 \t. (you are here)\n\
                 \t├─ .gitignore\n\
                 \t├─ dmm.ron\n\
-                \t├─ sources\n\
-                \t│  └─ yt-dlp.ron\n\
-                \t├─ playlists\n\
-                \t│  └─ example.ron\n\
+                {folders}\
                 \t├─ cache\n\
                 \t│  └─ <content omitted>\n\
                 \t└─ run\n\
@@ -54,16 +71,23 @@ pub fn dmm_init() -> Result<()> {
     }
     write_file("./.gitignore", GITIGNORE)?;
     write_file("./dmm.ron", DMM_DOT_RON)?;
-    fs::create_dir("sources")?;
-    write_file("./sources/yt-dlp.ron", YT_DLP)?;
-    fs::create_dir("playlists")?;
-    write_file("./playlists/example.ron", EX_PLAYLIST)?;
-    fs::create_dir("cache")?;
-    fs::create_dir("run")?;
+    fs::create_dir_all("sources")?;
+    fs::create_dir_all("playlists")?;
+    if !bare {
+        write_file("./sources/yt-dlp.ron", YT_DLP)?;
+        write_file("./playlists/example.ron", EX_PLAYLIST)?;
+    }
+    fs::create_dir_all("cache")?;
+    fs::create_dir_all("run")?;
     write_file("./run/dmm.log", "DMM's Log File")?;
+    if git {
+        git::init(&env::current_dir()?)?;
+    }
 
     info!("Created the directory structure");
-    info!("Download the playlist with `dmm download pl 'example'`, and play it with `dmm player 'example'`");
+    if !bare {
+        info!("Download the playlist with `dmm download pl 'example'`, and play it with `dmm player 'example'`");
+    }
     info!("For more information, check out the git page at <https://git.fawkes.io/mtnash/dmm>");
     warn!("Enjoy!");
 
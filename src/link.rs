@@ -0,0 +1,103 @@
+//! Handling of `dmm link`, and reading of `.dmm-link.ron`
+//!
+//! A `.dmm-link.ron` file makes DMM use another music directory when it is run in the directory containing it
+//! (unless `--in` is given).
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::{anyhow, bail, Result};
+
+use crate::{
+    output::{self, Format},
+    schema::{self, Link},
+};
+
+/// Name of the link file
+const LINK_FILE: &str = ".dmm-link.ron";
+
+/// The music directory that the link in `dir` points to (if there is one)
+pub fn read(dir: &Path) -> Result<Option<PathBuf>> {
+    let path = dir.join(LINK_FILE);
+    match fs::read_to_string(&path) {
+        Ok(content) => {
+            let link =
+                ron::from_str::<Link>(&content).map_err(|e| anyhow!("{}: {e}", path.display()))?;
+            Ok(Some(link.music_directory))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Checks that `target` is a music directory, and returns its absolute path
+fn validate(target: &Path) -> Result<PathBuf> {
+    let target = target.canonicalize().map_err(|e| {
+        error!("Failed to find {target:?}: {e}");
+        e
+    })?;
+    if !target.join("dmm.ron").is_file() {
+        error!("{target:?} is not a music directory (it has no dmm.ron)");
+        info!("Run `dmm init` there to set it up");
+        bail!("invalid link target");
+    }
+    Ok(target)
+}
+
+/// Handling of `dmm link set`: links the current directory to the music directory at `target`.
+///
+/// If there is already a link to another directory, it is only replaced if `replace` is set
+pub fn set(target: &Path, replace: bool) -> Result<()> {
+    let target = validate(target)?;
+    match read(Path::new("."))? {
+        Some(existing) if existing == target => {
+            info!("{LINK_FILE} already points to {target:?}");
+            return Ok(());
+        }
+        Some(existing) if !replace => {
+            error!("{LINK_FILE} already exists, and points to {existing:?}");
+            info!("Use `dmm link set` to point it to {target:?} instead");
+            bail!("link failed");
+        }
+        Some(existing) => info!("{LINK_FILE} used to point to {existing:?}"),
+        None => {}
+    }
+    let content = schema::to_ron_pretty(&Link {
+        music_directory: target.clone(),
+    })?;
+    fs::write(LINK_FILE, content)?;
+    info!("DMM now uses the music directory at {target:?} when run in this directory");
+    Ok(())
+}
+
+/// Handling of `dmm link show`: prints where the link in the current directory points to
+pub fn show(format: Format) -> Result<()> {
+    let Some(target) = read(Path::new("."))? else {
+        info!("There is no {LINK_FILE} in this directory");
+        return Ok(());
+    };
+    if !target.join("dmm.ron").is_file() {
+        warn!("{target:?} is not a music directory (it has no dmm.ron), so the link is broken");
+    }
+    match format {
+        Format::Json => output::print_json(&Link {
+            music_directory: target,
+        })?,
+        Format::Text => println!("{}", target.display()),
+    }
+    Ok(())
+}
+
+/// Handling of `dmm link unset`: removes the link in the current directory
+pub fn unset() -> Result<()> {
+    match read(Path::new("."))? {
+        Some(target) => {
+            fs::remove_file(LINK_FILE)?;
+            info!("Removed the link to {target:?}");
+        }
+        None => info!("There is no {LINK_FILE} in this directory"),
+    }
+    Ok(())
+}
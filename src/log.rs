@@ -1,34 +1,262 @@
-use std::path::PathBuf;
+use std::{
+    ffi::OsString,
+    fmt,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use color_eyre::eyre::Result;
+use flume::Sender;
+use indicatif::MultiProgress;
+use tracing::{field::Visit, Event, Level, Subscriber};
 use tracing_error::ErrorLayer;
 use tracing_subscriber::{
-    fmt::writer::BoxMakeWriter, prelude::__tracing_subscriber_SubscriberExt,
-    util::SubscriberInitExt, Layer,
+    fmt::writer::BoxMakeWriter, layer::Context, prelude::__tracing_subscriber_SubscriberExt,
+    reload, util::SubscriberInitExt, EnvFilter, Layer, Registry,
+};
+
+use crate::{
+    cfg::LogConfig,
+    output::Format,
+    project_meta::LOG_ENV,
+    ui::action::{Action, Severity},
 };
 
-use crate::project_meta::LOG_ENV;
+/// Progress bars being shown in the terminal, which log lines are printed above (instead of breaking them up)
+static PROGRESS: Mutex<Option<MultiProgress>> = Mutex::new(None);
+/// Changes what is logged, once the config is loaded (None if the level was set with an environment variable, which takes priority)
+static FILTER: Mutex<Option<reload::Handle<EnvFilter, Registry>>> = Mutex::new(None);
+/// Where warnings and errors are shown as toasts, while the player is open
+static TOASTS: Mutex<Option<Sender<Action>>> = Mutex::new(None);
+
+/// Prints log lines above `progress` while it is shown (until this is called with None)
+pub fn set_progress(progress: Option<MultiProgress>) {
+    *PROGRESS.lock().unwrap() = progress;
+}
+
+/// Writes log lines to the terminal, above the progress bars (if any are shown)
+struct Terminal {
+    stderr: bool,
+}
+
+impl Write for Terminal {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let write = || {
+            if self.stderr {
+                io::stderr().write_all(buf)
+            } else {
+                io::stdout().write_all(buf)
+            }
+        };
+        let progress = PROGRESS.lock().unwrap().clone();
+        match progress {
+            Some(progress) => progress.suspend(write)?,
+            None => write()?,
+        }
+        Ok(buf.len())
+    }
 
-pub fn initialize_logging(path: Option<PathBuf>) -> Result<()> {
-    std::env::set_var(
-        "RUST_LOG",
-        std::env::var("RUST_LOG")
-            .or_else(|_| std::env::var(LOG_ENV.clone()))
-            .unwrap_or_else(|_| format!("{}=info", env!("CARGO_CRATE_NAME"))),
+    fn flush(&mut self) -> io::Result<()> {
+        if self.stderr {
+            io::stderr().flush()
+        } else {
+            io::stdout().flush()
+        }
+    }
+}
+
+/// Log file that starts over (keeping the previous ones) once it grows too large, or a day has passed
+struct LogFile {
+    path: PathBuf,
+    max_size: Option<u64>,
+    daily: bool,
+    keep: usize,
+    file: File,
+    /// bytes written to `file`
+    written: u64,
+    /// day (since the epoch, in UTC) `file` was started on
+    day: u64,
+}
+
+/// Days since the epoch (in UTC)
+fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs() / (24 * 60 * 60))
+}
+
+/// `path` with `.n` added to the end, for the nth previous log file
+fn numbered(path: &Path, n: usize) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(format!(".{n}"));
+    PathBuf::from(name)
+}
+
+impl LogFile {
+    /// Starts a new log file at `path`, keeping the one that is there as the most recent previous one
+    fn open(path: PathBuf, cfg: &LogConfig) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        Self::rotate(&path, cfg.keep)?;
+        Ok(Self {
+            file: File::create(&path)?,
+            path,
+            max_size: cfg.max_size,
+            daily: cfg.daily,
+            keep: cfg.keep,
+            written: 0,
+            day: today(),
+        })
+    }
+
+    /// Moves each previous log file one number up (removing the oldest), and the current one to `.1`
+    fn rotate(path: &Path, keep: usize) -> io::Result<()> {
+        if !path.exists() {
+            return Ok(());
+        }
+        if keep == 0 {
+            return fs::remove_file(path);
+        }
+        for n in (1..keep).rev() {
+            let older = numbered(path, n);
+            if older.exists() {
+                fs::rename(older, numbered(path, n + 1))?;
+            }
+        }
+        fs::rename(path, numbered(path, 1))
+    }
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        let full = self
+            .max_size
+            .is_some_and(|max| self.written > 0 && self.written + buf.len() as u64 > max);
+        if full || (self.daily && self.day != today()) {
+            // if starting over fails, this keeps writing to the file it has
+            if let Ok(next) =
+                Self::rotate(&self.path, self.keep).and_then(|()| File::create(&self.path))
+            {
+                self.file = next;
+                self.written = 0;
+                self.day = today();
+            }
+        }
+        self.file.write_all(buf)?;
+        self.written += buf.len() as u64;
+        Ok(())
+    }
+}
+
+/// [`LogFile`] shared by every thread that logs
+#[derive(Clone)]
+struct SharedLogFile(Arc<Mutex<LogFile>>);
+
+impl Write for SharedLogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.lock().unwrap().file.flush()
+    }
+}
+
+/// Shows warnings and errors from DMM as toasts in the player (see [`set_toasts`]).
+/// The player's own problems are not included, as it already shows those itself
+struct ToastLayer;
+
+/// Text of the message of a log event
+#[derive(Default)]
+struct Message(String);
+
+impl Visit for Message {
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{value:?}");
+        }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for ToastLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let meta = event.metadata();
+        let severity = match *meta.level() {
+            Level::ERROR => Severity::Error,
+            Level::WARN => Severity::Warning,
+            _ => return,
+        };
+        let target = meta.target();
+        let crate_name = env!("CARGO_CRATE_NAME");
+        if !target.starts_with(crate_name) || target.starts_with(&format!("{crate_name}::ui")) {
+            return;
+        }
+        if let Some(tx) = TOASTS.lock().unwrap().as_ref() {
+            let mut message = Message::default();
+            event.record(&mut message);
+            let _ = tx.send(Action::Toast(message.0, severity));
+        }
+    }
+}
+
+/// Shows warnings and errors that are logged as toasts, by sending them to `tx` (until this is called with None)
+pub fn set_toasts(tx: Option<Sender<Action>>) {
+    *TOASTS.lock().unwrap() = tx;
+}
+
+/// Logs what `level` says (see [`LogConfig::level`]), unless the level was set with an environment variable
+pub fn set_level(level: &str) {
+    let Some(handle) = FILTER.lock().unwrap().clone() else {
+        return;
+    };
+    match EnvFilter::try_new(level) {
+        Ok(filter) => {
+            if let Err(e) = handle.reload(filter) {
+                warn!("Failed to change the log level: {e}");
+            }
+        }
+        Err(e) => warn!("Invalid log level {level:?} in the config: {e}"),
+    }
+}
+
+/// Logs to `file` (along with how the config says to, see [`LogConfig`]), or to the terminal if it is not given.
+/// When printing JSON, the terminal log goes to stderr, so that only the JSON is written to stdout
+pub fn initialize_logging(file: Option<(PathBuf, &LogConfig)>, format: Format) -> Result<()> {
+    let env_level = std::env::var("RUST_LOG").or_else(|_| std::env::var(LOG_ENV.clone()));
+    let level = match (&env_level, &file) {
+        (Ok(level), _) => level.clone(),
+        (Err(_), Some((_, cfg))) => cfg.level.clone(),
+        (Err(_), None) => LogConfig::default().level,
+    };
+    let (filter, handle) = reload::Layer::new(
+        EnvFilter::try_new(&level).unwrap_or_else(|_| EnvFilter::new(LogConfig::default().level)),
     );
+    if env_level.is_err() {
+        *FILTER.lock().unwrap() = Some(handle);
+    }
+    let to_file = file.is_some();
+    let writer = match file {
+        Some((path, cfg)) => {
+            let log_file = SharedLogFile(Arc::new(Mutex::new(LogFile::open(path, cfg)?)));
+            BoxMakeWriter::new(move || log_file.clone())
+        }
+        None => BoxMakeWriter::new(move || Terminal {
+            stderr: format == Format::Json,
+        }),
+    };
     let file_subscriber = tracing_subscriber::fmt::layer()
         .with_file(true)
         .with_line_number(true)
-        .with_writer(
-            path.as_ref()
-                .map(|x| Ok::<_, std::io::Error>(BoxMakeWriter::new(std::fs::File::create(x)?)))
-                .unwrap_or(Ok(BoxMakeWriter::new(std::io::stdout)))?,
-        )
-        .with_target(path.is_some())
-        .with_ansi(path.is_none())
-        .with_filter(tracing_subscriber::filter::EnvFilter::from_default_env());
+        .with_writer(writer)
+        .with_target(to_file)
+        .with_ansi(!to_file)
+        .with_filter(filter);
     tracing_subscriber::registry()
         .with(file_subscriber)
+        .with(ToastLayer)
         .with(ErrorLayer::default())
         .init();
     Ok(())
@@ -0,0 +1,131 @@
+//! Lyrics of tracks
+//!
+//! Lyrics are given with `lyrics` on a track in its playlist, or stored as a `<hash>.lrc` sidecar file in the store
+//! (fetched by `dmm download`, if `lyrics.cmd` is set in `dmm.ron`). Either can be plain text, or LRC
+//! (each line starting with the time it is sung at, as `[mm:ss.xx]`), which the player scrolls along with the track.
+
+use std::{
+    fs,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use color_eyre::eyre::{anyhow, bail, Result};
+
+use crate::{
+    cfg::LyricsConfig,
+    schema::{Meta, Track},
+};
+
+/// Extension of the sidecar files in the store
+pub const SIDECAR_EXT: &str = "lrc";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lyrics {
+    /// lines of the lyrics, with the time (in milliseconds) they start at.
+    /// either every line has a time (and they are sorted by it), or none do
+    pub lines: Vec<(Option<u64>, String)>,
+}
+
+/// Parses an LRC time (`mm:ss`, `mm:ss.xx`, or `mm:ss:xx`) into milliseconds
+fn parse_time(time: &str) -> Option<u64> {
+    let (minutes, rest) = time.split_once(':')?;
+    let rest = rest.replacen(':', ".", 1);
+    let seconds = rest.parse::<f64>().ok().filter(|s| *s >= 0.0)?;
+    Some(minutes.trim().parse::<u64>().ok()? * 60_000 + (seconds * 1000.0).round() as u64)
+}
+
+impl Lyrics {
+    pub fn parse(text: &str) -> Self {
+        let mut timed = vec![];
+        // `[offset:+/-ms]` moves every line earlier (positive) or later (negative)
+        let mut offset = 0i64;
+        for line in text.lines() {
+            let mut rest = line.trim();
+            let mut times = vec![];
+            while let Some((tag, after)) = rest.strip_prefix('[').and_then(|r| r.split_once(']')) {
+                match parse_time(tag) {
+                    Some(time) => times.push(time),
+                    None => {
+                        if let Some(ms) = tag.strip_prefix("offset:") {
+                            offset = ms.trim().parse().unwrap_or(0);
+                        }
+                    }
+                }
+                rest = after;
+            }
+            for time in times {
+                timed.push((time, rest.trim().to_string()));
+            }
+        }
+        if timed.is_empty() {
+            let lines = text
+                .trim()
+                .lines()
+                .map(|line| (None, line.trim_end().to_string()));
+            return Self {
+                lines: lines.collect(),
+            };
+        }
+        timed.sort_by_key(|(time, _)| *time);
+        Self {
+            lines: timed
+                .into_iter()
+                .map(|(time, line)| (Some(time.saturating_add_signed(-offset)), line))
+                .collect(),
+        }
+    }
+
+    /// If the lines have times, so they can follow the track
+    pub fn synced(&self) -> bool {
+        self.lines.first().is_some_and(|(time, _)| time.is_some())
+    }
+
+    /// Index of the line being sung `ms` milliseconds into the track (None before the first line, or if not synced)
+    pub fn current(&self, ms: u64) -> Option<usize> {
+        self.lines
+            .iter()
+            .take_while(|(time, _)| time.is_some_and(|time| time <= ms))
+            .count()
+            .checked_sub(1)
+    }
+}
+
+/// Reads the lyrics of `track`: its `lyrics` if it has them, otherwise the sidecar file `sidecar` (if it exists)
+pub fn load(track: &Track, sidecar: &Path) -> Option<Lyrics> {
+    let text = match &track.lyrics {
+        Some(lyrics) => lyrics.clone(),
+        None => fs::read_to_string(sidecar).ok()?,
+    };
+    let lyrics = Lyrics::parse(&text);
+    (!lyrics.lines.is_empty()).then_some(lyrics)
+}
+
+/// Runs the lyrics command for the track with `meta`, writing what it prints to `sidecar`.
+///
+/// If it prints nothing (there are no lyrics for the track), the sidecar is still written (empty),
+/// so the command is not run for the track again
+pub fn fetch(config: &LyricsConfig, meta: &Meta, sidecar: &Path) -> Result<()> {
+    let Some(cmd) = &config.cmd else {
+        bail!("no lyrics command is set");
+    };
+    let args = config.args.iter().map(|arg| {
+        arg.replace("${artist}", &meta.artist)
+            .replace("${name}", &meta.name)
+            .replace("${album}", meta.album.as_deref().unwrap_or_default())
+    });
+    let out = Command::new(cmd)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| anyhow!("failed to run {cmd}: {e}"))?;
+    if !out.status.success() {
+        bail!(
+            "{cmd} failed ({}): {}",
+            out.status,
+            String::from_utf8_lossy(&out.stderr).trim_end()
+        );
+    }
+    fs::write(sidecar, out.stdout)?;
+    Ok(())
+}
@@ -2,33 +2,66 @@
 extern crate tracing;
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::HashSet,
     env, fs,
-    io::{self, BufRead},
+    io::{self, BufRead, IsTerminal},
     path::PathBuf,
+    process::ExitCode,
 };
 
 use clap::{Parser, Subcommand};
-use color_eyre::eyre::{anyhow, bail, Result};
+use color_eyre::eyre::{bail, eyre, Result, WrapErr};
+use error::DmmError;
 use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
 use resolver::Resolver;
+use serde::Serialize;
 
+mod bench;
 mod cache;
 mod cfg;
+mod cover;
+mod doctor;
+mod download;
+mod duration;
+mod edit;
+mod enrich;
+mod error;
+mod git;
+mod history;
 mod init;
+mod link;
 mod log;
+mod lyrics;
+mod output;
 mod panic;
+mod pick;
 mod player2;
+mod playlist;
+mod plays;
 mod project_meta;
+mod resample;
 mod resolver;
 mod schema;
+mod scrobble;
+mod source;
+mod stats;
+mod status;
+mod store;
+mod sync;
 mod ui;
+mod watch;
+mod waveform;
+mod ytdlp;
 
 #[derive(Parser, Debug)]
 #[command(author, about, long_about = None)]
 struct Args {
     #[command(subcommand)]
     cmd: Command,
+    /// output format of commands that print results (`store gc`, `store extract`, and `playlist` commands).
+    /// with `json`, log messages are written to stderr
+    #[arg(long, global = true, value_enum, default_value_t)]
+    output: output::Format,
 }
 
 #[derive(Subcommand, Debug)]
@@ -37,17 +70,52 @@ enum Download {
     /// download the given playlist
     #[command(alias = "pl")]
     Playlist {
-        /// playlist to download
-        playlist: String,
+        /// playlist to download (picked from a list if not given)
+        playlist: Option<String>,
+        /// download the playlist the search finds without asking if it is correct
+        #[arg(long, short)]
+        yes: bool,
+        /// only download a playlist with exactly this name (without asking if it is correct)
+        #[arg(long)]
+        exact: bool,
+        /// only download tracks with this tag (can be given more than once, to download tracks with any of them)
+        #[arg(long = "tag")]
+        tags: Vec<String>,
     },
     /// download all playlists
-    All,
+    All {
+        /// only download tracks with this tag (can be given more than once, to download tracks with any of them)
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+    },
+    /// re-attempt the downloads that failed last time
+    Retry,
+    /// download a single track of a playlist
+    Track {
+        /// playlist the track is in (searched for, unless it matches a playlist name exactly)
+        playlist: String,
+        /// name of the track (searched for, unless it matches a track name exactly)
+        track: String,
+        /// download the track again even if it is already downloaded (for example, if the download was truncated).
+        /// the previous download is kept if this fails
+        #[arg(long)]
+        force: bool,
+    },
+    /// keep running, and download new tracks whenever the playlists, sources, or config change
+    Watch {
+        /// only download tracks with this tag (can be given more than once, to download tracks with any of them)
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+    },
 }
 
 #[derive(Subcommand, Debug)]
 enum Command {
     /// Download playlists
     Download {
+        /// number of tracks to download at once
+        #[arg(long, short, default_value_t = 4)]
+        jobs: usize,
         /// directory to "run in"
         #[arg(long = "in")]
         run_in: Option<PathBuf>,
@@ -56,17 +124,219 @@ enum Command {
     },
     /// Play the given playlist
     Player {
+        /// playlist to start playing (picked from a list if not given)
+        playlist: Option<String>,
         /// directory to "run in"
         #[arg(long = "in")]
         run_in: Option<PathBuf>,
+        /// start playing every track (of every playlist)
+        #[arg(long, conflicts_with_all = ["playlist", "pick"])]
+        all: bool,
+        /// pick the playlist to start playing from a list
+        #[arg(long, conflicts_with = "playlist")]
+        pick: bool,
+        /// start the player without picking a playlist
+        #[arg(long, conflicts_with_all = ["playlist", "pick"])]
+        no_pick: bool,
     },
     /// Print version information
     Version,
+    /// Show what the running player is playing
+    Status {
+        /// print the status as JSON on one line (for status bars, the same as `--output json`)
+        #[arg(long)]
+        json: bool,
+        /// directory to "run in"
+        #[arg(long = "in")]
+        run_in: Option<PathBuf>,
+    },
+    /// Show the most recently played tracks (and how long they were listened to)
+    History {
+        /// number of plays to list
+        #[arg(long, short = 'n', default_value_t = 20)]
+        limit: usize,
+        /// only list plays from this playlist
+        #[arg(long)]
+        playlist: Option<String>,
+        /// directory to "run in"
+        #[arg(long = "in")]
+        run_in: Option<PathBuf>,
+    },
+    /// Show statistics: track counts, playtime, disk usage and download coverage of every playlist,
+    /// and the most played tracks
+    Stats {
+        /// number of most played tracks to list
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+        /// directory to "run in"
+        #[arg(long = "in")]
+        run_in: Option<PathBuf>,
+    },
     /// Management of DMM's download store
     #[command(subcommand)]
     Store(Store),
+    /// Inspect and compare playlists
+    #[command(subcommand, alias = "pl")]
+    Playlist(Playlist),
+    /// Management of sources
+    #[command(subcommand, alias = "src")]
+    Source(Source),
+    /// Decode downloaded tracks as fast as possible, to check them for errors and measure performance
+    ///
+    /// decodes every track in the store, unless a playlist is given
+    Bench {
+        /// only decode the tracks of this playlist
+        #[arg(long, short)]
+        playlist: Option<String>,
+        /// directory to "run in"
+        #[arg(long = "in")]
+        run_in: Option<PathBuf>,
+    },
+    /// Make DMM use another music directory when run in the current directory (with a `.dmm-link.ron`)
+    #[command(subcommand)]
+    Link(Link),
+    /// Synchronize with another music directory
+    ///
+    /// the other directory can be a local path (such as a mounted drive), or `[user@]host:path` (using rsync over ssh)
+    #[command(subcommand)]
+    Sync(Sync),
+    /// Revert the last change DMM made to playlists or sources
+    ///
+    /// requires the music directory to be a git repository, and `git.auto_commit` to be enabled
+    Undo {
+        /// directory to "run in"
+        #[arg(long = "in")]
+        run_in: Option<PathBuf>,
+    },
     /// Set up the music directory folder structure in the current directory
-    Init,
+    ///
+    /// files that already exist are kept, so this is safe to run again (e.g. to add missing folders)
+    Init {
+        /// do not create the example playlist and source
+        #[arg(long)]
+        bare: bool,
+        /// also make the music directory a git repository (see `git.auto_commit` in dmm.ron)
+        #[arg(long)]
+        git: bool,
+        /// instead of creating a music directory, write a `.dmm-link.ron` here that points to the one at this path
+        #[arg(long, conflicts_with_all = ["bare", "git"])]
+        link: Option<PathBuf>,
+    },
+    /// Check the config, sources, and playlists for errors (reporting all of them at once)
+    Check {
+        /// directory to "run in"
+        #[arg(long = "in")]
+        run_in: Option<PathBuf>,
+    },
+    /// Check that the music directory, sources, and audio output are set up correctly
+    Doctor {
+        /// directory to "run in"
+        #[arg(long = "in")]
+        run_in: Option<PathBuf>,
+    },
+    /// Scrobbling to Last.fm and ListenBrainz
+    #[command(subcommand)]
+    Scrobble(Scrobble),
+    /// Control another DMM player (over its remote control)
+    #[command(subcommand)]
+    Ctl(Ctl),
+}
+
+/// Controlling another DMM player, which needs `remote: (enabled: true)` in its dmm.ron
+#[derive(Subcommand, Debug)]
+enum Ctl {
+    /// Open the player here, but play the music on another player (such as the one wired to the speakers)
+    ///
+    /// the other player is told to play whatever plays here (which is muted), and is sent the tracks it does not
+    /// have yet. `host` is its address (`host[:port]`, port 8420 if not given)
+    Cast {
+        /// address of the other player's remote control
+        host: String,
+        /// the other player's `remote.token`, if it has one
+        #[arg(long)]
+        token: Option<String>,
+        /// playlist to start playing
+        playlist: Option<String>,
+        /// directory to "run in"
+        #[arg(long = "in")]
+        run_in: Option<PathBuf>,
+    },
+}
+
+/// Linking a directory to a music directory (with a `.dmm-link.ron`)
+#[derive(Subcommand, Debug)]
+enum Link {
+    /// Point the current directory to the music directory at `path`
+    ///
+    /// replaces the link, if there already is one
+    Set {
+        /// music directory to use (it must contain a dmm.ron)
+        path: PathBuf,
+    },
+    /// Print the music directory that the current directory points to
+    Show,
+    /// Remove the link from the current directory
+    Unset,
+}
+
+/// Scrobbling to Last.fm and ListenBrainz
+#[derive(Subcommand, Debug)]
+enum Scrobble {
+    /// Allow DMM to scrobble to your Last.fm account
+    ///
+    /// requires `scrobble.lastfm.api_key` and `api_secret` to be set. prints the session key to add to dmm.ron
+    LastfmLogin {
+        /// directory to "run in"
+        #[arg(long = "in")]
+        run_in: Option<PathBuf>,
+    },
+}
+
+/// Synchronization with another music directory, or a shared index
+#[derive(Subcommand, Debug)]
+enum Sync {
+    /// Send playlists, sources, and downloads to another music directory
+    Push {
+        /// music directory to send to
+        target: String,
+        /// only send the downloads of these playlists (can be given multiple times)
+        #[arg(long = "playlist", short)]
+        playlists: Vec<String>,
+        /// directory to "run in"
+        #[arg(long = "in")]
+        run_in: Option<PathBuf>,
+    },
+    /// Fetch playlists and sources (but not downloads) from a shared index
+    ///
+    /// the index is a git repository, or a directory served over HTTP(S) with an `index.ron` listing its files.
+    /// files that were changed both here and in the index since the last fetch are conflicts, and are not changed
+    Fetch {
+        /// index to fetch from (defaults to `sync.index` in dmm.ron)
+        #[arg(long)]
+        index: Option<String>,
+        /// use the version in the index for files that conflict
+        #[arg(long)]
+        force: bool,
+        /// directory to "run in"
+        #[arg(long = "in")]
+        run_in: Option<PathBuf>,
+    },
+    /// Fetch playlists, sources, and downloads from another music directory
+    ///
+    /// files that were changed both here and there since the last pull are conflicts, and are not changed
+    Pull {
+        /// music directory to fetch from
+        target: String,
+        /// only fetch the downloads of these playlists (can be given multiple times)
+        #[arg(long = "playlist", short)]
+        playlists: Vec<String>,
+        /// use the other version of files that were changed both here and there since the last pull
+        #[arg(long)]
+        force: bool,
+        /// directory to "run in"
+        #[arg(long = "in")]
+        run_in: Option<PathBuf>,
+    },
 }
 
 /// Management of DMM's download store
@@ -83,90 +353,713 @@ enum Store {
         /// find, but do not remove, unreferenced files
         #[arg(long)]
         dry_run: bool,
+        /// remove the files without asking first (it is only asked when run in a terminal)
+        #[arg(long, short)]
+        yes: bool,
+        /// move the files to `run/trash` instead of deleting them, so `dmm store restore` can bring them back
+        #[arg(long)]
+        trash: bool,
+        /// remove files even if some playlists or sources failed to load (their tracks look unused)
+        #[arg(long)]
+        force: bool,
+    },
+    /// Remove the least recently played downloads, until the store fits in `cache.max_size`
+    ///
+    /// downloads that no playlist uses are removed first. this is also done after `dmm download`
+    Prune {
+        /// find, but do not remove, the files that would be removed
+        #[arg(long)]
+        dry_run: bool,
+        /// prune even if some playlists or sources failed to load (their tracks look unused, so they go first)
+        #[arg(long)]
+        force: bool,
+        /// directory to "run in"
+        #[arg(long = "in")]
+        run_in: Option<PathBuf>,
+    },
+    /// Restore files that `dmm store gc --trash` moved to the trash
+    ///
+    /// each run of gc moves files to its own directory in `run/trash`. by default, the most recent one is restored
+    Restore {
+        /// name of the directory in the trash to restore (see `--list`)
+        batch: Option<String>,
+        /// list the directories in the trash, instead of restoring anything
+        #[arg(long)]
+        list: bool,
+        /// directory to "run in"
+        #[arg(long = "in")]
+        run_in: Option<PathBuf>,
+    },
+    /// Move downloads to the hash of their source's `identity`
+    ///
+    /// downloads of sources without an identity are stored by a hash of the source's command or yt-dlp options.
+    /// after setting `identity` on a source (and before changing anything else about it), this moves its
+    /// downloads to the new hash, so they are not downloaded again
+    Migrate {
+        /// directory to "run in"
+        #[arg(long = "in")]
+        run_in: Option<PathBuf>,
+        /// find, but do not move, downloads stored under the old hash
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Check that every downloaded file is intact
+    ///
+    /// reports files that are empty, fail to decode, or have changed since they were downloaded.
+    /// files downloaded before checksums were recorded get one the first time they are checked
+    Verify {
+        /// delete broken files, and download them again
+        #[arg(long)]
+        fix: bool,
+        /// number of tracks to download at once (with `--fix`)
+        #[arg(long, short, default_value_t = 4)]
+        jobs: usize,
+        /// directory to "run in"
+        #[arg(long = "in")]
+        run_in: Option<PathBuf>,
+    },
+    /// Store identical files only once
+    ///
+    /// the same file downloaded from different sources (or inputs) is stored under each of their hashes.
+    /// this replaces the copies with hardlinks to a single file, and reports the space saved.
+    /// new downloads are linked like this automatically
+    Dedup {
+        /// directory to "run in"
+        #[arg(long = "in")]
+        run_in: Option<PathBuf>,
+    },
+    /// Package a playlist, its albums and sources, and its downloads into a single archive
+    ///
+    /// use `dmm store import` to unpack it into another music directory, e.g. on a machine without internet access
+    Export {
+        /// playlist to export
+        playlist: String,
+        /// path of the archive to write
+        #[arg(long)]
+        to: PathBuf,
+        /// directory to "run in"
+        #[arg(long = "in")]
+        run_in: Option<PathBuf>,
+    },
+    /// Unpack an archive made by `dmm store export` into this music directory
+    ///
+    /// downloads already in the store are skipped. playlists and sources that are different here are left alone
+    Import {
+        /// archive to unpack
+        bundle: PathBuf,
+        /// use the bundle's version of playlists and sources that are different here
+        #[arg(long)]
+        force: bool,
+        /// directory to "run in"
+        #[arg(long = "in")]
+        run_in: Option<PathBuf>,
     },
     /// Extract a downloaded file from the store - use this if a download link/primary source disapears
     ///
-    /// This is playlist-independant - only the source and input must be the same
+    /// This is playlist-independant - only the source and input must be the same.
+    /// Alternatively, use `--playlist` to extract every downloaded track in a playlist
     Extract {
         /// name of the source that this was downloaded from originally
-        source: String,
-        /// input to that source [string only]
-        input: String,
+        #[arg(required_unless_present = "playlist")]
+        source: Option<String>,
+        /// input to that source (a map input is written in RON, such as `{"id": "123", "quality": "high"}`)
+        #[arg(required_unless_present = "playlist")]
+        input: Option<String>,
+        /// extract all tracks of this playlist, instead of a single source + input
+        #[arg(long, short, conflicts_with_all = ["source", "input"])]
+        playlist: Option<String>,
         /// path to copy the file to (if found)
-        /// the extension of this file will be automatically set
+        /// the extension of this file will be automatically set.
+        /// with `--playlist`, this is the directory to copy the files into
         #[arg(long, short)]
         copy_to: Option<PathBuf>,
+        /// template for the names of files extracted with `--playlist`.
+        /// available placeholders are {artist}, {title}, {ext}, and {tracknum}
+        #[arg(long, short, requires = "playlist", default_value = store::DEFAULT_TEMPLATE)]
+        template: String,
+        /// directory to "run in"
+        #[arg(long = "in")]
+        run_in: Option<PathBuf>,
+    },
+}
+
+/// Inspect and compare playlists
+#[derive(Subcommand, Debug)]
+enum Playlist {
+    /// List every playlist, with its number of tracks, and how many of them are downloaded
+    #[command(alias = "ls")]
+    List {
+        /// directory to "run in"
+        #[arg(long = "in")]
+        run_in: Option<PathBuf>,
+    },
+    /// List the tracks of a playlist, and whether each of them is downloaded
+    Show {
+        /// playlist to show
+        playlist: String,
+        /// directory to "run in"
+        #[arg(long = "in")]
+        run_in: Option<PathBuf>,
+    },
+    /// Compare two playlists, or a playlist against the download store
+    ///
+    /// reports tracks unique to each side, shared tracks, and tracks
+    /// whose metadata differs between the two playlists
+    Diff {
+        /// playlist to compare
+        left: String,
+        /// playlist to compare against (if not given, compares against the store)
+        right: Option<String>,
+        /// output format (overrides the global `--output`)
+        #[arg(long, value_enum)]
+        format: Option<output::Format>,
+        /// directory to "run in"
+        #[arg(long = "in")]
+        run_in: Option<PathBuf>,
+    },
+    /// Fill in the artist, album, and name of tracks that are missing them, from the tags of their downloads
+    ///
+    /// only fields that are empty (or, for the album, not given) are changed, and only in the changed tracks,
+    /// so comments in the playlist file are kept
+    Enrich {
+        /// playlist to fill in
+        playlist: String,
+        /// show what would be filled in, without changing the playlist
+        #[arg(long)]
+        dry_run: bool,
+        /// directory to "run in"
+        #[arg(long = "in")]
+        run_in: Option<PathBuf>,
+    },
+}
+
+/// Management of sources
+#[derive(Subcommand, Debug)]
+enum Source {
+    /// List every source (global ones, and ones declared in playlists), with its kind, format,
+    /// and how many tracks use it
+    #[command(alias = "ls")]
+    List {
+        /// directory to "run in"
+        #[arg(long = "in")]
+        run_in: Option<PathBuf>,
+    },
+    /// Show a source, and which playlists use it
+    Show {
+        /// name of the source
+        name: String,
+        /// directory to "run in"
+        #[arg(long = "in")]
+        run_in: Option<PathBuf>,
+    },
+    /// Rename a source, updating every playlist that references it
+    ///
+    /// this rewrites the affected source and playlist files (comments in them are not preserved).
+    /// downloads are not affected, as the name of a source is not part of its hash
+    Rename {
+        /// current name of the source
+        old: String,
+        /// new name for the source
+        new: String,
         /// directory to "run in"
         #[arg(long = "in")]
         run_in: Option<PathBuf>,
     },
 }
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            error::exit_code(&e)
+        }
+    }
+}
+
+fn run() -> Result<()> {
     panic::initialize_panic_handler()?;
     let args = Args::parse();
+    let output = args.output;
     match args.cmd {
         Command::Download {
+            jobs,
+            run_in,
+            cmd:
+                Download::Playlist {
+                    playlist,
+                    yes,
+                    exact,
+                    tags,
+                },
+        } => {
+            log::initialize_logging(None, output)?;
+            let confirm = if exact {
+                Confirm::Exact
+            } else if yes {
+                Confirm::Yes
+            } else {
+                Confirm::Ask
+            };
+            match playlist {
+                Some(playlist) => download(run_in, Some((playlist, confirm)), &tags, jobs)?,
+                None => download_picked(run_in, &tags, jobs)?,
+            }
+        }
+        Command::Download {
+            jobs,
+            run_in,
+            cmd: Download::All { tags },
+        } => {
+            log::initialize_logging(None, output)?;
+            download(run_in, None, &tags, jobs)?;
+        }
+        Command::Download {
+            jobs,
+            run_in,
+            cmd: Download::Retry,
+        } => {
+            let mut res = Resolver::new(resolve_run_path(run_in)?);
+            res.create_dirs()?;
+            log::initialize_logging(None, output)?;
+            resolve(&mut res)?;
+            download::retry(&res, jobs)?;
+        }
+        Command::Download {
+            jobs,
             run_in,
-            cmd: Download::Playlist { playlist },
+            cmd:
+                Download::Track {
+                    playlist,
+                    track,
+                    force,
+                },
         } => {
-            log::initialize_logging(None)?;
-            download(run_in, Some(playlist))?;
+            let mut res = Resolver::new(resolve_run_path(run_in)?);
+            res.create_dirs()?;
+            log::initialize_logging(None, output)?;
+            resolve(&mut res)?;
+            download_track(&res, &playlist, &track, force, jobs)?;
         }
         Command::Download {
+            jobs,
             run_in,
-            cmd: Download::All,
+            cmd: Download::Watch { tags },
         } => {
-            log::initialize_logging(None)?;
-            download(run_in, None)?;
+            let mut res = Resolver::new(resolve_run_path(run_in)?);
+            res.create_dirs()?;
+            log::initialize_logging(None, output)?;
+            download_watch(&mut res, &tags, jobs)?;
         }
-        Command::Player { run_in } => {
+        Command::Player {
+            playlist,
+            run_in,
+            all,
+            pick,
+            no_pick,
+        } => {
             let mut res = Resolver::new(resolve_run_path(run_in)?);
             res.create_dirs()?;
-            log::initialize_logging(Some(res.tmp_file("dmm.log")))?;
-            res.resolve()?;
-            let mut app = ui::app::App::new(res, 15.0)?;
+            player_logging(&mut res, output)?;
+            resolve(&mut res)?;
+            let play = if all {
+                Some(ui::app::Play::All)
+            } else if let Some(name) = playlist {
+                let found = find_playlist(&res, &name)?;
+                let playlists = &res.out().playlists;
+                let index = playlists.iter().position(|pl| std::ptr::eq(pl, found));
+                Some(ui::app::Play::Playlist(index.unwrap()))
+            } else if pick || !no_pick {
+                match pick::playlist(&res, "Play", false)? {
+                    Some(index) => Some(ui::app::Play::Playlist(index)),
+                    None => return Ok(()),
+                }
+            } else {
+                None
+            };
+            let mut app = ui::app::App::new(res, 15.0, play, None)?;
+            app.run()?;
+        }
+        Command::Ctl(Ctl::Cast {
+            host,
+            token,
+            playlist,
+            run_in,
+        }) => {
+            let mut res = Resolver::new(resolve_run_path(run_in)?);
+            res.create_dirs()?;
+            player_logging(&mut res, output)?;
+            resolve(&mut res)?;
+            let target = ui::cast::Target::connect(&host, token)?;
+            let play = match playlist {
+                Some(name) => {
+                    let found = find_playlist(&res, &name)?;
+                    let playlists = &res.out().playlists;
+                    let index = playlists.iter().position(|pl| std::ptr::eq(pl, found));
+                    Some(ui::app::Play::Playlist(index.unwrap()))
+                }
+                None => None,
+            };
+            let mut app = ui::app::App::new(res, 15.0, play, Some(target))?;
             app.run()?;
         }
         Command::Version => {
             println!("{}", project_meta::version());
         }
-        Command::Store(Store::GC { run_in, dry_run }) => {
-            log::initialize_logging(None)?;
-            gc(run_in, dry_run)?;
+        Command::Store(Store::GC {
+            run_in,
+            dry_run,
+            yes,
+            trash,
+            force,
+        }) => {
+            log::initialize_logging(None, output)?;
+            gc(run_in, dry_run, yes, trash, force, output)?;
+        }
+        Command::Store(Store::Prune {
+            dry_run,
+            force,
+            run_in,
+        }) => {
+            let mut res = Resolver::new(resolve_run_path(run_in)?);
+            res.create_dirs()?;
+            log::initialize_logging(None, output)?;
+            resolve(&mut res)?;
+            store::prune(&res, dry_run, force)?;
+        }
+        Command::Store(Store::Restore {
+            batch,
+            list,
+            run_in,
+        }) => {
+            let mut res = Resolver::new(resolve_run_path(run_in)?);
+            res.create_dirs()?;
+            log::initialize_logging(None, output)?;
+            resolve(&mut res)?;
+            store::restore(&res, batch.as_deref(), list)?;
+        }
+        Command::Store(Store::Verify { fix, jobs, run_in }) => {
+            let mut res = Resolver::new(resolve_run_path(run_in)?);
+            res.create_dirs()?;
+            log::initialize_logging(None, output)?;
+            resolve(&mut res)?;
+            store::verify(&res, fix, jobs)?;
+        }
+        Command::Store(Store::Migrate { run_in, dry_run }) => {
+            let mut res = Resolver::new(resolve_run_path(run_in)?);
+            res.create_dirs()?;
+            log::initialize_logging(None, output)?;
+            resolve(&mut res)?;
+            let report = store::migrate(&res, dry_run)?;
+            if output == output::Format::Json {
+                output::print_json(&report)?;
+            }
+        }
+        Command::Store(Store::Dedup { run_in }) => {
+            let mut res = Resolver::new(resolve_run_path(run_in)?);
+            res.create_dirs()?;
+            log::initialize_logging(None, output)?;
+            resolve(&mut res)?;
+            store::dedup(&res)?;
+        }
+        Command::Store(Store::Extract {
+            playlist: Some(playlist),
+            copy_to,
+            template,
+            run_in,
+            ..
+        }) => {
+            let mut res = Resolver::new(resolve_run_path(run_in)?);
+            res.create_dirs()?;
+            log::initialize_logging(None, output)?;
+            resolve(&mut res)?;
+            let playlist = find_playlist(&res, &playlist)?;
+            let report = store::extract_playlist(&res, playlist, copy_to.as_deref(), &template)?;
+            if output == output::Format::Json {
+                output::print_json(&report)?;
+            }
         }
         Command::Store(Store::Extract {
-            source,
-            input,
+            source: Some(source),
+            input: Some(input),
+            playlist: None,
             copy_to,
+            template: _,
             run_in,
         }) => {
             let mut res = Resolver::new(resolve_run_path(run_in)?);
             res.create_dirs()?;
-            log::initialize_logging(None)?;
-            res.resolve()?;
+            log::initialize_logging(None, output)?;
+            resolve(&mut res)?;
             let Some(source) = res.out().sources.iter().find(|s| s.name == source) else {
                 error!("Could not find the source named {source:?}");
-                bail!("query failed");
+                bail!(DmmError::NotFound);
             };
-            let hash = cache::Hash::generate(source, &ron::Value::String(input));
+            let input = match ron::from_str(&input) {
+                Ok(map @ ron::Value::Map(_)) => map,
+                _ => ron::Value::String(input),
+            };
+            let hash = cache::Hash::generate(source, &input);
             let Some(found) = res.out().cache.find(hash) else {
                 info!("Calculated hash is {}", hash.to_string());
                 error!("Could not find the requested download in the store");
-                bail!("query failed");
+                bail!(DmmError::NotFound);
+            };
+            info!(
+                "File path is {found:?} (file format: '{}')",
+                source.stored_format()
+            );
+            let copied_to = match copy_to {
+                Some(path) => {
+                    let path = path.with_extension(source.stored_format());
+                    info!("Copying file to {path:?}");
+                    std::fs::copy(&found, &path)?;
+                    Some(path)
+                }
+                None => None,
+            };
+            if output == output::Format::Json {
+                output::print_json(&store::Extracted {
+                    meta: None,
+                    hash: hash.to_string(),
+                    path: found,
+                    format: source.stored_format().to_string(),
+                    copied_to,
+                })?;
+            }
+        }
+        Command::Store(Store::Export {
+            playlist,
+            to,
+            run_in,
+        }) => {
+            let mut res = Resolver::new(resolve_run_path(run_in)?);
+            res.create_dirs()?;
+            log::initialize_logging(None, output)?;
+            resolve(&mut res)?;
+            let playlist = find_playlist(&res, &playlist)?;
+            sync::export(&res, playlist, &to)?;
+        }
+        Command::Store(Store::Import {
+            bundle,
+            force,
+            run_in,
+        }) => {
+            let mut res = Resolver::new(resolve_run_path(run_in)?);
+            res.create_dirs()?;
+            log::initialize_logging(None, output)?;
+            resolve(&mut res)?;
+            sync::import(&res, &bundle, force)?;
+        }
+        Command::Store(Store::Extract { .. }) => {
+            unreachable!("clap requires either a source and input, or a playlist")
+        }
+        Command::Init { bare, git, link } => {
+            log::initialize_logging(None, output)?;
+            match link {
+                Some(target) => link::set(&target, false)?,
+                None => init::dmm_init(bare, git)?,
+            }
+        }
+        Command::Check { run_in } => {
+            log::initialize_logging(None, output)?;
+            doctor::check(resolve_run_path(run_in)?)?;
+        }
+        Command::Doctor { run_in } => {
+            log::initialize_logging(None, output)?;
+            doctor::run(resolve_run_path(run_in))?;
+        }
+        Command::Playlist(Playlist::List { run_in }) => {
+            let mut res = Resolver::new(resolve_run_path(run_in)?);
+            res.create_dirs()?;
+            log::initialize_logging(None, output)?;
+            resolve(&mut res)?;
+            playlist::list(&res, output)?;
+        }
+        Command::Playlist(Playlist::Show { playlist, run_in }) => {
+            let mut res = Resolver::new(resolve_run_path(run_in)?);
+            res.create_dirs()?;
+            log::initialize_logging(None, output)?;
+            resolve(&mut res)?;
+            let playlist = find_playlist(&res, &playlist)?;
+            playlist::show(&res, playlist, output)?;
+        }
+        Command::Playlist(Playlist::Diff {
+            left,
+            right,
+            format,
+            run_in,
+        }) => {
+            let format = format.unwrap_or(output);
+            let mut res = Resolver::new(resolve_run_path(run_in)?);
+            res.create_dirs()?;
+            log::initialize_logging(None, format)?;
+            resolve(&mut res)?;
+            let left = find_playlist(&res, &left)?;
+            let right = match right {
+                Some(right) => Some(find_playlist(&res, &right)?),
+                None => None,
+            };
+            playlist::diff(&res, left, right, format)?;
+        }
+        Command::Playlist(Playlist::Enrich {
+            playlist,
+            dry_run,
+            run_in,
+        }) => {
+            let mut res = Resolver::new(resolve_run_path(run_in)?);
+            res.create_dirs()?;
+            log::initialize_logging(None, output)?;
+            resolve(&mut res)?;
+            let playlist = find_playlist(&res, &playlist)?;
+            enrich::enrich(&res, playlist, dry_run)?;
+        }
+        Command::Source(Source::List { run_in }) => {
+            let mut res = Resolver::new(resolve_run_path(run_in)?);
+            res.create_dirs()?;
+            log::initialize_logging(None, output)?;
+            resolve(&mut res)?;
+            source::list(&res, output)?;
+        }
+        Command::Source(Source::Show { name, run_in }) => {
+            let mut res = Resolver::new(resolve_run_path(run_in)?);
+            res.create_dirs()?;
+            log::initialize_logging(None, output)?;
+            resolve(&mut res)?;
+            source::show(&res, &name, output)?;
+        }
+        Command::Source(Source::Rename { old, new, run_in }) => {
+            let mut res = Resolver::new(resolve_run_path(run_in)?);
+            res.create_dirs()?;
+            log::initialize_logging(None, output)?;
+            resolve(&mut res)?;
+            source::rename(&res, &old, &new)?;
+        }
+        Command::Bench { playlist, run_in } => {
+            let mut res = Resolver::new(resolve_run_path(run_in)?);
+            res.create_dirs()?;
+            log::initialize_logging(None, output)?;
+            resolve(&mut res)?;
+            let playlist = match playlist {
+                Some(name) => Some(find_playlist(&res, &name)?),
+                None => None,
+            };
+            bench::bench(&res, playlist)?;
+        }
+        Command::Sync(Sync::Fetch {
+            index,
+            force,
+            run_in,
+        }) => {
+            let mut res = Resolver::new(resolve_run_path(run_in)?);
+            res.create_dirs()?;
+            log::initialize_logging(None, output)?;
+            resolve(&mut res)?;
+            let Some(index) = index.or_else(|| res.out().config.sync.index.clone()) else {
+                error!("No index to fetch from");
+                info!("Set `sync: (index: Some(\"<url>\"))` in dmm.ron, or pass --index");
+                bail!(DmmError::SyncFailed);
             };
-            info!("File path is {found:?} (file format: '{}')", source.format);
-            if let Some(path) = copy_to {
-                let path = path.with_extension(&source.format);
-                info!("Copying file to {path:?}");
-                std::fs::copy(found, path)?;
+            sync::fetch_index(&res, &index, force)?;
+        }
+        Command::Sync(cmd) => {
+            let (direction, target, playlists, force, run_in) = match cmd {
+                Sync::Push {
+                    target,
+                    playlists,
+                    run_in,
+                } => (sync::Direction::Push, target, playlists, false, run_in),
+                Sync::Pull {
+                    target,
+                    playlists,
+                    force,
+                    run_in,
+                } => (sync::Direction::Pull, target, playlists, force, run_in),
+                // (handled above)
+                Sync::Fetch { .. } => unreachable!(),
+            };
+            let mut res = Resolver::new(resolve_run_path(run_in)?);
+            res.create_dirs()?;
+            log::initialize_logging(None, output)?;
+            resolve(&mut res)?;
+            sync::sync(
+                &mut res,
+                &sync::Target::parse(&target),
+                direction,
+                &playlists,
+                force,
+            )?;
+        }
+        Command::Status { json, run_in } => {
+            let res = Resolver::new(resolve_run_path(run_in)?);
+            log::initialize_logging(None, output)?;
+            let format = if json { output::Format::Json } else { output };
+            status::print(&res, format)?;
+        }
+        Command::Stats { top, run_in } => {
+            let mut res = Resolver::new(resolve_run_path(run_in)?);
+            res.create_dirs()?;
+            log::initialize_logging(None, output)?;
+            resolve(&mut res)?;
+            stats::print(&res, top, output)?;
+        }
+        Command::History {
+            limit,
+            playlist,
+            run_in,
+        } => {
+            let mut res = Resolver::new(resolve_run_path(run_in)?);
+            res.create_dirs()?;
+            log::initialize_logging(None, output)?;
+            resolve(&mut res)?;
+            history::print(&res, limit, playlist, output)?;
+        }
+        Command::Link(cmd) => {
+            log::initialize_logging(None, output)?;
+            match cmd {
+                Link::Set { path } => link::set(&path, true)?,
+                Link::Show => link::show(output)?,
+                Link::Unset => link::unset()?,
             }
         }
-        Command::Init => init::dmm_init()?,
+        Command::Undo { run_in } => {
+            let mut res = Resolver::new(resolve_run_path(run_in)?);
+            res.create_dirs()?;
+            log::initialize_logging(None, output)?;
+            resolve(&mut res)?;
+            git::undo(&res)?;
+        }
+        Command::Scrobble(Scrobble::LastfmLogin { run_in }) => {
+            let mut res = Resolver::new(resolve_run_path(run_in)?);
+            res.create_dirs()?;
+            log::initialize_logging(None, output)?;
+            resolve(&mut res)?;
+            scrobble::lastfm_login(&res)?;
+        }
     }
     Ok(())
 }
 
+/// Logs to the log file (as the player takes over the terminal)
+fn player_logging(res: &mut Resolver, output: output::Format) -> Result<()> {
+    // (the config is loaded again along with everything else, this is only to know where to log to)
+    let log_cfg = cfg::Config::new(res.dirs().root.clone())
+        .map(|config| config.log)
+        .unwrap_or_default();
+    let log_file = match &log_cfg.file {
+        Some(file) => res.dirs().root.join(file),
+        None => res.tmp_file("dmm.log"),
+    };
+    log::initialize_logging(Some((log_file, &log_cfg)), output)
+}
+
+/// Loads the music directory for a command (see [`Resolver::resolve`])
+fn resolve(res: &mut Resolver) -> Result<()> {
+    res.resolve().wrap_err(DmmError::ResolveError)?;
+    log::set_level(&res.out().config.log.level);
+    Ok(())
+}
+
 /// selects the path to run in, in this order
 /// - `--in` argument
 /// - path specified in .dmm-link.ron
@@ -174,125 +1067,382 @@ fn main() -> Result<()> {
 fn resolve_run_path(run_in: Option<PathBuf>) -> Result<PathBuf> {
     run_in.map(Ok).unwrap_or_else(|| {
         let cdir = env::current_dir()?;
-        let path = cdir.join(".dmm-link.ron");
-        Ok(if path.try_exists()? {
-            let content = fs::read_to_string(path)?;
-            let link = ron::from_str::<schema::Link>(&content)?;
-            link.music_directory
+        Ok(if let Some(linked) = link::read(&cdir)? {
+            linked
         } else {
             if !cdir.join("dmm.ron").try_exists()? {
-                bail!("Cannot locate music directory (it is not the current directory, and no .dmm-link.ron exists)");
+                return Err(eyre!("Cannot locate music directory (it is not the current directory, and no .dmm-link.ron exists, see `dmm link set`)")
+                    .wrap_err(DmmError::ResolveError));
             }
             cdir
         })
     })
 }
 
-fn download(run_in: Option<PathBuf>, name: Option<String>) -> Result<()> {
+/// Number of playlists listed to pick from when a search matches more than one
+const CANDIDATES: usize = 5;
+
+/// fuzzy-searches the resolved playlists for ones matching `name`, best match first
+/// (a playlist with exactly that name always comes first)
+fn rank_playlists<'r>(res: &'r Resolver, name: &str) -> Vec<&'r schema::Playlist> {
+    let matcher = SkimMatcherV2::default().ignore_case();
+    let mut scores = res
+        .out()
+        .playlists
+        .iter()
+        .filter_map(|pl| {
+            let score = matcher.fuzzy_match(&pl.name, name)?;
+            Some((pl.name == name, score, pl))
+        })
+        .collect::<Vec<_>>();
+    // (stable, so equally good matches stay in the order they were loaded in)
+    scores.sort_by_key(|&(exact, score, _)| std::cmp::Reverse((exact, score)));
+    scores.into_iter().map(|(_, _, pl)| pl).collect()
+}
+
+/// the playlist best matching `name`, for commands that do not ask which one to use
+fn find_playlist<'r>(res: &'r Resolver, name: &str) -> Result<&'r schema::Playlist> {
+    match select_playlist(res, name, Confirm::Yes)? {
+        Some(playlist) => Ok(playlist),
+        None => unreachable!("only asking can be aborted"),
+    }
+}
+
+/// finds the playlist `name` refers to, choosing between the matches as `confirm` says.
+/// Returns None if the user aborted
+fn select_playlist<'r>(
+    res: &'r Resolver,
+    name: &str,
+    confirm: Confirm,
+) -> Result<Option<&'r schema::Playlist>> {
+    let mut ranked = rank_playlists(res, name);
+    if confirm == Confirm::Exact {
+        ranked.retain(|pl| pl.name == name);
+    }
+    let Some(&best) = ranked.first() else {
+        error!("Failed to find matching playlist in input (searched for name: {name:?})");
+        bail!(DmmError::NotFound);
+    };
+    // (with only one match, or one named exactly `name`, there is nothing to choose between)
+    let chosen = if confirm != Confirm::Ask || ranked.len() == 1 || best.name == name {
+        info!(
+            "search returned playlist {:?} : {:?}",
+            best.name, best.file_path
+        );
+        let correct = confirm != Confirm::Ask
+            || ask("is this correct (cont/abort)? [y/N]:")?.eq_ignore_ascii_case("y");
+        correct.then_some(best)
+    } else {
+        ranked.truncate(CANDIDATES);
+        println!("{} playlists match {name:?}:", ranked.len());
+        for (i, pl) in ranked.iter().enumerate() {
+            println!("  {}) {} ({})", i + 1, pl.name, pl.file_path.display());
+        }
+        let answer = ask(&format!(
+            "which one (1-{}, anything else to abort)?",
+            ranked.len()
+        ))?;
+        answer
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .and_then(|i| ranked.get(i.checked_sub(1)?).copied())
+    };
+    if chosen.is_none() {
+        info!("Aborting");
+    }
+    Ok(chosen)
+}
+
+/// prints `question`, and reads the answer from stdin
+fn ask(question: &str) -> Result<String> {
+    println!("{question}");
+    let Some(next) = io::stdin().lock().lines().next() else {
+        bail!("Failed to get input");
+    };
+    Ok(next?)
+}
+
+/// fuzzy-searches the tracks of `playlist` for one named `name` (preferring an exact match)
+fn search_track<'p>(playlist: &'p schema::Playlist, name: &str) -> Option<&'p schema::Track> {
+    if let Some(track) = playlist.tracks.iter().find(|t| t.meta.name == name) {
+        return Some(track);
+    }
+    let matcher = SkimMatcherV2::default().ignore_case();
+    playlist
+        .tracks
+        .iter()
+        .filter_map(|t| Some((matcher.fuzzy_match(&t.meta.name, name)?, t)))
+        .max_by_key(|(score, _)| *score)
+        .map(|(_, t)| t)
+}
+
+/// Handling of `dmm download track`
+fn download_track(
+    res: &Resolver,
+    playlist: &str,
+    track: &str,
+    force: bool,
+    jobs: usize,
+) -> Result<()> {
+    let playlist = find_playlist(res, playlist)?;
+    let Some(track) = search_track(playlist, track) else {
+        error!(
+            "Failed to find a track named {track:?} in {:?}",
+            playlist.name
+        );
+        bail!(DmmError::NotFound);
+    };
+    let Some(source) = playlist.find_source(&track.src) else {
+        error!("Could not find the source named {:?}", track.src);
+        bail!(DmmError::NotFound);
+    };
+    info!(
+        "found track {:?} in playlist {:?}",
+        track.meta.name, playlist.name
+    );
+    let hash = cache::Hash::track(source, track);
+    let hashes = HashSet::from([hash]);
+    match (force, res.out().cache.find(hash)) {
+        (true, _) => download::redownload(res, &hashes, jobs, None),
+        (false, Some(_)) => {
+            info!("The track is already downloaded, use --force to download it again");
+            Ok(())
+        }
+        (false, None) => download::download_hashes(res, &hashes, jobs, None),
+    }
+}
+
+/// How a playlist given by name is chosen (see [`select_playlist`])
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Confirm {
+    /// search for the playlist, and ask if the result is correct (or which one, if there are several)
+    Ask,
+    /// search for the playlist, and use the best match without asking
+    Yes,
+    /// use the playlist with exactly the given name
+    Exact,
+}
+
+fn download(
+    run_in: Option<PathBuf>,
+    name: Option<(String, Confirm)>,
+    tags: &[String],
+    jobs: usize,
+) -> Result<()> {
     let mut res = Resolver::new(resolve_run_path(run_in)?);
     res.create_dirs()?;
-    res.resolve()?;
-    if let Some(name) = name {
-        let mut scores = vec![];
-        let matcher = SkimMatcherV2::default().ignore_case();
-        for (i, playlist) in res.out().playlists.iter().enumerate() {
-            if let Some(score) = matcher.fuzzy_match(&playlist.name, &name) {
-                scores.push((score, i));
-            }
-        }
-        if scores.is_empty() {
-            error!("Failed to find matching playlist in input (searched for name: {name:?})");
+    resolve(&mut res)?;
+    if let Some((name, confirm)) = name {
+        let Some(chosen) = select_playlist(&res, &name, confirm)? else {
             return Ok(());
-        } else {
-            scores.sort_by_key(|score| score.0);
-            let chosen = &res.out().playlists[scores[0].1];
-            info!(
-                "search returned playlist {:?} : {:?}",
-                chosen.name, chosen.file_path
-            );
-            println!("is this correct (cont/abort)? [y/N]:");
-            let Some(next) = io::stdin().lock().lines().next() else {
-                bail!("Failed to get input");
-            };
-            match next?.as_str() {
-                "y" | "Y" => {}
-                _ => {
-                    info!("Aborting");
-                    return Ok(());
+        };
+        download::download(&res, &with_tags(std::slice::from_ref(chosen), tags), jobs)?;
+    } else {
+        download_all(&res, tags, jobs)?;
+    }
+    Ok(())
+}
+
+/// Downloads a playlist the user picks from a list
+fn download_picked(run_in: Option<PathBuf>, tags: &[String], jobs: usize) -> Result<()> {
+    let mut res = Resolver::new(resolve_run_path(run_in)?);
+    res.create_dirs()?;
+    resolve(&mut res)?;
+    let Some(index) = pick::playlist(&res, "Download", true)? else {
+        info!("Aborting");
+        return Ok(());
+    };
+    let chosen = &res.out().playlists[index];
+    download::download(&res, &with_tags(std::slice::from_ref(chosen), tags), jobs)
+}
+
+/// Downloads every playlist that is not archived
+fn download_all(res: &Resolver, tags: &[String], jobs: usize) -> Result<()> {
+    let (archived, playlists) = res
+        .out()
+        .playlists
+        .iter()
+        .cloned()
+        .partition::<Vec<_>, _>(|pl| pl.archived);
+    if !archived.is_empty() {
+        info!(
+            "skipping {} archived playlist(s) (download them by name to include them)",
+            archived.len()
+        );
+    }
+    download::download(res, &with_tags(&playlists, tags), jobs)
+}
+
+/// Downloads every playlist, and then again every time the playlists, sources, or config change, until interrupted.
+/// Failing to load the music directory or to download something is logged, and does not stop watching
+fn download_watch(res: &mut Resolver, tags: &[String], jobs: usize) -> Result<()> {
+    let (_watcher, changes) = watch::watch(res.dirs())?;
+    loop {
+        match res.resolve() {
+            Ok(()) => {
+                if let Some(summary) = res.out().load_summary() {
+                    warn!("{summary} (run `dmm check` for details)");
+                }
+                if let Err(e) = download_all(res, tags, jobs) {
+                    error!("Downloading failed: {e}");
                 }
             }
-            let src = chosen.clone();
-            download_playlist(src, &res.out().cache)?;
+            Err(e) => error!("Failed to load the music directory: {e}"),
         }
-    } else {
-        for playlist in res.out().playlists.iter() {
-            info!("Downloading playlist {}", playlist.name);
-            download_playlist(playlist.clone(), &res.out().cache)?;
+        info!("Waiting for changes to the playlists, sources, or config");
+        if changes.recv().is_err() {
+            bail!("stopped watching for changes");
         }
+        info!("Files changed, downloading new tracks");
     }
-    Ok(())
 }
 
-fn download_playlist(playlist: schema::Playlist, cache: &cache::CacheDir) -> Result<()> {
-    info!("downloading tracks in playlist {} to cache", playlist.name);
-    for track in &playlist.tracks {
-        info!("downloading {}", track.meta.name);
-        let source = playlist.find_source(&track.src).ok_or(anyhow!(
-            "Could not find source {} for track {}",
-            track.src,
-            track.meta.name
-        ))?;
-        let hash = cache::Hash::generate(source, &track.input);
-        if cache.find(hash).is_some() {
-            info!("track exists in cache [skiping]");
-            continue;
-        }
-        let path = cache.create(hash);
-        source.execute(track.input.clone(), &path)?;
-        debug!("download complete");
+/// Filters `playlists` down to the tracks with any of `tags` (dropping playlists left empty)
+fn with_tags(playlists: &[schema::Playlist], tags: &[String]) -> Vec<schema::Playlist> {
+    if tags.is_empty() {
+        return playlists.to_vec();
     }
-    info!("Done!");
-    Ok(())
+    let filtered = playlists
+        .iter()
+        .map(|pl| pl.with_tags(tags))
+        .filter(|pl| !pl.tracks.is_empty())
+        .collect::<Vec<_>>();
+    if filtered.is_empty() {
+        warn!("no tracks are tagged with any of {tags:?}");
+    }
+    filtered
+}
+
+/// Result of `dmm store gc`, for `--output json`
+#[derive(Debug, Serialize)]
+struct GcReport {
+    dry_run: bool,
+    /// files that were deleted (or would be, with `--dry-run`)
+    removed: Vec<GcEntry>,
+    bytes_removed: u64,
+    /// where the files were moved to, with `--trash`
+    trashed_to: Option<PathBuf>,
 }
 
-fn gc(run_in: Option<PathBuf>, dry_run: bool) -> Result<()> {
+#[derive(Debug, Serialize)]
+struct GcEntry {
+    hash: String,
+    path: PathBuf,
+    bytes: u64,
+}
+
+fn gc(
+    run_in: Option<PathBuf>,
+    dry_run: bool,
+    yes: bool,
+    trash: bool,
+    force: bool,
+    output: output::Format,
+) -> Result<()> {
     let mut res = Resolver::new(resolve_run_path(run_in)?);
     res.create_dirs()?;
-    res.resolve()?;
-    let mut hashes = HashSet::new();
-    let mut source_map = HashMap::new();
-    for playlist in &res.out().playlists {
-        for source in playlist.resolved_sources.as_ref().unwrap() {
-            source_map.insert(source.name.clone(), source.clone());
-        }
-        for track in &playlist.tracks {
-            let source = source_map
-                .get(&track.src)
-                .expect("Cannot find source for track");
-            let hash = cache::Hash::generate(source, &track.input);
-            hashes.insert(hash);
+    resolve(&mut res)?;
+    if let Some(failed) = res.out().load_summary() {
+        // the downloads of a playlist that failed to load would look unused, and be removed
+        for e in res
+            .out()
+            .failed_playlists
+            .iter()
+            .chain(&res.out().failed_sources)
+        {
+            error!("{e}");
+        }
+        if dry_run || force {
+            warn!("{failed}, so files they use are counted as unused");
+        } else {
+            info!("Fix them (see `dmm check`), or pass `--force` to remove the files anyway");
+            bail!("{failed}, not removing anything");
         }
     }
-    let mut bytes_removed = 0u64;
-    let mut files_removed = 0usize;
+    let (hashes, unmigrated) = store::referenced(&res);
+    let mut report = GcReport {
+        dry_run,
+        removed: vec![],
+        bytes_removed: 0,
+        trashed_to: None,
+    };
     for entry in res.dirs().cache.read_dir()? {
         let entry = entry?;
-        let hash = entry
-            .path()
-            .file_name()
-            .unwrap()
-            .to_str()
-            .expect("path not utf-8")
-            .parse::<cache::Hash>()?;
+        let Some(hash) = cache::entry_hash(&entry.file_name()) else {
+            warn!("ignoring unknown file in store: {:?}", entry.file_name());
+            continue;
+        };
         if !hashes.contains(&hash) {
-            info!("deleting {}", hash.to_string());
-            bytes_removed += entry.metadata()?.len();
-            files_removed += 1;
-            if !dry_run {
-                fs::remove_file(entry.path())?;
+            let bytes = entry.metadata()?.len();
+            report.bytes_removed += bytes;
+            report.removed.push(GcEntry {
+                hash: hash.to_string(),
+                path: entry.path(),
+                bytes,
+            });
+        }
+    }
+    let mib = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+    if !dry_run && !yes && !report.removed.is_empty() && io::stdin().is_terminal() {
+        // (on stderr, so it does not end up in `--output json`)
+        eprintln!(
+            "{} file(s) in the store are not used by any playlist:",
+            report.removed.len()
+        );
+        for entry in &report.removed {
+            eprintln!("  {} ({:.1} MiB)", entry.path.display(), mib(entry.bytes));
+        }
+        let action = if trash {
+            "move them to the trash"
+        } else {
+            "delete them"
+        };
+        eprintln!(
+            "{action} ({:.1} MiB in total)? [y/N]:",
+            mib(report.bytes_removed)
+        );
+        let Some(next) = io::stdin().lock().lines().next() else {
+            bail!("Failed to get input");
+        };
+        if !matches!(next?.as_str(), "y" | "Y") {
+            info!("Aborting");
+            return Ok(());
+        }
+    }
+    if trash && !dry_run && !report.removed.is_empty() {
+        report.trashed_to = Some(store::new_trash_batch(&res)?);
+    }
+    for entry in &report.removed {
+        match &report.trashed_to {
+            Some(batch) => {
+                info!("moving {} to the trash", entry.hash);
+                store::move_file(&entry.path, &batch.join(entry.path.file_name().unwrap()))?;
+            }
+            None => {
+                info!("deleting {}", entry.hash);
+                if !dry_run {
+                    fs::remove_file(&entry.path)?;
+                }
             }
         }
     }
-    info!("removed {files_removed} entries, freed {bytes_removed} bytes");
+    info!(
+        "removed {} entries, freed {} bytes",
+        report.removed.len(),
+        report.bytes_removed
+    );
+    if let Some(batch) = &report.trashed_to {
+        info!(
+            "moved them to {} (run `dmm store restore` to undo)",
+            batch.display()
+        );
+    }
+    if unmigrated > 0 {
+        warn!("kept {unmigrated} download(s) stored under the hash from before their source's identity was set (run `dmm store migrate`)");
+    }
+    if output == output::Format::Json {
+        output::print_json(&report)?;
+    }
     Ok(())
 }
@@ -0,0 +1,26 @@
+//! Output format of commands that print results (set with the global `--output` flag)
+
+use color_eyre::eyre::Result;
+use serde::Serialize;
+
+/// Output format for commands that produce a report
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// human readable text
+    #[default]
+    Text,
+    /// JSON, for scripting
+    Json,
+}
+
+/// Prints `value` to stdout as JSON
+pub fn print_json<T: Serialize>(value: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// Prints `value` to stdout as JSON on a single line (for status bars, which read a line at a time)
+pub fn print_json_line<T: Serialize>(value: &T) -> Result<()> {
+    println!("{}", serde_json::to_string(value)?);
+    Ok(())
+}
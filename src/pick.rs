@@ -0,0 +1,237 @@
+//! Picking a playlist interactively, for `dmm player` and `dmm download playlist` when no playlist is given
+//!
+//! A small fuzzy-search list of the playlists (with how many tracks they have and when they were last played),
+//! shown before the player starts or the download begins
+
+use std::{
+    collections::HashMap,
+    io::{self, IsTerminal},
+};
+
+use color_eyre::eyre::{bail, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use ratatui::{
+    layout::{Constraint, Direction, Layout},
+    style::{Style, Stylize},
+    text::Line,
+    widgets::{Block, Borders, HighlightSpacing, Paragraph, Row, Table, TableState},
+};
+
+use crate::{
+    plays,
+    resolver::Resolver,
+    ui::tui::{Event, Frame, Tui},
+};
+
+/// A playlist that can be picked
+struct Entry {
+    /// index of the playlist in the resolved playlists
+    index: usize,
+    name: String,
+    tracks: usize,
+    /// when a track of it was last played (seconds since the unix epoch)
+    last_played: Option<u64>,
+}
+
+struct Picker<'r> {
+    res: &'r Resolver,
+    /// what the picker is for, shown as its title (such as "Play")
+    title: &'static str,
+    /// every playlist, most recently played first
+    entries: Vec<Entry>,
+    query: String,
+    /// indices into `entries` of the playlists matching the query, best match first
+    results: Vec<usize>,
+    table_state: TableState,
+}
+
+/// Lets the user pick a playlist, returning its index in the resolved playlists (None if they cancelled).
+/// Archived playlists are only listed with `archived`
+pub fn playlist(res: &Resolver, title: &'static str, archived: bool) -> Result<Option<usize>> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        error!("No playlist was given, and one can only be picked in a terminal");
+        bail!("no playlist was given (one can only be picked in a terminal)");
+    }
+    let last_played = match plays::read(res) {
+        Ok(plays) => plays.into_iter().fold(HashMap::new(), |mut last, play| {
+            if let Some(playlist) = play.playlist {
+                last.insert(playlist, play.time);
+            }
+            last
+        }),
+        Err(e) => {
+            warn!("Failed to read the listening history: {e}");
+            HashMap::new()
+        }
+    };
+    let mut entries = res
+        .out()
+        .playlists
+        .iter()
+        .enumerate()
+        .filter(|(_, pl)| archived || !pl.archived)
+        .map(|(index, pl)| Entry {
+            index,
+            name: pl.name.clone(),
+            tracks: pl.tracks.len(),
+            last_played: last_played.get(&pl.name).copied(),
+        })
+        .collect::<Vec<_>>();
+    if entries.is_empty() {
+        error!("There are no playlists to pick from");
+        bail!("no playlists");
+    }
+    // (stable, so playlists that were never played stay in order)
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.last_played));
+    let mut picker = Picker {
+        res,
+        title,
+        entries,
+        query: String::new(),
+        results: vec![],
+        table_state: TableState::default(),
+    };
+    picker.update_results();
+
+    let mut tui = Tui::new()?;
+    tui.enter()?;
+    let picked = loop {
+        match tui.next() {
+            Some(Event::Key(key)) => {
+                if let Some(picked) = picker.key(key) {
+                    break picked;
+                }
+            }
+            Some(Event::Render | Event::Resize(..)) => {
+                tui.draw(|f| picker.draw(f))?;
+            }
+            Some(_) => {}
+            None => break None,
+        }
+    };
+    tui.exit()?;
+    Ok(picked)
+}
+
+impl Picker<'_> {
+    /// Handles a key press, returning `Some` once a playlist was picked (or `Some(None)` if picking was cancelled)
+    fn key(&mut self, key: KeyEvent) -> Option<Option<usize>> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => return Some(None),
+            KeyCode::Char('c') if ctrl => return Some(None),
+            KeyCode::Enter => {
+                let selected = self.table_state.selected()?;
+                return Some(Some(self.entries[self.results[selected]].index));
+            }
+            KeyCode::Up | KeyCode::BackTab => self.table_state.select_previous(),
+            KeyCode::Char('p' | 'k') if ctrl => self.table_state.select_previous(),
+            KeyCode::Down | KeyCode::Tab => self.select_next(),
+            KeyCode::Char('n' | 'j') if ctrl => self.select_next(),
+            KeyCode::Char('u') if ctrl => {
+                self.query.clear();
+                self.update_results();
+            }
+            KeyCode::Char(c)
+                if !key
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                self.query.push(c);
+                self.update_results();
+            }
+            KeyCode::Backspace => {
+                self.query.pop();
+                self.update_results();
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn select_next(&mut self) {
+        if let Some(selected) = self.table_state.selected() {
+            self.table_state.select(Some(
+                (selected + 1).min(self.results.len().saturating_sub(1)),
+            ));
+        }
+    }
+
+    /// Fuzzy-searches the playlist names for the query (listing every playlist if it is empty)
+    fn update_results(&mut self) {
+        if self.query.is_empty() {
+            self.results = (0..self.entries.len()).collect();
+        } else {
+            let matcher = SkimMatcherV2::default().ignore_case();
+            let mut scores = self
+                .entries
+                .iter()
+                .enumerate()
+                .filter_map(|(i, entry)| Some((matcher.fuzzy_match(&entry.name, &self.query)?, i)))
+                .collect::<Vec<_>>();
+            scores.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+            self.results = scores.into_iter().map(|(_, i)| i).collect();
+        }
+        self.table_state
+            .select((!self.results.is_empty()).then_some(0));
+    }
+
+    fn draw(&mut self, f: &mut Frame<'_>) {
+        let theme = &self.res.out().config.theme;
+        let rows = self
+            .results
+            .iter()
+            .map(|&i| {
+                let entry = &self.entries[i];
+                Row::new([
+                    Line::from(entry.name.clone()),
+                    Line::from(format!("{} tracks", entry.tracks)).fg(theme.dim),
+                    match entry.last_played {
+                        Some(time) => Line::from(format!("played {}", plays::format_time(time))),
+                        None => Line::from("never played"),
+                    }
+                    .fg(theme.dim),
+                ])
+            })
+            .collect::<Vec<_>>();
+        let block = Block::new()
+            .title(format!("{} Playlist", self.title).bold())
+            .title_bottom(
+                format!(
+                    "{} of {} playlist(s), <enter> to pick, <esc> to cancel",
+                    self.results.len(),
+                    self.entries.len()
+                )
+                .dim(),
+            )
+            .border_style(Style::new().fg(theme.border))
+            .borders(Borders::ALL);
+        let inner = block.inner(f.area());
+        let layout = Layout::new(
+            Direction::Vertical,
+            [Constraint::Length(1), Constraint::Min(0)],
+        )
+        .split(inner);
+        f.render_widget(block, f.area());
+        f.render_widget(
+            Paragraph::new(Line::from(vec![
+                "/ ".fg(theme.accent),
+                self.query.clone().into(),
+            ])),
+            layout[0],
+        );
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Fill(1),
+                Constraint::Length(12),
+                Constraint::Length(23),
+            ],
+        )
+        .highlight_symbol(">")
+        .highlight_spacing(HighlightSpacing::Always)
+        .highlight_style(Style::new().fg(theme.highlight));
+        f.render_stateful_widget(table, layout[1], &mut self.table_state);
+    }
+}
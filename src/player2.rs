@@ -1,99 +1,366 @@
 use std::{
+    collections::VecDeque,
     fs::File,
     sync::{
-        atomic::{AtomicU64, AtomicU8, Ordering},
+        atomic::{AtomicU32, AtomicU64, AtomicU8, Ordering},
         Arc,
     },
     thread,
+    time::Duration,
 };
 
 use color_eyre::{
-    eyre::{bail, Result},
+    eyre::{anyhow, bail, Result},
     Report,
 };
 use cpal::{
-    traits::{DeviceTrait, StreamTrait},
+    traits::{DeviceTrait, HostTrait, StreamTrait},
     Stream, SupportedStreamConfig,
 };
 use derivative::Derivative;
-use flume::Sender;
+use flume::{Receiver, Sender};
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 use rb::{RbConsumer, RbProducer, SpscRb, RB};
 use symphonia::core::{
-    audio::{AudioBufferRef, RawSample, SampleBuffer, SignalSpec},
+    audio::{AsAudioBufferRef, AudioBuffer, AudioBufferRef, SampleBuffer, SignalSpec},
     codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL},
-    conv::{ConvertibleSample, IntoSample},
     errors::Error as AudioError,
-    formats::{FormatOptions, FormatReader, Packet, Track},
+    formats::{FormatOptions, FormatReader, Packet, SeekMode, SeekTo, Track},
     io::{MediaSource, MediaSourceStream, MediaSourceStreamOptions},
     meta::MetadataOptions,
     probe,
-    units::Time,
+    units::{Time, TimeBase},
 };
 
+use crate::resample::Resampler;
+
+/// Finds the output device named `name` (or the default one), along with the config to use it with
+pub fn find_device(name: Option<&str>) -> Result<(Arc<cpal::Device>, Arc<SupportedStreamConfig>)> {
+    let host = cpal::default_host();
+    let device = match name {
+        Some(name) => host
+            .output_devices()?
+            .find(|device| device.name().is_ok_and(|n| n == name))
+            .ok_or(anyhow!("there is no output device named {name:?}"))?,
+        None => host
+            .default_output_device()
+            .ok_or(anyhow!("no audio output device exists"))?,
+    };
+    let config = device.default_output_config()?;
+    Ok((Arc::new(device), Arc::new(config)))
+}
+
+/// A sample format audio can be output in (decoded audio is always converted from f32)
 pub trait AudioOutputSample:
     cpal::Sample
     + cpal::SizedSample
-    + ConvertibleSample
-    + IntoSample<f32>
-    + RawSample
+    + cpal::FromSample<f32>
+    + Default
     + std::marker::Sync
     + std::marker::Send
     + 'static
 {
 }
 
-impl AudioOutputSample for f32 {}
+impl AudioOutputSample for i8 {}
 impl AudioOutputSample for i16 {}
+impl AudioOutputSample for i32 {}
+impl AudioOutputSample for i64 {}
+impl AudioOutputSample for u8 {}
 impl AudioOutputSample for u16 {}
+impl AudioOutputSample for u32 {}
+impl AudioOutputSample for u64 {}
+impl AudioOutputSample for f32 {}
+impl AudioOutputSample for f64 {}
 
 trait IsAudioWriter {
-    fn write(&mut self, decoded: AudioBufferRef<'_>) -> Result<()>;
+    /// `gain` is applied on top of the pre-amp (it is used for fading).
+    ///
+    /// Samples taken from the front of `mix` (interleaved, with the same spec as `decoded`) are added to the audio
+    fn write(
+        &mut self,
+        decoded: AudioBufferRef<'_>,
+        gain: f32,
+        mix: Option<&mut VecDeque<f32>>,
+    ) -> Result<()>;
+    /// Writes interleaved samples (of the spec the output was opened with) as they are
+    fn write_samples(&mut self, samples: &[f32]) -> Result<()>;
 }
 
 struct AudioWriterImpl<T: AudioOutputSample> {
+    sample_buf: SampleBuffer<f32>,
+    output: Output<T>,
+}
+
+/// How long writing audio may wait for the output to make room (on top of the length of the ring buffer), before the
+/// output is considered to have stopped
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How much audio is buffered on its way to the output device, see [`SingleTrackPlayer::set_buffering`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Buffering {
+    /// length (in milliseconds) of the ring buffer between decoding and the output
+    pub latency_ms: u32,
+    /// frames the device is asked to take at once (None for its default)
+    pub buffer_size: Option<u32>,
+}
+
+impl Default for Buffering {
+    fn default() -> Self {
+        Self {
+            latency_ms: 200,
+            buffer_size: None,
+        }
+    }
+}
+
+/// Scale of the fixed point sums of squared samples in [`Levels`] (atomic floats can not be added to)
+const SQUARES_SCALE: f64 = (1u64 << 32) as f64;
+
+/// Peak and RMS levels of the audio written to the output, for level meters, and how much of it clipped
+#[derive(Debug, Default)]
+pub struct Levels {
+    /// highest level (linear) of the left and right channels since they were last taken, stored as f32 bits.
+    /// levels are never negative, and the bits of non-negative floats sort the same as the floats themselves
+    peaks: [AtomicU32; 2],
+    /// sum of the squared samples of the left and right channels since they were last taken (see [`SQUARES_SCALE`])
+    squares: [AtomicU64; 2],
+    /// frames the squares were summed over
+    frames: AtomicU64,
+    /// samples that were too loud for the output (and were cut off) since this was last taken
+    clipped: AtomicU64,
+}
+
+/// Levels (linear) of the left and right channels, see [`Levels::take`]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LevelReading {
+    pub peaks: [f32; 2],
+    pub rms: [f32; 2],
+}
+
+impl Levels {
+    /// Records the levels of `samples` (interleaved, with `channels` channels) once `gain` is applied.
+    /// Mono audio counts as both channels
+    fn record(&self, samples: &[f32], channels: usize, gain: f32) {
+        let (mut peaks, mut squares, mut clipped) = ([0f32; 2], [0f64; 2], 0);
+        for frame in samples.chunks(channels.max(1)) {
+            for (i, sample) in frame.iter().enumerate() {
+                let level = (sample * gain).abs();
+                if level > 1.0 {
+                    clipped += 1;
+                }
+                if let Some(peak) = peaks.get_mut(i) {
+                    // (the output is cut off at full scale, so that is as loud as it gets)
+                    let level = level.min(1.0);
+                    *peak = peak.max(level);
+                    squares[i] += (level * level) as f64;
+                }
+            }
+        }
+        if channels == 1 {
+            peaks[1] = peaks[0];
+            squares[1] = squares[0];
+        }
+        for (peak, level) in self.peaks.iter().zip(peaks) {
+            peak.fetch_max(level.to_bits(), Ordering::Relaxed);
+        }
+        for (sum, squares) in self.squares.iter().zip(squares) {
+            sum.fetch_add((squares * SQUARES_SCALE) as u64, Ordering::Relaxed);
+        }
+        self.frames
+            .fetch_add((samples.len() / channels.max(1)) as u64, Ordering::Relaxed);
+        self.clipped.fetch_add(clipped, Ordering::Relaxed);
+    }
+
+    /// Takes the peak and RMS levels of the left and right channels since this was last called
+    pub fn take(&self) -> LevelReading {
+        let frames = self.frames.swap(0, Ordering::Relaxed);
+        let squares = self
+            .squares
+            .each_ref()
+            .map(|sum| sum.swap(0, Ordering::Relaxed) as f64 / SQUARES_SCALE);
+        LevelReading {
+            peaks: self
+                .peaks
+                .each_ref()
+                .map(|peak| f32::from_bits(peak.swap(0, Ordering::Relaxed))),
+            rms: squares.map(|squares| match frames {
+                0 => 0.0,
+                frames => (squares / frames as f64).sqrt() as f32,
+            }),
+        }
+    }
+
+    /// Takes the number of samples that clipped since this was last called
+    pub fn take_clipped(&self) -> u64 {
+        self.clipped.swap(0, Ordering::Relaxed)
+    }
+}
+
+/// The part of the writer after decoded audio is interleaved
+struct Output<T: AudioOutputSample> {
     ring_buf_producer: rb::Producer<T>,
-    sample_buf: SampleBuffer<T>,
+    out_buf: Vec<T>,
+    /// pre-amp gain times the volume (linear, stored as f32 bits)
+    preamp: Arc<AtomicU32>,
+    levels: Arc<Levels>,
+    channels: usize,
+    /// converts to the sample rate of the output (if the device does not support the rate of the track)
+    resampler: Option<Resampler>,
+    resampled: Vec<f32>,
+    /// see [`WRITE_TIMEOUT`]
+    write_timeout: Duration,
+}
+
+impl<T: AudioOutputSample> Output<T> {
+    /// Resamples `samples`, applies the pre-amp, and writes them to the ring buffer
+    fn write(&mut self, samples: &[f32]) -> Result<()> {
+        let samples = match self.resampler.as_mut() {
+            Some(resampler) => {
+                resampler.process(samples, &mut self.resampled);
+                self.resampled.as_slice()
+            }
+            None => samples,
+        };
+
+        // Apply the pre-amp, and convert to the output sample format
+        let gain = f32::from_bits(self.preamp.load(Ordering::Relaxed));
+        self.levels.record(samples, self.channels, gain);
+        self.out_buf.clear();
+        self.out_buf.extend(
+            samples
+                .iter()
+                .map(|s| <T as cpal::Sample>::from_sample((s * gain).clamp(-1.0, 1.0))),
+        );
+
+        let mut samples = self.out_buf.as_slice();
+        // Write enough samples to fill the ring buffer.
+        // (if the output stops taking audio without reporting an error, this would block forever)
+        while let Some(written) = self
+            .ring_buf_producer
+            .write_blocking_timeout(samples, self.write_timeout)
+            .map_err(|_| anyhow!("the audio output stopped playing"))?
+        {
+            samples = &samples[written..];
+        }
+        Ok(())
+    }
 }
 
 impl<T: AudioOutputSample> IsAudioWriter for AudioWriterImpl<T> {
-    fn write(&mut self, decoded: AudioBufferRef<'_>) -> Result<()> {
+    fn write(
+        &mut self,
+        decoded: AudioBufferRef<'_>,
+        gain: f32,
+        mut mix: Option<&mut VecDeque<f32>>,
+    ) -> Result<()> {
         // Do nothing if there are no audio frames.
         if decoded.frames() == 0 {
             return Ok(());
         }
 
-        // Resampling is not required. Interleave the sample for cpal using a sample buffer.
+        // Interleave the sample for cpal using a sample buffer.
         // AudioBuffer capacity is duration, SampleBuf capacity is duration * channels (total samples)
         if self.sample_buf.capacity() < decoded.capacity() * decoded.spec().channels.count() {
             self.sample_buf = SampleBuffer::new(decoded.capacity() as u64, *decoded.spec());
         }
         self.sample_buf.copy_interleaved_ref(decoded);
+        for sample in self.sample_buf.samples_mut() {
+            *sample *= gain;
+            if let Some(mixed) = mix.as_mut().and_then(|mix| mix.pop_front()) {
+                *sample += mixed;
+            }
+        }
+        self.output.write(self.sample_buf.samples())
+    }
 
-        let mut samples = self.sample_buf.samples();
-        // Write enough samples to fill the ring buffer.
-        while let Some(written) = self.ring_buf_producer.write_blocking(samples) {
-            samples = &samples[written..];
+    fn write_samples(&mut self, samples: &[f32]) -> Result<()> {
+        self.output.write(samples)
+    }
+}
+
+/// Picks the sample rate to output audio at `rate` (with `channels` channels) on `device`.
+///
+/// This is `rate` itself if the device supports it, otherwise the default rate of the device
+/// (or the closest rate it supports)
+fn output_rate(
+    device: &cpal::Device,
+    device_config: &SupportedStreamConfig,
+    channels: usize,
+    rate: u32,
+) -> u32 {
+    let ranges = match device.supported_output_configs() {
+        Ok(configs) => configs
+            .filter(|c| {
+                c.channels() as usize == channels
+                    && c.sample_format() == device_config.sample_format()
+            })
+            .map(|c| (c.min_sample_rate().0, c.max_sample_rate().0))
+            .collect::<Vec<_>>(),
+        Err(e) => {
+            warn!(
+                "Failed to get the supported output configs: {e} [assuming any sample rate works]"
+            );
+            return rate;
         }
-        Ok(())
+    };
+    let supported = |rate: u32| ranges.iter().any(|&(min, max)| (min..=max).contains(&rate));
+    if ranges.is_empty() || supported(rate) {
+        return rate;
     }
+    let default_rate = device_config.sample_rate().0;
+    if supported(default_rate) {
+        return default_rate;
+    }
+    ranges
+        .iter()
+        .map(|&(min, max)| rate.clamp(min, max))
+        .min_by_key(|r| r.abs_diff(rate))
+        .unwrap()
 }
 
 fn open_stream<T: AudioOutputSample>(
     spec: SignalSpec,
     device: &cpal::Device,
+    device_config: &SupportedStreamConfig,
+    preamp: Arc<AtomicU32>,
+    levels: Arc<Levels>,
+    buffering: Buffering,
+    errors: Sender<String>,
 ) -> Result<(Box<dyn IsAudioWriter>, Stream)> {
     let num_channels = spec.channels.count();
+    let rate = output_rate(device, device_config, num_channels, spec.rate);
+    let resampler = (rate != spec.rate).then(|| {
+        debug!("resampling from {}Hz to {rate}Hz", spec.rate);
+        Resampler::new(num_channels, spec.rate, rate)
+    });
+
+    let buffer_size = buffering.buffer_size.map(|frames| match device_config.buffer_size() {
+        &cpal::SupportedBufferSize::Range { min, max } if !(min..=max).contains(&frames) => {
+            warn!("The output device only supports buffer sizes from {min} to {max} frames [using {}]", frames.clamp(min, max));
+            frames.clamp(min, max)
+        }
+        _ => frames,
+    });
 
     // Output audio stream config.
     let config = cpal::StreamConfig {
         channels: num_channels as cpal::ChannelCount,
-        sample_rate: cpal::SampleRate(spec.rate),
-        buffer_size: cpal::BufferSize::Default,
+        sample_rate: cpal::SampleRate(rate),
+        buffer_size: buffer_size.map_or(cpal::BufferSize::Default, cpal::BufferSize::Fixed),
     };
 
-    // Create a ring buffer with a capacity for up-to 200ms of audio.
-    let ring_len = ((200 * config.sample_rate.0 as usize) / 1000) * num_channels;
+    // Create a ring buffer with a capacity for `latency_ms` of audio (and at least two of the device's buffers, so it
+    // can be refilled while the device takes one)
+    let ring_frames = (buffering.latency_ms as usize * config.sample_rate.0 as usize / 1000)
+        .max(2 * buffer_size.unwrap_or(0) as usize)
+        .max(1);
+    let ring_len = ring_frames * num_channels;
+    debug!(
+        "output buffer: {ring_frames} frames ({}ms), device buffer: {}",
+        ring_frames * 1000 / config.sample_rate.0 as usize,
+        buffer_size.map_or("default".to_string(), |frames| format!("{frames} frames"))
+    );
 
     let ring_buf = SpscRb::new(ring_len);
     let (ring_buf_producer, ring_buf_consumer) = (ring_buf.producer(), ring_buf.consumer());
@@ -106,9 +373,13 @@ fn open_stream<T: AudioOutputSample>(
             let written = ring_buf_consumer.read(data).unwrap_or(0);
 
             // Mute any remaining samples.
-            data[written..].iter_mut().for_each(|s| *s = T::MID);
+            data[written..].iter_mut().for_each(|s| *s = T::EQUILIBRIUM);
+        },
+        move |err| {
+            error!("audio output error: {}", err);
+            // playback is paused by the decode thread
+            let _ = errors.send(err.to_string());
         },
-        move |err| error!("audio output error: {}", err),
         None,
     );
 
@@ -119,29 +390,50 @@ fn open_stream<T: AudioOutputSample>(
 
     let stream = stream_result.unwrap();
 
-    let sample_buf = SampleBuffer::<T>::new(0, spec);
+    let sample_buf = SampleBuffer::<f32>::new(0, spec);
     Ok((
         Box::new(AudioWriterImpl {
-            ring_buf_producer,
             sample_buf,
+            output: Output {
+                ring_buf_producer,
+                out_buf: vec![],
+                preamp,
+                levels,
+                channels: num_channels,
+                resampler,
+                resampled: vec![],
+                write_timeout: WRITE_TIMEOUT + Duration::from_millis(buffering.latency_ms.into()),
+            },
         }),
         stream,
     ))
 }
 
-struct AudioDecoder {
+/// Part of a track to play, in seconds (see [`crate::schema::Track::start`])
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Clip {
+    pub start: f64,
+    pub end: Option<f64>,
+}
+
+pub struct AudioDecoder {
     fmt_reader: Box<dyn FormatReader>,
     decoder: Box<dyn Decoder>,
     track: Track,
     track_id: u32,
+    /// converts packet timestamps to times
+    time_base: TimeBase,
+    clip: Clip,
 }
 
 impl AudioDecoder {
     pub fn new(source: impl MediaSource + 'static, format: probe::Hint) -> Result<Self> {
         let source_opts = MediaSourceStreamOptions::default();
         let metadata_opts = MetadataOptions::default();
-        let mut format_opts = FormatOptions::default();
-        format_opts.enable_gapless = true;
+        let format_opts = FormatOptions {
+            enable_gapless: true,
+            ..Default::default()
+        };
         let decoder_opts = DecoderOptions::default();
 
         let source_stream = MediaSourceStream::new(Box::new(source), source_opts);
@@ -162,25 +454,47 @@ impl AudioDecoder {
             .tracks()
             .iter()
             .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
-            .expect("no supported audio tracks")
+            .ok_or_else(|| anyhow!("no supported audio tracks"))?
             .clone();
 
         // Create a decoder for the track.
         let decoder = symphonia::default::get_codecs()
             .make(&track.codec_params, &decoder_opts)
-            .expect("unsupported codec");
+            .map_err(|e| anyhow!("unsupported codec: {e}"))?;
 
         // Store the track identifier, it will be used to filter packets.
         let track_id = track.id;
+        // (timestamps are in frames if the format does not say otherwise)
+        let time_base = track
+            .codec_params
+            .time_base
+            .or_else(|| {
+                track
+                    .codec_params
+                    .sample_rate
+                    .map(|rate| TimeBase::new(1, rate))
+            })
+            .ok_or_else(|| anyhow!("the track has no time base or sample rate"))?;
 
         Ok(Self {
             fmt_reader,
             decoder,
             track,
             track_id,
+            time_base,
+            clip: Clip::default(),
         })
     }
 
+    /// Only play `clip` of the track: seeks to its start, and ends the stream at its end
+    pub fn clip(&mut self, clip: Clip) -> Result<(), AudioError> {
+        self.clip = clip;
+        if clip.start > 0.0 {
+            self.seek_to(clip.start)?;
+        }
+        Ok(())
+    }
+
     pub fn decode_next<'buf>(&'buf mut self) -> Result<Decoded<'buf>, AudioError> {
         // Get the next packet from the media format.
         let packet = match self.fmt_reader.next_packet() {
@@ -207,6 +521,12 @@ impl AudioDecoder {
         if packet.track_id() != self.track_id {
             return Ok(Decoded::Retry);
         }
+        if let Some(end) = self.clip.end {
+            let time = self.time_base.calc_time(packet.ts());
+            if time.seconds as f64 + time.frac >= end {
+                return Ok(Decoded::StreamEnd);
+            }
+        }
 
         // Decode the packet into audio samples.
         match self.decoder.decode(&packet) {
@@ -228,16 +548,49 @@ impl AudioDecoder {
         }
     }
 
-    pub fn duration(&self) -> Time {
-        self.track
+    /// Seeks to `secs` seconds into the track (or the start of its clip, if that is later)
+    pub fn seek(&mut self, secs: u64) -> Result<(), AudioError> {
+        self.seek_to((secs as f64).max(self.clip.start))
+    }
+
+    fn seek_to(&mut self, secs: f64) -> Result<(), AudioError> {
+        self.fmt_reader.seek(
+            SeekMode::Accurate,
+            SeekTo::Time {
+                time: Time::from(secs),
+                track_id: Some(self.track_id),
+            },
+        )?;
+        // the decoder may still hold state from before the seek
+        self.decoder.reset();
+        Ok(())
+    }
+
+    /// Time the track ends at (the end of its clip, if that is earlier than the end of the file).
+    ///
+    /// Many files (such as webm/opus downloads) do not state how many frames they have, so this is not always known
+    pub fn duration(&self) -> Option<Time> {
+        let full = self
+            .track
             .codec_params
-            .time_base
-            .unwrap()
-            .calc_time(self.track.codec_params.n_frames.unwrap())
+            .n_frames
+            .map(|frames| self.time_base.calc_time(frames));
+        match (self.clip.end, full) {
+            (Some(end), Some(full)) if end >= full.seconds as f64 + full.frac => Some(full),
+            (Some(end), _) => Some(Time::from(end)),
+            (None, full) => full,
+        }
+    }
+
+    /// If the audio of this track can be mixed with audio of `spec` (they have the same sample rate and channels)
+    fn can_mix(&self, spec: SignalSpec) -> bool {
+        let params = &self.track.codec_params;
+        params.sample_rate == Some(spec.rate) && params.channels == Some(spec.channels)
     }
 }
 
-enum Decoded<'a> {
+#[allow(clippy::enum_variant_names)]
+pub enum Decoded<'a> {
     StreamEnd,
     /// something uninformative happened, need to consume another packet
     /// this indicates that decode_next should be called again.
@@ -261,6 +614,120 @@ fn ignore_end_of_stream_error(
     }
 }
 
+/// Drops silence at the start and end of a track.
+///
+/// Leading silence is discarded as it is decoded. Trailing silence can only be identified once
+/// the track has ended, so quiet audio is held back until something louder is decoded (at which
+/// point it is played as normal), and anything still held back at the end of the track is dropped.
+struct SilenceTrimmer {
+    /// peak amplitude below which a buffer is considered silent
+    threshold: f32,
+    /// if any non-silent audio has been decoded yet
+    started: bool,
+    held: Vec<AudioBuffer<f32>>,
+}
+
+impl SilenceTrimmer {
+    /// `threshold_db` is the level (in dBFS) below which audio is considered silent
+    pub fn new(threshold_db: f32) -> Self {
+        Self {
+            threshold: 10f32.powf(threshold_db / 20.0),
+            started: false,
+            held: vec![],
+        }
+    }
+
+    /// Returns the buffers that should be played (in order)
+    pub fn process(&mut self, decoded: AudioBufferRef<'_>) -> Vec<AudioBuffer<f32>> {
+        let mut owned = decoded.make_equivalent::<f32>();
+        decoded.convert(&mut owned);
+        let peak = owned
+            .planes()
+            .planes()
+            .iter()
+            .flat_map(|plane| plane.iter())
+            .fold(0.0f32, |peak, s| peak.max(s.abs()));
+        if peak >= self.threshold {
+            self.started = true;
+            let mut out = std::mem::take(&mut self.held);
+            out.push(owned);
+            out
+        } else {
+            if self.started {
+                self.held.push(owned);
+            }
+            vec![]
+        }
+    }
+}
+
+/// The start of the next track, decoded early to be mixed into the end of the current one
+struct Crossfade {
+    decoder: AudioDecoder,
+    trimmer: Option<SilenceTrimmer>,
+    /// samples of the next track (interleaved, already faded in) that have not been played yet
+    pending: VecDeque<f32>,
+    /// if the next track has been decoded to its end
+    ended: bool,
+}
+
+impl Crossfade {
+    /// Starts decoding `next` early (check it with [`AudioDecoder::can_mix`] first)
+    fn new(next: AudioDecoder, trim_silence: Option<f32>) -> Self {
+        Self {
+            decoder: next,
+            trimmer: trim_silence.map(SilenceTrimmer::new),
+            pending: VecDeque::new(),
+            ended: false,
+        }
+    }
+
+    /// Decodes the next track until at least `samples` samples are pending, fading them in by `gain`
+    fn fill(&mut self, samples: usize, gain: f32) -> Result<(), AudioError> {
+        while self.pending.len() < samples && !self.ended {
+            let buffer = match self.decoder.decode_next()? {
+                Decoded::StreamEnd => {
+                    self.ended = true;
+                    continue;
+                }
+                Decoded::Retry => continue,
+                Decoded::Decoded(_, buffer) => buffer,
+            };
+            let trimmed;
+            let buffers = match self.trimmer.as_mut() {
+                Some(trimmer) => {
+                    trimmed = trimmer.process(buffer);
+                    trimmed.iter().map(|b| b.as_audio_buffer_ref()).collect()
+                }
+                None => vec![buffer],
+            };
+            for buffer in buffers {
+                let mut interleaved =
+                    SampleBuffer::<f32>::new(buffer.capacity() as u64, *buffer.spec());
+                interleaved.copy_interleaved_ref(buffer);
+                self.pending
+                    .extend(interleaved.samples().iter().map(|s| s * gain));
+            }
+        }
+        Ok(())
+    }
+
+    /// Goes back to the start of the next track, so it plays normally once the current one ends
+    fn cancel(self) -> Option<AudioDecoder> {
+        let mut decoder = self.decoder;
+        match decoder.seek(0) {
+            Ok(()) => Some(decoder),
+            Err(e) => {
+                warn!("Failed to rewind the next track: {e} [it will not be played without a gap]");
+                None
+            }
+        }
+    }
+}
+
+/// Length (in seconds) of the fade out at the end of a preview
+const PREVIEW_FADE: f64 = 2.0;
+
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, IntoPrimitive, TryFromPrimitive)]
 pub enum State {
@@ -275,21 +742,102 @@ enum PlayTaskCmd {
     Play,
     Pause,
     Stop,
-    // start playing (from stopped)
-    Start,
+    // start playing (from stopped), `at` seconds into the track, and right away paused with `paused`
+    Start {
+        at: u64,
+        paused: bool,
+    },
     SetOnTrackComplete(#[derivative(Debug = "ignore")] Box<dyn Fn() + Send + Sync + 'static>),
-    SetNewSource { track_src: File, filetype: String },
+    SetNewSource {
+        track_src: File,
+        filetype: String,
+        clip: Clip,
+    },
+    /// threshold (dBFS) for silence trimming, or None to disable it
+    SetTrimSilence(Option<f32>),
+    /// only play this many seconds from the middle of the next track that is started
+    SetPreview(u64),
+    /// length (in seconds) of the crossfade into a preloaded track (0 to not crossfade)
+    SetCrossfade(f64),
+    /// buffering of the output (it is reopened if a track is playing)
+    SetBuffering(Buffering),
+    /// seek to this many seconds into the current track
+    Seek(u64),
+    /// go back to the first time (in seconds) whenever the second is reached, in the current track (None to stop)
+    SetLoop(Option<(u64, u64)>),
+    /// prepare the track to play when the current one ends (None cancels the preload)
+    Preload(Option<(File, String, Clip)>),
+    SetOnPreloadStart(#[derivative(Debug = "ignore")] Box<dyn Fn() + Send + Sync + 'static>),
+    SetOnStreamError(#[derivative(Debug = "ignore")] Box<dyn Fn(String) + Send + Sync + 'static>),
+    /// output to a different device (from the current position, if a track is playing)
+    SetDevice(
+        #[derivative(Debug = "ignore")] Arc<cpal::Device>,
+        Arc<SupportedStreamConfig>,
+    ),
+}
+
+/// Length of the playing track (in seconds), as far as the player knows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Length {
+    /// stated in the file (or the end of the track's clip)
+    Known(u64),
+    /// not stated in the file, so the track is only known to be at least as long as the furthest point decoded so far
+    AtLeast(u64),
+}
+
+impl Length {
+    /// Set in the stored bits of [`Length::AtLeast`]. Estimates stay in order when stored, so they can be updated with `fetch_max`
+    const ESTIMATED: u64 = 1 << 63;
+
+    fn to_bits(self) -> u64 {
+        match self {
+            Self::Known(secs) => secs,
+            Self::AtLeast(secs) => secs | Self::ESTIMATED,
+        }
+    }
+
+    fn from_bits(bits: u64) -> Self {
+        match bits & Self::ESTIMATED {
+            0 => Self::Known(bits),
+            _ => Self::AtLeast(bits & !Self::ESTIMATED),
+        }
+    }
+
+    /// The length if it is known
+    pub fn known(self) -> Option<u64> {
+        match self {
+            Self::Known(secs) => Some(secs),
+            Self::AtLeast(_) => None,
+        }
+    }
+
+    /// The length, or the estimate of it
+    pub fn estimate(self) -> u64 {
+        match self {
+            Self::Known(secs) | Self::AtLeast(secs) => secs,
+        }
+    }
 }
 
 pub struct SingleTrackPlayer {
     state: Arc<AtomicU8>,
     tx: Sender<PlayTaskCmd>,
+    /// see [`Length::to_bits`]
     duration: Arc<AtomicU64>,
     time: Arc<AtomicU64>,
+    /// pre-amp gain times the volume (linear, stored as f32 bits)
+    preamp: Arc<AtomicU32>,
+    /// pre-amp gain (in dB) and volume (in percent) that `preamp` is set from
+    gain: (f32, u8),
+    levels: Arc<Levels>,
+    errors: Receiver<String>,
 }
 
 impl SingleTrackPlayer {
-    pub fn new(config: Arc<SupportedStreamConfig>, device: Arc<cpal::Device>) -> Result<Self> {
+    pub fn new(
+        mut config: Arc<SupportedStreamConfig>,
+        mut device: Arc<cpal::Device>,
+    ) -> Result<Self> {
         let (tx, rx) = flume::unbounded::<PlayTaskCmd>();
         let state = Arc::new(AtomicU8::new(State::Stopped as u8));
         let state_2 = state.clone();
@@ -297,164 +845,527 @@ impl SingleTrackPlayer {
         let duration_2 = duration.clone();
         let time = Arc::new(AtomicU64::new(0));
         let time_2 = time.clone();
+        let preamp = Arc::new(AtomicU32::new(1.0f32.to_bits()));
+        let preamp_2 = preamp.clone();
+        let levels = Arc::new(Levels::default());
+        let levels_2 = levels.clone();
+        // errors that stopped a track from playing, or the whole thread
+        let (error_tx, errors) = flume::unbounded::<String>();
+        let fatal_error_tx = error_tx.clone();
+        let state_3 = state.clone();
 
         thread::Builder::new()
             .name("audio-decode".to_string())
             .spawn(move || {
-                let mut on_track_complete = None::<Box<dyn Fn() + Send + Sync + 'static>>;
-                let mut outer_decoder = None;
-                state_2.store(State::Stopped as u8, Ordering::SeqCst);
-                'run: loop {
-                    match rx.recv() {
-                        Ok(PlayTaskCmd::Start) => {
-                            assert!(outer_decoder.is_some(), "cannot start stream with no source set");
-                        },
-                        Ok(PlayTaskCmd::SetNewSource { track_src, filetype }) => {
-                            // Create the media source stream.
-                            let mss = MediaSourceStream::new(Box::new(track_src), Default::default());
-
-                            // Create a probe hint using the file's extension. [Optional]
-                            let mut hint = probe::Hint::new();
-                            hint.with_extension(&filetype);
-                            outer_decoder = Some(AudioDecoder::new(mss, hint)?);
-                            continue 'run;
-                        },
-                        Ok(PlayTaskCmd::SetOnTrackComplete(call)) => {
-                            on_track_complete = Some(call);
-                            continue 'run;
+                let result = (move || {
+                    let mut on_track_complete = None::<Box<dyn Fn() + Send + Sync + 'static>>;
+                    let mut on_preload_start = None::<Box<dyn Fn() + Send + Sync + 'static>>;
+                    let mut on_stream_error = None::<Box<dyn Fn(String) + Send + Sync + 'static>>;
+                    // errors of the output stream (such as the device being disconnected), which pause playback
+                    let (stream_err_tx, stream_err_rx) = flume::unbounded::<String>();
+                    // decoder for the track to continue with once the current one ends
+                    let mut preloaded = None::<AudioDecoder>;
+                    let open = |track_src: File, filetype: &str, clip: Clip| {
+                        let mss = MediaSourceStream::new(Box::new(track_src), Default::default());
+                        // Create a probe hint using the file's extension. [Optional]
+                        let mut hint = probe::Hint::new();
+                        hint.with_extension(filetype);
+                        let mut decoder = AudioDecoder::new(mss, hint)?;
+                        decoder.clip(clip)?;
+                        Ok::<_, Report>(decoder)
+                    };
+                    let preload = |next: Option<(File, String, Clip)>| {
+                        let (track_src, filetype, clip) = next?;
+                        match open(track_src, &filetype, clip) {
+                            Ok(decoder) => Some(decoder),
+                            Err(e) => {
+                                // the track will be opened again (and the error reported) when it is played normally
+                                warn!("Failed to preload the next track: {e}");
+                                None
+                            }
                         }
-                        Ok(got) => {
-                            error!("player received unexpected command while waiting for playback to start: {got:?}");
-                            unreachable!()
-                        },
-                        Err(flume::RecvError::Disconnected) => break 'run,
-                    }
-                    let mut decoder = outer_decoder.take().unwrap();
-                    let tb = decoder.track.codec_params.time_base.unwrap();
-                    let dur = decoder.duration();
-                    let mut audio_output = None::<(Box<dyn IsAudioWriter>, cpal::Stream)>;
-                    state_2.store(State::Playing as u8, Ordering::SeqCst);
-                    'play: loop {
-                        match rx.try_recv() {
-                            Ok(PlayTaskCmd::Play) => {
-                                warn!("Received play command, but audio is already playing")
+                    };
+                    let mut trim_silence = None::<f32>;
+                    let mut preview = None::<u64>;
+                    let mut crossfade_secs = 0.0f64;
+                    let mut buffering = Buffering::default();
+                    let mut outer_decoder = None;
+                    state_2.store(State::Stopped as u8, Ordering::SeqCst);
+                    'run: loop {
+                        let (start_at, mut start_paused) = match rx.recv() {
+                            Ok(PlayTaskCmd::Start { at, paused }) => {
+                                if outer_decoder.is_none() {
+                                    // opening the track failed (which was reported), so there is nothing to play
+                                    continue 'run;
+                                }
+                                (at, paused)
+                            },
+                            Ok(PlayTaskCmd::SetNewSource { track_src, filetype, clip }) => {
+                                outer_decoder = match open(track_src, &filetype, clip) {
+                                    Ok(decoder) => Some(decoder),
+                                    Err(e) => {
+                                        let _ = error_tx.send(format!("failed to open the track: {e}"));
+                                        None
+                                    }
+                                };
+                                // anything preloaded was meant to follow a different track
+                                preloaded = None;
+                                continue 'run;
+                            },
+                            Ok(PlayTaskCmd::Preload(next)) => {
+                                preloaded = preload(next);
+                                continue 'run;
+                            }
+                            Ok(PlayTaskCmd::SetOnPreloadStart(call)) => {
+                                on_preload_start = Some(call);
+                                continue 'run;
                             }
-                            Ok(PlayTaskCmd::Pause) => {
-                                state_2.store(State::Paused as u8, Ordering::SeqCst);
-                                if let Some(audio_output) = audio_output.as_mut() {
-                                    let _ = audio_output.1.pause();
+                            Ok(PlayTaskCmd::SetOnStreamError(call)) => {
+                                on_stream_error = Some(call);
+                                continue 'run;
+                            }
+                            Ok(PlayTaskCmd::SetOnTrackComplete(call)) => {
+                                on_track_complete = Some(call);
+                                continue 'run;
+                            }
+                            Ok(PlayTaskCmd::SetTrimSilence(threshold)) => {
+                                trim_silence = threshold;
+                                continue 'run;
+                            }
+                            Ok(PlayTaskCmd::SetPreview(length)) => {
+                                preview = Some(length);
+                                continue 'run;
+                            }
+                            Ok(PlayTaskCmd::SetCrossfade(secs)) => {
+                                crossfade_secs = secs;
+                                continue 'run;
+                            }
+                            Ok(PlayTaskCmd::SetBuffering(new)) => {
+                                buffering = new;
+                                continue 'run;
+                            }
+                            Ok(PlayTaskCmd::SetDevice(new_device, new_config)) => {
+                                device = new_device;
+                                config = new_config;
+                                continue 'run;
+                            }
+                            Ok(PlayTaskCmd::Seek(_) | PlayTaskCmd::SetLoop(_)) => {
+                                // the track ended before the seek (or loop) was processed
+                                continue 'run;
+                            }
+                            Ok(got) => {
+                                // (such as a pause sent by a remote control as the track ended, or after it failed to open)
+                                debug!("player ignored a command that needs a track, while none is playing: {got:?}");
+                                continue 'run;
+                            },
+                            Err(flume::RecvError::Disconnected) => break 'run,
+                        };
+                        let mut decoder = outer_decoder.take().unwrap();
+                        let mut tb = decoder.time_base;
+                        let mut dur = decoder.duration();
+                        // what is known of the length (see `Length`), before anything is decoded
+                        let initial_length = |dur: Option<Time>| match dur {
+                            Some(dur) => Length::Known(dur.seconds).to_bits(),
+                            None => Length::AtLeast(0).to_bits(),
+                        };
+                        // the output stream is kept open between preloaded tracks (if they have the same spec), so there is no gap
+                        let mut audio_output = None::<(Box<dyn IsAudioWriter>, cpal::Stream, SignalSpec)>;
+                        let mut trimmer = trim_silence.map(SilenceTrimmer::new);
+                        // time (in seconds) at which to stop playing the track, fading out before that
+                        let mut stop_at = None::<f64>;
+                        // the next track, once it has started fading in
+                        let mut crossfade = None::<Crossfade>;
+                        // A-B repeat of part of the track (start and end, in seconds)
+                        let mut ab_loop = None::<(u64, u64)>;
+                        // if the track stopped because of an error
+                        let mut failed = false;
+                        if let Some(length) = preview.take() {
+                            // (tracks of unknown length are previewed from the start)
+                            let middle = dur.map_or(0, |dur| dur.seconds / 2);
+                            let mut start = middle.saturating_sub(length / 2);
+                            if let Err(e) = decoder.seek(start) {
+                                warn!("Failed to seek to the middle of the track: {e} [previewing from the start]");
+                                start = 0;
+                            }
+                            stop_at = Some((start + length) as f64);
+                        }
+                        let mut start_time = 0;
+                        if start_at != 0 && stop_at.is_none() {
+                            match decoder.seek(start_at) {
+                                Ok(()) => start_time = start_at,
+                                Err(e) => warn!("Failed to seek to where the track should start: {e} [playing from the start]"),
+                            }
+                        }
+                        // (until the first packet is decoded, the time would still be that of the last track played)
+                        time_2.store(start_time, Ordering::Relaxed);
+                        duration_2.store(initial_length(dur), Ordering::Relaxed);
+                        state_2.store(State::Playing as u8, Ordering::SeqCst);
+                        // errors of an output that was already closed do not matter
+                        while stream_err_rx.try_recv().is_ok() {}
+                        'play: loop {
+                            let cmd = match stream_err_rx.try_recv() {
+                                Ok(e) => {
+                                    warn!("Pausing playback, as the audio output failed: {e}");
+                                    // reopened (on the same device) once playback continues
+                                    audio_output = None;
+                                    while stream_err_rx.try_recv().is_ok() {}
+                                    if let Some(call) = on_stream_error.as_ref() {
+                                        (call)(e);
+                                    }
+                                    Ok(PlayTaskCmd::Pause)
                                 }
-                                'paused: loop {
-                                    match rx.recv() {
-                                        Ok(PlayTaskCmd::Pause) => {
-                                            warn!("Received pause command, but audio is already paused")
-                                        }
-                                        Ok(PlayTaskCmd::Play) => {
-                                            if let Some(audio_output) = audio_output.as_mut() {
-                                                let _ = audio_output.1.play();
+                                // (before anything is decoded, so none of it is heard)
+                                Err(_) if std::mem::take(&mut start_paused) => Ok(PlayTaskCmd::Pause),
+                                Err(_) => rx.try_recv(),
+                            };
+                            match cmd {
+                                Ok(PlayTaskCmd::Play) => {
+                                    warn!("Received play command, but audio is already playing")
+                                }
+                                Ok(PlayTaskCmd::Pause) => {
+                                    state_2.store(State::Paused as u8, Ordering::SeqCst);
+                                    if let Some(audio_output) = audio_output.as_mut() {
+                                        let _ = audio_output.1.pause();
+                                    }
+                                    'paused: loop {
+                                        match rx.recv() {
+                                            Ok(PlayTaskCmd::Pause) => {
+                                                warn!("Received pause command, but audio is already paused")
                                             }
-                                            break 'paused;
-                                        }
-                                        Ok(PlayTaskCmd::Stop) => break 'play,
-                                        Ok(PlayTaskCmd::SetOnTrackComplete(call)) => {
-                                            on_track_complete = Some(call)
+                                            Ok(PlayTaskCmd::Play) => {
+                                                if let Some(audio_output) = audio_output.as_mut() {
+                                                    let _ = audio_output.1.play();
+                                                }
+                                                break 'paused;
+                                            }
+                                            Ok(PlayTaskCmd::Stop) => {
+                                                preloaded = None;
+                                                break 'play;
+                                            }
+                                            Ok(PlayTaskCmd::SetOnTrackComplete(call)) => {
+                                                on_track_complete = Some(call)
+                                            }
+                                            Ok(PlayTaskCmd::SetTrimSilence(threshold)) => trim_silence = threshold,
+                                            Ok(PlayTaskCmd::SetPreview(length)) => preview = Some(length),
+                                            Ok(PlayTaskCmd::SetCrossfade(secs)) => crossfade_secs = secs,
+                                            Ok(PlayTaskCmd::SetBuffering(new)) => {
+                                                if new != buffering {
+                                                    buffering = new;
+                                                    // reopened with the new buffering once playback continues
+                                                    audio_output = None;
+                                                }
+                                            }
+                                            Ok(PlayTaskCmd::Seek(secs)) => {
+                                                if let Some(fading) = crossfade.take() {
+                                                    preloaded = fading.cancel();
+                                                }
+                                                match decoder.seek(secs) {
+                                                    // no packets are decoded while paused, so the timestamp is set here
+                                                    Ok(()) => time_2.store(secs, Ordering::Relaxed),
+                                                    Err(e) => warn!("Failed to seek: {e}"),
+                                                }
+                                            }
+                                            Ok(PlayTaskCmd::SetLoop(section)) => {
+                                                if let Some(fading) = crossfade.take_if(|_| section.is_some()) {
+                                                    preloaded = fading.cancel();
+                                                }
+                                                ab_loop = section;
+                                            }
+                                            Ok(PlayTaskCmd::Preload(next)) => {
+                                                crossfade = None;
+                                                preloaded = preload(next);
+                                            }
+                                            Ok(PlayTaskCmd::SetOnPreloadStart(call)) => {
+                                                on_preload_start = Some(call)
+                                            }
+                                            Ok(PlayTaskCmd::SetOnStreamError(call)) => {
+                                                on_stream_error = Some(call)
+                                            }
+                                            Ok(PlayTaskCmd::SetDevice(new_device, new_config)) => {
+                                                device = new_device;
+                                                config = new_config;
+                                                // reopened on the new device once playback continues
+                                                audio_output = None;
+                                            }
+                                            // (the player is stopped before these are sent)
+                                            Ok(got @ (PlayTaskCmd::SetNewSource { .. } | PlayTaskCmd::Start { .. })) => {
+                                                debug!("player ignored a command that can not be handled while paused: {got:?}");
+                                            }
+                                            Err(flume::RecvError::Disconnected) => break 'run,
                                         }
-                                        // player is stopped before this happens
-                                        Ok(PlayTaskCmd::SetNewSource { .. }) => unreachable!(),
-                                        Ok(PlayTaskCmd::Start) => unreachable!(),
-                                        Err(flume::RecvError::Disconnected) => break 'run,
                                     }
+                                    state_2.store(State::Playing as u8, Ordering::SeqCst);
                                 }
-                                state_2.store(State::Playing as u8, Ordering::SeqCst);
-                            }
-                            Ok(PlayTaskCmd::Stop) => {
-                                if let Some(audio_output) = audio_output.as_mut() {
-                                    let _ = audio_output.1.pause();
+                                Ok(PlayTaskCmd::Stop) => {
+                                    if let Some(audio_output) = audio_output.as_mut() {
+                                        let _ = audio_output.1.pause();
+                                    }
+                                    preloaded = None;
+                                    break 'play;
                                 }
-                                break 'play;
-                            }
-                            Ok(PlayTaskCmd::SetOnTrackComplete(call)) => on_track_complete = Some(call),
-                            // player is stopped before this happens
-                            Ok(PlayTaskCmd::SetNewSource { .. }) => unreachable!(),
-                            Ok(PlayTaskCmd::Start) => unreachable!(),
-                            Err(flume::TryRecvError::Empty) => {}
-                            Err(flume::TryRecvError::Disconnected) => {
-                                if let Some(audio_output) = audio_output.as_mut() {
-                                    let _ = audio_output.1.pause();
+                                Ok(PlayTaskCmd::SetOnTrackComplete(call)) => on_track_complete = Some(call),
+                                Ok(PlayTaskCmd::SetTrimSilence(threshold)) => trim_silence = threshold,
+                                Ok(PlayTaskCmd::SetPreview(length)) => preview = Some(length),
+                                Ok(PlayTaskCmd::SetCrossfade(secs)) => crossfade_secs = secs,
+                                Ok(PlayTaskCmd::SetBuffering(new)) => {
+                                    if new != buffering {
+                                        trace!("output buffering changed, reopening audio output");
+                                        buffering = new;
+                                        audio_output = None;
+                                    }
+                                }
+                                Ok(PlayTaskCmd::Seek(secs)) => {
+                                    if let Some(fading) = crossfade.take() {
+                                        preloaded = fading.cancel();
+                                    }
+                                    if let Err(e) = decoder.seek(secs) {
+                                        warn!("Failed to seek: {e}");
+                                    }
+                                }
+                                Ok(PlayTaskCmd::SetLoop(section)) => {
+                                    // the track no longer ends while looping
+                                    if let Some(fading) = crossfade.take_if(|_| section.is_some()) {
+                                        preloaded = fading.cancel();
+                                    }
+                                    ab_loop = section;
+                                }
+                                Ok(PlayTaskCmd::Preload(next)) => {
+                                    crossfade = None;
+                                    preloaded = preload(next);
+                                }
+                                Ok(PlayTaskCmd::SetOnPreloadStart(call)) => on_preload_start = Some(call),
+                                Ok(PlayTaskCmd::SetOnStreamError(call)) => on_stream_error = Some(call),
+                                Ok(PlayTaskCmd::SetDevice(new_device, new_config)) => {
+                                    trace!("audio device changed, reopening audio output");
+                                    device = new_device;
+                                    config = new_config;
+                                    audio_output = None;
+                                }
+                                // (the player is stopped before these are sent)
+                                Ok(got @ (PlayTaskCmd::SetNewSource { .. } | PlayTaskCmd::Start { .. })) => {
+                                    debug!("player ignored a command that can not be handled while playing: {got:?}");
+                                }
+                                Err(flume::TryRecvError::Empty) => {}
+                                Err(flume::TryRecvError::Disconnected) => {
+                                    if let Some(audio_output) = audio_output.as_mut() {
+                                        let _ = audio_output.1.pause();
+                                    }
+                                    break 'run;
                                 }
-                                break 'run;
                             }
-                        }
-                        match decoder.decode_next() {
-                            // call on_track_complete and clean up audio stream
-                            Ok(Decoded::StreamEnd) => break 'play,
-                            Ok(Decoded::Retry) => continue,
-                            Ok(Decoded::Decoded(packet, buffer)) => {
-                                duration_2.store(dur.seconds, std::sync::atomic::Ordering::Relaxed);
-                                time_2.store(tb.calc_time(packet.ts()).seconds, std::sync::atomic::Ordering::Relaxed);
-                                // If the audio output is not open, try to open it.
-                                if audio_output.is_none() {
-                                    // Get the audio buffer specification. This is a description of the decoded
-                                    // audio buffer's sample format and sample rate.
-                                    let spec = *buffer.spec();
-                                    trace!(
-                                        "init audio output... [{:?} {}Hz]",
-                                        spec.channels,
-                                        spec.rate
-                                    );
-
-                                    // Get the capacity of the decoded buffer. Note that this is capacity, not
-                                    // length! The capacity of the decoded buffer is constant for the life of the
-                                    // decoder, but the length is not.
-                                    let _duration = buffer.capacity() as u64;
-
-                                    // Try to open the audio output.
-                                    // Select proper playback routine based on sample format.
-                                    let output = match config.sample_format() {
-                                        cpal::SampleFormat::F32 => open_stream::<f32>(spec, &device)?,
-                                        cpal::SampleFormat::I16 => open_stream::<i16>(spec, &device)?,
-                                        cpal::SampleFormat::U16 => open_stream::<u16>(spec, &device)?,
-                                        sample_format => {
-                                            error!("Unsupported sample format '{sample_format}'");
-                                            bail!("Failed to initialize audio backend");
+                            match decoder.decode_next() {
+                                // (the end of the loop may be past the end of a clip)
+                                Ok(Decoded::StreamEnd) if ab_loop.is_some() => {
+                                    let (start, _) = ab_loop.unwrap();
+                                    if let Err(e) = decoder.seek(start) {
+                                        warn!("Failed to seek back to the start of the loop: {e} [no longer looping]");
+                                        ab_loop = None;
+                                    }
+                                }
+                                Ok(Decoded::StreamEnd) => {
+                                    let next = match crossfade.take() {
+                                        // the next track has already started, and continues from where it is
+                                        Some(mut fading) => {
+                                            if let Some(audio_output) = audio_output.as_mut() {
+                                                if let Err(e) = audio_output.0.write_samples(fading.pending.make_contiguous()) {
+                                                    let _ = stream_err_tx.send(e.to_string());
+                                                }
+                                            }
+                                            trimmer = fading.trimmer;
+                                            fading.decoder
+                                        }
+                                        None => {
+                                            let Some(next) = preloaded.take() else {
+                                                // call on_track_complete and clean up audio stream
+                                                break 'play;
+                                            };
+                                            trimmer = trim_silence.map(SilenceTrimmer::new);
+                                            next
                                         }
                                     };
-                                    audio_output.replace(output);
-                                    if let Some(audio_output) = audio_output.as_mut() {
-                                        audio_output.0.write(buffer)?;
-                                        // Start the output stream.
-                                        if let Err(err) = audio_output.1.play() {
-                                            error!("audio output stream play error: {}", err);
-                                            bail!("failed to initialize audio backend");
+                                    // continue straight into the preloaded track
+                                    decoder = next;
+                                    ab_loop = None;
+                                    tb = decoder.time_base;
+                                    dur = decoder.duration();
+                                    stop_at = None;
+                                    // the time of the previous track must not be seen as the time of this one
+                                    time_2.store(0, Ordering::Relaxed);
+                                    duration_2.store(initial_length(dur), Ordering::Relaxed);
+                                    if let Some(call) = on_preload_start.as_ref() {
+                                        (call)();
+                                    }
+                                }
+                                Ok(Decoded::Retry) => continue,
+                                Ok(Decoded::Decoded(packet, buffer)) => {
+                                    let time = tb.calc_time(packet.ts());
+                                    if let Some((start, _)) = ab_loop.filter(|&(_, end)| time.seconds >= end) {
+                                        // (this packet is past the end of the loop either way)
+                                        if let Err(e) = decoder.seek(start) {
+                                            warn!("Failed to seek back to the start of the loop: {e} [no longer looping]");
+                                            ab_loop = None;
                                         }
+                                        continue 'play;
                                     }
-                                } else {
-                                    // TODO: Check the audio spec. and duration hasn't changed.
-                                    if let Some(audio_output) = audio_output.as_mut() {
-                                        audio_output.0.write(buffer)?
+                                    let (fade, remaining) = match dur {
+                                        Some(dur) => {
+                                            duration_2.store(Length::Known(dur.seconds).to_bits(), Ordering::Relaxed);
+                                            // (the crossfade is at most half as long as the track)
+                                            let fade = crossfade_secs.min(dur.seconds as f64 / 2.0);
+                                            (fade, (dur.seconds as f64 + dur.frac) - (time.seconds as f64 + time.frac))
+                                        }
+                                        // the end is not known, so it can not be crossfaded from
+                                        None => {
+                                            duration_2.fetch_max(Length::AtLeast(time.seconds).to_bits(), Ordering::Relaxed);
+                                            (0.0, f64::INFINITY)
+                                        }
+                                    };
+                                    time_2.store(time.seconds, std::sync::atomic::Ordering::Relaxed);
+                                    if crossfade.is_none() && stop_at.is_none() && ab_loop.is_none() && fade > 0.0 && remaining <= fade {
+                                        // (tracks with a different spec are played one after the other)
+                                        if let Some(next) = preloaded.take_if(|next| next.can_mix(*buffer.spec())) {
+                                            crossfade = Some(Crossfade::new(next, trim_silence));
+                                        }
+                                    }
+                                    let gain = match stop_at {
+                                        Some(stop_at) => {
+                                            let remaining = stop_at - (time.seconds as f64 + time.frac);
+                                            if remaining <= 0.0 {
+                                                break 'play;
+                                            }
+                                            (remaining / PREVIEW_FADE).min(1.0) as f32
+                                        }
+                                        None if crossfade.is_some() => (remaining / fade).clamp(0.0, 1.0) as f32,
+                                        None => 1.0,
+                                    };
+                                    let trimmed;
+                                    let buffers = match trimmer.as_mut() {
+                                        Some(trimmer) => {
+                                            trimmed = trimmer.process(buffer);
+                                            trimmed.iter().map(|b| b.as_audio_buffer_ref()).collect()
+                                        }
+                                        None => vec![buffer],
+                                    };
+                                    let mut crossfade_failed = false;
+                                    for buffer in buffers {
+                                        let mix = match crossfade.as_mut() {
+                                            Some(fading) => match fading.fill(buffer.frames() * buffer.spec().channels.count(), 1.0 - gain) {
+                                                Ok(()) => Some(&mut fading.pending),
+                                                Err(e) => {
+                                                    // the next track is started normally once this one ends (and the error reported then, if it fails again)
+                                                    warn!("Failed to crossfade into the next track: {e}");
+                                                    crossfade_failed = true;
+                                                    None
+                                                }
+                                            },
+                                            None => None,
+                                        };
+                                        // a preloaded track may need a different output stream
+                                        if audio_output.as_ref().is_some_and(|output| output.2 != *buffer.spec()) {
+                                            trace!("audio spec changed, reopening audio output");
+                                            audio_output = None;
+                                        }
+                                        // If the audio output is not open, try to open it.
+                                        if audio_output.is_none() {
+                                            // Get the audio buffer specification. This is a description of the decoded
+                                            // audio buffer's sample format and sample rate.
+                                            let spec = *buffer.spec();
+                                            trace!(
+                                                "init audio output... [{:?} {}Hz]",
+                                                spec.channels,
+                                                spec.rate
+                                            );
+
+                                            // Get the capacity of the decoded buffer. Note that this is capacity, not
+                                            // length! The capacity of the decoded buffer is constant for the life of the
+                                            // decoder, but the length is not.
+                                            let _duration = buffer.capacity() as u64;
+
+                                            // Try to open the audio output.
+                                            // Select proper playback routine based on sample format.
+                                            let open = match config.sample_format() {
+                                                cpal::SampleFormat::I8 => open_stream::<i8>,
+                                                cpal::SampleFormat::I16 => open_stream::<i16>,
+                                                cpal::SampleFormat::I32 => open_stream::<i32>,
+                                                cpal::SampleFormat::I64 => open_stream::<i64>,
+                                                cpal::SampleFormat::U8 => open_stream::<u8>,
+                                                cpal::SampleFormat::U16 => open_stream::<u16>,
+                                                cpal::SampleFormat::U32 => open_stream::<u32>,
+                                                cpal::SampleFormat::U64 => open_stream::<u64>,
+                                                cpal::SampleFormat::F32 => open_stream::<f32>,
+                                                cpal::SampleFormat::F64 => open_stream::<f64>,
+                                                sample_format => {
+                                                    error!("Unsupported sample format '{sample_format}'");
+                                                    bail!("Failed to initialize audio backend");
+                                                }
+                                            };
+                                            let opened = open(spec, &device, &config, preamp_2.clone(), levels_2.clone(), buffering, stream_err_tx.clone());
+                                            // (the device may not be back yet, after it failed)
+                                            let (writer, stream) = match opened {
+                                                Ok(output) => output,
+                                                Err(e) => {
+                                                    let _ = stream_err_tx.send(e.to_string());
+                                                    continue 'play;
+                                                }
+                                            };
+                                            audio_output.replace((writer, stream, spec));
+                                            if let Some(audio_output) = audio_output.as_mut() {
+                                                if let Err(e) = audio_output.0.write(buffer, gain, mix) {
+                                                    let _ = stream_err_tx.send(e.to_string());
+                                                    continue 'play;
+                                                }
+                                                // Start the output stream.
+                                                if let Err(err) = audio_output.1.play() {
+                                                    error!("audio output stream play error: {}", err);
+                                                    let _ = stream_err_tx.send(err.to_string());
+                                                    continue 'play;
+                                                }
+                                            }
+                                        } else {
+                                            if let Some(audio_output) = audio_output.as_mut() {
+                                                if let Err(e) = audio_output.0.write(buffer, gain, mix) {
+                                                    let _ = stream_err_tx.send(e.to_string());
+                                                    continue 'play;
+                                                }
+                                            }
+                                        }
+                                    }
+                                    if crossfade_failed {
+                                        crossfade = None;
                                     }
                                 }
-                            }
-                            Err(error) => {
-                                // report error and clean up audio stream
-                                if let Some(audio_output) = audio_output.as_mut() {
-                                    let _ = audio_output.1.pause();
+                                Err(error) => {
+                                    // report error and clean up audio stream (the track did not complete, so it is up to
+                                    // whoever gets the error to move on)
+                                    if let Some(audio_output) = audio_output.as_mut() {
+                                        let _ = audio_output.1.pause();
+                                    }
+                                    // (stopped before it is reported, so the track can be skipped right away)
+                                    state_2.store(State::Stopped as u8, Ordering::SeqCst);
+                                    let _ = error_tx.send(format!("failed to decode the track: {error}"));
+                                    failed = true;
+                                    break 'play;
                                 }
-                                Err(error)?
                             }
                         }
+                        state_2.store(State::Stopped as u8, Ordering::SeqCst);
+                        // flush audio stream
+                        if let Some(audio_output) = audio_output.as_mut() {
+                            let _ = audio_output.1.pause();
+                        }
+                        if let Some(call) = on_track_complete.as_ref().filter(|_| !failed) {
+                            (call)();
+                        }
                     }
                     state_2.store(State::Stopped as u8, Ordering::SeqCst);
-                    // flush audio stream
-                    if let Some(audio_output) = audio_output.as_mut() {
-                        let _ = audio_output.1.pause();
-                    }
-                    if let Some(call) = on_track_complete.as_ref() {
-                        (call)();
-                    }
+                    Ok::<_, Report>(())
+                })();
+                if let Err(e) = &result {
+                    error!("The audio decode thread stopped: {e}");
+                    state_3.store(State::Stopped as u8, Ordering::SeqCst);
+                    let _ = fatal_error_tx.send(format!("the player stopped working: {e}"));
                 }
-                state_2.store(State::Stopped as u8, Ordering::SeqCst);
-                Ok::<_, Report>(())
+                result
             })?;
 
         Ok(Self {
@@ -462,17 +1373,71 @@ impl SingleTrackPlayer {
             tx,
             duration,
             time,
+            preamp,
+            gain: (0.0, 100),
+            levels,
+            errors,
         })
     }
 
-    pub fn duration(&mut self) -> u64 {
-        self.duration.load(std::sync::atomic::Ordering::Relaxed)
+    /// Switches output to `device`. A track that is playing continues where it was on the new device
+    pub fn set_device(
+        &mut self,
+        device: Arc<cpal::Device>,
+        config: Arc<SupportedStreamConfig>,
+    ) -> Result<()> {
+        self.tx.try_send(PlayTaskCmd::SetDevice(device, config))?;
+        Ok(())
+    }
+
+    /// Sets the pre-amp gain (in dB), applied to all audio before it is output
+    pub fn set_preamp(&mut self, db: f32) {
+        self.gain.0 = db;
+        self.store_gain();
+    }
+
+    /// Sets the volume (in percent, up to 100), applied along with the pre-amp
+    pub fn set_volume(&mut self, percent: u8) {
+        self.gain.1 = percent.min(100);
+        self.store_gain();
+    }
+
+    fn store_gain(&self) {
+        let (db, percent) = self.gain;
+        let gain = 10f32.powf(db / 20.0) * f32::from(percent) / 100.0;
+        self.preamp.store(gain.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Peak and RMS levels of what is being played, see [`Levels::take`]
+    pub fn levels(&self) -> LevelReading {
+        self.levels.take()
+    }
+
+    /// Number of samples that were too loud for the output (with the pre-amp applied) since this was last called
+    pub fn clipped(&self) -> u64 {
+        self.levels.take_clipped()
+    }
+
+    pub fn length(&self) -> Length {
+        Length::from_bits(self.duration.load(Ordering::Relaxed))
     }
 
     pub fn timestamp(&mut self) -> u64 {
         self.time.load(std::sync::atomic::Ordering::Relaxed)
     }
 
+    /// The most recent error that stopped a track from playing (such as an unsupported codec, or a broken file),
+    /// if there has been one since this was last called. The track does not complete, so nothing else plays until
+    /// another track is set. If the player itself stopped working, this says so, and commands after that do nothing
+    pub fn last_error(&mut self) -> Option<String> {
+        self.errors.try_iter().last()
+    }
+
+    /// If the player still works (see [`Self::last_error`])
+    pub fn running(&self) -> bool {
+        !self.tx.is_disconnected()
+    }
+
     pub fn state(&self) -> State {
         self.state
             .load(std::sync::atomic::Ordering::SeqCst)
@@ -480,14 +1445,98 @@ impl SingleTrackPlayer {
             .unwrap()
     }
 
-    pub fn set_track(&mut self, track_src: File, filetype: String) -> Result<()> {
+    /// Sets the track to play, only playing `clip` of it
+    pub fn set_track(&mut self, track_src: File, filetype: String, clip: Clip) -> Result<()> {
         self.tx.try_send(PlayTaskCmd::SetNewSource {
             track_src,
             filetype,
+            clip,
         })?;
         Ok(())
     }
 
+    /// Skip silence (below `threshold` dBFS) at the start and end of tracks, or `None` to play tracks in full.
+    ///
+    /// This takes effect from the next track that is started
+    pub fn trim_silence(&mut self, threshold: Option<f32>) -> Result<()> {
+        self.tx.try_send(PlayTaskCmd::SetTrimSilence(threshold))?;
+        Ok(())
+    }
+
+    /// Only play `length` seconds from the middle of the next track that is started (fading out at the end).
+    pub fn preview(&mut self, length: u64) -> Result<()> {
+        self.tx.try_send(PlayTaskCmd::SetPreview(length))?;
+        Ok(())
+    }
+
+    /// Fade the end of each track into the start of the preloaded one over `secs` seconds (0 to not crossfade).
+    ///
+    /// Only tracks preloaded with [`Self::preload`] are crossfaded into
+    pub fn crossfade(&mut self, secs: f32) -> Result<()> {
+        self.tx
+            .try_send(PlayTaskCmd::SetCrossfade(secs.max(0.0) as f64))?;
+        Ok(())
+    }
+
+    /// Sets how much audio is buffered for the output device. If a track is playing, the output is reopened with it
+    pub fn set_buffering(&mut self, buffering: Buffering) -> Result<()> {
+        self.tx.try_send(PlayTaskCmd::SetBuffering(buffering))?;
+        Ok(())
+    }
+
+    /// Starts the track set with [`Self::set_track`] from `secs` seconds in, and paused right away with `paused` (so no
+    /// audio from the start of the track is played). Like [`Self::play`], this does nothing if a track is already playing
+    pub fn start_at(&mut self, secs: u64, paused: bool) -> Result<()> {
+        if let State::Stopped = self.state() {
+            self.tx.try_send(PlayTaskCmd::Start { at: secs, paused })?;
+        }
+        Ok(())
+    }
+
+    /// Seeks to `secs` seconds into the current track
+    pub fn seek(&mut self, secs: u64) -> Result<()> {
+        self.tx.try_send(PlayTaskCmd::Seek(secs))?;
+        Ok(())
+    }
+
+    /// Repeat the part of the current track from `start` to `end` (in seconds), going back to `start`
+    /// whenever `end` is reached, or stop repeating with `None`. This is reset when another track starts
+    pub fn set_loop(&mut self, section: Option<(u64, u64)>) -> Result<()> {
+        self.tx.try_send(PlayTaskCmd::SetLoop(section))?;
+        Ok(())
+    }
+
+    /// Prepare `track_src` to be played as soon as the current track ends, without a gap between them.
+    ///
+    /// If the preloaded track is started, the callback set with [`Self::on_preload_start`] is called
+    /// instead of the one set with [`Self::on_track_complete`]. The preload is discarded if playback
+    /// is stopped, or a new track is set
+    pub fn preload(&mut self, track_src: File, filetype: String, clip: Clip) -> Result<()> {
+        self.tx
+            .try_send(PlayTaskCmd::Preload(Some((track_src, filetype, clip))))?;
+        Ok(())
+    }
+
+    /// Discards the preloaded track (if any), so playback stops when the current track ends
+    pub fn cancel_preload(&mut self) -> Result<()> {
+        self.tx.try_send(PlayTaskCmd::Preload(None))?;
+        Ok(())
+    }
+
+    pub fn on_preload_start(&mut self, call: impl Fn() + Send + Sync + 'static) -> Result<()> {
+        self.tx
+            .try_send(PlayTaskCmd::SetOnPreloadStart(Box::new(call)))?;
+        Ok(())
+    }
+
+    /// Called with the error when the audio output fails while playing (for example, when the device is disconnected).
+    /// Playback is paused, and the output is opened again when it continues
+    pub fn on_stream_error(&mut self, call: impl Fn(String) + Send + Sync + 'static) -> Result<()> {
+        self.tx
+            .try_send(PlayTaskCmd::SetOnStreamError(Box::new(call)))?;
+        Ok(())
+    }
+
     pub fn on_track_complete(&mut self, call: impl Fn() + Send + Sync + 'static) -> Result<()> {
         self.tx
             .try_send(PlayTaskCmd::SetOnTrackComplete(Box::new(call)))?;
@@ -506,7 +1555,10 @@ impl SingleTrackPlayer {
             self.tx.try_send(PlayTaskCmd::Play)?;
         }
         if let State::Stopped = self.state() {
-            self.tx.try_send(PlayTaskCmd::Start)?;
+            self.tx.try_send(PlayTaskCmd::Start {
+                at: 0,
+                paused: false,
+            })?;
         }
         Ok(())
     }
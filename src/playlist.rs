@@ -0,0 +1,308 @@
+//! Handling of `dmm playlist`
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+};
+
+use color_eyre::eyre::{anyhow, Result};
+use crossterm::style::Stylize;
+use serde::Serialize;
+
+use crate::{
+    cache::Hash,
+    output::{self, Format},
+    resolver::Resolver,
+    schema::{Meta, Playlist},
+};
+
+#[derive(Debug, Clone, Serialize)]
+struct DiffEntry {
+    hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    meta: Option<Meta>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct MetaChange {
+    hash: String,
+    left: Meta,
+    right: Meta,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Diff {
+    left: String,
+    right: String,
+    only_left: Vec<DiffEntry>,
+    only_right: Vec<DiffEntry>,
+    shared: Vec<DiffEntry>,
+    changed: Vec<MetaChange>,
+}
+
+/// Maps each track in the playlist to its hash in the store.
+/// If a track appears more than once, the first occurence wins.
+pub fn track_hashes(playlist: &Playlist) -> Result<BTreeMap<Hash, Meta>> {
+    let mut tracks = BTreeMap::new();
+    for track in &playlist.tracks {
+        let source = playlist.find_source(&track.src).ok_or(anyhow!(
+            "Could not find source {} for track {}",
+            track.src,
+            track.meta.name
+        ))?;
+        tracks
+            .entry(Hash::track(source, track))
+            .or_insert_with(|| track.meta.clone());
+    }
+    Ok(tracks)
+}
+
+/// Compares playlist `left` against playlist `right`, or the download store if `right` is `None`
+pub fn diff(
+    res: &Resolver,
+    left: &Playlist,
+    right: Option<&Playlist>,
+    format: Format,
+) -> Result<()> {
+    let left_tracks = track_hashes(left)?;
+    let right_tracks = match right {
+        Some(right) => track_hashes(right)?
+            .into_iter()
+            .map(|(hash, meta)| (hash, Some(meta)))
+            .collect::<BTreeMap<_, _>>(),
+        None => res
+            .out()
+            .cache
+            .list()?
+            .into_iter()
+            .map(|hash| (hash, None))
+            .collect(),
+    };
+
+    let mut diff = Diff {
+        left: left.name.clone(),
+        right: right.map_or_else(|| "<store>".to_string(), |pl| pl.name.clone()),
+        only_left: vec![],
+        only_right: vec![],
+        shared: vec![],
+        changed: vec![],
+    };
+    let all = left_tracks
+        .keys()
+        .chain(right_tracks.keys())
+        .collect::<BTreeSet<_>>();
+    for hash in all {
+        match (left_tracks.get(hash), right_tracks.get(hash)) {
+            (Some(l), Some(r)) => {
+                if let Some(r) = r.as_ref().filter(|r| *r != l) {
+                    diff.changed.push(MetaChange {
+                        hash: hash.to_string(),
+                        left: l.clone(),
+                        right: r.clone(),
+                    });
+                }
+                diff.shared.push(DiffEntry {
+                    hash: hash.to_string(),
+                    meta: Some(l.clone()),
+                });
+            }
+            (Some(l), None) => diff.only_left.push(DiffEntry {
+                hash: hash.to_string(),
+                meta: Some(l.clone()),
+            }),
+            (None, Some(r)) => diff.only_right.push(DiffEntry {
+                hash: hash.to_string(),
+                meta: r.clone(),
+            }),
+            (None, None) => unreachable!(),
+        }
+    }
+
+    match format {
+        Format::Json => output::print_json(&diff)?,
+        Format::Text => print_diff(&diff),
+    }
+    Ok(())
+}
+
+fn print_diff(diff: &Diff) {
+    let describe = |entry: &DiffEntry| match &entry.meta {
+        Some(meta) => format!("{} - {} ({})", meta.artist, meta.name, entry.hash),
+        None => entry.hash.clone(),
+    };
+    println!("only in {:?} ({}):", diff.left, diff.only_left.len());
+    for entry in &diff.only_left {
+        println!("  - {}", describe(entry));
+    }
+    println!("only in {:?} ({}):", diff.right, diff.only_right.len());
+    for entry in &diff.only_right {
+        println!("  + {}", describe(entry));
+    }
+    println!("shared ({}):", diff.shared.len());
+    for entry in &diff.shared {
+        println!("  = {}", describe(entry));
+    }
+    if !diff.changed.is_empty() {
+        println!("metadata differences ({}):", diff.changed.len());
+        for change in &diff.changed {
+            println!(
+                "  ~ {}: {:?} by {:?} -> {:?} by {:?}",
+                change.hash,
+                change.left.name,
+                change.left.artist,
+                change.right.name,
+                change.right.artist
+            );
+        }
+    }
+}
+
+/// A track of a playlist, and whether it is downloaded
+#[derive(Debug, Clone, Serialize)]
+struct TrackStatus {
+    meta: Meta,
+    source: String,
+    hash: String,
+    downloaded: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    disabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct PlaylistStatus {
+    name: String,
+    path: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    group: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    archived: bool,
+    track_count: usize,
+    downloaded: usize,
+    /// only included by `dmm playlist show`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tracks: Option<Vec<TrackStatus>>,
+}
+
+impl PlaylistStatus {
+    fn new(res: &Resolver, playlist: &Playlist, with_tracks: bool) -> Result<Self> {
+        let mut tracks = vec![];
+        for track in &playlist.tracks {
+            let source = playlist.find_source(&track.src).ok_or(anyhow!(
+                "Could not find source {} for track {}",
+                track.src,
+                track.meta.name
+            ))?;
+            let hash = Hash::track(source, track);
+            tracks.push(TrackStatus {
+                meta: track.meta.clone(),
+                source: track.src.clone(),
+                hash: hash.to_string(),
+                downloaded: res.out().cache.find(hash).is_some(),
+                tags: track.tags.clone(),
+                disabled: track.disabled,
+            });
+        }
+        Ok(Self {
+            name: playlist.name.clone(),
+            path: playlist.file_path.clone(),
+            group: playlist.group.clone(),
+            tags: playlist.tags.clone(),
+            archived: playlist.archived,
+            // (disabled tracks are not downloaded, so they are left out of the counts)
+            track_count: tracks.iter().filter(|track| !track.disabled).count(),
+            downloaded: tracks
+                .iter()
+                .filter(|track| track.downloaded && !track.disabled)
+                .count(),
+            tracks: with_tracks.then_some(tracks),
+        })
+    }
+
+    /// Name, file, and download count, on one line
+    fn summary(&self, res: &Resolver) -> String {
+        let path = self
+            .path
+            .strip_prefix(&res.dirs().playlists)
+            .unwrap_or(&self.path);
+        let mut line = format!(
+            "{} ({}): {} track(s), {} downloaded",
+            self.name.clone().bold(),
+            path.display(),
+            self.track_count,
+            self.downloaded
+        );
+        if let Some(group) = &self.group {
+            line += &format!(", group {group:?}");
+        }
+        if !self.tags.is_empty() {
+            line += &format!(", tagged {}", self.tags.join(", "));
+        }
+        if self.archived {
+            line += ", archived";
+        }
+        line
+    }
+}
+
+/// Lists every playlist, with its number of tracks, and how many of them are downloaded
+pub fn list(res: &Resolver, format: Format) -> Result<()> {
+    let playlists = res
+        .out()
+        .playlists
+        .iter()
+        .map(|pl| PlaylistStatus::new(res, pl, false))
+        .collect::<Result<Vec<_>>>()?;
+    match format {
+        Format::Json => output::print_json(&playlists)?,
+        Format::Text => {
+            for playlist in &playlists {
+                let mark = if playlist.downloaded == playlist.track_count {
+                    "✓".green()
+                } else {
+                    "✗".red()
+                };
+                println!("{mark} {}", playlist.summary(res));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Lists the tracks of `playlist`, and whether each of them is downloaded
+pub fn show(res: &Resolver, playlist: &Playlist, format: Format) -> Result<()> {
+    let status = PlaylistStatus::new(res, playlist, true)?;
+    match format {
+        Format::Json => output::print_json(&status)?,
+        Format::Text => {
+            println!("{}", status.summary(res));
+            let tracks = status.tracks.as_deref().unwrap_or_default();
+            let width = tracks.len().to_string().len();
+            for (i, track) in tracks.iter().enumerate() {
+                let mark = if track.downloaded {
+                    "✓".green()
+                } else {
+                    "✗".red()
+                };
+                let mut line = format!(
+                    "{mark} {:0>width$}: {} - {} [{}]",
+                    i + 1,
+                    track.meta.artist,
+                    track.meta.name,
+                    track.source
+                );
+                if !track.tags.is_empty() {
+                    line += &format!(" (tagged {})", track.tags.join(", "));
+                }
+                if track.disabled {
+                    line += " (disabled)";
+                }
+                println!("{line}");
+            }
+        }
+    }
+    Ok(())
+}
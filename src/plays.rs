@@ -0,0 +1,155 @@
+//! Listening history, for `dmm history` and `dmm stats`
+//!
+//! The player appends a line to `run/plays.log` every time a track stops playing (because it ended, another one was
+//! picked, or the player exited): when it started (in seconds since the unix epoch), the store hash of the track,
+//! how many seconds of it were listened to, and the playlist it was played from.
+//! The file is read as a whole for play counts, so it is never rewritten
+
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::Result;
+
+use crate::{cache::Hash, resolver::Resolver};
+
+/// Name of the listening history (in the `run` directory)
+const PLAYS_FILE: &str = "plays.log";
+
+/// A playback, as recorded in the history
+#[derive(Debug, Clone)]
+pub struct Play {
+    /// when playback started (seconds since the unix epoch)
+    pub time: u64,
+    pub hash: Hash,
+    /// how far into the track it was played (seconds). Unknown for plays recorded by older versions of DMM
+    pub listened: Option<u64>,
+    /// the playlist it was played from. Unknown for plays recorded by older versions of DMM
+    pub playlist: Option<String>,
+}
+
+/// The track that is playing. It is added to the history once it stops, or when this is dropped
+pub struct Listening {
+    path: PathBuf,
+    play: Play,
+}
+
+impl Listening {
+    pub fn start(res: &Resolver, hash: Hash, playlist: String) -> Self {
+        Self {
+            path: res.dirs().run.join(PLAYS_FILE),
+            play: Play {
+                time: now(),
+                hash,
+                listened: Some(0),
+                playlist: Some(playlist),
+            },
+        }
+    }
+
+    /// Updates how far into the track it was played (with the current position, in seconds)
+    pub fn update(&mut self, position: u64) {
+        let listened = self.play.listened.get_or_insert(0);
+        *listened = (*listened).max(position);
+    }
+
+    fn write(&self) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let Play {
+            time,
+            hash,
+            listened,
+            playlist,
+        } = &self.play;
+        let (listened, playlist) = (listened.unwrap_or(0), playlist.as_deref().unwrap_or(""));
+        // written at once, so lines of players running at the same time are not mixed up
+        file.write_all(format!("{time} {hash} {listened} {playlist}\n").as_bytes())?;
+        Ok(())
+    }
+}
+
+impl Drop for Listening {
+    fn drop(&mut self) {
+        if let Err(e) = self.write() {
+            warn!("Failed to add the track to the listening history: {e}");
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+/// Parses a line of the history (`<time> <hash> [<listened> <playlist>]`)
+fn parse_line(line: &str) -> Option<Play> {
+    let mut fields = line.splitn(4, ' ');
+    let time = fields.next()?.parse().ok()?;
+    let hash = fields.next()?.parse().ok()?;
+    let listened = match fields.next() {
+        Some(listened) => Some(listened.parse().ok()?),
+        None => None,
+    };
+    let playlist = fields.next().map(str::to_string);
+    Some(Play {
+        time,
+        hash,
+        listened,
+        playlist,
+    })
+}
+
+/// Every recorded playback, oldest first
+pub fn read(res: &Resolver) -> Result<Vec<Play>> {
+    let path = res.dirs().run.join(PLAYS_FILE);
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+    let mut plays = vec![];
+    for (i, line) in text.lines().enumerate() {
+        match parse_line(line) {
+            Some(play) => plays.push(play),
+            None => warn!("{path:?}:{}: ignoring invalid line {line:?}", i + 1),
+        }
+    }
+    Ok(plays)
+}
+
+/// How many times each track has been played
+pub fn counts(res: &Resolver) -> Result<HashMap<Hash, usize>> {
+    let mut counts = HashMap::new();
+    for play in read(res)? {
+        *counts.entry(play.hash).or_default() += 1;
+    }
+    Ok(counts)
+}
+
+/// `YYYY-MM-DD HH:MM` (in UTC) for a time in seconds since the unix epoch
+pub fn format_time(time: u64) -> String {
+    let (days, secs) = (time / 86400, time % 86400);
+    // days to a (proleptic gregorian) date, from http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}",
+        secs / 3600,
+        secs % 3600 / 60
+    )
+}
@@ -4,14 +4,14 @@ use const_cmp::const_eq;
 use lazy_static::lazy_static;
 use static_assertions::const_assert;
 
-pub static GIT_COMMIT_HASH: &'static str = env!("_GIT_INFO");
-pub static PROJECT_NAME: &'static str = clap::crate_name!();
+pub static GIT_COMMIT_HASH: &str = env!("_GIT_INFO");
+pub static PROJECT_NAME: &str = clap::crate_name!();
 // in git_commit_hash
-// pub static PROJECT_VERSION: &'static str = clap::crate_version!();
-pub static PROJECT_AUTHORS: &'static str = clap::crate_authors!();
+// pub static PROJECT_VERSION: &str = clap::crate_version!();
+pub static PROJECT_AUTHORS: &str = clap::crate_authors!();
 
 /// Codename associated with the current project version
-pub static PROJECT_VERSION_CODENAME: &'static str = "finally";
+pub static PROJECT_VERSION_CODENAME: &str = "finally";
 const_assert!(const_eq!(clap::crate_version!(), "1.0.0"));
 
 lazy_static! {
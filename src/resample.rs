@@ -0,0 +1,59 @@
+//! Sample rate conversion, for output devices that do not support the sample rate of a track
+//!
+//! Samples are interpolated with a cubic (Catmull-Rom) curve through the surrounding four frames, which is cheap
+//! enough to run on the decoding thread and sounds clean for the common conversions (44.1kHz <-> 48kHz).
+
+/// Converts interleaved audio from one sample rate to another, one buffer at a time
+pub struct Resampler {
+    channels: usize,
+    /// input frames per output frame
+    step: f64,
+    /// position of the next output frame, in frames of `pending`
+    pos: f64,
+    /// interleaved input that is still needed to interpolate the next output frames
+    pending: Vec<f32>,
+}
+
+impl Resampler {
+    pub fn new(channels: usize, from_rate: u32, to_rate: u32) -> Self {
+        Self {
+            channels,
+            step: from_rate as f64 / to_rate as f64,
+            pos: 1.0,
+            // a frame of silence before the first frame, which the interpolation of the first frames needs
+            pending: vec![0.0; channels],
+        }
+    }
+
+    /// Resamples `input`, replacing the contents of `output` with the result.
+    ///
+    /// The last few frames of the input are held back until the next call, as the frames after them are needed to interpolate them
+    pub fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        output.clear();
+        self.pending.extend_from_slice(input);
+        let channels = self.channels;
+        let frames = self.pending.len() / channels;
+        // each output frame needs one input frame before it, and two after it
+        while (self.pos as usize) + 2 < frames {
+            let i = self.pos as usize;
+            let t = (self.pos - i as f64) as f32;
+            for c in 0..channels {
+                let p = |frame: usize| self.pending[frame * channels + c];
+                output.push(catmull_rom(p(i - 1), p(i), p(i + 1), p(i + 2), t));
+            }
+            self.pos += self.step;
+        }
+        // drop the frames that are no longer needed
+        let used = (self.pos as usize).saturating_sub(1).min(frames);
+        self.pending.drain(..used * channels);
+        self.pos -= used as f64;
+    }
+}
+
+/// Interpolates between `p1` (at `t` = 0) and `p2` (at `t` = 1)
+fn catmull_rom(p0: f32, p1: f32, p2: f32, p3: f32, t: f32) -> f32 {
+    let a = -0.5 * p0 + 1.5 * p1 - 1.5 * p2 + 0.5 * p3;
+    let b = p0 - 2.5 * p1 + 2.0 * p2 - 0.5 * p3;
+    let c = -0.5 * p0 + 0.5 * p2;
+    ((a * t + b) * t + c) * t + p1
+}
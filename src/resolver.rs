@@ -1,16 +1,24 @@
 use std::{
+    collections::{HashMap, HashSet},
     fs,
     path::{Path, PathBuf},
+    time::SystemTime,
 };
 
 use color_eyre::eyre::{anyhow, Result};
+use serde::de::DeserializeOwned;
+use tracing::{info, warn};
 
 use crate::{
-    cache::CacheDir,
+    cache::{CacheDir, Hash},
     cfg::Config,
-    schema::{self, Playlist, Source},
+    plays,
+    schema::{self, Album, Import, Playlist, SmartPlaylist, Source, Track},
 };
 
+/// Name of the playlist added by [`Resolver::add_all_tracks`]
+pub const ALL_TRACKS: &str = "All tracks";
+
 struct State {
     pub resolved: bool,
 }
@@ -20,7 +28,58 @@ pub struct Output {
     pub config: Config,
     pub sources: Vec<Source>,
     pub playlists: Vec<Playlist>,
+    /// smart playlists, which the player adds to `playlists` (see [`Resolver::add_smart_playlists`])
+    pub smart_playlists: Vec<SmartPlaylist>,
+    /// albums, whose tracks are already added to the playlists importing them
+    pub albums: Vec<Album>,
     pub cache: CacheDir,
+    /// Source files that failed to load (these are left out of `sources`)
+    pub failed_sources: Vec<String>,
+    /// Playlist files (and smart playlist files) that failed to load (these are left out of `playlists`)
+    pub failed_playlists: Vec<String>,
+    /// Album files that failed to load (these are left out of `albums`, and playlists importing them fail to load)
+    pub failed_albums: Vec<String>,
+    /// Where each track is in the playlist files, by its store hash: (playlist, track) indices into `playlists`, in order.
+    /// Tracks whose source can not be found are left out, and the player's virtual playlists are not included
+    pub track_index: HashMap<Hash, Vec<(usize, usize)>>,
+}
+
+impl Output {
+    /// Says how many files failed to load (if any did), e.g. "3 playlists failed to load"
+    pub fn load_summary(&self) -> Option<String> {
+        let count = |n: usize, what: &str| match n {
+            0 => None,
+            1 => Some(format!("1 {what}")),
+            n => Some(format!("{n} {what}s")),
+        };
+        let failed = [
+            count(self.failed_playlists.len(), "playlist"),
+            count(self.failed_sources.len(), "source"),
+            count(self.failed_albums.len(), "album"),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+        if failed.is_empty() {
+            return None;
+        }
+        Some(format!("{} failed to load", failed.join(" and ")))
+    }
+
+    /// The first track with this store hash, and the playlist it is in
+    pub fn find_track(&self, hash: Hash) -> Option<(&Track, &Playlist)> {
+        let &(playlist, track) = self.track_index.get(&hash)?.first()?;
+        let playlist = &self.playlists[playlist];
+        Some((&playlist.tracks[track], playlist))
+    }
+
+    /// How many playlists a track with this store hash is in (0 if none)
+    pub fn playlists_with(&self, hash: Hash) -> usize {
+        self.track_index.get(&hash).map_or(0, |places| {
+            // (in order, so each playlist's tracks are next to each other)
+            places.chunk_by(|a, b| a.0 == b.0).count()
+        })
+    }
 }
 
 pub struct Directories {
@@ -28,6 +87,10 @@ pub struct Directories {
     pub run: PathBuf,
     pub sources: PathBuf,
     pub playlists: PathBuf,
+    /// smart playlists (optional)
+    pub smart: PathBuf,
+    /// albums (optional)
+    pub albums: PathBuf,
     pub cache: PathBuf,
 }
 
@@ -39,11 +102,115 @@ impl Directories {
             run: subpath("run"),
             sources: subpath("sources"),
             playlists: subpath("playlists"),
+            smart: subpath("smart"),
+            albums: subpath("albums"),
             cache: subpath("cache"),
         }
     }
 }
 
+/// Reads the RON file at `path`. Errors say where in the file the problem is (`path:line:column: ...`)
+pub fn read_ron<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let read = fs::read_to_string(path).map_err(|e| anyhow!("Failed to read {path:?}: {e}"))?;
+    ron::from_str(&read).map_err(|e| {
+        anyhow!(
+            "{}:{}:{}: {}",
+            path.display(),
+            e.position.line,
+            e.position.col,
+            e.code
+        )
+    })
+}
+
+/// Finds every playlist file in `dir`, along with the group it belongs to (the path of the subdirectory it is in)
+pub fn find_playlists(
+    dir: &Path,
+    group: Option<&str>,
+    out: &mut Vec<(PathBuf, Option<String>)>,
+) -> Result<()> {
+    for entry in fs::read_dir(dir)?.filter_map(Result::ok) {
+        let file_type = entry.file_type()?;
+        if file_type.is_file() {
+            out.push((entry.path(), group.map(str::to_string)));
+        } else if file_type.is_dir() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let subgroup = match group {
+                Some(group) => format!("{group}/{name}"),
+                None => name,
+            };
+            find_playlists(&entry.path(), Some(&subgroup), out)?;
+        }
+    }
+    Ok(())
+}
+
+/// See [`Output::track_index`]
+fn index_tracks(playlists: &[Playlist]) -> HashMap<Hash, Vec<(usize, usize)>> {
+    let mut index = HashMap::<_, Vec<_>>::new();
+    for (i, playlist) in playlists.iter().enumerate() {
+        for (j, track) in playlist.tracks.iter().enumerate() {
+            if let Some(source) = playlist.find_source(&track.src) {
+                index
+                    .entry(Hash::track(source, track))
+                    .or_default()
+                    .push((i, j));
+            }
+        }
+    }
+    index
+}
+
+/// The (enabled) tracks of several playlists, each download only once (where it is first found), along with their
+/// sources. Tracks get the tags of the playlist they are in, and sources with the same name are renamed
+fn merge_tracks<'a>(
+    playlists: impl Iterator<Item = (&'a Playlist, Vec<&'a Track>)>,
+) -> (Vec<Source>, Vec<Track>) {
+    let mut sources = Vec::<Source>::new();
+    // sources of the other playlists, and what they are named in this one
+    let mut renamed = Vec::<(&Source, String)>::new();
+    let mut tracks = vec![];
+    let mut seen = HashSet::new();
+    for (pl, pl_tracks) in playlists {
+        for track in pl_tracks.into_iter().filter(|track| !track.disabled) {
+            let Some(source) = pl.find_source(&track.src) else {
+                continue;
+            };
+            if !seen.insert(Hash::track(source, track)) {
+                continue;
+            }
+            let src = match renamed.iter().find(|(other, _)| *other == source) {
+                Some((_, name)) => name.clone(),
+                None => {
+                    // different playlists can have different sources with the same name
+                    // (renaming a source does not change the hash of its downloads)
+                    let mut name = source.name.clone();
+                    let mut n = 1;
+                    while sources.iter().any(|src| src.name == name) {
+                        n += 1;
+                        name = format!("{} ({n})", source.name);
+                    }
+                    sources.push(Source {
+                        name: name.clone(),
+                        ..source.clone()
+                    });
+                    renamed.push((source, name.clone()));
+                    name
+                }
+            };
+            let mut track = Track {
+                src,
+                // (the sections of different playlists would be mixed up)
+                group: None,
+                ..track.clone()
+            };
+            track.tags.extend(pl.tags.iter().cloned());
+            tracks.push(track);
+        }
+    }
+    (sources, tracks)
+}
+
 pub struct Resolver {
     s: State,
     d: Directories,
@@ -93,34 +260,71 @@ impl Resolver {
 
         self.o.config = Config::new(self.d.root.clone())?;
 
+        // files that fail to load are left out (with the error saying which file it was), rather than failing
+        // to load anything. `dmm check` reports everything that is wrong with them
         {
             for src_file in fs::read_dir(&self.d.sources)?.filter_map(Result::ok) {
                 if src_file.file_type()?.is_file() {
-                    let read = fs::read_to_string(src_file.path())?;
-                    let decode = ron::from_str::<schema::Source>(&read)?;
-                    self.o.sources.push(decode);
+                    match read_ron::<schema::Source>(&src_file.path()) {
+                        Ok(mut decode) => {
+                            decode.file_path = src_file.path();
+                            self.o.sources.push(decode);
+                        }
+                        Err(e) => {
+                            warn!("Skipping source: {e}");
+                            self.o.failed_sources.push(e.to_string());
+                        }
+                    }
+                }
+            }
+        }
+
+        if self.d.albums.try_exists()? {
+            for file in fs::read_dir(&self.d.albums)?.filter_map(Result::ok) {
+                if file.file_type()?.is_file() {
+                    match read_ron::<Album>(&file.path()) {
+                        Ok(mut decode) => {
+                            decode.file_path = file.path();
+                            self.o.albums.push(decode);
+                        }
+                        Err(e) => {
+                            warn!("Skipping album: {e}");
+                            self.o.failed_albums.push(e.to_string());
+                        }
+                    }
                 }
             }
         }
 
         {
-            for src_file in fs::read_dir(&self.d.playlists)?.filter_map(Result::ok) {
-                if src_file.file_type()?.is_file() {
-                    let read = fs::read_to_string(src_file.path())?;
-                    let mut pl = ron::from_str::<schema::Playlist>(&read)?;
-                    pl.resolved_sources = Some(pl.sources.clone());
-                    pl.file_path = src_file.path();
-                    for schema::Import::Source(source) in &pl.import {
-                        let source = self
-                            .o
-                            .sources
-                            .iter()
-                            .find(|src| &src.name == source)
-                            .ok_or(anyhow!("Failed to find source {source}"))?;
-                        let res = pl.resolved_sources.as_mut().unwrap();
-                        res.push(source.clone());
+            let mut files = vec![];
+            find_playlists(&self.d.playlists, None, &mut files)?;
+            for (path, group) in files {
+                match self.load_playlist(path, group) {
+                    Ok(pl) => self.o.playlists.push(pl),
+                    Err(e) => {
+                        warn!("Skipping playlist: {e}");
+                        self.o.failed_playlists.push(e.to_string());
+                    }
+                }
+            }
+        }
+
+        self.o.track_index = index_tracks(&self.o.playlists);
+
+        if self.d.smart.try_exists()? {
+            for file in fs::read_dir(&self.d.smart)?.filter_map(Result::ok) {
+                if file.file_type()?.is_file() {
+                    match read_ron::<SmartPlaylist>(&file.path()) {
+                        Ok(mut decode) => {
+                            decode.file_path = file.path();
+                            self.o.smart_playlists.push(decode);
+                        }
+                        Err(e) => {
+                            warn!("Skipping smart playlist: {e}");
+                            self.o.failed_playlists.push(e.to_string());
+                        }
                     }
-                    self.o.playlists.push(pl);
                 }
             }
         }
@@ -132,4 +336,213 @@ impl Resolver {
         self.s.resolved = true;
         Ok(())
     }
+
+    /// Adds a playlist of every track in the other playlists (each download only once, where it is first found),
+    /// for the player. It is only added if there is more than one playlist
+    pub fn add_all_tracks(&mut self) {
+        assert!(self.s.resolved, "Resolver has not yet been run!");
+        let playlists = || {
+            self.o
+                .playlists
+                .iter()
+                .filter(|pl| !pl.archived && !pl.is_virtual())
+        };
+        if playlists().count() < 2 {
+            return;
+        }
+        let (sources, tracks) =
+            merge_tracks(playlists().map(|pl| (pl, pl.tracks.iter().collect())));
+        let all = Playlist {
+            file_path: PathBuf::new(),
+            name: ALL_TRACKS.to_string(),
+            import: vec![],
+            sources: vec![],
+            resolved_sources: Some(sources),
+            tracks,
+            trim_silence: None,
+            group: None,
+            tags: vec![],
+            default_source: None,
+            archived: false,
+            smart: false,
+        };
+        self.o.playlists.push(all);
+    }
+
+    /// Adds a playlist for each smart playlist, of the tracks in the other playlists that match its rules
+    /// (each download only once), for the player. Smart playlists that no tracks match are left out
+    pub fn add_smart_playlists(&mut self) {
+        assert!(self.s.resolved, "Resolver has not yet been run!");
+        if self.o.smart_playlists.is_empty() {
+            return;
+        }
+        let counts = plays::counts(self).unwrap_or_else(|e| {
+            warn!("Failed to read the listening history: {e}");
+            HashMap::new()
+        });
+        let now = SystemTime::now();
+        let days_ago = |hash: Hash| {
+            let created = fs::metadata(self.o.cache.find(hash)?)
+                .ok()?
+                .created()
+                .ok()?;
+            Some(now.duration_since(created).unwrap_or_default().as_secs() / (24 * 60 * 60))
+        };
+        let mut smart = vec![];
+        for sp in &self.o.smart_playlists {
+            let playlists = self
+                .o
+                .playlists
+                .iter()
+                .filter(|pl| !pl.archived && !pl.is_virtual())
+                .map(|pl| {
+                    let tracks = pl
+                        .tracks
+                        .iter()
+                        .filter(|track| {
+                            let Some(source) = pl.find_source(&track.src) else {
+                                return false;
+                            };
+                            let hash = Hash::track(source, track);
+                            let input = schema::RuleInput {
+                                playlist: pl,
+                                track,
+                                plays: counts.get(&hash).copied().unwrap_or(0),
+                                added_days_ago: days_ago(hash),
+                            };
+                            sp.rules.iter().all(|rule| rule.matches(&input))
+                        })
+                        .collect();
+                    (pl, tracks)
+                });
+            let (sources, tracks) = merge_tracks(playlists);
+            if tracks.is_empty() {
+                info!("No tracks match the rules of smart playlist {:?}", sp.name);
+                continue;
+            }
+            smart.push(Playlist {
+                file_path: sp.file_path.clone(),
+                name: sp.name.clone(),
+                import: vec![],
+                sources: vec![],
+                resolved_sources: Some(sources),
+                tracks,
+                trim_silence: None,
+                group: sp.group.clone(),
+                tags: vec![],
+                default_source: None,
+                archived: false,
+                smart: true,
+            });
+        }
+        self.o.playlists.extend(smart);
+    }
+
+    /// Reads the playlist at `path`, and finds the sources and albums it imports (which must already be loaded)
+    fn load_playlist(&self, path: PathBuf, group: Option<String>) -> Result<Playlist> {
+        let mut pl = read_ron::<schema::Playlist>(&path)?;
+        pl.resolved_sources = Some(pl.sources.clone());
+        pl.file_path = path;
+        if pl.group.is_none() {
+            pl.group = group;
+        }
+        pl.fill_default_source()
+            .map_err(|e| anyhow!("{}: {e}", pl.file_path.display()))?;
+        for source in pl.import.iter().filter_map(Import::source) {
+            let source = self
+                .o
+                .sources
+                .iter()
+                .find(|src| &src.name == source)
+                .ok_or(anyhow!(
+                    "{}: failed to find imported source {source}",
+                    pl.file_path.display()
+                ))?;
+            let res = pl.resolved_sources.as_mut().unwrap();
+            res.push(source.clone());
+        }
+        self.add_albums(&mut pl)?;
+        Ok(pl)
+    }
+
+    /// Adds the tracks of the albums `pl` imports to the end of its tracks, along with the sources they use.
+    /// The tracks get the album's name, its artist (if they leave theirs empty), and are shown in a section named
+    /// after it (unless they set these themselves)
+    fn add_albums(&self, pl: &mut Playlist) -> Result<()> {
+        let names = pl
+            .import
+            .iter()
+            .filter_map(Import::album)
+            .cloned()
+            .collect::<Vec<_>>();
+        for name in names {
+            let album = self
+                .o
+                .albums
+                .iter()
+                .find(|album| album.name == name)
+                .ok_or(anyhow!(
+                    "{}: failed to find imported album {name}",
+                    pl.file_path.display()
+                ))?;
+            let mut sources = album.sources.clone();
+            for source in album.import.iter().filter_map(Import::source) {
+                let found = self
+                    .o
+                    .sources
+                    .iter()
+                    .find(|src| &src.name == source)
+                    .ok_or(anyhow!(
+                        "{}: failed to find imported source {source}",
+                        album.file_path.display()
+                    ))?;
+                sources.push(found.clone());
+            }
+            for track in &album.tracks {
+                let src = match track.src.is_empty() {
+                    true => album.default_source.as_ref(),
+                    false => Some(&track.src),
+                };
+                let source = src
+                    .and_then(|src| sources.iter().find(|source| &source.name == src))
+                    .ok_or(anyhow!(
+                        "{}: track {:?} does not have a `src` that the album imports or defines",
+                        album.file_path.display(),
+                        track.meta.name
+                    ))?;
+                // the playlist can have another source with the same name
+                // (renaming a source does not change the hash of its downloads)
+                let resolved = pl.resolved_sources.as_mut().unwrap();
+                let mut src = source.name.clone();
+                let mut n = 1;
+                loop {
+                    match resolved.iter().find(|other| other.name == src) {
+                        Some(other) if other == source => break,
+                        Some(_) => {
+                            n += 1;
+                            src = format!("{} ({n})", source.name);
+                        }
+                        None => {
+                            resolved.push(Source {
+                                name: src.clone(),
+                                ..source.clone()
+                            });
+                            break;
+                        }
+                    }
+                }
+                let mut track = Track {
+                    src,
+                    ..track.clone()
+                };
+                if track.meta.artist.is_empty() {
+                    track.meta.artist = album.artist.clone();
+                }
+                track.meta.album.get_or_insert_with(|| album.name.clone());
+                track.group.get_or_insert_with(|| album.name.clone());
+                pl.tracks.push(track);
+            }
+        }
+        Ok(())
+    }
 }
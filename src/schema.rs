@@ -1,11 +1,30 @@
 use std::{
+    ffi::{OsStr, OsString},
+    fs,
+    hash::{Hash, Hasher},
     path::{Path, PathBuf},
-    process::Command,
+    process::{Command, ExitStatus},
 };
 
 use color_eyre::eyre::{anyhow, bail, Result};
 use serde::{Deserialize, Serialize};
 
+use crate::ytdlp;
+
+/// Serializes `value` in the same layout as the hand-written files in the music directory.
+///
+/// Note that this does not preserve comments from the original file
+pub fn to_ron_pretty<T: Serialize>(value: &T) -> Result<String> {
+    let mut out = ron::ser::to_string_pretty(
+        value,
+        ron::ser::PrettyConfig::new()
+            .struct_names(true)
+            .indentor("    ".to_string()),
+    )?;
+    out.push('\n');
+    Ok(out)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct Link {
     pub music_directory: PathBuf,
@@ -21,9 +40,41 @@ pub struct Playlist {
     #[serde(skip)]
     pub resolved_sources: Option<Vec<Source>>,
     pub tracks: Vec<Track>,
+    /// overrides the `audio.trim_silence` config option for this playlist
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trim_silence: Option<bool>,
+    /// group this playlist is shown under in the player.
+    /// defaults to the subdirectory of `playlists/` that it is in (if any)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// tags that apply to every track in this playlist
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// source used by tracks that do not set `src`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_source: Option<String>,
+    /// hides this playlist from the player and `dmm download all`. its downloads are still kept by `dmm store gc`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub archived: bool,
+    /// made from a [`SmartPlaylist`] (see [`crate::resolver::Resolver::add_smart_playlists`]).
+    /// `file_path` is the file of the smart playlist
+    #[serde(skip)]
+    pub smart: bool,
 }
 
 impl Playlist {
+    /// If this is the player's playlist of every track (see [`crate::resolver::Resolver::add_all_tracks`]),
+    /// which is not loaded from a file
+    pub fn is_all_tracks(&self) -> bool {
+        self.file_path.as_os_str().is_empty()
+    }
+
+    /// If this playlist is made by the player from the tracks of the others (the playlist of all tracks, and
+    /// smart playlists), so there is no playlist file to edit
+    pub fn is_virtual(&self) -> bool {
+        self.is_all_tracks() || self.smart
+    }
+
     /// Panics if playlist sources are not yet resolved
     pub fn find_source(&self, name: &str) -> Option<&Source> {
         self.resolved_sources
@@ -32,64 +83,787 @@ impl Playlist {
             .iter()
             .find(|x| x.name == name)
     }
+
+    /// Name of the source `track` is downloaded with (its own `src`, or the default source of this playlist)
+    pub fn track_source<'a>(&'a self, track: &'a Track) -> Option<&'a str> {
+        if track.src.is_empty() {
+            self.default_source.as_deref()
+        } else {
+            Some(&track.src)
+        }
+    }
+
+    /// Sets the `src` of tracks that do not have one to the default source
+    pub fn fill_default_source(&mut self) -> Result<()> {
+        for i in 0..self.tracks.len() {
+            let Some(src) = self.track_source(&self.tracks[i]).map(str::to_string) else {
+                bail!(
+                    "track {:?} has no `src`, and the playlist has no `default_source`",
+                    self.tracks[i].meta.name
+                );
+            };
+            self.tracks[i].src = src;
+        }
+        Ok(())
+    }
+
+    /// Tags of `track` (its own, and the ones it gets from this playlist)
+    pub fn track_tags<'a>(&'a self, track: &'a Track) -> impl Iterator<Item = &'a String> {
+        self.tags.iter().chain(&track.tags)
+    }
+
+    /// Checks if `track` has any of `tags` (tags are not case sensitive). Every track matches an empty list
+    pub fn track_has_tag(&self, track: &Track, tags: &[String]) -> bool {
+        tags.is_empty()
+            || self
+                .track_tags(track)
+                .any(|tag| tags.iter().any(|want| want.eq_ignore_ascii_case(tag)))
+    }
+
+    /// Copy of this playlist, with only the tracks that have any of `tags`
+    pub fn with_tags(&self, tags: &[String]) -> Playlist {
+        Playlist {
+            tracks: self
+                .tracks
+                .iter()
+                .filter(|track| self.track_has_tag(track, tags))
+                .cloned()
+                .collect(),
+            ..self.clone()
+        }
+    }
+}
+
+/// A playlist of every track (in the other playlists) that matches its rules, kept in the `smart` directory
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SmartPlaylist {
+    #[serde(skip)]
+    pub file_path: PathBuf,
+    pub name: String,
+    /// tracks must match every rule
+    pub rules: Vec<Rule>,
+    /// group this playlist is shown under in the player
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+/// Rule of a [`SmartPlaylist`]. Text is not case sensitive
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Rule {
+    /// has this tag (its own, or one of its playlist)
+    Tag(String),
+    /// artist contains this
+    ArtistContains(String),
+    /// name contains this
+    NameContains(String),
+    /// album contains this
+    AlbumContains(String),
+    /// is in this playlist
+    InPlaylist(String),
+    /// has been played more than this many times
+    PlayedMoreThan(usize),
+    /// has been played fewer than this many times
+    PlayedFewerThan(usize),
+    /// was downloaded within this many days
+    AddedWithinDays(u64),
+    /// matches every one of these rules
+    All(Vec<Rule>),
+    /// matches any of these rules
+    Any(Vec<Rule>),
+    /// does not match this rule
+    Not(Box<Rule>),
+}
+
+/// What [`Rule`]s are checked against
+pub struct RuleInput<'a> {
+    pub playlist: &'a Playlist,
+    pub track: &'a Track,
+    /// how many times the track has been played
+    pub plays: usize,
+    /// how many days ago the track was downloaded (if it is)
+    pub added_days_ago: Option<u64>,
+}
+
+impl Rule {
+    pub fn matches(&self, input: &RuleInput) -> bool {
+        let contains = |text: &str, part: &str| text.to_lowercase().contains(&part.to_lowercase());
+        let meta = &input.track.meta;
+        match self {
+            Rule::Tag(tag) => input
+                .playlist
+                .track_has_tag(input.track, std::slice::from_ref(tag)),
+            Rule::ArtistContains(part) => contains(&meta.artist, part),
+            Rule::NameContains(part) => contains(&meta.name, part),
+            Rule::AlbumContains(part) => meta.album.as_ref().is_some_and(|a| contains(a, part)),
+            Rule::InPlaylist(name) => input.playlist.name.eq_ignore_ascii_case(name),
+            Rule::PlayedMoreThan(n) => input.plays > *n,
+            Rule::PlayedFewerThan(n) => input.plays < *n,
+            Rule::AddedWithinDays(days) => input.added_days_ago.is_some_and(|ago| ago < *days),
+            Rule::All(rules) => rules.iter().all(|rule| rule.matches(input)),
+            Rule::Any(rules) => rules.iter().any(|rule| rule.matches(input)),
+            Rule::Not(rule) => !rule.matches(input),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum Import {
     Source(String),
+    /// adds the tracks of an [`Album`] (by name) to the end of the playlist
+    Album(String),
+}
+
+impl Import {
+    /// Name of the imported source (None if this imports something else)
+    pub fn source(&self) -> Option<&String> {
+        match self {
+            Self::Source(name) => Some(name),
+            Self::Album(_) => None,
+        }
+    }
+
+    /// Name of the imported album (None if this imports something else)
+    pub fn album(&self) -> Option<&String> {
+        match self {
+            Self::Album(name) => Some(name),
+            Self::Source(_) => None,
+        }
+    }
+}
+
+/// Tracks of an album, kept in the (optional) `albums` directory, so that they can be listed once and
+/// imported by any number of playlists (with `Import::Album`)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Album {
+    #[serde(skip)]
+    pub file_path: PathBuf,
+    pub name: String,
+    /// artist of the tracks that leave theirs empty
+    pub artist: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub year: Option<u32>,
+    /// sources the tracks are downloaded with (only `Import::Source`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub import: Vec<Import>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<Source>,
+    /// source used by tracks that do not set `src`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_source: Option<String>,
+    pub tracks: Vec<Track>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct Source {
+    /// file this source was loaded from (empty for sources declared inline in a playlist)
+    #[serde(skip)]
+    pub file_path: PathBuf,
     pub name: String,
     pub format: String,
     pub kind: SourceKind,
+    /// stable name for the downloads of this source in the store. if set, downloads are stored by this (and the format)
+    /// instead of the source's kind, so its command or arguments can be changed without downloading everything again.
+    /// `dmm store migrate` moves downloads made before it was set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<String>,
+    /// commands run (in order) on each download once the source has downloaded it,
+    /// such as ffmpeg to normalize its loudness or convert it to another format
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub postprocess: Vec<PostProcess>,
+    /// convert each download to another format with ffmpeg (after `postprocess`), so it is stored in that format
+    /// instead of `format`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub convert: Option<Convert>,
+}
+
+/// Format the downloads of a source are converted to (see [`Source::convert`])
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct Convert {
+    /// format to convert to, as a file extension (such as `opus`, `mp3`, `ogg`, or `flac`)
+    pub format: String,
+    /// bitrate (in kbit/s) to encode at, or None for ffmpeg's default (not used by lossless formats)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitrate: Option<u32>,
+}
+
+/// A command run on a download after its source downloads it (see [`Source::postprocess`])
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct PostProcess {
+    pub cmd: String,
+    /// `${input}` is replaced with the file to process, and `${output}` with the file to write the result to
+    pub args: Vec<String>,
+    /// shell to run `cmd` with (see `SourceKind::Shell`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<CommandShell>,
+    /// keep the download as it was if this command fails, instead of failing the download
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub optional: bool,
+}
+
+/// Command sources convert their downloads with (see [`Source::convert`])
+const FFMPEG: &str = "ffmpeg";
+
+/// Overrides that tracks can give to `YtDlp` sources
+const YTDLP_OVERRIDES: [&str; 4] = ["format", "extra_args", "start", "end"];
+
+/// Progress of a download, as reported by its source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    /// bytes downloaded so far
+    pub downloaded: u64,
+    /// total size of the download (if known)
+    pub total: Option<u64>,
 }
 
 impl Source {
-    pub fn execute(&self, input: ron::Value, output: &Path) -> Result<()> {
-        let SourceKind::Shell { cmd, args } = &self.kind;
-        let ron::Value::String(input) = input else {
-            bail!("shell source expects a string for its input argument (found: {input:?})");
+    /// Checks that anything this source needs to run is installed
+    pub fn check_available(&self) -> Result<()> {
+        match &self.kind {
+            SourceKind::Shell { .. } => {}
+            SourceKind::YtDlp { .. } => ytdlp::check_installed()?,
+        }
+        if self.convert.is_some() {
+            if let Err(e) = Command::new(FFMPEG).arg("-version").output() {
+                error!("Failed to run {FFMPEG}: {e}");
+                info!("Source {} converts its downloads, which needs ffmpeg to be installed, and in your PATH", self.name);
+                bail!("{FFMPEG} not found");
+            }
+        }
+        Ok(())
+    }
+
+    /// Format the downloads of this source are stored in (the format they are converted to, if they are)
+    pub fn stored_format(&self) -> &str {
+        self.convert
+            .as_ref()
+            .map_or(&self.format, |convert| &convert.format)
+    }
+
+    /// Reads a track's `overrides`, checking that this source can use them.
+    ///
+    /// Overrides are a map of names to values. `Shell` sources replace `${name}` in their arguments with the value,
+    /// and `YtDlp` sources take `format` (replacing the source's format selection), `extra_args` (added to the source's),
+    /// and `start`/`end` (to only download part of the video)
+    pub fn overrides(&self, overrides: Option<&ron::Value>) -> Result<Vec<(String, ron::Value)>> {
+        let Some(overrides) = overrides else {
+            return Ok(vec![]);
         };
+        let ron::Value::Map(map) = overrides else {
+            bail!(
+                "overrides must be a map, such as {{\"start\": \"1:30\"}} (found: {overrides:?})"
+            );
+        };
+        map.iter()
+            .map(|(name, value)| {
+                let ron::Value::String(name) = name else {
+                    bail!("override names must be strings (found: {name:?})");
+                };
+                match &self.kind {
+                    SourceKind::Shell { args, .. }
+                        if !args.iter().any(|arg| arg.contains(&format!("${{{name}}}"))) =>
+                    {
+                        bail!(
+                            "source {} does not use the override {name:?} (none of its arguments contain ${{{name}}})",
+                            self.name
+                        );
+                    }
+                    SourceKind::YtDlp { .. } if !YTDLP_OVERRIDES.contains(&name.as_str()) => {
+                        bail!(
+                            "yt-dlp source {} does not take the override {name:?} (it takes {})",
+                            self.name,
+                            YTDLP_OVERRIDES.join(", ")
+                        );
+                    }
+                    _ => {}
+                }
+                if name == "extra_args" && !matches!(value, ron::Value::Seq(_)) {
+                    bail!("the extra_args override must be a list of arguments (found: {value:?})");
+                }
+                Ok((name.clone(), value.clone()))
+            })
+            .collect()
+    }
+
+    /// Reads a track's input, checking that this source can use it. Returns what to replace in the source's arguments
+    /// (each pattern along with its value).
+    ///
+    /// A string input replaces `${input}`. `Shell` sources can also take a map (such as `{"id": "123", "quality": "high"}`),
+    /// with `${input.<name>}` replaced by each of its fields, so downloads that need several values do not have to pack them into one string
+    pub fn input(&self, input: &ron::Value) -> Result<Vec<(String, String)>> {
+        let fields = match (input, &self.kind) {
+            (ron::Value::String(input), _) => vec![("${input}".to_string(), input.clone())],
+            (ron::Value::Map(map), SourceKind::Shell { .. }) => map
+                .iter()
+                .map(|(name, value)| {
+                    let ron::Value::String(name) = name else {
+                        bail!("input field names must be strings (found: {name:?})");
+                    };
+                    Ok((format!("${{input.{name}}}"), override_str(value)))
+                })
+                .collect::<Result<_>>()?,
+            (ron::Value::Map(_), SourceKind::YtDlp { .. }) => {
+                bail!(
+                    "yt-dlp source {} expects a string (a URL or video id) for its input argument (found: {input:?})",
+                    self.name
+                );
+            }
+            _ => {
+                bail!(
+                    "source {} expects a string or a map for its input argument (found: {input:?})",
+                    self.name
+                );
+            }
+        };
+        if let SourceKind::Shell { args, .. } = &self.kind {
+            // (this also catches a source using `${input}` given a map, or `${input.<name>}` given a string)
+            for used in args.iter().flat_map(|arg| input_patterns(arg)) {
+                if !fields.iter().any(|(pattern, _)| pattern == used) {
+                    bail!(
+                        "source {} uses {used} in its arguments, which the input does not have (found: {})",
+                        self.name,
+                        override_str(input)
+                    );
+                }
+            }
+        }
+        Ok(fields)
+    }
+
+    /// Downloads `input` to `output`, with the track's `overrides` (see [`Source::overrides`]).
+    /// See [`Source::input`] for the inputs it takes.
+    ///
+    /// If `show_output` is false, the command's output is captured (and included in the error, if it fails).
+    /// `progress` is called as the download progresses, if the source is able to report it
+    pub fn execute(
+        &self,
+        input: ron::Value,
+        overrides: Option<&ron::Value>,
+        output: &Path,
+        show_output: bool,
+        progress: &dyn Fn(Progress),
+    ) -> Result<()> {
+        let fields = self.input(&input)?;
+        let overrides = self.overrides(overrides)?;
+        match &self.kind {
+            SourceKind::Shell { cmd, args, shell } => {
+                let args = args
+                    .iter()
+                    .map(|arg| {
+                        let arg = fields.iter().fold(arg.clone(), |arg, (pattern, value)| {
+                            arg.replace(pattern, value)
+                        });
+                        overrides.iter().fold(arg, |arg, (name, value)| {
+                            arg.replace(&format!("${{{name}}}"), &override_str(value))
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                self.execute_shell(
+                    cmd,
+                    *shell,
+                    &args,
+                    &override_str(&input),
+                    output,
+                    show_output,
+                )
+            }
+            SourceKind::YtDlp { format, extra_args } => {
+                let ron::Value::String(input) = input else {
+                    unreachable!("yt-dlp sources only take strings (checked by Source::input)");
+                };
+                let mut format = format.clone();
+                let mut extra_args = extra_args.clone();
+                let (mut start, mut end) = (None, None);
+                for (name, value) in overrides {
+                    match name.as_str() {
+                        "format" => format = Some(override_str(&value)),
+                        "extra_args" => {
+                            if let ron::Value::Seq(args) = value {
+                                extra_args.extend(args.iter().map(override_str))
+                            }
+                        }
+                        "start" => start = Some(override_str(&value)),
+                        _ => end = Some(override_str(&value)),
+                    }
+                }
+                if start.is_some() || end.is_some() {
+                    extra_args.push("--download-sections".to_string());
+                    extra_args.push(format!(
+                        "*{}-{}",
+                        start.as_deref().unwrap_or("0"),
+                        end.as_deref().unwrap_or("inf")
+                    ));
+                }
+                self.execute_ytdlp(
+                    format.as_deref(),
+                    &extra_args,
+                    &input,
+                    output,
+                    show_output,
+                    progress,
+                )
+            }
+        }
+    }
+
+    fn execute_ytdlp(
+        &self,
+        format: Option<&str>,
+        extra_args: &[String],
+        input: &str,
+        output: &Path,
+        show_output: bool,
+        progress: &dyn Fn(Progress),
+    ) -> Result<()> {
+        ytdlp::download(
+            &ytdlp::Options {
+                format,
+                audio_format: &self.format,
+                extra_args,
+            },
+            input,
+            output,
+            show_output,
+            progress,
+        )
+        .map_err(|e| {
+            anyhow!(
+                "Failed to download {input:?} from yt-dlp source {} - {e}",
+                self.name
+            )
+        })
+    }
+
+    /// Runs the `postprocess` commands on the download at `path`, replacing it with the result of each one
+    pub fn postprocess(&self, path: &Path, show_output: bool) -> Result<()> {
+        // (keeping the extension, so tools like ffmpeg can tell the format)
+        let processed = match path.extension() {
+            Some(ext) => {
+                let mut post = OsString::from("post.");
+                post.push(ext);
+                path.with_extension(post)
+            }
+            None => path.with_extension("post"),
+        };
+        for (i, step) in self.postprocess.iter().enumerate() {
+            let args = step
+                .args
+                .iter()
+                .map(|arg| {
+                    let mut parts = arg.split("${output}");
+                    let mut out = replace_path(parts.next().unwrap_or_default(), "${input}", path);
+                    for part in parts {
+                        out.push(&processed);
+                        out.push(replace_path(part, "${input}", path));
+                    }
+                    out
+                })
+                .collect::<Vec<_>>();
+            let mut command = match step.shell {
+                None => Command::new(&step.cmd),
+                Some(shell) => shell.command(&step.cmd),
+            };
+            command.args(args);
+            let result = run_command(&mut command, show_output).and_then(|(status, stderr)| {
+                if !status.success() {
+                    let mut err = format!("command exited with status {status}");
+                    if !stderr.trim().is_empty() {
+                        err += &format!("\n{}", stderr.trim_end());
+                    }
+                    bail!(err);
+                }
+                if !processed.exists() {
+                    bail!("command did not write anything to ${{output}}");
+                }
+                Ok(fs::rename(&processed, path)?)
+            });
+            if let Err(e) = result {
+                let _ = fs::remove_file(&processed);
+                let err = format!(
+                    "Post-processing step {} ({}) of source {} failed - {e}",
+                    i + 1,
+                    step.cmd,
+                    self.name
+                );
+                if !step.optional {
+                    bail!(err);
+                }
+                warn!("{err} [skipping the step, it is optional]");
+            }
+        }
+        Ok(())
+    }
+
+    /// Converts the download at `path` to the `convert` format (if it is set), replacing it
+    pub fn transcode(&self, path: &Path, show_output: bool) -> Result<()> {
+        let Some(convert) = &self.convert else {
+            return Ok(());
+        };
+        // (ffmpeg picks the encoder and container from the extension)
+        let mut ext = OsString::from("convert.");
+        ext.push(&convert.format);
+        let converted = path.with_extension(ext);
+        let mut command = Command::new(FFMPEG);
+        command
+            .args(["-y", "-loglevel", "error", "-i"])
+            .arg(path)
+            // (leave out cover art, most audio-only formats can not hold it as a stream)
+            .arg("-vn");
+        if let Some(bitrate) = convert.bitrate {
+            command.args(["-b:a", &format!("{bitrate}k")]);
+        }
+        command.arg(&converted);
+        let result = run_command(&mut command, show_output).and_then(|(status, stderr)| {
+            if !status.success() {
+                let mut err = format!("{FFMPEG} exited with status {status}");
+                if !stderr.trim().is_empty() {
+                    err += &format!("\n{}", stderr.trim_end());
+                }
+                bail!(err);
+            }
+            Ok(fs::rename(&converted, path)?)
+        });
+        result.map_err(|e| {
+            let _ = fs::remove_file(&converted);
+            anyhow!(
+                "Converting a download of source {} to {} failed - {e}",
+                self.name,
+                convert.format
+            )
+        })
+    }
+
+    /// Runs a `Shell` source's command, with the input already put in its `args` (`input` is only for the error message)
+    fn execute_shell(
+        &self,
+        cmd: &str,
+        shell: Option<CommandShell>,
+        args: &[String],
+        input: &str,
+        output: &Path,
+        show_output: bool,
+    ) -> Result<()> {
+        // (the output path is passed as it is, as it does not have to be valid UTF-8)
         let args = args
             .iter()
-            .map(|arg| {
-                Ok(arg.replace("${input}", &input).replace(
-                    "${output}",
-                    output
-                        .to_str()
-                        .ok_or(anyhow!("output path not valid UTF-8"))?,
-                ))
-            })
-            .collect::<Result<Vec<String>>>()?;
-        let res = Command::new(cmd).args(args).status()?;
-        if res.success() {
+            .map(|arg| replace_path(arg, "${output}", output))
+            .collect::<Vec<_>>();
+        let mut command = match shell {
+            None => Command::new(cmd),
+            Some(shell) => shell.command(cmd),
+        };
+        command.args(args);
+        let (status, stderr) = run_command(&mut command, show_output)?;
+        if status.success() {
             Ok(())
         } else {
-            Err(anyhow!(
+            let mut err = format!(
                 "Failed to download {input:?} from shell source {} - command exited with status {}",
-                self.name,
-                res
-            ))
+                self.name, status
+            );
+            if !stderr.trim().is_empty() {
+                err += &format!("\n{}", stderr.trim_end());
+            }
+            Err(anyhow!(err))
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+/// Runs `command`, returning its exit status, and what it wrote to stderr (if its output is not shown)
+fn run_command(command: &mut Command, show_output: bool) -> Result<(ExitStatus, String)> {
+    if show_output {
+        return Ok((command.status()?, String::new()));
+    }
+    let out = command.output()?;
+    Ok((
+        out.status,
+        String::from_utf8_lossy(&out.stderr).into_owned(),
+    ))
+}
+
+/// `arg` with every `pattern` in it replaced by `path` (which does not have to be valid UTF-8)
+fn replace_path(arg: &str, pattern: &str, path: impl AsRef<OsStr>) -> OsString {
+    let mut parts = arg.split(pattern);
+    let mut out = OsString::from(parts.next().unwrap_or_default());
+    for part in parts {
+        out.push(path.as_ref());
+        out.push(part);
+    }
+    out
+}
+
+/// The `${input}` and `${input.<name>}` patterns in a `Shell` source's argument
+fn input_patterns(arg: &str) -> Vec<&str> {
+    const START: &str = "${input";
+    let mut found = vec![];
+    let mut rest = arg;
+    while let Some(start) = rest.find(START) {
+        rest = &rest[start..];
+        let end = match rest[START.len()..].chars().next() {
+            Some('}' | '.') => rest.find('}'),
+            // such as `${inputs}`, which is not the input
+            _ => None,
+        };
+        match end {
+            Some(end) => {
+                found.push(&rest[..=end]);
+                rest = &rest[end + 1..];
+            }
+            None => rest = &rest[START.len()..],
+        }
+    }
+    found
+}
+
+/// Value of an override (or a field of a track's input) as text: strings as they are, anything else (such as a number) as it is written in RON
+fn override_str(value: &ron::Value) -> String {
+    match value {
+        ron::Value::String(s) => s.clone(),
+        other => ron::to_string(other).unwrap_or_default(),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum SourceKind {
-    Shell { cmd: String, args: Vec<String> },
+    /// run a command. `${input}` and `${output}` in `args` are replaced with the track's input, and the file to download to
+    /// (with a map input, `${input.<name>}` is replaced with each of its fields instead)
+    Shell {
+        cmd: String,
+        args: Vec<String>,
+        /// shell to run `cmd` with (by default, it is run directly). with a shell, `cmd` can use its syntax and
+        /// builtins (such as `cmd /C` on Windows), and `args` are added to the end of it
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        shell: Option<CommandShell>,
+    },
+    /// download with yt-dlp. the input can be a URL, or a YouTube video id
+    YtDlp {
+        /// yt-dlp format selection (`-f`), defaults to the best available audio
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        format: Option<String>,
+        /// extra arguments passed to yt-dlp
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        extra_args: Vec<String>,
+    },
+}
+
+// written by hand, so that adding a kind does not change the hash of existing sources (which would re-download everything)
+impl Hash for SourceKind {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            // (the shell is not hashed: it only changes how the command is run on this machine, not what it downloads)
+            Self::Shell {
+                cmd,
+                args,
+                shell: _,
+            } => {
+                cmd.hash(state);
+                args.hash(state);
+            }
+            Self::YtDlp { format, extra_args } => {
+                "YtDlp".hash(state);
+                format.hash(state);
+                extra_args.hash(state);
+            }
+        }
+    }
+}
+
+/// Shell that `Shell` sources can run their command with
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum CommandShell {
+    /// `sh -c` (the arguments are passed as `"$@"`, so they are never split or expanded)
+    Sh,
+    /// `cmd /C`, the Windows command prompt
+    Cmd,
+    /// `powershell -Command`
+    PowerShell,
+}
+
+impl CommandShell {
+    /// Name of the shell's program
+    pub fn program(self) -> &'static str {
+        match self {
+            Self::Sh => "sh",
+            Self::Cmd => "cmd",
+            Self::PowerShell => "powershell",
+        }
+    }
+
+    /// Command that runs `cmd` in this shell (the arguments still need to be added)
+    fn command(self, cmd: &str) -> Command {
+        let mut command = Command::new(self.program());
+        match self {
+            Self::Sh => command.arg("-c").arg(format!("{cmd} \"$@\"")).arg("sh"),
+            Self::Cmd => command.arg("/C").arg(cmd),
+            Self::PowerShell => command
+                .args(["-NoProfile", "-NonInteractive", "-Command"])
+                .arg(cmd),
+        };
+        command
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct Track {
     pub meta: Meta,
+    /// name of the source to download with. if empty, the playlist's `default_source` is used
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub src: String,
     pub input: ron::Value,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// lyrics of the track, as plain text or LRC (see [`crate::lyrics`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lyrics: Option<String>,
+    /// changes to how the source downloads this track, as a map (see [`Source::overrides`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overrides: Option<ron::Value>,
+    /// time to start playing the track from (to skip an intro)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<Seconds>,
+    /// time to stop playing the track at (to skip an outro)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<Seconds>,
+    /// keeps the track in the playlist, but it is not downloaded, and the player skips it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub disabled: bool,
+    /// section of the playlist the player lists this track under (such as an album, or a mood).
+    /// the tracks of a section should be next to each other
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+}
+
+impl Track {
+    /// How long the track plays for, if its download is `full` seconds long (taking `start` and `end` into account)
+    pub fn played_length(&self, full: u64) -> u64 {
+        let end = self.end.map_or(full as f64, |end| end.0.min(full as f64));
+        let start = self.start.map_or(0.0, |start| start.0);
+        (end - start).max(0.0).round() as u64
+    }
+}
+
+/// A time in a track, in seconds (such as `83.5`)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(transparent)]
+pub struct Seconds(pub f64);
+
+// (NaN, the only value not equal to itself, is not a meaningful time)
+impl Eq for Seconds {}
+
+impl Hash for Seconds {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct Meta {
     pub name: String,
+    /// (tracks of an [`Album`] can leave this out, to use the album's artist)
+    #[serde(default)]
     pub artist: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    /// URL of the cover art (downloaded into the store along with the track)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cover: Option<String>,
 }
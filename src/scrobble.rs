@@ -0,0 +1,257 @@
+//! Scrobbling (reporting what is played) to Last.fm and ListenBrainz
+//!
+//! Submissions are made on a background thread, so a slow or unreachable server never holds up the player.
+//! Following Last.fm's rules, a track is scrobbled once half of it (or 4 minutes, for long tracks) has been played,
+//! and tracks shorter than 30 seconds are not scrobbled at all.
+
+use std::{
+    io::{self, BufRead},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::{anyhow, bail, Result};
+use flume::Sender;
+use serde_json::json;
+
+use crate::{
+    cfg::{LastfmConfig, ListenBrainzConfig, ScrobbleConfig},
+    resolver::Resolver,
+};
+
+const LASTFM_API: &str = "https://ws.audioscrobbler.com/2.0/";
+/// Tracks shorter than this (in seconds) are not scrobbled
+const MIN_LENGTH: u64 = 30;
+/// Tracks are scrobbled after this many seconds, even if half of them has not been played yet
+const MAX_WAIT: u64 = 4 * 60;
+
+/// A track being played
+#[derive(Debug, Clone)]
+pub struct Listen {
+    pub artist: String,
+    pub track: String,
+    pub album: Option<String>,
+    /// seconds (0 if unknown)
+    pub duration: u64,
+    /// when playback started (unix timestamp)
+    pub started_at: u64,
+}
+
+impl Listen {
+    /// A listen of a track that is starting now
+    pub fn start(artist: String, track: String, album: Option<String>, duration: u64) -> Self {
+        Self {
+            artist,
+            track,
+            album,
+            duration,
+            started_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_secs()),
+        }
+    }
+}
+
+/// If a track that is `duration` seconds long has been played long enough to scrobble it
+pub fn should_scrobble(played: u64, duration: u64) -> bool {
+    duration >= MIN_LENGTH && played >= (duration / 2).min(MAX_WAIT)
+}
+
+enum Submission {
+    NowPlaying(Listen),
+    Scrobble(Listen),
+}
+
+trait Service: Send {
+    fn name(&self) -> &'static str;
+    fn submit(&self, submission: &Submission) -> Result<()>;
+}
+
+/// Submits listens to the configured services
+pub struct Scrobbler {
+    tx: Sender<Submission>,
+}
+
+impl Scrobbler {
+    /// Starts the scrobbler, if any services are configured
+    pub fn new(cfg: &ScrobbleConfig) -> Option<Self> {
+        let mut services: Vec<Box<dyn Service>> = vec![];
+        if cfg.lastfm.is_configured() {
+            services.push(Box::new(cfg.lastfm.clone()));
+        }
+        if cfg.listenbrainz.is_configured() {
+            services.push(Box::new(cfg.listenbrainz.clone()));
+        }
+        if services.is_empty() {
+            return None;
+        }
+        let (tx, rx) = flume::unbounded();
+        thread::spawn(move || {
+            for submission in rx.iter() {
+                for service in &services {
+                    if let Err(e) = service.submit(&submission) {
+                        warn!("Failed to submit to {}: {e}", service.name());
+                    }
+                }
+            }
+        });
+        Some(Self { tx })
+    }
+
+    /// Reports that `listen` has started (or continues after being paused)
+    pub fn now_playing(&self, listen: Listen) {
+        let _ = self.tx.send(Submission::NowPlaying(listen));
+    }
+
+    /// Reports that `listen` has been played long enough to count
+    pub fn scrobble(&self, listen: Listen) {
+        debug!("scrobbling {} by {}", listen.track, listen.artist);
+        let _ = self.tx.send(Submission::Scrobble(listen));
+    }
+}
+
+/// Turns an error response from an API into a readable error
+fn request_error(e: ureq::Error) -> color_eyre::eyre::Report {
+    match e {
+        ureq::Error::Status(status, response) => {
+            anyhow!("{status}: {}", response.into_string().unwrap_or_default())
+        }
+        e => e.into(),
+    }
+}
+
+impl LastfmConfig {
+    fn is_configured(&self) -> bool {
+        !self.api_key.is_empty() && !self.api_secret.is_empty() && !self.session_key.is_empty()
+    }
+
+    /// Calls a Last.fm API method, signing the request
+    fn call(&self, method: &str, params: &[(&str, String)]) -> Result<serde_json::Value> {
+        let mut params = params.to_vec();
+        params.push(("method", method.to_string()));
+        params.push(("api_key", self.api_key.clone()));
+        if !self.session_key.is_empty() {
+            params.push(("sk", self.session_key.clone()));
+        }
+        // the signature is the md5 of every parameter (sorted by name) followed by the secret
+        params.sort();
+        let mut sig = String::new();
+        for (name, value) in &params {
+            sig += name;
+            sig += value;
+        }
+        sig += &self.api_secret;
+        params.push(("api_sig", format!("{:x}", md5::compute(sig))));
+        params.push(("format", "json".to_string()));
+
+        let form = params
+            .iter()
+            .map(|(name, value)| (*name, value.as_str()))
+            .collect::<Vec<_>>();
+        Ok(ureq::post(LASTFM_API)
+            .send_form(&form)
+            .map_err(request_error)?
+            .into_json()?)
+    }
+}
+
+impl Service for LastfmConfig {
+    fn name(&self) -> &'static str {
+        "Last.fm"
+    }
+
+    fn submit(&self, submission: &Submission) -> Result<()> {
+        let (method, listen) = match submission {
+            Submission::NowPlaying(listen) => ("track.updateNowPlaying", listen),
+            Submission::Scrobble(listen) => ("track.scrobble", listen),
+        };
+        let mut params = vec![
+            ("artist", listen.artist.clone()),
+            ("track", listen.track.clone()),
+        ];
+        if let Some(album) = &listen.album {
+            params.push(("album", album.clone()));
+        }
+        if listen.duration != 0 {
+            params.push(("duration", listen.duration.to_string()));
+        }
+        if let Submission::Scrobble(_) = submission {
+            params.push(("timestamp", listen.started_at.to_string()));
+        }
+        self.call(method, &params)?;
+        Ok(())
+    }
+}
+
+impl ListenBrainzConfig {
+    fn is_configured(&self) -> bool {
+        !self.token.is_empty()
+    }
+}
+
+impl Service for ListenBrainzConfig {
+    fn name(&self) -> &'static str {
+        "ListenBrainz"
+    }
+
+    fn submit(&self, submission: &Submission) -> Result<()> {
+        let (listen_type, listen) = match submission {
+            Submission::NowPlaying(listen) => ("playing_now", listen),
+            Submission::Scrobble(listen) => ("single", listen),
+        };
+        let mut metadata = json!({
+            "artist_name": listen.artist,
+            "track_name": listen.track,
+            "additional_info": { "submission_client": "dmm" },
+        });
+        if let Some(album) = &listen.album {
+            metadata["release_name"] = json!(album);
+        }
+        if listen.duration != 0 {
+            metadata["additional_info"]["duration"] = json!(listen.duration);
+        }
+        let mut payload = json!({ "track_metadata": metadata });
+        if let Submission::Scrobble(_) = submission {
+            payload["listened_at"] = json!(listen.started_at);
+        }
+        ureq::post(&format!(
+            "{}/1/submit-listens",
+            self.url.trim_end_matches('/')
+        ))
+        .set("Authorization", &format!("Token {}", self.token))
+        .send_json(json!({ "listen_type": listen_type, "payload": [payload] }))
+        .map_err(request_error)?;
+        Ok(())
+    }
+}
+
+/// Handling of `dmm scrobble lastfm-login`: authorizes DMM to scrobble to a Last.fm account, and prints the session key
+pub fn lastfm_login(res: &Resolver) -> Result<()> {
+    let cfg = LastfmConfig {
+        session_key: String::new(),
+        ..res.out().config.scrobble.lastfm.clone()
+    };
+    if cfg.api_key.is_empty() || cfg.api_secret.is_empty() {
+        error!("`scrobble.lastfm.api_key` and `scrobble.lastfm.api_secret` must be set in dmm.ron");
+        info!("Create an API account at https://www.last.fm/api/account/create to get them");
+        bail!("Last.fm API account not configured");
+    }
+    let token = cfg.call("auth.getToken", &[])?["token"]
+        .as_str()
+        .ok_or(anyhow!("Last.fm did not return a token"))?
+        .to_string();
+    println!(
+        "Open https://www.last.fm/api/auth/?api_key={}&token={token} to allow DMM to scrobble, then press enter",
+        cfg.api_key
+    );
+    io::stdin().lock().lines().next();
+    let session = cfg.call("auth.getSession", &[("token", token)])?;
+    let Some(key) = session["session"]["key"].as_str() else {
+        error!("Failed to get a session from Last.fm: {session}");
+        info!("Make sure you allowed access before pressing enter");
+        bail!("Last.fm login failed");
+    };
+    println!("Logged in! Add this to the `scrobble.lastfm` section of dmm.ron:");
+    println!("    session_key: \"{key}\",");
+    Ok(())
+}
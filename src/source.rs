@@ -0,0 +1,333 @@
+//! Handling of `dmm source`
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::{bail, Result};
+use crossterm::style::Stylize;
+use serde::Serialize;
+
+use crate::{
+    error::DmmError,
+    git,
+    output::{self, Format},
+    resolver::Resolver,
+    schema::{self, Album, Convert, Import, Playlist, PostProcess, Source, SourceKind},
+};
+
+#[derive(Debug, Serialize)]
+struct SourceUse {
+    playlist: String,
+    tracks: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct SourceStatus {
+    name: String,
+    /// file of a global source
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<PathBuf>,
+    /// playlist a source is declared in (for sources that are not global)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    declared_in: Option<String>,
+    format: String,
+    kind: SourceKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    identity: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    postprocess: Vec<PostProcess>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    convert: Option<Convert>,
+    /// tracks using this source, in every playlist
+    track_count: usize,
+    /// playlists with tracks using this source
+    used_by: Vec<SourceUse>,
+}
+
+impl SourceStatus {
+    fn new(res: &Resolver, source: &Source, declared_in: Option<&Playlist>) -> Self {
+        let mut used_by = vec![];
+        for pl in &res.out().playlists {
+            // (a source declared in a playlist takes the place of a global one with the same name)
+            let tracks = pl
+                .tracks
+                .iter()
+                .filter(|track| pl.find_source(&track.src) == Some(source))
+                .count();
+            if tracks != 0 {
+                used_by.push(SourceUse {
+                    playlist: pl.name.clone(),
+                    tracks,
+                });
+            }
+        }
+        Self {
+            name: source.name.clone(),
+            path: declared_in.is_none().then(|| source.file_path.clone()),
+            declared_in: declared_in.map(|pl| pl.name.clone()),
+            format: source.format.clone(),
+            kind: source.kind.clone(),
+            identity: source.identity.clone(),
+            postprocess: source.postprocess.clone(),
+            convert: source.convert.clone(),
+            track_count: used_by.iter().map(|used| used.tracks).sum(),
+            used_by,
+        }
+    }
+
+    /// Name, kind, where it is declared, and how many tracks use it, on one line
+    fn summary(&self, res: &Resolver) -> String {
+        let kind = match self.kind {
+            SourceKind::Shell { .. } => "shell",
+            SourceKind::YtDlp { .. } => "yt-dlp",
+        };
+        let declared = match (&self.path, &self.declared_in) {
+            (_, Some(playlist)) => format!("in playlist {playlist:?}"),
+            (Some(path), None) => path
+                .strip_prefix(&res.dirs().sources)
+                .unwrap_or(path)
+                .display()
+                .to_string(),
+            (None, None) => String::new(),
+        };
+        let format = match &self.convert {
+            Some(convert) => format!("{} -> {}", self.format, convert.format),
+            None => self.format.clone(),
+        };
+        format!(
+            "{} ({declared}): {kind}, {format}, used by {} track(s) in {} playlist(s)",
+            self.name.clone().bold(),
+            self.track_count,
+            self.used_by.len()
+        )
+    }
+}
+
+/// Every source: the global ones, and the ones declared in playlists
+fn all_sources(res: &Resolver) -> Vec<SourceStatus> {
+    let out = res.out();
+    let global = out
+        .sources
+        .iter()
+        .map(|source| SourceStatus::new(res, source, None));
+    let inline = out.playlists.iter().flat_map(|pl| {
+        pl.sources
+            .iter()
+            .map(move |source| SourceStatus::new(res, source, Some(pl)))
+    });
+    global.chain(inline).collect()
+}
+
+/// Lists every source, with its kind, format, and how many tracks use it
+pub fn list(res: &Resolver, format: Format) -> Result<()> {
+    let sources = all_sources(res);
+    match format {
+        Format::Json => output::print_json(&sources)?,
+        Format::Text => {
+            for source in &sources {
+                println!("{}", source.summary(res));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Shows the sources named `name` (a global one, and any declared in playlists), and which playlists use them
+pub fn show(res: &Resolver, name: &str, format: Format) -> Result<()> {
+    let sources = all_sources(res)
+        .into_iter()
+        .filter(|source| source.name == name)
+        .collect::<Vec<_>>();
+    if sources.is_empty() {
+        error!("Could not find the source named {name:?}");
+        bail!(DmmError::NotFound);
+    }
+    match format {
+        Format::Json => output::print_json(&sources)?,
+        Format::Text => {
+            for (i, source) in sources.iter().enumerate() {
+                if i != 0 {
+                    println!();
+                }
+                println!("{}", source.summary(res));
+                match &source.kind {
+                    SourceKind::Shell { cmd, args, shell } => {
+                        println!("  command: {cmd}");
+                        println!("  arguments: {args:?}");
+                        if let Some(shell) = shell {
+                            println!("  shell: {shell:?}");
+                        }
+                    }
+                    SourceKind::YtDlp { format, extra_args } => {
+                        if let Some(format) = format {
+                            println!("  format selection: {format}");
+                        }
+                        if !extra_args.is_empty() {
+                            println!("  extra arguments: {extra_args:?}");
+                        }
+                    }
+                }
+                if let Some(identity) = &source.identity {
+                    println!("  identity: {identity}");
+                }
+                for (i, step) in source.postprocess.iter().enumerate() {
+                    let optional = if step.optional { " (optional)" } else { "" };
+                    println!(
+                        "  post-processing {}{optional}: {} {:?}",
+                        i + 1,
+                        step.cmd,
+                        step.args
+                    );
+                }
+                if let Some(convert) = &source.convert {
+                    match convert.bitrate {
+                        Some(bitrate) => {
+                            println!("  converted to: {} ({bitrate} kbit/s)", convert.format)
+                        }
+                        None => println!("  converted to: {}", convert.format),
+                    }
+                }
+                for used in &source.used_by {
+                    println!("  {} track(s) in {:?}", used.tracks, used.playlist);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes all of `files` to disk, or none of them.
+///
+/// The new contents are first written next to the originals, and only once every file has been
+/// written successfully are they moved into place.
+pub fn write_all_or_nothing(files: &[(PathBuf, String)]) -> Result<()> {
+    let tmp_path = |path: &Path| path.with_extension("ron.tmp");
+    for (i, (path, content)) in files.iter().enumerate() {
+        if let Err(e) = fs::write(tmp_path(path), content) {
+            for (path, _) in &files[..=i] {
+                let _ = fs::remove_file(tmp_path(path));
+            }
+            return Err(e.into());
+        }
+    }
+    for (path, _) in files {
+        fs::rename(tmp_path(path), path)?;
+    }
+    Ok(())
+}
+
+/// Renames the source `old` to `new`, updating every playlist (and album) that references it.
+///
+/// Since the name of a source is not part of the cache hash, nothing needs to be re-downloaded.
+pub fn rename(res: &Resolver, old: &str, new: &str) -> Result<()> {
+    let out = res.out();
+    let global = out.sources.iter().find(|src| src.name == old);
+    let inline = out
+        .playlists
+        .iter()
+        .filter(|pl| pl.sources.iter().any(|src| src.name == old))
+        .collect::<Vec<_>>();
+    if global.is_none() && inline.is_empty() {
+        error!("Could not find the source named {old:?}");
+        bail!(DmmError::NotFound);
+    }
+    if out.sources.iter().any(|src| src.name == new) {
+        error!("A source named {new:?} already exists");
+        bail!("rename failed");
+    }
+    if let Some(pl) = out
+        .playlists
+        .iter()
+        .find(|pl| pl.sources.iter().any(|src| src.name == new))
+    {
+        error!(
+            "A source named {new:?} already exists in playlist {:?} ({:?})",
+            pl.name, pl.file_path
+        );
+        bail!("rename failed");
+    }
+
+    let mut files = vec![];
+    if let Some(global) = global {
+        // re-read the file, so the resolved (unserialized) fields do not matter
+        let mut source = ron::from_str::<Source>(&fs::read_to_string(&global.file_path)?)?;
+        source.name = new.to_string();
+        files.push((global.file_path.clone(), schema::to_ron_pretty(&source)?));
+    }
+    for resolved in &out.playlists {
+        let mut pl = ron::from_str::<Playlist>(&fs::read_to_string(&resolved.file_path)?)?;
+        let mut changed = false;
+        if global.is_some() {
+            for import in &mut pl.import {
+                match import {
+                    Import::Source(name) if name == old => {
+                        *name = new.to_string();
+                        changed = true;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        for source in &mut pl.sources {
+            if source.name == old {
+                source.name = new.to_string();
+                changed = true;
+            }
+        }
+        if pl.default_source.as_deref() == Some(old) {
+            pl.default_source = Some(new.to_string());
+            changed = true;
+        }
+        for track in &mut pl.tracks {
+            if track.src == old {
+                track.src = new.to_string();
+                changed = true;
+            }
+        }
+        if changed {
+            files.push((resolved.file_path.clone(), schema::to_ron_pretty(&pl)?));
+        }
+    }
+    // albums only use the renamed source if they import it (their own sources are separate from the playlists')
+    let imported = Import::Source(old.to_string());
+    for resolved in out.albums.iter().filter(|_| global.is_some()) {
+        let mut album = ron::from_str::<Album>(&fs::read_to_string(&resolved.file_path)?)?;
+        if !album.import.contains(&imported) || album.sources.iter().any(|src| src.name == old) {
+            continue;
+        }
+        for import in &mut album.import {
+            if *import == imported {
+                *import = Import::Source(new.to_string());
+            }
+        }
+        if album.default_source.as_deref() == Some(old) {
+            album.default_source = Some(new.to_string());
+        }
+        for track in &mut album.tracks {
+            if track.src == old {
+                track.src = new.to_string();
+            }
+        }
+        files.push((resolved.file_path.clone(), schema::to_ron_pretty(&album)?));
+    }
+
+    for (path, _) in &files {
+        info!("updating {path:?}");
+    }
+    write_all_or_nothing(&files)?;
+    git::commit_changes(
+        res,
+        &files
+            .iter()
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>(),
+        &format!("rename source {old:?} to {new:?}"),
+    );
+    info!(
+        "renamed source {old:?} to {new:?} ({} file(s) updated)",
+        files.len()
+    );
+    Ok(())
+}
@@ -0,0 +1,186 @@
+//! Handling of `dmm stats`
+
+use std::collections::{HashMap, HashSet};
+
+use color_eyre::eyre::{anyhow, Result};
+use crossterm::style::Stylize;
+use serde::Serialize;
+
+use crate::{
+    cache::Hash,
+    duration,
+    output::{self, Format},
+    plays,
+    resolver::Resolver,
+    schema::Playlist,
+    store,
+};
+
+#[derive(Debug, Default, Serialize)]
+struct Totals {
+    track_count: usize,
+    downloaded: usize,
+    /// seconds, of the tracks whose length is known (they have been downloaded)
+    playtime: u64,
+    /// bytes used by the downloads of these tracks (and their sidecar files)
+    disk_usage: u64,
+}
+
+impl Totals {
+    /// Percentage of the tracks that are downloaded
+    fn coverage(&self) -> f64 {
+        match self.track_count {
+            0 => 100.0,
+            n => self.downloaded as f64 * 100.0 / n as f64,
+        }
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "{} track(s), {} downloaded ({:.1}%), {} long, {:.1} MiB",
+            self.track_count,
+            self.downloaded,
+            self.coverage(),
+            duration::format(self.playtime),
+            self.disk_usage as f64 / (1024.0 * 1024.0)
+        )
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct PlaylistStats {
+    name: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    archived: bool,
+    #[serde(flatten)]
+    totals: Totals,
+    coverage: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct Played {
+    name: String,
+    artist: String,
+    playlist: String,
+    plays: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct Stats {
+    /// every track (counted once, even if it is in more than one playlist)
+    #[serde(flatten)]
+    totals: Totals,
+    coverage: f64,
+    /// bytes used by the whole store, including downloads no playlist uses
+    store_size: u64,
+    playlists: Vec<PlaylistStats>,
+    most_played: Vec<Played>,
+}
+
+/// Adds the (enabled) tracks of `playlist` that are not in `seen` to `totals`
+fn add_tracks(
+    res: &Resolver,
+    playlist: &Playlist,
+    sizes: &HashMap<Hash, u64>,
+    seen: &mut HashSet<Hash>,
+    totals: &mut Totals,
+) -> Result<()> {
+    let cache = &res.out().cache;
+    for track in playlist.tracks.iter().filter(|track| !track.disabled) {
+        let source = playlist.find_source(&track.src).ok_or(anyhow!(
+            "Could not find source {} for track {}",
+            track.src,
+            track.meta.name
+        ))?;
+        let hash = Hash::track(source, track);
+        if !seen.insert(hash) {
+            continue;
+        }
+        totals.track_count += 1;
+        if cache.find(hash).is_some() {
+            totals.downloaded += 1;
+        }
+        if let Some(full) = duration::read(&cache.sidecar(hash, duration::SIDECAR_EXT)) {
+            totals.playtime += track.played_length(full);
+        }
+        totals.disk_usage += sizes.get(&hash).copied().unwrap_or(0);
+    }
+    Ok(())
+}
+
+/// Prints the number of tracks, their length, disk usage, and download coverage (of every playlist, and in total),
+/// along with the `top` most played tracks
+pub fn print(res: &Resolver, top: usize, format: Format) -> Result<()> {
+    let sizes = store::sizes(res)?;
+    let mut totals = Totals::default();
+    let mut seen_total = HashSet::new();
+    let mut playlists = vec![];
+    for playlist in &res.out().playlists {
+        let mut pl_totals = Totals::default();
+        add_tracks(res, playlist, &sizes, &mut HashSet::new(), &mut pl_totals)?;
+        add_tracks(res, playlist, &sizes, &mut seen_total, &mut totals)?;
+        playlists.push(PlaylistStats {
+            name: playlist.name.clone(),
+            archived: playlist.archived,
+            coverage: pl_totals.coverage(),
+            totals: pl_totals,
+        });
+    }
+
+    let mut most_played = plays::counts(res)?
+        .into_iter()
+        .filter_map(|(hash, plays)| {
+            let (track, playlist) = res.out().find_track(hash)?;
+            Some(Played {
+                name: track.meta.name.clone(),
+                artist: track.meta.artist.clone(),
+                playlist: playlist.name.clone(),
+                plays,
+            })
+        })
+        .collect::<Vec<_>>();
+    most_played.sort_by(|a, b| b.plays.cmp(&a.plays).then_with(|| a.name.cmp(&b.name)));
+    most_played.truncate(top);
+
+    let stats = Stats {
+        coverage: totals.coverage(),
+        totals,
+        store_size: sizes.values().sum(),
+        playlists,
+        most_played,
+    };
+    match format {
+        Format::Json => output::print_json(&stats)?,
+        Format::Text => {
+            println!("{} {}", "Total:".bold(), stats.totals.summary());
+            println!(
+                "{} {:.1} MiB (including downloads no playlist uses)",
+                "Store:".bold(),
+                stats.store_size as f64 / (1024.0 * 1024.0)
+            );
+            println!();
+            for playlist in &stats.playlists {
+                let archived = if playlist.archived { ", archived" } else { "" };
+                println!(
+                    "{}: {}{archived}",
+                    playlist.name.clone().bold(),
+                    playlist.totals.summary()
+                );
+            }
+            println!();
+            if stats.most_played.is_empty() {
+                println!("{}", "No tracks have been played yet".bold());
+            } else {
+                println!("{}", "Most played:".bold());
+                let width = stats.most_played[0].plays.to_string().len();
+                for played in &stats.most_played {
+                    println!(
+                        "  {:>width$} {} - {} ({})",
+                        played.plays, played.artist, played.name, played.playlist
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,152 @@
+//! Status of the running player, for `dmm status`
+//!
+//! The player keeps a file in the `run` directory up to date with what it is playing, and removes it when it exits
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+use color_eyre::eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    output::{self, Format},
+    resolver::Resolver,
+};
+
+/// Name of the player status file (in the `run` directory)
+const STATUS_FILE: &str = "player-status.ron";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    Playing,
+    Paused,
+    Stopped,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Status {
+    /// process id of the player
+    pub pid: u32,
+    pub state: State,
+    pub playlist: String,
+    pub name: String,
+    pub artist: String,
+    /// position of the track in its playlist (starting at 1)
+    pub track: usize,
+    pub tracks: usize,
+    /// seconds into the track
+    pub position: u64,
+    /// length of the track (in seconds), if it is known
+    pub duration: Option<u64>,
+}
+
+/// What `dmm status` prints
+#[derive(Debug, Serialize)]
+struct Report {
+    running: bool,
+    #[serde(flatten)]
+    status: Option<Status>,
+}
+
+/// The status file of a running player. It is removed when this is dropped
+pub struct StatusFile {
+    path: PathBuf,
+    /// what was last written, so the file is only written when the status changes
+    last: Option<Status>,
+}
+
+impl StatusFile {
+    pub fn new(res: &Resolver) -> Self {
+        Self {
+            path: res.dirs().run.join(STATUS_FILE),
+            last: None,
+        }
+    }
+
+    pub fn update(&mut self, status: Status) -> Result<()> {
+        if self.last.as_ref() == Some(&status) {
+            return Ok(());
+        }
+        // written to a temporary file first, so `dmm status` never reads a partly written one
+        let tmp = self.path.with_extension("ron.tmp");
+        fs::write(&tmp, ron::to_string(&status)?)?;
+        fs::rename(&tmp, &self.path)?;
+        self.last = Some(status);
+        Ok(())
+    }
+}
+
+impl Drop for StatusFile {
+    fn drop(&mut self) {
+        if self.last.is_some() {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// If the process `pid` is running (assumed to be, where this can not be checked)
+fn is_running(pid: u32) -> bool {
+    let proc = Path::new("/proc");
+    !proc.is_dir() || proc.join(pid.to_string()).exists()
+}
+
+/// Reads the status of the running player (None if it is not running)
+fn read(res: &Resolver) -> Result<Option<Status>> {
+    let path = res.dirs().run.join(STATUS_FILE);
+    if !path.try_exists()? {
+        return Ok(None);
+    }
+    let status = match ron::from_str::<Status>(&fs::read_to_string(&path)?) {
+        Ok(status) => status,
+        Err(e) => {
+            warn!("Failed to read the player status ({path:?}): {e}");
+            return Ok(None);
+        }
+    };
+    // the file is left behind if the player does not exit cleanly
+    if status.pid != process::id() && !is_running(status.pid) {
+        debug!("the player that wrote {path:?} is no longer running");
+        return Ok(None);
+    }
+    Ok(Some(status))
+}
+
+/// Prints what the running player is playing
+pub fn print(res: &Resolver, format: Format) -> Result<()> {
+    let status = read(res)?;
+    match format {
+        Format::Json => output::print_json_line(&Report {
+            running: status.is_some(),
+            status,
+        })?,
+        Format::Text => match status {
+            Some(status) => {
+                let state = match status.state {
+                    State::Playing => "playing",
+                    State::Paused => "paused",
+                    State::Stopped => "stopped",
+                };
+                println!("{state}: {} - {}", status.name, status.artist);
+                println!(
+                    "track {} of {} in {:?}",
+                    status.track, status.tracks, status.playlist
+                );
+                let duration = match status.duration {
+                    Some(duration) => format!("{}:{:0>2}", duration / 60, duration % 60),
+                    None => "--:--".to_string(),
+                };
+                println!(
+                    "{}:{:0>2} of {duration}",
+                    status.position / 60,
+                    status.position % 60,
+                );
+            }
+            None => println!("the player is not running"),
+        },
+    }
+    Ok(())
+}
@@ -0,0 +1,761 @@
+//! Handling of `dmm store`
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs, io,
+    io::Cursor,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::{anyhow, bail, Result};
+use highway::{HighwayHash, HighwayHasher};
+use serde::Serialize;
+use symphonia::core::probe;
+
+use crate::{
+    cache::{self, CacheDir, Hash},
+    cover, download,
+    error::DmmError,
+    player2::{AudioDecoder, Decoded},
+    resolver::Resolver,
+    schema::{Meta, Playlist},
+};
+
+/// Default template for the names of extracted files
+pub const DEFAULT_TEMPLATE: &str = "{artist} - {title}.{ext}";
+
+/// Extension of the checksum sidecar files in the store
+pub const CHECKSUM_EXT: &str = "sum";
+
+/// Checksum of the contents of a file
+fn checksum(data: &[u8]) -> String {
+    let mut hasher = HighwayHasher::default();
+    hasher.append(data);
+    hasher
+        .finalize256()
+        .iter()
+        .map(|x| format!("{x:016x}"))
+        .collect()
+}
+
+/// Records the checksum of a downloaded file in `sidecar`, so `dmm store verify` can tell if it changes later.
+/// Returns the checksum
+pub fn write_checksum(path: &Path, sidecar: &Path) -> io::Result<String> {
+    let sum = checksum(&fs::read(path)?);
+    fs::write(sidecar, &sum)?;
+    Ok(sum)
+}
+
+/// The files in the store, by the checksum of their contents.
+///
+/// The store is keyed on source + input, so the same file downloaded through two sources (or two URLs)
+/// would be stored twice. Instead, identical files are stored once, and hardlinked under each of their hashes
+#[derive(Debug, Default)]
+pub struct ContentIndex {
+    files: HashMap<String, PathBuf>,
+}
+
+impl ContentIndex {
+    /// Indexes the store using the checksum sidecars (entries without one are not included)
+    pub fn load(cache: &CacheDir) -> io::Result<Self> {
+        let mut index = Self::default();
+        for hash in cache.list()? {
+            if let Ok(sum) = fs::read_to_string(cache.sidecar(hash, CHECKSUM_EXT)) {
+                index
+                    .files
+                    .entry(sum.trim().to_string())
+                    .or_insert_with(|| cache.create(hash));
+            }
+        }
+        Ok(index)
+    }
+
+    /// Adds `path` (with checksum `sum`) to the index. If the store already has a file with the same contents,
+    /// `path` is replaced with a hardlink to it.
+    ///
+    /// Returns the number of bytes this saved
+    pub fn dedup(&mut self, path: &Path, sum: &str) -> io::Result<u64> {
+        let Some(existing) = self.files.get(sum).filter(|existing| existing.exists()) else {
+            self.files.insert(sum.to_string(), path.to_path_buf());
+            return Ok(0);
+        };
+        if same_file::is_same_file(existing, path)? {
+            return Ok(0);
+        }
+        // the checksum sidecar of the other file could be outdated, so the contents are compared as well
+        if fs::read(existing)? != fs::read(path)? {
+            return Ok(0);
+        }
+        let size = fs::metadata(path)?.len();
+        // linked under a temporary name first, so `path` is never missing
+        let tmp = path.with_extension("link");
+        let _ = fs::remove_file(&tmp);
+        fs::hard_link(existing, &tmp)?;
+        fs::rename(&tmp, path)?;
+        debug!("{path:?} is the same as {existing:?}, linked them");
+        Ok(size)
+    }
+}
+
+/// Handling of `dmm store dedup`: hardlinks identical files in the store together
+pub fn dedup(res: &Resolver) -> Result<()> {
+    let cache = &res.out().cache;
+    let mut index = ContentIndex::default();
+    let (mut linked, mut saved) = (0, 0);
+    let mut hashes = cache.list()?;
+    hashes.sort();
+    for hash in hashes {
+        let path = cache.create(hash);
+        let sidecar = cache.sidecar(hash, CHECKSUM_EXT);
+        let sum = match fs::read_to_string(&sidecar) {
+            Ok(sum) => sum.trim().to_string(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                debug!("adding missing checksum for {hash}");
+                write_checksum(&path, &sidecar)?
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let bytes = index.dedup(&path, &sum)?;
+        if bytes != 0 {
+            linked += 1;
+            saved += bytes;
+        }
+    }
+    info!(
+        "linked {linked} duplicate file(s), saving {:.1} MiB",
+        saved as f64 / (1024.0 * 1024.0)
+    );
+    Ok(())
+}
+
+/// Hashes of everything in the store that playlists use: downloads of their tracks, and cover art.
+///
+/// Also returns how many downloads are only found under the hash from before their source's `identity` was set
+/// (these are included, until `dmm store migrate` moves them)
+pub fn referenced(res: &Resolver) -> (HashSet<Hash>, usize) {
+    let out = res.out();
+    let mut hashes = HashSet::new();
+    let mut unmigrated = 0;
+    // each download is only counted once, however many playlists it is in
+    for (&hash, places) in &out.track_index {
+        hashes.insert(hash);
+        for &(playlist, track) in places {
+            let playlist = &out.playlists[playlist];
+            let track = &playlist.tracks[track];
+            // downloaded before the source's identity was set, and not moved yet
+            let old = Hash::of_kind(playlist.find_source(&track.src).unwrap(), track);
+            if old != hash
+                && out.cache.find(hash).is_none()
+                && out.cache.find(old).is_some()
+                && hashes.insert(old)
+            {
+                unmigrated += 1;
+            }
+            if let Some(url) = &track.meta.cover {
+                hashes.insert(Hash::cover(url));
+            }
+        }
+    }
+    (hashes, unmigrated)
+}
+
+/// Marks a download as just played (by setting its modification time), so `dmm store prune` keeps it over
+/// ones that were played longer ago
+pub fn touch(path: &Path) -> io::Result<()> {
+    fs::File::options()
+        .write(true)
+        .open(path)?
+        .set_modified(SystemTime::now())
+}
+
+/// An entry of the store: a download (or cover art) and its sidecar files
+struct Entry {
+    hash: Hash,
+    files: Vec<StoreFile>,
+    /// size of all of its files (even ones that are hardlinked to files of other entries)
+    bytes: u64,
+    /// when the download was last played (or downloaded, if it has not been played since)
+    last_used: SystemTime,
+}
+
+struct StoreFile {
+    path: PathBuf,
+    bytes: u64,
+    /// which file it is on disk, as `dmm store dedup` hardlinks files with the same contents
+    id: Option<FileId>,
+}
+
+/// Device and inode of a file
+type FileId = (u64, u64);
+
+/// Which file `meta` is on disk (None where that is not known, so files are counted as separate copies)
+fn file_id(meta: &fs::Metadata) -> Option<FileId> {
+    // (same-file would need every file of the store to be kept open while they are compared)
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        Some((meta.dev(), meta.ino()))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = meta;
+        None
+    }
+}
+
+/// Every entry of the store
+fn entries(res: &Resolver) -> Result<Vec<Entry>> {
+    let mut entries = BTreeMap::<Hash, Entry>::new();
+    for file in res.dirs().cache.read_dir()? {
+        let file = file?;
+        let Some(hash) = cache::entry_hash(&file.file_name()) else {
+            continue;
+        };
+        let meta = file.metadata()?;
+        let entry = entries.entry(hash).or_insert(Entry {
+            hash,
+            files: vec![],
+            bytes: 0,
+            last_used: UNIX_EPOCH,
+        });
+        entry.files.push(StoreFile {
+            path: file.path(),
+            bytes: meta.len(),
+            id: file_id(&meta),
+        });
+        entry.bytes += meta.len();
+        // (the download itself, rather than a sidecar, is what is touched when it is played)
+        if file.file_name().to_string_lossy() == hash.to_string() {
+            entry.last_used = meta.modified()?;
+        }
+    }
+    Ok(entries.into_values().collect())
+}
+
+/// Disk usage of each entry of the store (the download, and its sidecar files), in bytes
+pub fn sizes(res: &Resolver) -> Result<HashMap<Hash, u64>> {
+    Ok(entries(res)?
+        .into_iter()
+        .map(|entry| (entry.hash, entry.bytes))
+        .collect())
+}
+
+/// Handling of `dmm store prune` (also run after `dmm download`): if the store is larger than `cache.max_size`,
+/// removes the least recently played entries until it fits. Entries that no playlist uses are removed first,
+/// and ones that are used only if that is not enough (they are downloaded again by the next `dmm download`).
+///
+/// If a playlist or source failed to load, the entries it uses would look unused, so nothing is removed unless
+/// `force` is given
+pub fn prune(res: &Resolver, dry_run: bool, force: bool) -> Result<()> {
+    let Some(max_size) = res.out().config.cache.max_size else {
+        info!("`cache.max_size` is not set in dmm.ron, so there is nothing to prune");
+        return Ok(());
+    };
+    let mib = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+    let mut entries = entries(res)?;
+    // how many links the store has to each file (hardlinked ones are only counted once, and are only freed once
+    // the last link is removed)
+    let mut links = HashMap::<FileId, usize>::new();
+    let mut size = 0;
+    for file in entries.iter().flat_map(|entry| &entry.files) {
+        let first = file.id.is_none_or(|id| {
+            let count = links.entry(id).or_default();
+            *count += 1;
+            *count == 1
+        });
+        if first {
+            size += file.bytes;
+        }
+    }
+    if size <= max_size {
+        info!(
+            "the store uses {:.1} MiB of its {:.1} MiB",
+            mib(size),
+            mib(max_size)
+        );
+        return Ok(());
+    }
+    let (referenced, _) = referenced(res);
+    if let Some(failed) = res.out().load_summary() {
+        for e in res
+            .out()
+            .failed_playlists
+            .iter()
+            .chain(&res.out().failed_sources)
+        {
+            error!("{e}");
+        }
+        if dry_run || force {
+            warn!("{failed}, so files they use are counted as unused");
+        } else {
+            info!("Fix them (see `dmm check`), or pass `--force` to `dmm store prune` to prune anyway");
+            bail!("{failed}, not pruning the store");
+        }
+    }
+    // unused entries first, then the least recently used first
+    entries.sort_by_key(|entry| (referenced.contains(&entry.hash), entry.last_used));
+    let (mut removed, mut used) = (0, 0);
+    for entry in entries {
+        if size <= max_size {
+            break;
+        }
+        let in_use = referenced.contains(&entry.hash);
+        let mut freed = 0;
+        for file in &entry.files {
+            let last = file.id.is_none_or(|id| {
+                let count = links.get_mut(&id).unwrap();
+                *count -= 1;
+                *count == 0
+            });
+            if last {
+                freed += file.bytes;
+            }
+        }
+        info!(
+            "removing {} ({:.1} MiB{})",
+            entry.hash,
+            mib(freed),
+            if in_use { ", used by a playlist" } else { "" }
+        );
+        if !dry_run {
+            for file in &entry.files {
+                fs::remove_file(&file.path)?;
+            }
+        }
+        size -= freed;
+        removed += 1;
+        used += usize::from(in_use);
+    }
+    match dry_run {
+        true => info!(
+            "would remove {removed} entries, leaving {:.1} MiB (without --dry-run)",
+            mib(size)
+        ),
+        false => info!("removed {removed} entries, leaving {:.1} MiB", mib(size)),
+    }
+    if used > 0 {
+        warn!("{used} of them are used by playlists, and will be downloaded again by `dmm download` (raise `cache.max_size` to keep them)");
+    }
+    Ok(())
+}
+
+/// Directory `dmm store gc --trash` moves files to (in a directory for each time it is run)
+fn trash_dir(res: &Resolver) -> PathBuf {
+    res.dirs().run.join("trash")
+}
+
+/// Creates the directory in the trash for the files of this run of `dmm store gc --trash`
+pub fn new_trash_batch(res: &Resolver) -> Result<PathBuf> {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let dir = trash_dir(res).join(format!("gc-{secs}"));
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Moves a file, copying it if it can not be renamed (such as when moving to another filesystem)
+pub fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to)?;
+        fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+/// Handling of `dmm store restore`: moves the files of a run of `dmm store gc --trash` back into the store
+/// (the most recent one, if `batch` is not given), or lists what is in the trash
+pub fn restore(res: &Resolver, batch: Option<&str>, list: bool) -> Result<()> {
+    let trash = trash_dir(res);
+    let mut batches = match fs::read_dir(&trash) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect::<Vec<_>>(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+        Err(e) => return Err(e.into()),
+    };
+    batches.sort();
+    if list {
+        if batches.is_empty() {
+            info!("the trash is empty");
+        }
+        for name in &batches {
+            let count = fs::read_dir(trash.join(name))?.count();
+            println!("{name}: {count} file(s)");
+        }
+        return Ok(());
+    }
+    let name = match batch {
+        Some(batch) if batches.iter().any(|name| name == batch) => batch,
+        Some(batch) => {
+            error!("{batch:?} is not in the trash (see `dmm store restore --list`)");
+            bail!(DmmError::NotFound);
+        }
+        None => match batches.last() {
+            Some(name) => name,
+            None => {
+                error!("the trash is empty");
+                bail!("nothing to restore");
+            }
+        },
+    };
+    let dir = trash.join(name);
+    let (mut restored, mut kept) = (0, 0);
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        let to = res.dirs().cache.join(entry.file_name());
+        if to.exists() {
+            warn!(
+                "{:?} is already in the store (it was downloaded again), leaving it in the trash",
+                entry.file_name()
+            );
+            kept += 1;
+            continue;
+        }
+        move_file(&entry.path(), &to)?;
+        restored += 1;
+    }
+    if kept == 0 {
+        fs::remove_dir(&dir)?;
+    }
+    info!("restored {restored} file(s) from {name}");
+    Ok(())
+}
+
+/// A download moved by `dmm store migrate`, for `--output json`
+#[derive(Debug, Clone, Serialize)]
+pub struct Migrated {
+    pub name: String,
+    pub from: String,
+    pub to: String,
+}
+
+/// Result of `dmm store migrate`, for `--output json`
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrateReport {
+    pub dry_run: bool,
+    /// downloads that were moved (or would be, with `--dry-run`)
+    pub migrated: Vec<Migrated>,
+}
+
+/// Handling of `dmm store migrate`: moves the downloads of sources with an `identity` from the hash they were
+/// downloaded under (which comes from the kind of the source) to the hash of the identity.
+///
+/// The old hash is found from the source as it is now, so this has to be run before its command or arguments change
+pub fn migrate(res: &Resolver, dry_run: bool) -> Result<MigrateReport> {
+    let cache = &res.out().cache;
+    let mut report = MigrateReport {
+        dry_run,
+        migrated: vec![],
+    };
+    let mut seen = HashSet::new();
+    for playlist in &res.out().playlists {
+        for track in &playlist.tracks {
+            let Some(source) = playlist
+                .find_source(&track.src)
+                .filter(|source| source.identity.is_some())
+            else {
+                continue;
+            };
+            let from = Hash::of_kind(source, track);
+            let to = Hash::track(source, track);
+            if !seen.insert(from) || cache.find(from).is_none() {
+                continue;
+            }
+            if cache.find(to).is_some() {
+                warn!(
+                    "{} is stored under both its old and new hash, keeping the new one (`dmm store gc` removes the other)",
+                    track.meta.name
+                );
+                continue;
+            }
+            info!("moving {} from {from} to {to}", track.meta.name);
+            if !dry_run {
+                cache.rename(from, to)?;
+            }
+            report.migrated.push(Migrated {
+                name: track.meta.name.clone(),
+                from: from.to_string(),
+                to: to.to_string(),
+            });
+        }
+    }
+    match dry_run {
+        true => info!("would move {} download(s)", report.migrated.len()),
+        false => info!("moved {} download(s)", report.migrated.len()),
+    }
+    Ok(report)
+}
+
+/// Fills in the placeholders in a file name template.
+///
+/// - `{artist}`: track artist
+/// - `{title}`: track name
+/// - `{ext}`: file extension (the format of the track's source)
+/// - `{tracknum}`: position of the track in the playlist, zero padded
+///
+/// Any path separators in the substituted values are replaced (as are values of `.` and `..`), so a track can not
+/// escape the output directory. The template is filled in a single pass, so placeholders in the values are kept as
+/// they are, and unknown placeholders are left in the name.
+fn fill_template(template: &str, artist: &str, title: &str, ext: &str, tracknum: &str) -> String {
+    let clean = |s: &str| match s {
+        "." | ".." => s.replace('.', "_"),
+        s => s.replace(['/', '\\', '\0'], "_"),
+    };
+    let mut name = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        name.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest.find('}').and_then(|end| {
+            let value = match &rest[1..end] {
+                "artist" => clean(artist),
+                "title" => clean(title),
+                "ext" => clean(ext),
+                "tracknum" => tracknum.to_string(),
+                _ => return None,
+            };
+            Some((value, end))
+        });
+        match value {
+            Some((value, end)) => {
+                name.push_str(&value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                name.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    name.push_str(rest);
+    name
+}
+
+/// A file found by `dmm store extract`, for `--output json`
+#[derive(Debug, Clone, Serialize)]
+pub struct Extracted {
+    /// the track this is (when extracting a playlist)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Meta>,
+    pub hash: String,
+    /// the file in the store
+    pub path: PathBuf,
+    pub format: String,
+    /// where the file was copied to (with `--copy-to`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub copied_to: Option<PathBuf>,
+}
+
+/// Result of extracting a playlist, for `--output json`
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtractReport {
+    pub playlist: String,
+    pub tracks: Vec<Extracted>,
+    /// tracks that are not downloaded
+    pub missing: Vec<Meta>,
+}
+
+/// Extracts every downloaded track in `playlist` from the store.
+///
+/// If `copy_to` is given, files are copied into that directory (which is created if needed),
+/// and named according to `template`. Otherwise, the location of each file in the store is printed.
+pub fn extract_playlist(
+    res: &Resolver,
+    playlist: &Playlist,
+    copy_to: Option<&Path>,
+    template: &str,
+) -> Result<ExtractReport> {
+    if let Some(dir) = copy_to {
+        fs::create_dir_all(dir)?;
+    }
+    let width = playlist.tracks.len().to_string().len();
+    let mut report = ExtractReport {
+        playlist: playlist.name.clone(),
+        tracks: vec![],
+        missing: vec![],
+    };
+    for (i, track) in playlist.tracks.iter().enumerate() {
+        let source = playlist.find_source(&track.src).ok_or(anyhow!(
+            "Could not find source {} for track {}",
+            track.src,
+            track.meta.name
+        ))?;
+        let hash = Hash::track(source, track);
+        let Some(found) = res.out().cache.find(hash) else {
+            warn!("{} is not downloaded [skipping]", track.meta.name);
+            report.missing.push(track.meta.clone());
+            continue;
+        };
+        let copied_to = match copy_to {
+            Some(dir) => {
+                let name = fill_template(
+                    template,
+                    &track.meta.artist,
+                    &track.meta.name,
+                    source.stored_format(),
+                    &format!("{:0>width$}", i + 1),
+                );
+                let path = dir.join(name);
+                info!("Copying {} to {path:?}", track.meta.name);
+                fs::copy(&found, &path)?;
+                Some(path)
+            }
+            None => {
+                info!(
+                    "{}: file path is {found:?} (file format: '{}')",
+                    track.meta.name,
+                    source.stored_format()
+                );
+                None
+            }
+        };
+        report.tracks.push(Extracted {
+            meta: Some(track.meta.clone()),
+            hash: hash.to_string(),
+            path: found,
+            format: source.stored_format().to_string(),
+            copied_to,
+        });
+    }
+    info!(
+        "extracted {} of {} tracks",
+        report.tracks.len(),
+        playlist.tracks.len()
+    );
+    Ok(report)
+}
+
+/// What a store entry contains
+#[derive(Debug, Clone)]
+enum Content {
+    /// a track, in the given format (if known)
+    Audio(Option<String>),
+    Cover,
+}
+
+/// Checks that a store entry is intact, returning a description of the problem if it is not.
+///
+/// If the entry has no checksum yet (it was downloaded with an older version of DMM), one is written
+fn check(res: &Resolver, hash: Hash, content: Content) -> Result<Option<String>> {
+    let path = res.out().cache.create(hash);
+    let sidecar = res.out().cache.sidecar(hash, CHECKSUM_EXT);
+    let data = fs::read(&path)?;
+    if data.is_empty() {
+        return Ok(Some("the file is empty".to_string()));
+    }
+    let sum = checksum(&data);
+    let expected = match fs::read_to_string(&sidecar) {
+        Ok(expected) => Some(expected),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+    if expected
+        .as_ref()
+        .is_some_and(|expected| expected.trim() != sum)
+    {
+        return Ok(Some(
+            "the file has changed since it was downloaded (checksum mismatch)".to_string(),
+        ));
+    }
+
+    let format = match content {
+        Content::Audio(format) => format,
+        Content::Cover => {
+            if let Some(problem) = cover::check(&data) {
+                return Ok(Some(format!("failed to decode the image: {problem}")));
+            }
+            if expected.is_none() {
+                debug!("adding missing checksum for {hash}");
+                fs::write(sidecar, sum)?;
+            }
+            return Ok(None);
+        }
+    };
+    let mut hint = probe::Hint::new();
+    if let Some(format) = &format {
+        hint.with_extension(format);
+    }
+    let mut decoder = match AudioDecoder::new(Cursor::new(data), hint) {
+        Ok(decoder) => decoder,
+        Err(e) => return Ok(Some(format!("failed to probe: {e}"))),
+    };
+    // only the start of the track is decoded (`dmm bench` decodes everything)
+    loop {
+        match decoder.decode_next() {
+            Ok(Decoded::Decoded(..)) => break,
+            Ok(Decoded::Retry) => continue,
+            Ok(Decoded::StreamEnd) => return Ok(Some("the file contains no audio".to_string())),
+            Err(e) => return Ok(Some(format!("failed to decode: {e}"))),
+        }
+    }
+
+    if expected.is_none() {
+        debug!("adding missing checksum for {hash}");
+        fs::write(sidecar, sum)?;
+    }
+    Ok(None)
+}
+
+/// Checks every entry in the store, reporting any that are empty, corrupt, or have changed since they were downloaded.
+///
+/// With `fix`, broken entries are deleted and downloaded again
+pub fn verify(res: &Resolver, fix: bool, jobs: usize) -> Result<()> {
+    // name and contents of each entry, from the playlists that use it
+    let mut entries = BTreeMap::<Hash, (String, Content)>::new();
+    for pl in &res.out().playlists {
+        for track in &pl.tracks {
+            if let Some(source) = pl.find_source(&track.src) {
+                entries
+                    .entry(Hash::track(source, track))
+                    .or_insert_with(|| {
+                        (
+                            track.meta.name.clone(),
+                            Content::Audio(Some(source.stored_format().to_string())),
+                        )
+                    });
+            }
+            if let Some(url) = &track.meta.cover {
+                entries
+                    .entry(Hash::cover(url))
+                    .or_insert_with(|| (format!("cover of {}", track.meta.name), Content::Cover));
+            }
+        }
+    }
+
+    let hashes = res.out().cache.list()?;
+    let mut broken = vec![];
+    for (i, hash) in hashes.iter().enumerate() {
+        let entry = entries.get(hash);
+        let name = entry
+            .map(|(name, _)| name.clone())
+            .unwrap_or_else(|| format!("<unreferenced {hash}>"));
+        let content = entry.map_or(Content::Audio(None), |(_, content)| content.clone());
+        match check(res, *hash, content)? {
+            None => debug!("[{}/{}] {name}: ok", i + 1, hashes.len()),
+            Some(problem) => {
+                error!("[{}/{}] {name}: {problem}", i + 1, hashes.len());
+                broken.push(*hash);
+            }
+        }
+    }
+
+    info!(
+        "checked {} entries, {} are broken",
+        hashes.len(),
+        broken.len()
+    );
+    if broken.is_empty() {
+        return Ok(());
+    }
+    if !fix {
+        info!("Run `dmm store verify --fix` to delete and re-download them");
+        bail!("store verification failed");
+    }
+    for hash in &broken {
+        info!("deleting {hash}");
+        res.out().cache.remove(*hash)?;
+    }
+    download::download_hashes(res, &broken.into_iter().collect::<HashSet<_>>(), jobs, None)
+}
@@ -0,0 +1,384 @@
+//! Handling of `dmm sync`
+//!
+//! Playlists, albums, and sources are always synchronized in full (they are small), while store entries
+//! are only transferred if the receiving side does not have them yet. Since entries are named by
+//! their hash, an entry that exists on both sides is guaranteed to be the same download.
+//!
+//! The other side can either be a local path (such as a mounted drive), or `[user@]host:path`,
+//! in which case the transfer is done using `rsync` over ssh.
+//!
+//! Like fetching from an index, pulling remembers the version of each file it pulled last time, so files changed on
+//! both sides since then are conflicts, which are left alone unless `--force` is given.
+//!
+//! Playlists and sources can also be fetched (without the store) from a shared index, see [`index`],
+//! and a playlist can be carried to a machine without internet access in a [`bundle`]
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use color_eyre::eyre::{anyhow, bail, Result};
+
+use crate::{
+    cache::{self, CacheDir, Hash},
+    error::DmmError,
+    playlist,
+    resolver::{Directories, Resolver},
+    schema::Playlist,
+    source,
+};
+
+mod bundle;
+mod index;
+
+pub use bundle::{export, import};
+pub use index::fetch_index;
+
+/// Name of the state of the last pull (in the `run` directory), see [`index::State`]
+const PULL_STATE_FILE: &str = "sync-pull-state.ron";
+/// Copy of the other side's playlists, sources, and albums, when pulling over ssh (in the `run` directory)
+const PULL_DIR: &str = "sync-pull";
+
+/// The other music directory taking part in a sync
+#[derive(Debug, Clone)]
+pub enum Target {
+    /// a directory on this machine
+    Local(PathBuf),
+    /// `[user@]host:path`, accessed with rsync
+    Remote(String),
+}
+
+impl Target {
+    pub fn parse(target: &str) -> Self {
+        // `C:\...` is a local path, not a host named `C`
+        let looks_remote = target
+            .split_once(':')
+            .is_some_and(|(host, _)| host.len() > 1 && !host.contains('/'));
+        if looks_remote && !Path::new(target).exists() {
+            Self::Remote(target.to_string())
+        } else {
+            Self::Local(PathBuf::from(target))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// from this music directory to the target
+    Push,
+    /// from the target to this music directory
+    Pull,
+}
+
+/// Synchronizes the music directory with `target`.
+///
+/// If `playlists` is not empty, only the store entries used by those playlists are transferred,
+/// otherwise the entries of every playlist are. With `force`, files that were changed on both sides are pulled anyway
+pub fn sync(
+    res: &mut Resolver,
+    target: &Target,
+    direction: Direction,
+    playlists: &[String],
+    force: bool,
+) -> Result<()> {
+    if let Target::Local(path) = target {
+        if direction == Direction::Pull && !path.join("playlists").is_dir() {
+            error!("{path:?} is not a music directory");
+            bail!(DmmError::SyncFailed);
+        }
+    }
+
+    // when pulling, the playlists on the other side decide what is needed, so fetch them first
+    if direction == Direction::Pull {
+        pull_metadata(res, target, force)?;
+        res.resolve()?;
+    }
+
+    let wanted = wanted_hashes(res, playlists)?;
+    match direction {
+        Direction::Push => {
+            push_metadata(res.dirs(), target)?;
+            let have = res.out().cache.list()?.into_iter().collect::<BTreeSet<_>>();
+            let missing = wanted.difference(&have).count();
+            if missing > 0 {
+                warn!("{missing} track(s) are not downloaded, and will not be pushed (run `dmm download` first)");
+            }
+            let hashes = wanted.intersection(&have).copied().collect();
+            transfer_store(&res.dirs().cache, target, direction, &hashes)?;
+        }
+        Direction::Pull => {
+            transfer_store(&res.dirs().cache, target, direction, &wanted)?;
+        }
+    }
+    info!("sync complete");
+    Ok(())
+}
+
+/// Hashes of the store entries used by the selected playlists
+fn wanted_hashes(res: &Resolver, playlists: &[String]) -> Result<BTreeSet<Hash>> {
+    let mut selected = vec![];
+    for name in playlists {
+        selected.push(crate::find_playlist(res, name)?);
+    }
+    if playlists.is_empty() {
+        selected.extend(&res.out().playlists);
+    }
+    let mut hashes = BTreeSet::new();
+    for pl in selected {
+        hashes.extend(playlist_hashes(pl)?);
+    }
+    Ok(hashes)
+}
+
+/// Hashes of the store entries used by `pl` (downloads of its tracks, and their cover art)
+fn playlist_hashes(pl: &Playlist) -> Result<BTreeSet<Hash>> {
+    let mut hashes = playlist::track_hashes(pl)?
+        .into_keys()
+        .collect::<BTreeSet<_>>();
+    hashes.extend(
+        pl.tracks
+            .iter()
+            .filter_map(|track| track.meta.cover.as_deref().map(Hash::cover)),
+    );
+    Ok(hashes)
+}
+
+/// Directories of the metadata that is synchronized, and if they are optional
+/// (albums are optional, so they are skipped if the sending side has none)
+const METADATA_DIRS: [(&str, bool); 3] =
+    [("sources", false), ("playlists", false), ("albums", true)];
+
+fn push_metadata(dirs: &Directories, target: &Target) -> Result<()> {
+    for (dir, optional) in METADATA_DIRS {
+        let local = dirs.root.join(dir);
+        if optional && !local.is_dir() {
+            continue;
+        }
+        match target {
+            Target::Local(path) => copy_changed(&local, &path.join(dir))?,
+            Target::Remote(remote) => {
+                let remote = format!("{}/{dir}/", remote.trim_end_matches('/'));
+                let local = format!("{}/", local.display());
+                info!("syncing {dir}");
+                rsync(&["--mkpath", &local, &remote], None)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Pulls the playlists, sources, and albums of `target` into the music directory. Files that were changed both here
+/// and there since the last pull are conflicts, and are only overwritten with `force`
+fn pull_metadata(res: &Resolver, target: &Target, force: bool) -> Result<()> {
+    // (the other side's files are copied here first when going over ssh, so they can be compared)
+    let from = match target {
+        Target::Local(path) => path.clone(),
+        Target::Remote(remote) => {
+            let copy = res.dirs().run.join(PULL_DIR);
+            for (dir, optional) in METADATA_DIRS {
+                let remote = format!("{}/{dir}/", remote.trim_end_matches('/'));
+                let local = format!("{}/", copy.join(dir).display());
+                info!("fetching {dir}");
+                let mut args = vec!["--mkpath", "--delete", &remote, &local];
+                if optional {
+                    args.insert(0, "--ignore-missing-args");
+                }
+                rsync(&args, None)?;
+            }
+            copy
+        }
+    };
+    let origin = match target {
+        Target::Local(path) => path.display().to_string(),
+        Target::Remote(remote) => remote.clone(),
+    };
+    let mut state = index::State::load(res, PULL_STATE_FILE, &origin)?;
+    state.index = origin;
+    let root = &res.dirs().root;
+    let mut paths = vec![];
+    for (dir, _) in METADATA_DIRS {
+        index::list_files(&from, &from.join(dir), &mut paths)?;
+    }
+
+    let mut writes = vec![];
+    let mut conflicts = vec![];
+    let mut kept = 0usize;
+    for path in paths {
+        let content = fs::read_to_string(from.join(&path))?;
+        let theirs = index::hash(&content);
+        let local = fs::read(root.join(&path)).ok().map(index::hash);
+        let base = state.files.get(&path);
+        if local.as_ref() == Some(&theirs) {
+            // already the same
+        } else if base == Some(&theirs) {
+            // only changed here, which is kept
+            kept += 1;
+            continue;
+        } else if local.is_some() && local.as_ref() != base && !force {
+            conflicts.push(path);
+            continue;
+        } else {
+            info!(
+                "{} {path:?}",
+                if local.is_some() {
+                    "updating"
+                } else {
+                    "adding"
+                }
+            );
+            writes.push((root.join(&path), content));
+        }
+        state.files.insert(path, theirs);
+    }
+    for (path, _) in &writes {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    source::write_all_or_nothing(&writes)?;
+    state.save(res, PULL_STATE_FILE)?;
+
+    if kept > 0 {
+        info!("kept {kept} file(s) that were only changed here");
+    }
+    if !conflicts.is_empty() {
+        for path in &conflicts {
+            error!("{path:?} was changed both here and in the other music directory");
+        }
+        info!("Merge the changes by hand, or run again with --force to use the other version");
+        bail!("sync conflicts");
+    }
+    Ok(())
+}
+
+/// Copies every file in `from` (recursively) that is missing or different in `to`
+fn copy_changed(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)?.filter_map(Result::ok) {
+        let dest = to.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            copy_changed(&entry.path(), &dest)?;
+            continue;
+        } else if !file_type.is_file() {
+            continue;
+        }
+        let content = fs::read(entry.path())?;
+        if fs::read(&dest).is_ok_and(|existing| existing == content) {
+            continue;
+        }
+        info!("updating {dest:?}");
+        fs::write(dest, content)?;
+    }
+    Ok(())
+}
+
+fn transfer_store(
+    cache_dir: &Path,
+    target: &Target,
+    direction: Direction,
+    hashes: &BTreeSet<Hash>,
+) -> Result<()> {
+    match target {
+        Target::Local(path) => {
+            let (from, to) = match direction {
+                Direction::Push => (cache_dir.to_path_buf(), path.join("cache")),
+                Direction::Pull => (path.join("cache"), cache_dir.to_path_buf()),
+            };
+            fs::create_dir_all(&to)?;
+            let have = CacheDir::new(to.clone())
+                .list()?
+                .into_iter()
+                .collect::<BTreeSet<_>>();
+            let mut copied = 0usize;
+            let mut unavailable = 0usize;
+            // copy the downloads along with their sidecar files
+            let mut entries = fs::read_dir(&from)?
+                .filter_map(Result::ok)
+                .filter_map(|entry| Some((cache::entry_hash(&entry.file_name())?, entry)))
+                .filter(|(hash, _)| hashes.contains(hash) && !have.contains(hash))
+                .collect::<Vec<_>>();
+            // sidecars sort after the entry itself, so the download is always copied first
+            entries.sort_by_key(|(_, entry)| entry.file_name());
+            for (hash, entry) in entries {
+                let dest = to.join(entry.file_name());
+                if entry.file_name().to_str() == Some(&hash.to_string()) {
+                    info!("copying {hash}");
+                    copied += 1;
+                }
+                let tmp = dest.with_extension("tmp");
+                fs::copy(entry.path(), &tmp)?;
+                fs::rename(tmp, dest)?;
+            }
+            if direction == Direction::Pull {
+                let available = CacheDir::new(from).list()?;
+                unavailable = hashes
+                    .iter()
+                    .filter(|hash| !have.contains(hash) && !available.contains(hash))
+                    .count();
+            }
+            if unavailable > 0 {
+                warn!("{unavailable} track(s) are not downloaded on the other side either (run `dmm download`)");
+            }
+            info!(
+                "transferred {copied} track(s) ({} already present)",
+                hashes.intersection(&have).count()
+            );
+        }
+        Target::Remote(remote) => {
+            let remote = format!("{}/cache/", remote.trim_end_matches('/'));
+            let local = format!("{}/", cache_dir.display());
+            let (from, to) = match direction {
+                Direction::Push => (local, remote),
+                Direction::Pull => (remote, local),
+            };
+            // only the selected entries (and their sidecars) are included, and rsync skips any that already exist
+            let filter = hashes
+                .iter()
+                .map(|hash| format!("+ /{hash}\n+ /{hash}.*\n"))
+                .collect::<String>();
+            info!("syncing store ({} track(s) selected)", hashes.len());
+            rsync(
+                &[
+                    "--mkpath",
+                    "--ignore-existing",
+                    // keeps files that were deduplicated in the store linked on the other side
+                    "--hard-links",
+                    "--filter=merge -",
+                    "--exclude=*",
+                    &from,
+                    &to,
+                ],
+                Some(&filter),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn rsync(args: &[&str], stdin: Option<&str>) -> Result<()> {
+    let mut child = Command::new("rsync")
+        .arg("--archive")
+        .arg("--human-readable")
+        .args(args)
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .spawn()
+        .map_err(|e| anyhow!("Failed to run rsync (is it installed?): {e}"))?;
+    if let Some(input) = stdin {
+        child.stdin.take().unwrap().write_all(input.as_bytes())?;
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        error!("rsync exited with {status}");
+        bail!(DmmError::SyncFailed);
+    }
+    Ok(())
+}
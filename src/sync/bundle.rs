@@ -0,0 +1,178 @@
+//! Offline bundles (`dmm store export` and `dmm store import`)
+//!
+//! A bundle is a tar archive with the layout of a music directory: a playlist (in `playlists/`), the album and
+//! source files it imports (in `albums/` and `sources/`), and the store entries of its tracks (in `cache/`, with
+//! their sidecar files).
+//! Importing it into another music directory needs no internet access, as nothing has to be downloaded.
+
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::{bail, Result};
+
+use crate::{
+    cache,
+    error::DmmError,
+    git,
+    resolver::Resolver,
+    schema::{Import, Playlist},
+    source,
+};
+
+use super::index::{is_contained, validate};
+
+/// Writes `playlist`, its albums and sources, and its downloads to a bundle at `to`
+pub fn export(res: &Resolver, playlist: &Playlist, to: &Path) -> Result<()> {
+    let root = &res.dirs().root;
+    let mut files = vec![playlist.file_path.clone()];
+    let mut sources = playlist
+        .import
+        .iter()
+        .filter_map(Import::source)
+        .collect::<Vec<_>>();
+    for name in playlist.import.iter().filter_map(Import::album) {
+        let Some(album) = res.out().albums.iter().find(|album| &album.name == name) else {
+            error!("Could not find the album named {name:?}");
+            bail!(DmmError::NotFound);
+        };
+        files.push(album.file_path.clone());
+        sources.extend(album.import.iter().filter_map(Import::source));
+    }
+    sources.sort();
+    sources.dedup();
+    for name in &sources {
+        let Some(source) = res.out().sources.iter().find(|src| &src.name == *name) else {
+            error!("Could not find the source named {name:?}");
+            bail!(DmmError::NotFound);
+        };
+        files.push(source.file_path.clone());
+    }
+
+    let hashes = super::playlist_hashes(playlist)?;
+    let mut entries = fs::read_dir(&res.dirs().cache)?
+        .filter_map(Result::ok)
+        .filter(|entry| cache::entry_hash(&entry.file_name()).is_some_and(|h| hashes.contains(&h)))
+        .map(|entry| entry.path())
+        .collect::<Vec<_>>();
+    // sidecars sort after the entry itself, so the download is always unpacked first
+    entries.sort();
+    let bundled = entries
+        .iter()
+        .filter(|path| {
+            path.file_name()
+                .and_then(cache::entry_hash)
+                .is_some_and(|hash| path.ends_with(hash.to_string()))
+        })
+        .count();
+    if bundled < hashes.len() {
+        warn!(
+            "{} track(s) are not downloaded, and will not be bundled (run `dmm download` first)",
+            hashes.len() - bundled
+        );
+    }
+    files.extend(entries);
+
+    let mut archive = tar::Builder::new(fs::File::create(to)?);
+    let mut bytes = 0;
+    for path in &files {
+        let name = path.strip_prefix(root)?;
+        bytes += fs::metadata(path)?.len();
+        archive.append_path_with_name(path, name)?;
+    }
+    archive.into_inner()?.sync_all()?;
+    info!(
+        "bundled {:?} ({} source(s), {bundled} download(s), {:.1} MiB) into {to:?}",
+        playlist.name,
+        sources.len(),
+        bytes as f64 / (1024.0 * 1024.0)
+    );
+    Ok(())
+}
+
+/// Unpacks the bundle at `from` into the music directory.
+///
+/// Downloads that are already in the store are skipped. Playlists and sources that exist here with different
+/// contents are conflicts, and are left alone unless `force` is given (in which case the bundle's version is used)
+pub fn import(res: &Resolver, from: &Path, force: bool) -> Result<()> {
+    let root = &res.dirs().root;
+    let mut archive = tar::Archive::new(fs::File::open(from)?);
+    let mut writes = vec![];
+    let mut conflicts = vec![];
+    let (mut added, mut present) = (0usize, 0usize);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        if !is_contained(&path) || !entry.header().entry_type().is_file() {
+            warn!("Skipping {path:?} from the bundle, as it is not a file of a music directory");
+            continue;
+        }
+        if path.starts_with("cache") {
+            let Some(hash) = path.file_name().and_then(cache::entry_hash) else {
+                warn!("Skipping {path:?} from the bundle, as it is not a store entry");
+                continue;
+            };
+            let dest = root.join(&path);
+            if dest.exists() {
+                present += usize::from(path.ends_with(hash.to_string()));
+                continue;
+            }
+            let tmp = dest.with_extension("tmp");
+            entry.unpack(&tmp)?;
+            fs::rename(tmp, dest)?;
+            added += usize::from(path.ends_with(hash.to_string()));
+        } else if ["playlists", "sources", "albums"]
+            .iter()
+            .any(|dir| path.starts_with(dir))
+        {
+            let mut content = String::new();
+            entry.read_to_string(&mut content)?;
+            if let Err(e) = validate(&path, &content) {
+                warn!("Skipping {path:?} from the bundle, as it is invalid: {e}");
+                continue;
+            }
+            match fs::read_to_string(root.join(&path)) {
+                Ok(local) if local == content => {}
+                Ok(_) if !force => conflicts.push(path),
+                local => {
+                    info!(
+                        "{} {path:?}",
+                        if local.is_ok() { "updating" } else { "adding" }
+                    );
+                    writes.push((root.join(path), content));
+                }
+            }
+        } else {
+            warn!("Skipping {path:?} from the bundle, as it is not a file of a music directory");
+        }
+    }
+
+    for (path, _) in &writes {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    source::write_all_or_nothing(&writes)?;
+    let changed = writes
+        .into_iter()
+        .map(|(path, _)| path)
+        .collect::<Vec<PathBuf>>();
+    git::commit_changes(res, &changed, &format!("import bundle {}", from.display()));
+
+    info!(
+        "imported {added} download(s) ({present} already present), {} file(s) changed",
+        changed.len()
+    );
+    if !conflicts.is_empty() {
+        for path in &conflicts {
+            error!("{path:?} is different here than in the bundle");
+        }
+        info!(
+            "Merge the changes by hand, or run again with --force to use the version in the bundle"
+        );
+        bail!("import conflicts");
+    }
+    Ok(())
+}
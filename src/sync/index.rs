@@ -0,0 +1,295 @@
+//! Fetching shared playlists and sources from an index (`dmm sync fetch`)
+//!
+//! An index is either a git repository, or a directory served over HTTP(S) with an `index.ron` listing its files.
+//! Both have the layout of a music directory (`playlists/` and `sources/`), without the store.
+//!
+//! To find conflicts, the version of each file fetched last time is remembered (by its hash). A file that changed
+//! on both sides since then is a conflict, and is left alone unless `--force` is given.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use color_eyre::eyre::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    git,
+    resolver::Resolver,
+    schema::{self, Album, Playlist, Source},
+    source,
+};
+
+/// Name of the sync state file (in the `run` directory)
+const STATE_FILE: &str = "sync-state.ron";
+/// Checkout of an index that is a git repository (in the `run` directory)
+const MIRROR_DIR: &str = "sync-index";
+
+/// Where shared playlists and sources are fetched from
+#[derive(Debug, Clone)]
+enum Index {
+    Git(String),
+    Http(String),
+}
+
+impl Index {
+    /// `git+<url>` and URLs ending in `.git` are git repositories, as is anything that is not HTTP(S)
+    fn parse(url: &str) -> Self {
+        if let Some(url) = url.strip_prefix("git+") {
+            Self::Git(url.to_string())
+        } else if (url.starts_with("https://") || url.starts_with("http://"))
+            && !url.trim_end_matches('/').ends_with(".git")
+        {
+            Self::Http(url.trim_end_matches('/').to_string())
+        } else {
+            Self::Git(url.to_string())
+        }
+    }
+}
+
+/// Files listed by the `index.ron` of an index served over HTTP(S)
+#[derive(Debug, Deserialize)]
+struct HttpIndex {
+    /// paths relative to `playlists/`
+    #[serde(default)]
+    playlists: Vec<PathBuf>,
+    /// paths relative to `sources/`
+    #[serde(default)]
+    sources: Vec<PathBuf>,
+}
+
+/// Files as they were fetched last time (also used by `dmm sync pull`, for the files pulled last time)
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(super) struct State {
+    /// the index they were fetched from (or the music directory they were pulled from)
+    pub index: String,
+    /// hash of each file (by its path in the music directory)
+    pub files: BTreeMap<PathBuf, String>,
+}
+
+impl State {
+    fn path(res: &Resolver, file: &str) -> PathBuf {
+        res.dirs().run.join(file)
+    }
+
+    /// The state of the last fetch from `index`, kept in `file` (in the `run` directory). It is empty if the last
+    /// fetch was from a different index
+    pub fn load(res: &Resolver, file: &str, index: &str) -> Result<Self> {
+        let path = Self::path(res, file);
+        if !path.try_exists()? {
+            return Ok(Self::default());
+        }
+        match ron::from_str::<Self>(&fs::read_to_string(&path)?) {
+            Ok(state) if state.index == index => Ok(state),
+            Ok(_) => Ok(Self::default()),
+            Err(e) => {
+                error!("Failed to read the sync state ({path:?}): {e}");
+                info!("Deleting the file will reset it (every file that differs from the index is then a conflict)");
+                bail!("invalid sync state");
+            }
+        }
+    }
+
+    pub fn save(&self, res: &Resolver, file: &str) -> Result<()> {
+        fs::write(Self::path(res, file), schema::to_ron_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+pub(super) fn hash(content: impl AsRef<[u8]>) -> String {
+    format!("{:x}", md5::compute(content))
+}
+
+/// If `path` stays inside the directory it is relative to
+pub(super) fn is_contained(path: &Path) -> bool {
+    path.components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+/// Every file in `dir` (recursively), relative to `base`
+pub(super) fn list_files(base: &Path, dir: &Path, out: &mut Vec<PathBuf>) -> Result<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir)?.filter_map(Result::ok) {
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            list_files(base, &entry.path(), out)?;
+        } else if file_type.is_file() {
+            out.push(entry.path().strip_prefix(base)?.to_path_buf());
+        }
+    }
+    Ok(())
+}
+
+/// Fetches the playlists and sources of `index` (by their path in the music directory)
+fn fetch(res: &Resolver, index: &Index) -> Result<BTreeMap<PathBuf, String>> {
+    let mut files = BTreeMap::new();
+    match index {
+        Index::Git(url) => {
+            let mirror = res.dirs().run.join(MIRROR_DIR);
+            git::mirror(url, &mirror)?;
+            let mut paths = vec![];
+            for dir in ["playlists", "sources"] {
+                list_files(&mirror, &mirror.join(dir), &mut paths)?;
+            }
+            for path in paths {
+                let content = fs::read_to_string(mirror.join(&path))
+                    .map_err(|e| anyhow!("Failed to read {path:?} from the index: {e}"))?;
+                files.insert(path, content);
+            }
+        }
+        Index::Http(url) => {
+            let get = |path: &str| -> Result<String> {
+                let url = format!("{url}/{path}");
+                debug!("fetching {url}");
+                Ok(ureq::get(&url)
+                    .call()
+                    .map_err(|e| anyhow!("Failed to fetch {url}: {e}"))?
+                    .into_string()?)
+            };
+            let listing = ron::from_str::<HttpIndex>(&get("index.ron")?)
+                .map_err(|e| anyhow!("The index.ron of {url} is invalid: {e}"))?;
+            let listed = listing
+                .playlists
+                .iter()
+                .map(|path| Path::new("playlists").join(path))
+                .chain(
+                    listing
+                        .sources
+                        .iter()
+                        .map(|path| Path::new("sources").join(path)),
+                );
+            for path in listed {
+                if !is_contained(&path) {
+                    warn!("Skipping {path:?}, which is outside of the index");
+                    continue;
+                }
+                let content = get(&path.to_string_lossy())?;
+                files.insert(path, content);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Checks that a fetched file is a valid playlist, album, or source
+pub(super) fn validate(path: &Path, content: &str) -> Result<()> {
+    if path.starts_with("playlists") {
+        ron::from_str::<Playlist>(content)?;
+    } else if path.starts_with("albums") {
+        ron::from_str::<Album>(content)?;
+    } else {
+        ron::from_str::<Source>(content)?;
+    }
+    Ok(())
+}
+
+/// Fetches the playlists and sources of `url` into the music directory.
+///
+/// With `force`, the version of the index is used for conflicting files
+pub fn fetch_index(res: &Resolver, url: &str, force: bool) -> Result<()> {
+    let index = Index::parse(url);
+    info!("fetching playlists and sources from {url}");
+    let fetched = fetch(res, &index)?;
+    let mut state = State::load(res, STATE_FILE, url)?;
+    state.index = url.to_string();
+    let root = &res.dirs().root;
+
+    let mut writes = vec![];
+    let mut removals = vec![];
+    let mut conflicts = vec![];
+    let mut kept = 0usize;
+    for (path, content) in &fetched {
+        if let Err(e) = validate(path, content) {
+            warn!("Skipping {path:?} from the index, as it is invalid: {e}");
+            continue;
+        }
+        let remote = hash(content);
+        let local = fs::read_to_string(root.join(path)).ok().map(|l| hash(&l));
+        let base = state.files.get(path).cloned();
+        let changed_locally = local != base;
+        let changed_remotely = base.as_ref() != Some(&remote);
+        if local.as_ref() == Some(&remote) {
+            // already the same
+        } else if !changed_remotely {
+            // only changed here (or deleted here), which is kept
+            kept += 1;
+            continue;
+        } else if changed_locally && !force {
+            // (this includes files deleted here, but changed in the index)
+            conflicts.push(path.clone());
+            continue;
+        } else {
+            info!(
+                "{} {path:?}",
+                if local.is_some() {
+                    "updating"
+                } else {
+                    "adding"
+                }
+            );
+            writes.push((root.join(path), content.clone()));
+        }
+        state.files.insert(path.clone(), remote);
+    }
+    // files that were removed from the index are removed here too, unless they were changed here
+    for (path, base) in state.files.clone() {
+        if fetched.contains_key(&path) {
+            continue;
+        }
+        let local = fs::read_to_string(root.join(&path)).ok().map(|l| hash(&l));
+        match local {
+            Some(local) if local != base && !force => {
+                warn!("{path:?} was removed from the index, but is kept as it was changed here");
+            }
+            Some(_) => {
+                info!("removing {path:?}");
+                removals.push(root.join(&path));
+            }
+            None => {}
+        }
+        state.files.remove(&path);
+    }
+
+    for (path, _) in &writes {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    source::write_all_or_nothing(&writes)?;
+    for path in &removals {
+        fs::remove_file(path)?;
+    }
+    state.save(res, STATE_FILE)?;
+    let changed = writes
+        .into_iter()
+        .map(|(path, _)| path)
+        .chain(removals)
+        .collect::<Vec<_>>();
+    git::commit_changes(
+        res,
+        &changed,
+        &format!("fetch playlists and sources from {url}"),
+    );
+
+    if kept > 0 {
+        info!("kept {kept} file(s) that were only changed here");
+    }
+    if !conflicts.is_empty() {
+        for path in &conflicts {
+            error!("{path:?} was changed both here and in the index");
+        }
+        info!(
+            "Merge the changes by hand, or run again with --force to use the version in the index"
+        );
+        bail!("sync conflicts");
+    }
+    info!(
+        "fetched {} file(s), {} changed",
+        fetched.len(),
+        changed.len()
+    );
+    Ok(())
+}
@@ -1,6 +1,12 @@
 pub mod action;
 pub mod app;
+pub mod cast;
 pub mod components;
+pub mod hotkeys;
+pub mod inhibit;
 pub mod mode;
+pub mod mpris;
+pub mod remote;
 pub mod symbol;
 pub mod tui;
+pub mod watch;
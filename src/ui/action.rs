@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use super::mode::Mode;
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Action {
     Render,
@@ -7,6 +9,12 @@ pub enum Action {
     Quit,
     // application
     TrackComplete,
+    // the preloaded track started playing (without stopping in between)
+    PreloadStarted,
+    // the audio output failed (e.g. the device was disconnected), so playback was paused
+    OutputFailed(String),
+    // the current track could not be played (e.g. its file is broken), or the player stopped working
+    PlayerError(String),
     PausePlay,
     // change track selection method to the next option
     ChangeModeSelection,
@@ -14,11 +22,131 @@ pub enum Action {
     ChangeModeRepeat,
     // select the next track using the current selection mode
     NextTrack,
+    // go back to the previously played track (or the start of the current one, if it has been playing for a bit)
+    PrevTrack,
+    // seek to this many seconds into the current track
+    Seek(u64),
+    // turn the volume up/down a step
+    VolumeUp,
+    VolumeDown,
+    // set the volume (in percent, up to 100)
+    SetVolume(u8),
+    // play the track downloaded to the store as `hash`, from `position` seconds in (for another player casting to this one)
+    PlayStored {
+        hash: String,
+        position: u64,
+        paused: bool,
+    },
+    // mark the current position as the start/end of the part of the track to repeat (A-B repeat)
+    LoopMarkStart,
+    LoopMarkEnd,
+    // stop repeating part of the track
+    LoopClear,
     ListLeft,
     ListRight,
     ListSelNext,
     ListSelPrev,
     ListChooseSelected,
+    // expand/collapse the selected group in the playlist list
+    ListToggleGroup,
+    // mark/unmark the selected track, for the batch actions below (and QueueAdd)
+    ListToggleMark,
+    ListClearMarks,
+    // download the marked tracks (or the selected one), if they are not downloaded yet
+    ListDownload,
+    // download the marked tracks (or the selected one) again
+    ListRedownload,
+    // check the store again for which tracks are downloaded (such as after running `dmm download`)
+    RefreshDownloads,
+    // download every track in the selected playlist that is not downloaded yet
+    PlaylistDownload,
+    // some of the tracks downloading from the player are done (by the number the download was given, then how many are done out of the total)
+    DownloadProgress(u64, usize, usize),
+    // tracks downloading from the player (by the number the download was given) are done
+    DownloadDone(u64),
+    // remove the marked tracks (or the selected one) from the playlist file
+    ListRemoveTracks,
+    // open the popup to move/copy the marked tracks (or the selected one) to another playlist
+    ListMoveTracks,
+    ListCopyTracks,
+    // close the move/copy popup without changing anything
+    CloseTransfer,
+    TransferSelNext,
+    TransferSelPrev,
+    // move/copy the tracks to the selected playlist
+    TransferChoose,
+    // play a short part of the selected track, then go back to what was playing before
+    PreviewSelected,
+    // select the queue list
+    ListQueue,
+    // add the selected track to the end of the queue
+    QueueAdd,
+    // remove the selected entry from the queue
+    QueueRemove,
+    // move the selected queue entry towards the front/back of the queue
+    QueueMoveUp,
+    QueueMoveDown,
+    // switch to a different set of keybinds
+    ChangeMode(Mode),
+    // open the search popup
+    OpenSearch,
+    // close the search popup without choosing anything
+    CloseSearch,
+    SearchSelNext,
+    SearchSelPrev,
+    // play the selected search result
+    SearchChoose,
+    // open the output device picker
+    OpenDevices,
+    // close the device picker without changing the device
+    CloseDevices,
+    DeviceSelNext,
+    DeviceSelPrev,
+    // switch output to the selected device
+    DeviceChoose,
+    // open the tag filter popup
+    OpenTags,
+    // close the tag filter popup without changing the filter
+    CloseTags,
+    TagSelNext,
+    TagSelPrev,
+    // show only the tracks with the selected tag
+    TagChoose,
+    // show/hide the level meter of what is playing
+    ToggleMeter,
+    // show/hide the lyrics of the current track
+    ToggleLyrics,
+    // turn scrolling the track list along with the playing track on/off
+    ToggleFollow,
+    // open/close the popup listing the keybinds of every mode
+    ToggleHelp,
+    HelpScrollDown,
+    HelpScrollUp,
+    // start editing the order of the tracks in the current playlist
+    EditStart,
+    // move the selected track towards the start/end of the playlist (while editing)
+    EditMoveUp,
+    EditMoveDown,
+    // write the new track order to the playlist file
+    EditSave,
+    // stop editing without changing the playlist
+    EditCancel,
+    // files in the music directory changed, and should be loaded again
+    ReloadFiles,
+    // load the config, playlists, and sources again (for when changes to them are not noticed, such as on a network drive)
+    Reload,
+    // the config, playlists, and sources were reloaded
+    ConfigReloaded,
+    // show a short-lived message over the player
+    Toast(String, Severity),
+}
+
+/// How important a [`Action::Toast`] is, which decides its color and how long it is shown for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
 }
 
 // impl<'de> Deserialize<'de> for Action {
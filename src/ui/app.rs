@@ -1,17 +1,28 @@
 use std::sync::Arc;
 
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{bail, Result};
 use crossterm::event::KeyEvent;
+use flume::Sender;
 use ratatui::{layout::Size, prelude::Rect};
 
 use super::{
-    action::Action,
-    components::{fps::FpsCounter, home::Home, Component},
+    action::{Action, Severity},
+    cast::Target,
+    components::{fps::FpsCounter, home::Home, toast::Toasts, Component},
+    hotkeys,
     mode::Mode,
-    tui,
+    tui, watch,
 };
 use crate::resolver::Resolver;
 
+/// What the player starts playing right away (given on the command line)
+pub enum Play {
+    /// every track (see [`Home::play_all`])
+    All,
+    /// the playlist at this index in the resolved playlists
+    Playlist(usize),
+}
+
 pub struct App {
     pub frame_rate: f64,
     pub components: Vec<Box<dyn Component>>,
@@ -22,14 +33,31 @@ pub struct App {
 }
 
 impl App {
-    pub fn new(res: Resolver, frame_rate: f64) -> Result<Self> {
+    /// With `cast`, the music plays on that player instead (see [`super::cast`])
+    pub fn new(
+        mut res: Resolver,
+        frame_rate: f64,
+        play: Option<Play>,
+        cast: Option<Target>,
+    ) -> Result<Self> {
+        res.add_all_tracks();
+        res.add_smart_playlists();
         let resolver = Arc::new(res);
-        let home = Home::new(resolver.clone())?;
+        let mut home = Home::new(resolver.clone())?;
+        match play {
+            Some(Play::All) => home.play_all(),
+            Some(Play::Playlist(playlist)) => home.play_playlist(playlist),
+            None => {}
+        }
+        if let Some(target) = cast {
+            home.cast_to(target);
+        }
         let fps = FpsCounter::default();
+        let toasts = Toasts::default();
         let mode = Mode::Home;
         Ok(Self {
             frame_rate,
-            components: vec![Box::new(home), Box::new(fps)],
+            components: vec![Box::new(home), Box::new(fps), Box::new(toasts)],
             should_quit: false,
             mode,
             last_tick_key_events: Vec::new(),
@@ -40,17 +68,34 @@ impl App {
     pub fn run(&mut self) -> Result<()> {
         let (action_tx, action_rx) = flume::unbounded();
 
-        let mut tui = tui::Tui::new()?.frame_rate(self.frame_rate);
+        let mut tui = tui::Tui::new()?
+            .frame_rate(self.frame_rate)
+            .mouse(self.resolver.out().config.mouse);
         tui.enter()?;
 
         for component in self.components.iter_mut() {
             component.register_action_handler(action_tx.clone())?;
         }
+        self.show_log_toasts(&action_tx);
+
+        hotkeys::listen(
+            &self.resolver.out().config.global_hotkeys,
+            action_tx.clone(),
+        )?;
 
         for component in self.components.iter_mut() {
             component.register_config_handler(self.resolver.out().config.clone())?;
         }
 
+        // kept alive until the player exits
+        let _watcher = match watch::watch(self.resolver.dirs(), action_tx.clone()) {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                warn!("Failed to watch for changes to the config and playlists (they can still be reloaded with a keybind): {e}");
+                None
+            }
+        };
+
         for component in self.components.iter_mut() {
             let Size { width, height } = tui.size()?;
             component.init(Rect {
@@ -100,6 +145,10 @@ impl App {
                 }
                 match action {
                     Action::Quit => self.should_quit = true,
+                    Action::ChangeMode(mode) => {
+                        self.mode = mode;
+                        self.last_tick_key_events.clear();
+                    }
                     Action::Resize(w, h) => {
                         tui.resize(Rect::new(0, 0, w, h))?;
                         let mut errors = vec![];
@@ -114,6 +163,27 @@ impl App {
                             Err(errors.remove(0))?
                         }
                     }
+                    Action::ReloadFiles | Action::Reload => {
+                        match self.reload() {
+                            Ok(()) => {
+                                self.show_log_toasts(&action_tx);
+                                action_tx.send(Action::ConfigReloaded)?;
+                                if action == Action::Reload {
+                                    action_tx.send(Action::Toast(
+                                        "Reloaded the config and playlists".to_string(),
+                                        Severity::Info,
+                                    ))?;
+                                }
+                            }
+                            Err(e) => {
+                                error!("Failed to reload, keeping the previous config and playlists: {e}");
+                                action_tx.send(Action::Toast(
+                                    format!("Reload failed: {e}"),
+                                    Severity::Error,
+                                ))?;
+                            }
+                        }
+                    }
                     Action::Render => {
                         let mut errors = vec![];
                         tui.draw(|f| {
@@ -140,7 +210,35 @@ impl App {
                 break;
             }
         }
+        crate::log::set_toasts(None);
         tui.exit()?;
         Ok(())
     }
+
+    /// Shows warnings and errors that are logged as toasts, if the config says to
+    fn show_log_toasts(&self, action_tx: &Sender<Action>) {
+        let toasts = self.resolver.out().config.log.toasts;
+        crate::log::set_toasts(toasts.then(|| action_tx.clone()));
+    }
+
+    /// Resolves the music directory again, and hands the result to every component.
+    /// If resolving fails, nothing is changed
+    fn reload(&mut self) -> Result<()> {
+        let mut res = Resolver::new(self.resolver.dirs().root.clone());
+        res.resolve()?;
+        if res.out().playlists.iter().all(|pl| pl.tracks.is_empty()) {
+            bail!("there are no tracks to play");
+        }
+        res.add_all_tracks();
+        res.add_smart_playlists();
+        info!("Reloaded the config, playlists, and sources");
+        crate::log::set_level(&res.out().config.log.level);
+        self.resolver = Arc::new(res);
+        self.last_tick_key_events.clear();
+        for component in self.components.iter_mut() {
+            component.register_resolver(self.resolver.clone())?;
+            component.register_config_handler(self.resolver.out().config.clone())?;
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,345 @@
+//! Casting to another DMM player (`dmm ctl cast <host>`), so playlists can be browsed on one machine, while the music
+//! plays on another (such as the one wired to the speakers)
+//!
+//! The other player has to have its remote control enabled (see [`super::remote`]). While casting, the local player
+//! keeps playing, but silently, and the other player is told to play the same track, from the same position, whenever
+//! anything but the position changes. Tracks its store does not have (and their waveform, duration, and lyrics) are
+//! uploaded to it first, along with the tracks that are going to play next.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    fs::File,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use color_eyre::eyre::{anyhow, bail, Result};
+use flume::{Receiver, RecvTimeoutError, Sender, TryRecvError};
+
+use crate::{cache::Hash, duration, lyrics, waveform};
+
+/// Port of the remote control, if the host does not include one
+const DEFAULT_PORT: u16 = 8420;
+
+/// How far (in seconds) the position can be from where the other player should be, before it is told to seek
+const MAX_DRIFT: u64 = 2;
+
+/// How long to wait before trying again after the other player could not be reached
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Sidecar files that are sent along with a track
+const SIDECARS: [&str; 3] = [
+    duration::SIDECAR_EXT,
+    waveform::SIDECAR_EXT,
+    lyrics::SIDECAR_EXT,
+];
+
+/// How long the other player may take to answer (or to read more of an upload)
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The player to cast to
+#[derive(Debug, Clone)]
+pub struct Target {
+    /// such as `http://192.168.1.20:8420`
+    url: String,
+    token: Option<String>,
+    agent: ureq::Agent,
+}
+
+impl Target {
+    /// Checks that the remote control of the player at `host` (`host[:port]`, or a URL) can be reached
+    pub fn connect(host: &str, token: Option<String>) -> Result<Self> {
+        let host = host.trim_end_matches('/');
+        let mut url = if host.contains("://") {
+            host.to_string()
+        } else {
+            format!("http://{host}")
+        };
+        let authority = url.split_once("://").map_or("", |(_, rest)| rest);
+        if !authority.contains(':') {
+            url += &format!(":{DEFAULT_PORT}");
+        }
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(Duration::from_secs(5))
+            .timeout_read(TIMEOUT)
+            .timeout_write(TIMEOUT)
+            .build();
+        let target = Self { url, token, agent };
+        match target.request("GET", "/api/status").call() {
+            Ok(_) => {
+                info!("Casting to {}", target.url);
+                if target.token.is_none() {
+                    warn!("Without a token, tracks the other player does not have can not be sent to it");
+                }
+                Ok(target)
+            }
+            Err(ureq::Error::Status(403, _)) => {
+                error!("The player at {} did not accept the token", target.url);
+                info!("Pass the `remote.token` from its dmm.ron with --token");
+                bail!(
+                    "{} did not accept the token (see `remote.token` in its dmm.ron)",
+                    target.url
+                );
+            }
+            Err(e) => {
+                error!("Failed to reach the player at {}: {e}", target.url);
+                info!("The player has to be running there, with `remote: (enabled: true)` in its dmm.ron");
+                info!("(it only listens on 127.0.0.1 by default, set `remote.address` to \"0.0.0.0:{DEFAULT_PORT}\" to reach it from other machines)");
+                bail!(
+                    "could not connect to {} (is a player running there, with its remote control enabled?)",
+                    target.url
+                );
+            }
+        }
+    }
+
+    /// Where casting to (for showing in the player)
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn request(&self, method: &str, path: &str) -> ureq::Request {
+        let request = self.agent.request(method, &format!("{}{path}", self.url));
+        match &self.token {
+            Some(token) => request.query("token", token),
+            None => request,
+        }
+    }
+}
+
+/// What the other player should be doing
+#[derive(Debug, Clone, PartialEq)]
+pub struct Casting {
+    /// the playing track (None if playback is stopped)
+    pub track: Option<Hash>,
+    pub paused: bool,
+    /// seconds into the track
+    pub position: u64,
+    /// percent
+    pub volume: u8,
+}
+
+struct Update {
+    now: Casting,
+    /// when `now` was the case (the track has played on for a bit if it was not sent right away)
+    at: Instant,
+    /// tracks to upload after the playing one, if the other player does not have them yet
+    upcoming: Vec<Hash>,
+}
+
+pub struct Cast {
+    tx: Sender<Update>,
+    target: Target,
+    /// what the other player was last told, and when
+    sent: Option<(Casting, Instant)>,
+}
+
+impl Cast {
+    /// Starts casting (in the background). `store` is the directory of the local store
+    pub fn start(target: Target, store: PathBuf) -> Result<Self> {
+        let (tx, rx) = flume::unbounded();
+        thread::Builder::new().name("cast".to_string()).spawn({
+            let target = target.clone();
+            move || run(&target, &store, rx)
+        })?;
+        Ok(Self {
+            tx,
+            target,
+            sent: None,
+        })
+    }
+
+    pub fn target(&self) -> &Target {
+        &self.target
+    }
+
+    /// If the other player has to be told about `now`: anything but the position changed, or the position is not
+    /// about where the track would have got to by playing since it was last told
+    pub fn changed(&self, now: &Casting) -> bool {
+        let Some((sent, at)) = &self.sent else {
+            return true;
+        };
+        let expected = if sent.paused || sent.track.is_none() {
+            sent.position
+        } else {
+            sent.position + at.elapsed().as_secs()
+        };
+        let moved = now.position.abs_diff(expected) > MAX_DRIFT;
+        *now != Casting {
+            position: now.position,
+            ..sent.clone()
+        } || moved
+    }
+
+    /// Tells the other player about `now`, uploading the tracks in `upcoming` afterwards
+    pub fn send(&mut self, now: Casting, upcoming: Vec<Hash>) {
+        let at = Instant::now();
+        self.sent = Some((now.clone(), at));
+        let _ = self.tx.send(Update { now, at, upcoming });
+    }
+}
+
+/// Sends updates to the other player as they come in, and uploads the upcoming tracks while there are none
+fn run(target: &Target, store: &Path, rx: Receiver<Update>) {
+    let mut client = Client {
+        target,
+        store,
+        present: HashSet::new(),
+    };
+    // the last update the other player got, and the one it has not got yet (if sending it failed)
+    let mut told = None::<Casting>;
+    let mut pending = None::<Update>;
+    let mut upcoming = VecDeque::new();
+    let mut failing = false;
+    loop {
+        let received = if failing {
+            match rx.recv_timeout(RETRY_INTERVAL) {
+                Ok(update) => Some(update),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        } else if pending.is_none() && upcoming.is_empty() {
+            match rx.recv() {
+                Ok(update) => Some(update),
+                Err(_) => return,
+            }
+        } else {
+            match rx.try_recv() {
+                Ok(update) => Some(update),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        };
+        if let Some(update) = received {
+            // (only the newest one matters)
+            let update = rx.try_iter().last().unwrap_or(update);
+            upcoming = update.upcoming.iter().copied().collect();
+            pending = Some(update);
+        }
+        if let Some(update) = &pending {
+            match client.tell(update, told.as_ref()) {
+                Ok(()) => {
+                    if failing {
+                        info!("Casting to {} again", target.url);
+                    }
+                    failing = false;
+                    told = Some(update.now.clone());
+                    pending = None;
+                }
+                Err(e) => {
+                    if !failing {
+                        warn!("Failed to cast to {}: {e} [retrying]", target.url);
+                    }
+                    failing = true;
+                }
+            }
+        } else if let Some(hash) = upcoming.pop_front() {
+            if let Err(e) = client.upload(hash) {
+                debug!("Failed to upload an upcoming track to {}: {e}", target.url);
+            }
+        }
+    }
+}
+
+struct Client<'a> {
+    target: &'a Target,
+    store: &'a Path,
+    /// tracks the other player is known to have
+    present: HashSet<Hash>,
+}
+
+impl Client<'_> {
+    /// Makes the other player match `update` (`told` is what it was told before)
+    fn tell(&mut self, update: &Update, told: Option<&Casting>) -> Result<()> {
+        let now = &update.now;
+        if told.map(|told| told.volume) != Some(now.volume) {
+            self.post("/api/volume", &[("value", now.volume.to_string())])?;
+        }
+        let (hash, position, paused) = match (now.track, told) {
+            (Some(hash), _) => {
+                self.upload(hash)?;
+                let position = if now.paused {
+                    now.position
+                } else {
+                    now.position + update.at.elapsed().as_secs()
+                };
+                (hash, position, now.paused)
+            }
+            // stopped here, so it is paused there
+            (
+                None,
+                Some(Casting {
+                    track: Some(hash),
+                    position,
+                    paused: false,
+                    ..
+                }),
+            ) => (*hash, *position, true),
+            (None, _) => return Ok(()),
+        };
+        self.post(
+            "/api/play",
+            &[
+                ("hash", hash.to_string()),
+                ("position", position.to_string()),
+                ("paused", paused.to_string()),
+            ],
+        )
+    }
+
+    /// Uploads the track stored as `hash` (and its sidecar files), if the other player does not have it yet
+    fn upload(&mut self, hash: Hash) -> Result<()> {
+        let path = self.store.join(hash.to_string());
+        if self.present.contains(&hash) || !path.exists() {
+            return Ok(());
+        }
+        match self
+            .target
+            .request("GET", &format!("/api/store/{hash}"))
+            .call()
+        {
+            Ok(_) => {}
+            Err(ureq::Error::Status(404, _)) => {
+                for ext in SIDECARS {
+                    let sidecar = self.store.join(format!("{hash}.{ext}"));
+                    if sidecar.exists() {
+                        self.put(&format!("{hash}.{ext}"), &sidecar)?;
+                    }
+                }
+                info!("Uploading {hash} to {}", self.target.url);
+                self.put(&hash.to_string(), &path)?;
+            }
+            Err(e) => return Err(anyhow!("checking if it has {hash} failed - {e}")),
+        }
+        self.present.insert(hash);
+        Ok(())
+    }
+
+    fn put(&self, name: &str, path: &Path) -> Result<()> {
+        let file = File::open(path)?;
+        let length = file.metadata()?.len();
+        let result = self
+            .target
+            .request("PUT", &format!("/api/store/{name}"))
+            .set("Content-Length", &length.to_string())
+            .send(file);
+        match result {
+            // (it got there some other way in the meantime)
+            Ok(_) | Err(ureq::Error::Status(409, _)) => Ok(()),
+            Err(ureq::Error::Status(403, _)) => Err(anyhow!(
+                "uploading {name} failed - the other player only accepts uploads with a `remote.token` set"
+            )),
+            Err(e) => Err(anyhow!("uploading {name} failed - {e}")),
+        }
+    }
+
+    fn post(&self, path: &str, params: &[(&str, String)]) -> Result<()> {
+        let mut request = self.target.request("POST", path);
+        for (name, value) in params {
+            request = request.query(name, value);
+        }
+        request.call().map_err(|e| anyhow!("{path} failed - {e}"))?;
+        Ok(())
+    }
+}
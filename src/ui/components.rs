@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use color_eyre::eyre::Result;
 use crossterm::event::{KeyEvent, MouseEvent};
 use flume::Sender;
@@ -5,11 +7,13 @@ use ratatui::{layout::Rect, Frame};
 
 use crate::{
     cfg::Config,
+    resolver::Resolver,
     ui::{action::Action, tui::Event},
 };
 
 pub mod fps;
 pub mod home;
+pub mod toast;
 
 /// `Component` is a trait that represents a visual and interactive element of the user interface.
 /// Implementors of this trait can be registered with the main application loop and will be able to receive events,
@@ -41,6 +45,20 @@ pub trait Component {
     fn register_config_handler(&mut self, config: Config) -> Result<()> {
         Ok(())
     }
+    /// Register a new resolver, after the config, playlists, and sources were reloaded.
+    /// Called before [`Component::register_config_handler`] is called with the new config.
+    ///
+    /// # Arguments
+    ///
+    /// * `res` - The reloaded resolver.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<()>` - An Ok result or an error.
+    #[allow(unused_variables)]
+    fn register_resolver(&mut self, res: Arc<Resolver>) -> Result<()> {
+        Ok(())
+    }
     /// Initialize the component with a specified area if necessary.
     ///
     /// # Arguments
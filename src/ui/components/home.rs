@@ -1,40 +1,130 @@
-use std::{cmp, fs, sync::Arc};
+use std::{
+    cmp,
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    path::PathBuf,
+    process::Command,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
 
-use color_eyre::eyre::{anyhow, bail, Result};
-use cpal::traits::{DeviceTrait, HostTrait};
+use color_eyre::eyre::{anyhow, bail, Result, WrapErr};
+use cpal::traits::DeviceTrait;
+use crossterm::event::{KeyEvent, MouseButton, MouseEvent, MouseEventKind};
 use flume::Sender;
 use notify_rust::Notification;
-use rand::Rng;
+use rand::seq::SliceRandom;
 use ratatui::{prelude::*, widgets::*};
+use serde::{Deserialize, Serialize};
 
 use super::Component;
 use crate::{
     cache,
     cfg::Config,
+    duration,
+    error::DmmError,
+    lyrics::{self, Lyrics},
     player2::{self, SingleTrackPlayer},
+    plays,
     resolver::Resolver,
     schema::{Playlist, Track},
-    ui::action::Action,
+    scrobble::{self, Scrobbler},
+    status::{self, StatusFile},
+    store,
+    ui::{
+        action::{Action, Severity},
+        cast::{Cast, Target},
+        inhibit::Inhibitor,
+        mode::Mode,
+        mpris,
+        remote::{self, Remote},
+    },
+    waveform,
 };
 
+mod ab_repeat;
+mod cast;
+mod cover;
+mod devices;
+mod downloads;
 mod draw;
+mod edit;
+mod follow;
+mod groups;
+mod help;
+mod marks;
+mod meter;
+mod missing;
+mod queue;
+mod radio;
+mod reload;
+mod resume;
+mod search;
+mod sections;
+mod shuffle;
+mod tags;
+mod transfer;
+
+use ab_repeat::AbRepeat;
+use cover::CoverArt;
+use devices::DevicePicker;
+use downloads::{Download, DownloadStatus};
+use edit::Edit;
+use follow::TrackView;
+use groups::PlaylistRow;
+use help::Help;
+use meter::LevelMeter;
+use queue::Queue;
+use resume::PlaybackState;
+use search::Search;
+use sections::TrackRow;
+use shuffle::Shuffle;
+use tags::TagPicker;
+use transfer::TransferPicker;
+
+/// How long (in seconds) track previews are
+const PREVIEW_LENGTH: u64 = 10;
+/// Going back to the previous track restarts the current one instead, once it has played for this many seconds
+const PREV_RESTART_AFTER: u64 = 3;
+/// Number of played tracks remembered for going back
+const HISTORY_LEN: usize = 100;
+/// Most tracks skipped in a row because they could not be played (with `skip_on_error`), so a playlist
+/// of broken tracks is not skipped through forever
+const MAX_SKIPPED_ERRORS: usize = 5;
+/// How long the clipping indicator stays up after the audio last clipped
+const CLIP_SHOWN: Duration = Duration::from_secs(2);
+/// How much (in percent) the volume keys change the volume by
+const VOLUME_STEP: u8 = 5;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 enum TrackSelectionMethod {
     Random,
     Sequential,
+    /// pick tracks similar to the one that just played, from any playlist
+    Radio,
 }
 
 impl TrackSelectionMethod {
     pub fn next(&mut self) {
         match self {
             Self::Random => *self = Self::Sequential,
-            Self::Sequential => *self = Self::Random,
+            Self::Sequential => *self = Self::Radio,
+            Self::Radio => *self = Self::Random,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Random => "shuffle",
+            Self::Sequential => "sequential",
+            Self::Radio => "radio",
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[allow(clippy::enum_variant_names)]
 enum Repeat {
     Never,
     RepeatPlaylist,
@@ -49,6 +139,14 @@ impl Repeat {
             Self::RepeatTrack => Self::Never,
         };
     }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Never => "off",
+            Self::RepeatPlaylist => "playlist",
+            Self::RepeatTrack => "track",
+        }
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
@@ -62,6 +160,32 @@ pub struct PlaylistID {
     pub playlist: usize,
 }
 
+/// What was playing before a preview started
+#[derive(Clone, Copy, Debug)]
+struct Resume {
+    track: TrackID,
+    /// position in the track (seconds)
+    position: u64,
+    paused: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Preview {
+    track: TrackID,
+    /// if the preview is playing (otherwise, it starts on the next TrackComplete)
+    started: bool,
+    /// what to go back to once the preview is done (None if nothing was playing)
+    resume: Option<Resume>,
+}
+
+/// Track that the player is ready to continue with once the current one ends
+#[derive(Clone, Copy, Debug)]
+struct Preloaded {
+    track: TrackID,
+    /// if the track is the first entry of the queue (which is removed once it starts)
+    from_queue: bool,
+}
+
 pub struct Home {
     command_tx: Option<Sender<Action>>,
     // resolver
@@ -78,38 +202,147 @@ pub struct Home {
     autoplay: bool,
     // config
     cfg: Config,
-    // track selection list
+    // track selection list (the selection is a track index, which may be hidden by the tag filter)
     t_list_state: ListState,
-    // playlist selection list
+    /// track list as drawn (the selection is a row of track_rows, kept in sync with t_list_state)
+    t_list_view: ListState,
+    /// only tracks with this tag are shown in the track list
+    tag_filter: Option<String>,
+    /// tag filter popup (if it is open)
+    tags: Option<TagPicker>,
+    /// popup picking the playlist to move or copy tracks to (if it is open)
+    transfer: Option<TransferPicker>,
+    /// new track order of the current playlist, while editing it
+    edit: Option<Edit>,
+    // playlist selection list (the selection is a row from playlist_rows, not a playlist index)
     p_list_state: ListState,
+    /// playlist groups that are collapsed in the playlist selection list
+    collapsed_groups: HashSet<String>,
+    /// sections of the track lists that are collapsed (a section of tracks without a `group` is None)
+    collapsed_sections: HashSet<(PlaylistID, Option<String>)>,
+    // queue list
+    q_list_state: ListState,
+    /// tracks to play next (takes precedence over normal track selection)
+    queue: Queue,
+    /// play order while shuffling (None when not shuffling)
+    shuffle: Option<Shuffle>,
+    /// tracks that were played (most recent last, including the current track once it has started), for going back
+    history: VecDeque<TrackID>,
     /// jump to track # when receiving TrackComplete (takes precedence over normal track selection)
     /// used in track selection (set jump_on_track_complete -> stop playback -> trigger Action::TrackComplete -> play jump_on_track_complete)
     jump_on_track_complete: Option<TrackID>,
+    /// where the track jumped to on the next TrackComplete starts from (when another player casts to this one)
+    resume_jump: Option<Resume>,
+    /// waveform of the current track (if it has been generated)
+    waveform: Option<Vec<u8>>,
+    /// part of the current track marked for A-B repeat
+    ab_repeat: Option<AbRepeat>,
+    /// durations of tracks (in seconds), read from the store as they are needed
+    durations: HashMap<TrackID, Option<u64>>,
+    /// if tracks are downloaded, checked in the store when the playlists are loaded
+    downloads: HashMap<TrackID, DownloadStatus>,
+    /// tracks being downloaded from the player (by the number each download was given)
+    downloading: HashMap<u64, Download>,
+    /// number given to the next download from the player
+    next_download: u64,
+    /// cover art of the current track (if it has one, and it has been downloaded)
+    cover: Option<CoverArt>,
+    /// level meter of what is playing (if it is shown)
+    meter: Option<LevelMeter>,
+    /// lyrics of the current track (if it has any)
+    lyrics: Option<Lyrics>,
+    /// if the lyrics panel is shown (in place of the playlist and queue lists)
+    show_lyrics: bool,
+    /// scroll the track list to keep the playing track in view
+    follow: bool,
+    /// the playing track the track list was last scrolled to
+    followed: Option<TrackID>,
+    /// where the track lists of other playlists were, to go back there when switching to them
+    track_views: HashMap<PlaylistID, TrackView>,
+    /// tracks of the current playlist marked for batch actions
+    marked: HashSet<usize>,
+    /// the help popup (if it is open)
+    help: Option<Help>,
+    /// where the progress bar was last drawn, for seeking with the mouse
+    progress_area: Rect,
+    /// track preview that is playing or about to start. this takes precedence over jump_on_track_complete and normal track selection
+    preview: Option<Preview>,
+    /// track preloaded for gapless playback
+    preloaded: Option<Preloaded>,
+    /// if the preloaded track may no longer be the one that should play next.
+    /// the preload is updated after the current action is handled
+    preload_outdated: bool,
+    /// search popup (if it is open)
+    search: Option<Search>,
+    /// name of the output device in use
+    device_name: Option<String>,
+    /// output device picker popup (if it is open)
+    devices: Option<DevicePicker>,
+    /// recently played tracks (most recent first), which radio mode avoids
+    radio_history: VecDeque<cache::Hash>,
+    /// last change in state, shown in accessibility mode
+    announcement: Option<String>,
+    /// why playback was paused, if the audio output failed (shown until playback continues)
+    output_error: Option<String>,
+    /// why the current track could not be played (shown until another track is played)
+    player_error: Option<String>,
+    /// tracks that could not be played in a row
+    errors_in_a_row: usize,
+    /// when the audio last clipped (was too loud for the output, because of the pre-amp)
+    clipped_at: Option<Instant>,
+    mpris: Option<mpris::Mpris>,
+    /// keeps the system awake while playing (if the config says to)
+    inhibitor: Inhibitor,
+    remote: Option<Remote>,
+    /// the player being cast to (`dmm ctl cast`), which plays instead of this one
+    cast: Option<Cast>,
+    /// the player to cast to, until casting starts
+    cast_target: Option<Target>,
+    /// volume (in percent), applied along with the pre-amp
+    volume: u8,
+    /// what is playing, for `dmm status`
+    status_file: Option<StatusFile>,
+    scrobbler: Option<Scrobbler>,
+    /// the current track, until it has been scrobbled
+    listen: Option<scrobble::Listen>,
+    /// the current track, added to the listening history once it stops
+    listening: Option<plays::Listening>,
+    /// how many times each track has been played (shown in the track info)
+    play_counts: HashMap<cache::Hash, usize>,
+    /// start playing the playlist given on the command line (`dmm player <playlist>`, or `--all`)
+    play_chosen: bool,
+    /// playback settings of each playlist, restored when it is selected
+    playback_state: PlaybackState,
+    /// where the selected playlist left off, continued from once playback is started
+    resume_playlist: Option<Resume>,
 }
 
 impl Home {
     pub fn new(res: Arc<Resolver>) -> Result<Self> {
         debug!("Initializing audio backend");
-        let host = cpal::default_host();
-        let Some(device) = host.default_output_device().map(Arc::new) else {
-            error!("No audio output device exists!");
-            bail!("failed to initialize audio backend");
-        };
-        let config = Arc::new(match device.default_output_config() {
-            Ok(config) => config,
+        // the configured device (if any) is switched to once the config is registered
+        let (device, config) = match player2::find_device(None) {
+            Ok(found) => found,
             Err(err) => {
-                error!("failed to get default audio output device config: {}", err);
-                bail!("failed to initialize audio backend");
+                error!("Failed to open the default audio output device: {err}");
+                bail!(DmmError::AudioBackend);
             }
-        });
-        let player = SingleTrackPlayer::new(config.clone(), device.clone())?;
+        };
+        let device_name = device.name().ok();
+        let player = SingleTrackPlayer::new(config, device).wrap_err(DmmError::AudioBackend)?;
+        // FIXME: proper mechanism for selecting default playlist
+        let playlist = res
+            .out()
+            .playlists
+            .iter()
+            .position(|pl| !pl.archived)
+            .unwrap_or(0);
 
         Ok(Self {
             command_tx: None,
             current: TrackID {
                 track: 0,
-                // FIXME: proper mechanism for selecting default playlist
-                playlist: PlaylistID { playlist: 0 },
+                playlist: PlaylistID { playlist },
             },
             player,
             sel_method: TrackSelectionMethod::Sequential,
@@ -117,12 +350,94 @@ impl Home {
             autoplay: true,
             cfg: Config::default(),
             t_list_state: ListState::default().with_selected(Some(0)),
+            t_list_view: ListState::default(),
+            tag_filter: None,
+            tags: None,
+            transfer: None,
+            edit: None,
             p_list_state: ListState::default().with_selected(None),
+            collapsed_groups: HashSet::new(),
+            collapsed_sections: HashSet::new(),
+            q_list_state: ListState::default().with_selected(None),
+            queue: Queue::default(),
+            shuffle: None,
+            history: VecDeque::new(),
             jump_on_track_complete: None,
+            resume_jump: None,
+            waveform: None,
+            ab_repeat: None,
+            durations: HashMap::new(),
+            downloads: HashMap::new(),
+            downloading: HashMap::new(),
+            next_download: 0,
+            cover: None,
+            meter: None,
+            lyrics: None,
+            show_lyrics: false,
+            follow: true,
+            followed: None,
+            track_views: HashMap::new(),
+            marked: HashSet::new(),
+            help: None,
+            progress_area: Rect::default(),
+            preview: None,
+            preloaded: None,
+            preload_outdated: false,
+            search: None,
+            device_name,
+            devices: None,
+            radio_history: VecDeque::new(),
+            announcement: None,
+            output_error: None,
+            player_error: None,
+            errors_in_a_row: 0,
+            clipped_at: None,
+            status_file: None,
+            mpris: None,
+            inhibitor: Inhibitor::default(),
+            remote: None,
+            cast: None,
+            cast_target: None,
+            volume: 100,
+            scrobbler: None,
+            listen: None,
+            listening: None,
+            play_counts: HashMap::new(),
+            play_chosen: false,
+            playback_state: PlaybackState::default(),
+            resume_playlist: None,
             resolver: res,
         })
     }
 
+    /// Makes the playlist of all tracks (or the only playlist, if there is just one) the current playlist,
+    /// and starts playing it once the player starts
+    pub fn play_all(&mut self) {
+        let playlist = self
+            .resolver
+            .out()
+            .playlists
+            .iter()
+            .position(Playlist::is_all_tracks)
+            .unwrap_or(0);
+        self.play_playlist(playlist);
+    }
+
+    /// Starts playing the playlist at index `playlist` once the player starts (for `dmm player <playlist>`)
+    pub fn play_playlist(&mut self, playlist: usize) {
+        let playlist = PlaylistID { playlist };
+        let track = self.playable_tracks(playlist).first().copied().unwrap_or(0);
+        self.set_current(TrackID { track, playlist });
+        self.t_list_state.select(Some(track));
+        self.play_chosen = true;
+    }
+
+    /// Tracks of `playlist` that the player can pick (the ones that are not disabled)
+    fn playable_tracks(&self, playlist: PlaylistID) -> Vec<usize> {
+        let tracks = &self.get_playlist(playlist).tracks;
+        (0..tracks.len()).filter(|&i| !tracks[i].disabled).collect()
+    }
+
     fn get_track(&self, track: TrackID) -> &Track {
         &self.get_playlist(track.playlist).tracks[track.track]
     }
@@ -131,82 +446,536 @@ impl Home {
         &self.resolver.out().playlists[playlist.playlist]
     }
 
-    fn select_next_track(&mut self) -> Result<()> {
-        match (self.repeat, self.sel_method) {
+    /// Time (in seconds) the playing track ends at, if the file states it, or it was found when the track was downloaded
+    fn playing_length(&mut self) -> Option<u64> {
+        if let Some(length) = self.player.length().known() {
+            return Some(length);
+        }
+        // (timestamps start at the start of the file, not where the track starts playing)
+        let start = self
+            .get_track(self.current)
+            .start
+            .map_or(0, |start| start.0 as u64);
+        Some(self.track_duration(self.current)? + start)
+    }
+
+    /// Seconds of the playing track that the progress bar stands for. For tracks of unknown length,
+    /// this is estimated from how far into the track has been played
+    fn progress_length(&mut self) -> u64 {
+        self.playing_length()
+            .unwrap_or_else(|| self.player.length().estimate())
+    }
+
+    /// Duration of a track in seconds (the part of it that is played), if it has been downloaded (and its duration was found)
+    fn track_duration(&mut self, id: TrackID) -> Option<u64> {
+        if let Some(duration) = self.durations.get(&id) {
+            return *duration;
+        }
+        let playlist = self.get_playlist(id.playlist);
+        let track = &playlist.tracks[id.track];
+        let duration = playlist.find_source(&track.src).and_then(|source| {
+            let hash = cache::Hash::track(source, track);
+            duration::read(
+                &self
+                    .resolver
+                    .out()
+                    .cache
+                    .sidecar(hash, duration::SIDECAR_EXT),
+            )
+            .map(|full| track.played_length(full))
+        });
+        self.durations.insert(id, duration);
+        duration
+    }
+
+    /// Makes `track` the current track, keeping the track list selection valid if it is in another playlist
+    fn set_current(&mut self, track: TrackID) {
+        if track.playlist != self.current.playlist {
+            self.switch_track_view(self.current.playlist, track.playlist);
+            self.marked.clear();
+            if self.t_list_state.selected().is_some() {
+                self.t_list_state.select(Some(track.track));
+            }
+        }
+        self.current = track;
+    }
+
+    /// Takes the track that played before the current one from the history
+    /// (it is added back once it starts playing again)
+    fn history_prev(&mut self) -> Option<TrackID> {
+        if self.history.back() == Some(&self.current) {
+            self.history.pop_back();
+        }
+        self.history.pop_back()
+    }
+
+    /// Reports a change in state for screen reader users (only in accessibility mode)
+    fn announce(&mut self, message: String) {
+        if !self.cfg.accessibility.enabled {
+            return;
+        }
+        if self.cfg.accessibility.speak {
+            match Command::new("spd-say").arg("--").arg(&message).spawn() {
+                Ok(mut child) => {
+                    thread::spawn(move || child.wait());
+                }
+                Err(e) => warn!("Failed to run spd-say: {e}"),
+            }
+        }
+        self.announcement = Some(message);
+    }
+
+    /// Shows a short-lived message over the player (see [`Action::Toast`])
+    fn toast(&self, message: String, severity: Severity) {
+        if let Some(tx) = &self.command_tx {
+            let _ = tx.send(Action::Toast(message, severity));
+        }
+    }
+
+    /// What is playing, for `dmm status`
+    fn status(&mut self) -> status::Status {
+        let (position, duration) = (self.player.timestamp(), self.playing_length());
+        let playlist = self.get_playlist(self.current.playlist);
+        let meta = &playlist.tracks[self.current.track].meta;
+        status::Status {
+            pid: std::process::id(),
+            state: match self.player.state() {
+                player2::State::Playing => status::State::Playing,
+                player2::State::Paused => status::State::Paused,
+                player2::State::Stopped => status::State::Stopped,
+            },
+            playlist: playlist.name.clone(),
+            name: meta.name.clone(),
+            artist: meta.artist.clone(),
+            track: self.current.track + 1,
+            tracks: playlist.tracks.len(),
+            position,
+            duration,
+        }
+    }
+
+    fn set_volume(&mut self, percent: u8) {
+        self.volume = percent.min(100);
+        // (while casting, the other player plays at this volume instead)
+        if self.cast.is_none() {
+            self.player.set_volume(self.volume);
+        }
+        self.announce(format!("Volume {}%", self.volume));
+    }
+
+    fn mpris_status(&mut self) -> mpris::Status {
+        let track = self.get_track(self.current);
+        mpris::Status {
+            playback: match self.player.state() {
+                player2::State::Playing => mpris::Playback::Playing,
+                player2::State::Paused => mpris::Playback::Paused,
+                player2::State::Stopped => mpris::Playback::Stopped,
+            },
+            shuffle: self.sel_method == TrackSelectionMethod::Random,
+            loop_status: match self.repeat {
+                Repeat::Never => "None",
+                Repeat::RepeatPlaylist => "Playlist",
+                Repeat::RepeatTrack => "Track",
+            },
+            track_index: self.current.track,
+            title: track.meta.name.clone(),
+            artist: track.meta.artist.clone(),
+            playlist: self.get_playlist(self.current.playlist).name.clone(),
+            length: self.playing_length(),
+            position: self.player.timestamp(),
+            volume: self.volume,
+        }
+    }
+
+    /// The track that the selection method picks to play after the current one,
+    /// or None if the end of the playlist was reached (on Repeat::Never)
+    fn next_track(&self) -> Result<Option<TrackID>> {
+        let playlist = self.current.playlist;
+        Ok(match (self.repeat, self.sel_method) {
             (
                 Repeat::RepeatTrack,
-                TrackSelectionMethod::Random | TrackSelectionMethod::Sequential,
-            ) => { /* no-op: select current track */ }
-            (Repeat::Never | Repeat::RepeatPlaylist, TrackSelectionMethod::Random) => {
-                self.current.track = rand::thread_rng()
-                    .gen_range(0..self.get_playlist(self.current.playlist).tracks.len());
+                TrackSelectionMethod::Random
+                | TrackSelectionMethod::Sequential
+                | TrackSelectionMethod::Radio,
+            ) => Some(self.current),
+            (Repeat::Never | Repeat::RepeatPlaylist, TrackSelectionMethod::Radio) => {
+                Some(self.radio_next()?)
+            }
+            (rep, TrackSelectionMethod::Random) => {
+                let tracks = self.playable_tracks(playlist);
+                match &self.shuffle {
+                    Some(shuffle) if shuffle.is_for(playlist, &tracks) => shuffle
+                        .peek(rep == Repeat::RepeatPlaylist)
+                        .map(|track| TrackID { track, playlist }),
+                    // not shuffled yet (the order is made once the next track starts)
+                    _ => tracks
+                        .choose(&mut rand::thread_rng())
+                        .map(|&track| TrackID { track, playlist }),
+                }
             }
             (rep, TrackSelectionMethod::Sequential) => {
-                if self.current.track != self.get_playlist(self.current.playlist).tracks.len() - 1 {
-                    self.current.track += 1;
-                } else {
-                    match rep {
-                        Repeat::Never => {
-                            self.autoplay = false;
-                            self.player.stop()?;
-                            let _handle = Notification::new()
-                                .summary("DMM Player")
-                                .body("Playlist Complete - Stopping")
-                                .show()?;
-                        }
+                let tracks = self.playable_tracks(playlist);
+                // (disabled tracks are skipped)
+                match tracks.iter().find(|&&track| track > self.current.track) {
+                    Some(&track) => Some(TrackID { track, playlist }),
+                    None => match rep {
+                        Repeat::Never => None,
                         Repeat::RepeatPlaylist => {
-                            self.current.track = 0;
+                            tracks.first().map(|&track| TrackID { track, playlist })
                         }
                         Repeat::RepeatTrack => unreachable!(),
-                    }
+                    },
+                }
+            }
+        })
+    }
+
+    fn select_next_track(&mut self) -> Result<()> {
+        match self.next_track()? {
+            Some(next) => self.set_current(next),
+            None => {
+                self.autoplay = false;
+                self.player.stop()?;
+                self.announce("Playlist complete".to_string());
+                if self.cfg.notifications.on_playlist_complete {
+                    self.notify("Playlist Complete - Stopping");
                 }
             }
         }
         Ok(())
     }
 
-    fn play_c_track(&mut self) -> Result<()> {
+    fn notify_now_playing(&self) {
+        if !self.cfg.notifications.on_track_change {
+            return;
+        }
         let track = self.get_track(self.current);
-        let hash = cache::Hash::generate(
+        self.notify(&format!(
+            "Now Playing: {name}\nby {artist}",
+            name = track.meta.name,
+            artist = track.meta.artist
+        ));
+    }
+
+    /// Shows a desktop notification (if they are enabled). Failing to show it does not stop the player,
+    /// as not every system can show notifications
+    fn notify(&self, body: &str) {
+        if !self.cfg.notifications.enabled {
+            return;
+        }
+        if let Err(e) = Notification::new().summary("DMM Player").body(body).show() {
+            warn!("Failed to show a notification: {e}");
+        }
+    }
+
+    /// Part of a track to play, from its `start` and `end`
+    fn track_clip(&self, id: TrackID) -> player2::Clip {
+        let track = self.get_track(id);
+        player2::Clip {
+            start: track.start.map_or(0.0, |start| start.0),
+            end: track.end.map(|end| end.0),
+        }
+    }
+
+    /// Store hash of a track, its file in the store (None if it is not downloaded), and its format
+    fn track_file(&self, id: TrackID) -> Result<(cache::Hash, Option<PathBuf>, String)> {
+        let track = self.get_track(id);
+        let hash = cache::Hash::track(
             self.resolver
                 .out()
                 .sources
                 .iter()
                 .find(|x| x.name == track.src)
                 .ok_or(anyhow!("could not find track source"))?,
-            &track.input,
+            track,
         );
-        let track_path = self.resolver.out().cache.find(hash).ok_or_else(|| {
-            error!("Could not find file for track. It is probably not downloaded");
-            info!("Try downloading the playlist with `dmm download`");
-            anyhow!("could not find file for track!")
-        })?;
         let track_fmt = self
-            .get_playlist(self.current.playlist)
+            .get_playlist(id.playlist)
             .find_source(&track.src)
             .unwrap()
-            .format
-            .clone();
+            .stored_format()
+            .to_string();
+        Ok((hash, self.resolver.out().cache.find(hash), track_fmt))
+    }
+
+    /// Silence trimming threshold for tracks in `playlist` (None if silence is not trimmed)
+    fn trim_silence(&self, playlist: PlaylistID) -> Option<f32> {
+        self.get_playlist(playlist)
+            .trim_silence
+            .unwrap_or(self.cfg.audio.trim_silence)
+            .then_some(self.cfg.audio.silence_threshold)
+    }
+
+    fn play_c_track(&mut self) -> Result<()> {
+        self.play_c_track_from(0, false)
+    }
+
+    /// Plays the current track from `secs` seconds in, paused with `paused`. If it is not downloaded, and another
+    /// track is played instead, that one starts from the beginning
+    fn play_c_track_from(&mut self, secs: u64, paused: bool) -> Result<()> {
+        let (hash, track_path, track_fmt) = self.track_file(self.current)?;
+        let Some(track_path) = track_path else {
+            return self.skip_not_downloaded();
+        };
         self.player
-            .set_track(fs::File::open(&track_path)?, track_fmt)?;
-        self.player.play()?;
+            .trim_silence(self.trim_silence(self.current.playlist))?;
+        self.player.set_track(
+            fs::File::open(&track_path)?,
+            track_fmt,
+            self.track_clip(self.current),
+        )?;
+        self.player.start_at(secs, paused)?;
+        self.downloads
+            .insert(self.current, DownloadStatus::Downloaded);
+        self.output_error = None;
+        self.player_error = None;
+        self.track_started(hash);
+        Ok(())
+    }
+
+    /// Updates everything that depends on the current track, once it has started playing
+    fn track_started(&mut self, hash: cache::Hash) {
+        let cache = &self.resolver.out().cache;
+        if let Some(path) = cache.find(hash) {
+            if let Err(e) = store::touch(&path) {
+                debug!("Failed to mark the track as played: {e}");
+            }
+        }
+        let full = duration::read(&cache.sidecar(hash, duration::SIDECAR_EXT));
+        let end = self.get_track(self.current).end;
+        self.waveform =
+            waveform::read(&cache.sidecar(hash, waveform::SIDECAR_EXT)).map(|mut peaks| {
+                // the progress bar ends where the track stops playing
+                if let (Some(end), Some(full @ 1..)) = (end, full) {
+                    let keep = (end.0 / full as f64 * peaks.len() as f64).ceil() as usize;
+                    peaks.truncate(keep.max(1));
+                }
+                peaks
+            });
+        // (the player stops repeating by itself when another track starts)
+        self.ab_repeat = None;
+        self.load_cover();
+        self.load_lyrics();
+        self.radio_played(hash);
+        self.preload_outdated = true;
+        // previews are announced separately (and not scrobbled)
+        if !self.preview.is_some_and(|preview| preview.started) {
+            let track = self.get_track(self.current);
+            let message = format!("Now playing: {} by {}", track.meta.name, track.meta.artist);
+            self.announce(message);
+            let playlist = self.get_playlist(self.current.playlist).name.clone();
+            // (the track that played before is added to the history as it is replaced)
+            self.listening = Some(plays::Listening::start(&self.resolver, hash, playlist));
+            *self.play_counts.entry(hash).or_default() += 1;
+            self.shuffle_played();
+            if self.history.back() != Some(&self.current) {
+                self.history.push_back(self.current);
+                if self.history.len() > HISTORY_LEN {
+                    self.history.pop_front();
+                }
+            }
+            self.start_listen();
+        } else {
+            self.listen = None;
+            self.listening = None;
+        }
+    }
+
+    /// Reports the current track as now playing to the scrobbler
+    fn start_listen(&mut self) {
+        if self.scrobbler.is_none() {
+            return;
+        }
+        let duration = self.track_duration(self.current).unwrap_or(0);
+        let meta = &self.get_track(self.current).meta;
+        let listen = scrobble::Listen::start(
+            meta.artist.clone(),
+            meta.name.clone(),
+            meta.album.clone(),
+            duration,
+        );
+        self.scrobbler.as_ref().unwrap().now_playing(listen.clone());
+        self.listen = Some(listen);
+    }
+
+    /// Scrobbles the current track once enough of it has been played
+    fn update_scrobble(&mut self) {
+        let (Some(scrobbler), Some(listen)) = (&self.scrobbler, &mut self.listen) else {
+            return;
+        };
+        if self.player.state() != player2::State::Playing {
+            return;
+        }
+        if listen.duration == 0 {
+            // not known until the track was downloaded with a version of DMM that finds durations
+            listen.duration = self.player.length().known().unwrap_or(0);
+        }
+        if scrobble::should_scrobble(self.player.timestamp(), listen.duration) {
+            scrobbler.scrobble(self.listen.take().unwrap());
+        }
+    }
+
+    fn load_lyrics(&mut self) {
+        self.lyrics = self.track_file(self.current).ok().and_then(|(hash, _, _)| {
+            let sidecar = self.resolver.out().cache.sidecar(hash, lyrics::SIDECAR_EXT);
+            lyrics::load(self.get_track(self.current), &sidecar)
+        });
+    }
+
+    fn load_cover(&mut self) {
+        let url = match &self.get_track(self.current).meta.cover {
+            Some(url) if self.cfg.cover_art.show => url.clone(),
+            _ => {
+                self.cover = None;
+                return;
+            }
+        };
+        if self.cover.as_ref().is_some_and(|cover| cover.url == url) {
+            return;
+        }
+        let path = self.resolver.out().cache.find(cache::Hash::cover(&url));
+        self.cover = path.and_then(|path| match crate::cover::load(&path) {
+            Ok(image) => Some(CoverArt::new(url, image)),
+            Err(e) => {
+                warn!("Failed to load cover art: {e}");
+                None
+            }
+        });
+    }
+
+    /// Preloads the track that will play after the current one, so the player can continue without a gap
+    fn update_preload(&mut self) -> Result<()> {
+        self.preload_outdated = false;
+        self.preloaded = None;
+        let next = if !self.cfg.audio.gapless
+            || !self.autoplay
+            || self.preview.is_some()
+            || self.jump_on_track_complete.is_some()
+        {
+            None
+        } else if let Some(track) = self.queue.front() {
+            Some(Preloaded {
+                track,
+                from_queue: true,
+            })
+        } else {
+            self.next_track()?.map(|track| Preloaded {
+                track,
+                from_queue: false,
+            })
+        };
+        let file = match next {
+            Some(next) => match self.track_file(next.track)? {
+                (_, Some(path), format) => Some((next, path, format)),
+                // it is reported once it is played normally
+                (_, None, _) => None,
+            },
+            None => None,
+        };
+        match file {
+            Some((next, path, format)) => {
+                // takes effect from the next track that starts, which is the preloaded one
+                self.player
+                    .trim_silence(self.trim_silence(next.track.playlist))?;
+                let clip = self.track_clip(next.track);
+                self.player.preload(fs::File::open(path)?, format, clip)?;
+                self.preloaded = Some(next);
+            }
+            None => self.player.cancel_preload()?,
+        }
+        Ok(())
+    }
+
+    /// Keeps the queue list selection in bounds after removing an entry.
+    /// Once the queue is empty, the track list is selected again
+    fn clamp_queue_selection(&mut self) {
+        let Some(i) = self.q_list_state.selected() else {
+            return;
+        };
+        if self.queue.is_empty() {
+            self.q_list_state.select(None);
+            self.t_list_state.select(Some(self.current.track));
+        } else {
+            self.q_list_state
+                .select(Some(cmp::min(i, self.queue.len() - 1)));
+        }
+    }
+
+    fn start_preview(&mut self) -> Result<()> {
+        let preview = self.preview.as_mut().unwrap();
+        preview.started = true;
+        self.current = preview.track;
+        self.player.preview(PREVIEW_LENGTH)?;
+        self.play_c_track()?;
+        let message = format!("Previewing: {}", self.get_track(self.current).meta.name);
+        self.announce(message);
         Ok(())
     }
 }
 
 impl Component for Home {
     fn init(&mut self, _area: Rect) -> Result<()> {
-        if self.cfg.play_on_start {
+        self.status_file = Some(StatusFile::new(&self.resolver));
+        self.playback_state = PlaybackState::load(&self.resolver);
+        self.refresh_downloads();
+        match plays::counts(&self.resolver) {
+            Ok(counts) => self.play_counts = counts,
+            Err(e) => warn!("Failed to read the listening history: {e}"),
+        }
+        if self.cfg.mpris.enabled {
+            match mpris::serve(self.command_tx.clone().unwrap()) {
+                Ok(mpris) => self.mpris = mpris,
+                Err(e) => warn!("Failed to start MPRIS (is a D-Bus session bus running?): {e}"),
+            }
+        }
+        if self.cfg.remote.enabled {
+            match Remote::serve(
+                &self.cfg.remote,
+                self.resolver.dirs().cache.clone(),
+                self.command_tx.clone().unwrap(),
+            ) {
+                Ok(remote) => self.remote = Some(remote),
+                Err(e) => warn!(
+                    "Failed to start the remote control on {}: {e}",
+                    self.cfg.remote.address
+                ),
+            }
+        }
+        if let Err(e) = self.start_cast() {
+            warn!("Failed to start casting: {e}");
+        }
+        if self.cfg.resume_on_start && !self.play_chosen {
+            self.resume_last_playlist();
+        }
+        if (self.cfg.play_on_start || self.play_chosen) && !self.continue_playlist()? {
             self.play_c_track()?;
         }
         Ok(())
     }
 
     fn register_config_handler(&mut self, config: Config) -> Result<()> {
+        self.player.set_preamp(config.audio.preamp);
+        self.player.crossfade(config.audio.crossfade_secs)?;
+        self.player.set_buffering(config.audio.buffering())?;
+        if config.audio.device_name != self.cfg.audio.device_name {
+            if let Err(e) = self.switch_device(config.audio.device_name.as_deref()) {
+                error!("Failed to switch the output device: {e}");
+                self.toast(
+                    format!("Switching the output device failed: {e}"),
+                    Severity::Error,
+                );
+            }
+        }
+        self.scrobbler = Scrobbler::new(&config.scrobble);
         self.cfg = config;
         Ok(())
     }
 
+    fn register_resolver(&mut self, res: Arc<Resolver>) -> Result<()> {
+        self.reload(res);
+        Ok(())
+    }
+
     fn register_action_handler(&mut self, tx: Sender<Action>) -> Result<()> {
         self.command_tx = Some(tx);
         let copy = self.command_tx.as_ref().unwrap().clone();
@@ -214,43 +983,138 @@ impl Component for Home {
             trace!("Track Complete");
             let _ = copy.send(Action::TrackComplete);
         })?;
+        let copy = self.command_tx.as_ref().unwrap().clone();
+        self.player.on_preload_start(move || {
+            trace!("Preload Started");
+            let _ = copy.send(Action::PreloadStarted);
+        })?;
+        let copy = self.command_tx.as_ref().unwrap().clone();
+        self.player.on_stream_error(move |e| {
+            let _ = copy.send(Action::OutputFailed(e));
+        })?;
         Ok(())
     }
 
+    fn handle_key_events(&mut self, key: KeyEvent) -> Result<Option<Action>> {
+        self.search_key(key);
+        Ok(None)
+    }
+
+    fn handle_mouse_events(&mut self, mouse: MouseEvent) -> Result<Option<Action>> {
+        let area = self.progress_area;
+        if mouse.kind != MouseEventKind::Down(MouseButton::Left)
+            || !area.contains(Position::new(mouse.column, mouse.row))
+        {
+            return Ok(None);
+        }
+        // the progress bar is drawn with one column per `duration / width` seconds
+        let column = (mouse.column - area.x) as u64;
+        Ok(Some(Action::Seek(
+            column * self.progress_length() / area.width as u64,
+        )))
+    }
+
     fn update(&mut self, action: Action) -> Result<Option<Action>> {
+        let mut next_action = None;
         match action {
             Action::TrackComplete => {
                 trace!("Received Track Complete");
                 assert_eq!(self.player.state(), player2::State::Stopped);
-                if self.autoplay {
+                // the player discards the preload when it stops
+                self.preloaded = None;
+                if let Some(preview) = self.preview {
+                    if !preview.started {
+                        self.start_preview()?;
+                    } else {
+                        self.preview = None;
+                        if let Some(resume) = preview.resume {
+                            self.set_current(resume.track);
+                            self.play_c_track_from(resume.position, resume.paused)?;
+                        }
+                    }
+                } else if self.autoplay {
                     trace!("Playing next track");
+                    let resume = self.resume_jump.take();
                     if let Some(idx) = self.jump_on_track_complete.take() {
-                        self.current = idx;
+                        self.set_current(idx);
                         // do not send notifications about playing a track by selection (the person using the app did this, they don't need to know)
                     } else {
-                        self.select_next_track()?;
-                        let track = self.get_track(self.current);
-                        let _handle = Notification::new()
-                            .summary("DMM Player")
-                            .body(&format!(
-                                "Now Playing: {name}\nby {artist}",
-                                name = track.meta.name,
-                                artist = track.meta.artist
-                            ))
-                            .show()?;
+                        if let Some(next) = self.queue.pop() {
+                            self.set_current(next);
+                        } else {
+                            self.select_next_track()?;
+                        }
+                        self.notify_now_playing();
+                    }
+                    match resume.filter(|resume| resume.track == self.current) {
+                        Some(resume) => self.play_c_track_from(resume.position, resume.paused)?,
+                        None => self.play_c_track()?,
+                    }
+                }
+            }
+            Action::PreloadStarted => {
+                trace!("Received Preload Started");
+                if let Some(preloaded) = self.preloaded.take() {
+                    if preloaded.from_queue {
+                        self.queue.pop();
+                        self.clamp_queue_selection();
+                    }
+                    self.set_current(preloaded.track);
+                    let (hash, _, _) = self.track_file(self.current)?;
+                    self.track_started(hash);
+                    self.notify_now_playing();
+                }
+            }
+            Action::OutputFailed(e) => {
+                self.announce(format!("Paused, the audio output failed: {e}"));
+                self.toast(format!("Audio output failed: {e}"), Severity::Error);
+                self.output_error = Some(e);
+            }
+            Action::PlayerError(e) => {
+                let name = self.get_track(self.current).meta.name.clone();
+                error!("Failed to play {name}: {e}");
+                self.announce(format!("Failed to play {name}: {e}"));
+                self.toast(format!("Failed to play {name}: {e}"), Severity::Error);
+                self.player_error = Some(e);
+                self.errors_in_a_row += 1;
+                let stopped = self.player.state() == player2::State::Stopped;
+                if self.cfg.audio.skip_on_error && stopped && self.player.running() {
+                    if self.errors_in_a_row <= MAX_SKIPPED_ERRORS {
+                        // as if the track had been played
+                        next_action = Some(Action::TrackComplete);
+                    } else {
+                        warn!("{MAX_SKIPPED_ERRORS} tracks in a row could not be played, not skipping to the next one");
                     }
-                    self.play_c_track()?;
                 }
             }
             Action::PausePlay => {
                 self.autoplay = true;
+                self.output_error = None;
                 match self.player.state() {
-                    player2::State::Playing => self.player.pause()?,
-                    player2::State::Paused => self.player.play()?,
+                    player2::State::Playing => {
+                        self.player.pause()?;
+                        self.announce("Paused".to_string());
+                    }
+                    player2::State::Paused => {
+                        self.player.play()?;
+                        self.announce("Playing".to_string());
+                        if let (Some(scrobbler), Some(listen)) = (&self.scrobbler, &self.listen) {
+                            scrobbler.now_playing(listen.clone());
+                        }
+                    }
                     player2::State::Stopped => {
-                        match self.sel_method {
-                            TrackSelectionMethod::Random => self.select_next_track()?,
-                            TrackSelectionMethod::Sequential => self.current.track = 0,
+                        if let Some(next) = self.queue.pop() {
+                            self.resume_playlist = None;
+                            self.set_current(next);
+                        } else if self.continue_playlist()? {
+                            return Ok(None);
+                        } else {
+                            match self.sel_method {
+                                TrackSelectionMethod::Random => self.select_next_track()?,
+                                TrackSelectionMethod::Sequential => self.current.track = 0,
+                                // start from the current track, radio mode takes it from there
+                                TrackSelectionMethod::Radio => {}
+                            }
                         }
                         self.play_c_track()?;
                     }
@@ -258,50 +1122,114 @@ impl Component for Home {
             }
             Action::ChangeModeSelection => {
                 self.sel_method.next();
+                // a new order is made every time shuffling is turned on
+                self.shuffle = None;
+                self.shuffle_played();
+                self.preload_outdated = true;
+                self.remember_playback(false);
+                self.announce(format!("Mode: {}", self.sel_method.label()));
+                self.toast(format!("Mode: {}", self.sel_method.label()), Severity::Info);
             }
             Action::ChangeModeRepeat => {
                 self.repeat.next();
+                self.preload_outdated = true;
+                self.remember_playback(false);
+                self.announce(format!("Repeat: {}", self.repeat.label()));
+                self.toast(format!("Repeat: {}", self.repeat.label()), Severity::Info);
             }
             Action::NextTrack => {
                 // will trigger Action::TrackComplete
                 self.player.stop()?;
             }
+            // previews go back to what was playing before by themselves
+            Action::PrevTrack if self.preview.is_none() => {
+                let stopped = self.player.state() == player2::State::Stopped;
+                if !stopped && self.player.timestamp() >= PREV_RESTART_AFTER {
+                    self.player.seek(0)?;
+                } else if let Some(prev) = self.history_prev() {
+                    self.autoplay = true;
+                    if stopped {
+                        self.set_current(prev);
+                        self.play_c_track()?;
+                    } else {
+                        self.jump_on_track_complete = Some(prev);
+                        // will trigger Action::TrackComplete
+                        self.player.stop()?;
+                    }
+                } else if !stopped {
+                    // nothing to go back to
+                    self.player.seek(0)?;
+                }
+            }
+            Action::Seek(secs) => {
+                // previews play a fixed part of the track, seeking would make them run over
+                if self.player.state() != player2::State::Stopped && self.preview.is_none() {
+                    // (the end of tracks of unknown length is found by seeking past it)
+                    let secs = match self.playing_length() {
+                        Some(length) => secs.min(length),
+                        None => secs,
+                    };
+                    self.player.seek(secs)?;
+                }
+            }
+            Action::VolumeUp => self.set_volume(self.volume.saturating_add(VOLUME_STEP)),
+            Action::VolumeDown => self.set_volume(self.volume.saturating_sub(VOLUME_STEP)),
+            Action::SetVolume(percent) => self.set_volume(percent),
+            Action::PlayStored {
+                hash,
+                position,
+                paused,
+            } => self.play_stored(&hash, position, paused)?,
+            Action::LoopMarkStart => self.mark_loop_start()?,
+            Action::LoopMarkEnd => self.mark_loop_end()?,
+            Action::LoopClear => self.clear_loop()?,
             Action::ListLeft => {
-                self.t_list_state.select(Some(self.current.track));
+                self.t_list_state
+                    .select(Some(self.visible_track_near(self.current.track)));
                 self.p_list_state.select(None);
+                self.q_list_state.select(None);
             }
             Action::ListRight => {
                 self.t_list_state.select(None);
+                self.q_list_state.select(None);
                 self.p_list_state
-                    .select(Some(self.current.playlist.playlist));
+                    .select(Some(self.playlist_row_of(self.current.playlist)));
             }
             Action::ListSelNext => {
                 if self.t_list_state.selected().is_some() {
-                    self.t_list_state.select(Some(cmp::min(
-                        self.t_list_state.selected().unwrap() + 1,
-                        self.get_playlist(self.current.playlist).tracks.len() - 1,
-                    )))
+                    self.select_visible_track(true);
                 } else if self.p_list_state.selected().is_some() {
                     self.p_list_state.select(Some(cmp::min(
                         self.p_list_state.selected().unwrap() + 1,
-                        self.resolver.out().playlists.len() - 1,
+                        self.playlist_rows().len() - 1,
                     )))
+                } else if let Some(i) = self.q_list_state.selected() {
+                    self.q_list_state
+                        .select(Some(cmp::min(i + 1, self.queue.len().saturating_sub(1))))
                 }
             }
             Action::ListSelPrev => {
                 if self.t_list_state.selected().is_some() {
-                    self.t_list_state.select(Some(
-                        self.t_list_state.selected().unwrap().saturating_sub(1),
-                    ))
+                    self.select_visible_track(false);
                 } else if self.p_list_state.selected().is_some() {
                     self.p_list_state.select(Some(
                         self.p_list_state.selected().unwrap().saturating_sub(1),
                     ))
+                } else if let Some(i) = self.q_list_state.selected() {
+                    self.q_list_state.select(Some(i.saturating_sub(1)))
                 }
             }
             Action::ListChooseSelected => {
-                if self.t_list_state.selected().is_some() {
+                if self
+                    .t_list_state
+                    .selected()
+                    .is_some_and(|track| self.collapsed_section_of(track).is_some())
+                {
+                    self.toggle_selected_section();
+                } else if self.t_list_state.selected().is_some() {
                     self.autoplay = true;
+                    self.preview = None;
+                    self.resume_playlist = None;
                     if self.player.state() == player2::State::Stopped {
                         self.current.track = self.t_list_state.selected().unwrap();
                         self.play_c_track()?;
@@ -312,22 +1240,378 @@ impl Component for Home {
                         });
                         self.player.stop()?;
                     }
-                } else if self.p_list_state.selected().is_some() {
-                    if self.current.playlist.playlist != self.p_list_state.selected().unwrap() {
-                        self.autoplay = false;
-                        if self.player.state() != player2::State::Stopped {
+                } else if let Some(row) = self.p_list_state.selected() {
+                    match self.playlist_rows().get(row) {
+                        Some(PlaylistRow::Group { .. }) => self.toggle_selected_group(),
+                        Some(PlaylistRow::Playlist(id)) if *id != self.current.playlist => {
+                            let id = *id;
+                            self.remember_playback(true);
+                            self.autoplay = false;
+                            self.preview = None;
+                            if self.player.state() != player2::State::Stopped {
+                                self.player.stop()?;
+                            }
+                            let selected = self.switch_track_view(self.current.playlist, id);
+                            self.marked.clear();
+                            self.current.track = 0;
+                            self.current.playlist = id;
+                            self.p_list_state.select(None);
+                            self.t_list_state
+                                .select(Some(self.visible_track_near(selected.unwrap_or(0))));
+                            self.restore_playback();
+                            let message = format!(
+                                "Selected playlist: {}",
+                                self.get_playlist(self.current.playlist).name
+                            );
+                            self.announce(message);
+                        }
+                        _ => {}
+                    }
+                } else if let Some(i) = self.q_list_state.selected() {
+                    if let Some(track) = self.queue.remove(i) {
+                        self.clamp_queue_selection();
+                        self.autoplay = true;
+                        self.preview = None;
+                        if self.player.state() == player2::State::Stopped {
+                            self.set_current(track);
+                            self.play_c_track()?;
+                        } else {
+                            self.jump_on_track_complete = Some(track);
                             self.player.stop()?;
                         }
-                        self.current.track = 0;
-                        self.current.playlist.playlist = self.p_list_state.selected().unwrap();
-                        self.p_list_state.select(None);
-                        self.t_list_state.select(Some(0));
                     }
                 }
             }
+            Action::ListToggleGroup if self.t_list_state.selected().is_some() => {
+                self.toggle_selected_section();
+            }
+            Action::ListToggleGroup => self.toggle_selected_group(),
+            Action::ListToggleMark => self.toggle_mark(),
+            Action::ListClearMarks => self.marked.clear(),
+            Action::ListDownload => self.download_chosen(),
+            Action::ListRedownload => self.redownload_chosen(),
+            Action::PlaylistDownload => self.download_playlist(),
+            Action::DownloadProgress(id, done, total) => self.download_progress(id, done, total),
+            Action::RefreshDownloads => {
+                self.refresh_downloads();
+                self.announce("Checked which tracks are downloaded".to_string());
+            }
+            Action::DownloadDone(id) => self.download_done(id),
+            Action::ListRemoveTracks => self.remove_chosen(),
+            Action::ListMoveTracks | Action::ListCopyTracks => {
+                if self.open_transfer(action == Action::ListMoveTracks) {
+                    next_action = Some(Action::ChangeMode(Mode::Transfer));
+                }
+            }
+            Action::CloseTransfer => {
+                self.transfer = None;
+                next_action = Some(Action::ChangeMode(Mode::Home));
+            }
+            Action::TransferSelNext => {
+                if let Some(transfer) = self.transfer.as_mut() {
+                    transfer.select_next();
+                }
+            }
+            Action::TransferSelPrev => {
+                if let Some(transfer) = self.transfer.as_mut() {
+                    transfer.select_prev();
+                }
+            }
+            Action::TransferChoose => {
+                self.transfer_chosen();
+                next_action = Some(Action::ChangeMode(Mode::Home));
+            }
+            Action::ListQueue => {
+                self.t_list_state.select(None);
+                self.p_list_state.select(None);
+                self.q_list_state
+                    .select((!self.queue.is_empty()).then_some(0));
+            }
+            Action::QueueAdd if !self.marked.is_empty() => self.queue_marked(),
+            Action::QueueAdd => {
+                if let Some(track) = self.t_list_state.selected() {
+                    let track = TrackID {
+                        track,
+                        playlist: self.current.playlist,
+                    };
+                    self.queue.push(track);
+                    self.preload_outdated = true;
+                    let message = format!("Queued: {}", self.get_track(track).meta.name);
+                    self.toast(message.clone(), Severity::Info);
+                    self.announce(message);
+                }
+            }
+            Action::QueueRemove => {
+                if let Some(i) = self.q_list_state.selected() {
+                    self.queue.remove(i);
+                    self.clamp_queue_selection();
+                    self.preload_outdated = true;
+                }
+            }
+            Action::QueueMoveUp => {
+                if let Some(i) = self.q_list_state.selected() {
+                    self.q_list_state.select(Some(self.queue.move_up(i)));
+                    self.preload_outdated = true;
+                }
+            }
+            Action::QueueMoveDown => {
+                if let Some(i) = self.q_list_state.selected() {
+                    self.q_list_state.select(Some(self.queue.move_down(i)));
+                    self.preload_outdated = true;
+                }
+            }
+            Action::OpenSearch => {
+                self.search = Some(Search::default());
+                next_action = Some(Action::ChangeMode(Mode::Search));
+            }
+            Action::CloseSearch => {
+                self.search = None;
+                next_action = Some(Action::ChangeMode(Mode::Home));
+            }
+            Action::SearchSelNext => {
+                if let Some(search) = self.search.as_mut() {
+                    search.select_next();
+                }
+            }
+            Action::SearchSelPrev => {
+                if let Some(search) = self.search.as_mut() {
+                    search.select_prev();
+                }
+            }
+            Action::OpenDevices => match DevicePicker::new(self.device_name.as_deref()) {
+                Ok(picker) => {
+                    self.devices = Some(picker);
+                    next_action = Some(Action::ChangeMode(Mode::Devices));
+                }
+                Err(e) => {
+                    error!("Failed to list output devices: {e}");
+                    self.toast(
+                        format!("Listing output devices failed: {e}"),
+                        Severity::Error,
+                    );
+                }
+            },
+            Action::CloseDevices => {
+                self.devices = None;
+                next_action = Some(Action::ChangeMode(Mode::Home));
+            }
+            Action::DeviceSelNext => {
+                if let Some(devices) = self.devices.as_mut() {
+                    devices.select_next();
+                }
+            }
+            Action::DeviceSelPrev => {
+                if let Some(devices) = self.devices.as_mut() {
+                    devices.select_prev();
+                }
+            }
+            Action::DeviceChoose => {
+                if let Some(name) = self
+                    .devices
+                    .take()
+                    .and_then(|devices| devices.selected().map(str::to_string))
+                {
+                    match self.switch_device(Some(&name)) {
+                        Ok(()) => self.announce(format!("Output: {name}")),
+                        Err(e) => {
+                            error!("Failed to switch the output device: {e}");
+                            self.toast(
+                                format!("Switching the output device failed: {e}"),
+                                Severity::Error,
+                            );
+                        }
+                    }
+                }
+                next_action = Some(Action::ChangeMode(Mode::Home));
+            }
+            Action::OpenTags => {
+                self.tags = Some(TagPicker::new(
+                    &self.resolver.out().playlists,
+                    self.tag_filter.as_deref(),
+                ));
+                next_action = Some(Action::ChangeMode(Mode::Tags));
+            }
+            Action::CloseTags => {
+                self.tags = None;
+                next_action = Some(Action::ChangeMode(Mode::Home));
+            }
+            Action::TagSelNext => {
+                if let Some(tags) = self.tags.as_mut() {
+                    tags.select_next();
+                }
+            }
+            Action::TagSelPrev => {
+                if let Some(tags) = self.tags.as_mut() {
+                    tags.select_prev();
+                }
+            }
+            Action::TagChoose => {
+                if let Some(tags) = self.tags.take() {
+                    self.set_tag_filter(tags.selected().map(str::to_string));
+                }
+                next_action = Some(Action::ChangeMode(Mode::Home));
+            }
+            Action::ToggleLyrics => self.show_lyrics = !self.show_lyrics,
+            Action::ToggleFollow => {
+                self.follow = !self.follow;
+                // scroll to the playing track right away
+                self.followed = None;
+                let message = format!(
+                    "Follow playing track: {}",
+                    if self.follow { "on" } else { "off" }
+                );
+                self.toast(message.clone(), Severity::Info);
+                self.announce(message);
+            }
+            Action::ToggleHelp => match self.help.take() {
+                Some(_) => next_action = Some(Action::ChangeMode(Mode::Home)),
+                None => {
+                    self.help = Some(Help::default());
+                    self.announce("Showing all keybinds".to_string());
+                    next_action = Some(Action::ChangeMode(Mode::Help));
+                }
+            },
+            Action::HelpScrollDown => {
+                if let Some(help) = self.help.as_mut() {
+                    // limited to the length of the help text when drawing
+                    help.scroll = help.scroll.saturating_add(1);
+                }
+            }
+            Action::HelpScrollUp => {
+                if let Some(help) = self.help.as_mut() {
+                    help.scroll = help.scroll.saturating_sub(1);
+                }
+            }
+            Action::ToggleMeter => {
+                self.meter = match self.meter {
+                    Some(_) => None,
+                    None => Some(LevelMeter::new()),
+                };
+            }
+            Action::EditStart => {
+                if self.start_edit() {
+                    next_action = Some(Action::ChangeMode(Mode::Edit));
+                }
+            }
+            Action::EditMoveUp => self.move_edited(true),
+            Action::EditMoveDown => self.move_edited(false),
+            Action::EditSave => match self.save_edit() {
+                Ok(()) => {
+                    self.announce("Saved the track order".to_string());
+                    next_action = Some(Action::ChangeMode(Mode::Home));
+                }
+                Err(e) => {
+                    error!("Failed to save the track order: {e}");
+                    self.toast(
+                        format!("Saving the track order failed: {e}"),
+                        Severity::Error,
+                    );
+                }
+            },
+            Action::EditCancel => {
+                self.edit = None;
+                next_action = Some(Action::ChangeMode(Mode::Home));
+            }
+            Action::SearchChoose => {
+                if let Some(track) = self.search.take().and_then(|search| search.selected()) {
+                    self.autoplay = true;
+                    self.preview = None;
+                    self.p_list_state.select(None);
+                    self.q_list_state.select(None);
+                    if track.playlist == self.current.playlist {
+                        self.t_list_state.select(Some(track.track));
+                    } else {
+                        // the track list is updated once the track starts (see set_current)
+                        self.t_list_state.select(Some(self.current.track));
+                    }
+                    if self.player.state() == player2::State::Stopped {
+                        self.set_current(track);
+                        self.play_c_track()?;
+                    } else {
+                        self.jump_on_track_complete = Some(track);
+                        self.player.stop()?;
+                    }
+                }
+                next_action = Some(Action::ChangeMode(Mode::Home));
+            }
+            Action::PreviewSelected => {
+                if let Some(track) = self.t_list_state.selected() {
+                    let state = self.player.state();
+                    // when replacing a preview, go back to what was playing before the first one
+                    let resume = match self.preview.take() {
+                        Some(preview) => preview.resume,
+                        None => (state != player2::State::Stopped).then(|| Resume {
+                            track: self.current,
+                            position: self.player.timestamp(),
+                            paused: state == player2::State::Paused,
+                        }),
+                    };
+                    self.preview = Some(Preview {
+                        track: TrackID {
+                            track,
+                            playlist: self.current.playlist,
+                        },
+                        started: false,
+                        resume,
+                    });
+                    if state == player2::State::Stopped {
+                        self.start_preview()?;
+                    } else {
+                        // will trigger Action::TrackComplete
+                        self.player.stop()?;
+                    }
+                }
+            }
+            Action::ConfigReloaded => self.announce("Config reloaded".to_string()),
+            Action::Quit => self.remember_playback(true),
             _ => {}
         }
-        Ok(None)
+        if self.preload_outdated {
+            self.update_preload()?;
+        }
+        if let Some(e) = self.player.last_error() {
+            if let Some(tx) = &self.command_tx {
+                let _ = tx.send(Action::PlayerError(e));
+            }
+        }
+        if self.player.timestamp() != 0 && self.player.state() == player2::State::Playing {
+            // (the track did get to play)
+            self.errors_in_a_row = 0;
+        }
+        self.update_scrobble();
+        if let Some(listening) = &mut self.listening {
+            if self.player.state() == player2::State::Playing {
+                listening.update(self.player.timestamp());
+            }
+        }
+        self.inhibitor
+            .set(self.cfg.prevent_sleep && self.player.state() == player2::State::Playing);
+        if self.mpris.is_some() {
+            let status = self.mpris_status();
+            if let Err(e) = self.mpris.as_ref().unwrap().update(status) {
+                warn!("Failed to update MPRIS status: {e}");
+            }
+        }
+        if self.player.state() != player2::State::Stopped {
+            self.remember_playback(false);
+        }
+        if self.remote.is_some() {
+            let now = remote::NowPlaying {
+                status: self.status(),
+                volume: self.volume,
+            };
+            self.remote.as_ref().unwrap().update(now);
+        }
+        self.update_cast();
+        if let Some(mut status_file) = self.status_file.take() {
+            if let Err(e) = status_file.update(self.status()) {
+                // (not retried, as it would most likely fail every time)
+                warn!("Failed to write the player status: {e}");
+            } else {
+                self.status_file = Some(status_file);
+            }
+        }
+        Ok(next_action)
     }
 
     fn draw(&mut self, f: &mut Frame<'_>, area: Rect) -> Result<()> {
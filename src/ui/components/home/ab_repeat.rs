@@ -0,0 +1,102 @@
+//! A-B repeat: playing part of the current track over and over, such as to practice along with it
+
+use color_eyre::eyre::Result;
+
+use crate::{player2, ui::action::Severity};
+
+/// Part of the current track to repeat, as marked with the keybinds
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum AbRepeat {
+    /// only the start (in seconds) has been marked so far
+    Start(u64),
+    /// repeating from the start to the end (in seconds)
+    Looping(u64, u64),
+}
+
+impl AbRepeat {
+    /// `A 1:05` or `A-B 1:05-1:20`
+    pub fn label(self) -> String {
+        let time = |secs: u64| format!("{}:{:0>2}", secs / 60, secs % 60);
+        match self {
+            Self::Start(start) => format!("A {}", time(start)),
+            Self::Looping(start, end) => format!("A-B {}-{}", time(start), time(end)),
+        }
+    }
+
+    /// The part of the track that is repeated (start and end, in seconds), once both are marked
+    pub fn section(self) -> Option<(u64, u64)> {
+        match self {
+            Self::Start(_) => None,
+            Self::Looping(start, end) => Some((start, end)),
+        }
+    }
+}
+
+impl super::Home {
+    /// If A-B repeat can be used now (a track is playing or paused, and it is not a preview)
+    fn can_repeat(&self) -> bool {
+        self.player.state() != player2::State::Stopped && self.preview.is_none()
+    }
+
+    /// Marks the current position as the start of the part to repeat (point A).
+    /// Any part that was being repeated is stopped, until the end is marked again
+    pub(super) fn mark_loop_start(&mut self) -> Result<()> {
+        if !self.can_repeat() {
+            return Ok(());
+        }
+        if self.ab_repeat.and_then(AbRepeat::section).is_some() {
+            self.player.set_loop(None)?;
+        }
+        let marked = AbRepeat::Start(self.player.timestamp());
+        self.ab_repeat = Some(marked);
+        self.announce(format!("Marked {}", marked.label()));
+        self.toast(
+            format!("Marked {}, mark the end to start repeating", marked.label()),
+            Severity::Info,
+        );
+        Ok(())
+    }
+
+    /// Marks the current position as the end of the part to repeat (point B), and starts repeating it
+    pub(super) fn mark_loop_end(&mut self) -> Result<()> {
+        if !self.can_repeat() {
+            return Ok(());
+        }
+        let start = match self.ab_repeat {
+            Some(AbRepeat::Start(start) | AbRepeat::Looping(start, _)) => start,
+            None => {
+                self.toast(
+                    "Mark the start of the part to repeat first".to_string(),
+                    Severity::Warning,
+                );
+                return Ok(());
+            }
+        };
+        let end = self.player.timestamp();
+        if end <= start {
+            self.toast(
+                "The end of the part to repeat must be after its start".to_string(),
+                Severity::Warning,
+            );
+            return Ok(());
+        }
+        let looping = AbRepeat::Looping(start, end);
+        self.player.set_loop(looping.section())?;
+        self.player.seek(start)?;
+        self.ab_repeat = Some(looping);
+        self.announce(format!("Repeating {}", looping.label()));
+        self.toast(format!("Repeating {}", looping.label()), Severity::Info);
+        Ok(())
+    }
+
+    /// Stops repeating, and forgets the marked points
+    pub(super) fn clear_loop(&mut self) -> Result<()> {
+        if self.ab_repeat.take().is_none() {
+            return Ok(());
+        }
+        self.player.set_loop(None)?;
+        self.announce("Stopped repeating".to_string());
+        self.toast("Stopped repeating".to_string(), Severity::Info);
+        Ok(())
+    }
+}
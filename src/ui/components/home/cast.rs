@@ -0,0 +1,128 @@
+//! Both sides of casting (see [`crate::ui::cast`]): telling the other player what is playing here, and playing what
+//! a player casting to this one says to (with [`Action::PlayStored`](crate::ui::action::Action::PlayStored))
+
+use color_eyre::eyre::Result;
+
+use super::{PlaylistID, Resume, TrackID};
+use crate::{
+    cache::Hash,
+    player2,
+    ui::{
+        action::Severity,
+        cast::{Cast, Casting, Target},
+    },
+};
+
+/// How far (in seconds) the track can be from the position it should be at, before it is seeked to it
+const MAX_DRIFT: u64 = 2;
+
+impl super::Home {
+    /// Casts to `target` once the player starts, playing silently here
+    pub fn cast_to(&mut self, target: Target) {
+        self.cast_target = Some(target);
+    }
+
+    /// Starts casting, if a target was given (on init)
+    pub(super) fn start_cast(&mut self) -> Result<()> {
+        let Some(target) = self.cast_target.take() else {
+            return Ok(());
+        };
+        self.cast = Some(Cast::start(target, self.resolver.dirs().cache.clone())?);
+        self.player.set_volume(0);
+        Ok(())
+    }
+
+    /// Tells the other player what is playing, if it changed
+    pub(super) fn update_cast(&mut self) {
+        let Some(mut cast) = self.cast.take() else {
+            return;
+        };
+        let state = self.player.state();
+        let now = Casting {
+            track: (state != player2::State::Stopped && self.preview.is_none())
+                .then(|| self.track_file(self.current).ok())
+                .flatten()
+                .map(|(hash, ..)| hash),
+            paused: state == player2::State::Paused,
+            position: self.player.timestamp(),
+            volume: self.volume,
+        };
+        if cast.changed(&now) {
+            let next = match self.preloaded {
+                Some(preloaded) => Some(preloaded.track),
+                None => self
+                    .queue
+                    .front()
+                    .or_else(|| self.next_track().ok().flatten()),
+            };
+            let upcoming = next
+                .into_iter()
+                .filter_map(|id| match self.track_file(id) {
+                    Ok((hash, Some(_), _)) => Some(hash),
+                    _ => None,
+                })
+                .collect();
+            cast.send(now, upcoming);
+        }
+        self.cast = Some(cast);
+    }
+
+    /// Plays the track downloaded as `hash`, from `position` seconds in (for a player casting to this one). If it is
+    /// already playing, it is only seeked, paused, or unpaused to match
+    pub(super) fn play_stored(&mut self, hash: &str, position: u64, paused: bool) -> Result<()> {
+        let Some(track) = hash
+            .parse::<Hash>()
+            .ok()
+            .and_then(|hash| self.find_stored(hash))
+        else {
+            warn!("Got told to play {hash}, which is in none of the playlists");
+            self.toast(
+                "The casting player played a track that is in none of the playlists".to_string(),
+                Severity::Warning,
+            );
+            return Ok(());
+        };
+        let state = self.player.state();
+        if track == self.current && state != player2::State::Stopped && self.preview.is_none() {
+            if self.player.timestamp().abs_diff(position) > MAX_DRIFT {
+                self.player.seek(position)?;
+            }
+            match state {
+                player2::State::Playing if paused => self.player.pause()?,
+                player2::State::Paused if !paused => self.player.play()?,
+                _ => {}
+            }
+            return Ok(());
+        }
+        self.autoplay = true;
+        self.preview = None;
+        self.resume_playlist = None;
+        if state == player2::State::Stopped {
+            self.set_current(track);
+            self.play_c_track_from(position, paused)?;
+        } else {
+            self.jump_on_track_complete = Some(track);
+            self.resume_jump = Some(Resume {
+                track,
+                position,
+                paused,
+            });
+            // will trigger Action::TrackComplete
+            self.player.stop()?;
+        }
+        Ok(())
+    }
+
+    /// Where the track stored as `hash` is, preferring the current playlist
+    fn find_stored(&self, hash: Hash) -> Option<TrackID> {
+        let places = self.resolver.out().track_index.get(&hash)?;
+        let &(playlist, track) = places
+            .iter()
+            .find(|&&(playlist, _)| playlist == self.current.playlist.playlist)
+            .or(places.first())?;
+        Some(TrackID {
+            track,
+            playlist: PlaylistID { playlist },
+        })
+    }
+}
@@ -0,0 +1,73 @@
+use image::{imageops, RgbImage};
+use ratatui::prelude::*;
+
+/// Cover art of the current track, drawn with half blocks (two pixels per cell, one above the other)
+pub(super) struct CoverArt {
+    /// URL the cover was downloaded from, so it is not loaded again for the next track of the same album
+    pub url: String,
+    image: RgbImage,
+    /// the image drawn at the size it was last drawn at
+    rendered: Option<((u16, u16), Vec<Line<'static>>)>,
+}
+
+impl CoverArt {
+    pub fn new(url: String, image: RgbImage) -> Self {
+        Self {
+            url,
+            image,
+            rendered: None,
+        }
+    }
+
+    /// Number of rows the cover takes up when drawn `width` cells wide
+    pub fn height(&self, width: u16) -> u16 {
+        let (w, h) = self.image.dimensions();
+        ((h as u64 * width as u64 / w.max(1) as u64) as u16).div_ceil(2)
+    }
+
+    /// The cover, scaled to fit in `width` by `height` cells (keeping its aspect ratio)
+    pub fn lines(&mut self, width: u16, height: u16) -> &[Line<'static>] {
+        if self.rendered.as_ref().map(|(size, _)| *size) != Some((width, height)) {
+            let lines = self.render(width, height);
+            self.rendered = Some(((width, height), lines));
+        }
+        &self.rendered.as_ref().unwrap().1
+    }
+
+    fn render(&self, width: u16, height: u16) -> Vec<Line<'static>> {
+        let (w, h) = self.image.dimensions();
+        if w == 0 || h == 0 || width == 0 || height == 0 {
+            return vec![];
+        }
+        // each cell is (roughly) twice as tall as it is wide, so it fits two square pixels
+        let (max_w, max_h) = (width as u32, height as u32 * 2);
+        let (new_w, new_h) = if w * max_h > h * max_w {
+            (max_w, (h * max_w / w).max(1))
+        } else {
+            ((w * max_h / h).max(1), max_h)
+        };
+        let scaled = imageops::resize(&self.image, new_w, new_h, imageops::FilterType::Triangle);
+        let color = |x, y| {
+            let [r, g, b] = scaled.get_pixel(x, y).0;
+            Color::Rgb(r, g, b)
+        };
+        (0..new_h)
+            .step_by(2)
+            .map(|y| {
+                Line::from(
+                    (0..new_w)
+                        .map(|x| {
+                            let style = Style::new().fg(color(x, y));
+                            let style = if y + 1 < new_h {
+                                style.bg(color(x, y + 1))
+                            } else {
+                                style
+                            };
+                            Span::styled("▀", style)
+                        })
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect()
+    }
+}
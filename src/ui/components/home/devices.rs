@@ -0,0 +1,65 @@
+use color_eyre::eyre::Result;
+use cpal::traits::{DeviceTrait, HostTrait};
+use ratatui::widgets::ListState;
+
+use crate::player2::find_device;
+
+/// State of the output device picker popup
+#[derive(Debug, Default)]
+pub(super) struct DevicePicker {
+    /// names of the available output devices
+    pub names: Vec<String>,
+    pub list_state: ListState,
+}
+
+impl DevicePicker {
+    /// Lists the output devices, selecting the one named `current`
+    pub fn new(current: Option<&str>) -> Result<Self> {
+        let names = cpal::default_host()
+            .output_devices()?
+            .filter_map(|device| device.name().ok())
+            .collect::<Vec<_>>();
+        let selected = names
+            .iter()
+            .position(|name| Some(name.as_str()) == current)
+            .or((!names.is_empty()).then_some(0));
+        Ok(Self {
+            names,
+            list_state: ListState::default().with_selected(selected),
+        })
+    }
+
+    pub fn select_next(&mut self) {
+        if let Some(i) = self.list_state.selected() {
+            self.list_state
+                .select(Some((i + 1).min(self.names.len().saturating_sub(1))));
+        }
+    }
+
+    pub fn select_prev(&mut self) {
+        if let Some(i) = self.list_state.selected() {
+            self.list_state.select(Some(i.saturating_sub(1)));
+        }
+    }
+
+    pub fn selected(&self) -> Option<&str> {
+        self.names
+            .get(self.list_state.selected()?)
+            .map(String::as_str)
+    }
+}
+
+impl super::Home {
+    /// Switches playback to the output device named `name` (or the default one)
+    pub(super) fn switch_device(&mut self, name: Option<&str>) -> Result<()> {
+        let (device, config) = find_device(name)?;
+        info!(
+            "Switching output to {}",
+            device.name().unwrap_or("an unnamed device".to_string())
+        );
+        self.device_name = device.name().ok();
+        self.player.set_device(device, config)?;
+        self.output_error = None;
+        Ok(())
+    }
+}
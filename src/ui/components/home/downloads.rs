@@ -0,0 +1,212 @@
+//! Showing which tracks are downloaded in the track list (so it is clear what `dmm download` still needs to fetch),
+//! and downloading them from the player
+
+use std::{collections::HashSet, thread};
+
+use super::{PlaylistID, TrackID};
+use crate::{
+    cache, download,
+    ui::{
+        action::{Action, Severity},
+        symbol,
+    },
+};
+
+/// Number of tracks downloaded at once when downloading from the player
+const DOWNLOAD_JOBS: usize = 4;
+
+/// If the file for a track is in the store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum DownloadStatus {
+    Downloaded,
+    Missing,
+    /// being downloaded from the player
+    Downloading,
+}
+
+impl DownloadStatus {
+    /// Shown before the track in the track list
+    pub fn icon(self) -> &'static str {
+        match self {
+            Self::Downloaded => symbol::CHECK,
+            Self::Missing => symbol::CLOSE,
+            Self::Downloading => symbol::DOWNLOAD,
+        }
+    }
+
+    /// Shown after the track in the track list with accessibility enabled (as the icons are not read out)
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Downloaded => "",
+            Self::Missing => " (not downloaded)",
+            Self::Downloading => " (downloading)",
+        }
+    }
+}
+
+/// Tracks being downloaded from the player
+pub(super) struct Download {
+    hashes: HashSet<cache::Hash>,
+    /// how many downloads are done
+    done: usize,
+    /// how many there are in total (which includes cover art, and is known once the first one is done)
+    total: Option<usize>,
+}
+
+impl super::Home {
+    fn find_download_status(&self, id: TrackID) -> DownloadStatus {
+        match self.track_file(id) {
+            Ok((hash, _, _))
+                if self
+                    .downloading
+                    .values()
+                    .any(|download| download.hashes.contains(&hash)) =>
+            {
+                DownloadStatus::Downloading
+            }
+            Ok((_, Some(_), _)) => DownloadStatus::Downloaded,
+            _ => DownloadStatus::Missing,
+        }
+    }
+
+    /// Checks the store for every track, such as after the playlists are loaded, or files were downloaded
+    pub(super) fn refresh_downloads(&mut self) {
+        let tracks = self
+            .resolver
+            .out()
+            .playlists
+            .iter()
+            .enumerate()
+            .flat_map(|(playlist, pl)| {
+                (0..pl.tracks.len()).map(move |track| TrackID {
+                    track,
+                    playlist: PlaylistID { playlist },
+                })
+            })
+            .collect::<Vec<_>>();
+        self.downloads = tracks
+            .into_iter()
+            .map(|id| (id, self.find_download_status(id)))
+            .collect();
+    }
+
+    /// If the file for a track is in the store (as of the last time it was checked)
+    pub(super) fn download_status(&mut self, id: TrackID) -> DownloadStatus {
+        if let Some(&status) = self.downloads.get(&id) {
+            return status;
+        }
+        let status = self.find_download_status(id);
+        self.downloads.insert(id, status);
+        status
+    }
+
+    pub(super) fn is_downloaded(&mut self, id: TrackID) -> bool {
+        self.download_status(id) == DownloadStatus::Downloaded
+    }
+
+    /// Downloads the tracks with these hashes in the background (with `again`, even if they are already downloaded,
+    /// keeping what is there if it fails). They are shown as downloading until it is done
+    pub(super) fn download_in_background(&mut self, mut hashes: HashSet<cache::Hash>, again: bool) {
+        hashes.retain(|hash| {
+            !self
+                .downloading
+                .values()
+                .any(|download| download.hashes.contains(hash))
+        });
+        if hashes.is_empty() {
+            self.toast("Already downloading".to_string(), Severity::Info);
+            return;
+        }
+        let again_msg = if again { " again" } else { "" };
+        let message = format!("Downloading {} track(s){again_msg}", hashes.len());
+        self.toast(message.clone(), Severity::Info);
+        self.announce(message);
+        let id = self.next_download;
+        self.next_download += 1;
+        self.downloading.insert(
+            id,
+            Download {
+                hashes: hashes.clone(),
+                done: 0,
+                total: None,
+            },
+        );
+        self.refresh_downloads();
+        let res = self.resolver.clone();
+        let tx = self.command_tx.clone();
+        thread::spawn(move || {
+            let report = |done, total| {
+                if let Some(tx) = &tx {
+                    let _ = tx.send(Action::DownloadProgress(id, done, total));
+                }
+            };
+            let result = if again {
+                download::redownload(&res, &hashes, DOWNLOAD_JOBS, Some(&report))
+            } else {
+                download::download_hashes(&res, &hashes, DOWNLOAD_JOBS, Some(&report))
+            };
+            let toast = match result {
+                Ok(()) => Action::Toast(
+                    format!("Downloaded {} track(s){again_msg}", hashes.len()),
+                    Severity::Info,
+                ),
+                Err(e) => {
+                    error!("Failed to download tracks{again_msg}: {e}");
+                    Action::Toast(format!("Download failed: {e}"), Severity::Error)
+                }
+            };
+            if let Some(tx) = &tx {
+                let _ = tx.send(toast);
+                let _ = tx.send(Action::DownloadDone(id));
+            }
+        });
+    }
+
+    /// Downloads every track in the selected playlist that is not downloaded yet, in the background
+    pub(super) fn download_playlist(&mut self) {
+        let pl = self.get_playlist(self.selected_playlist());
+        let cache = &self.resolver.out().cache;
+        let hashes = pl
+            .tracks
+            .iter()
+            .filter(|track| !track.disabled)
+            .filter_map(|track| Some(cache::Hash::track(pl.find_source(&track.src)?, track)))
+            .filter(|&hash| cache.find(hash).is_none())
+            .collect::<HashSet<_>>();
+        if hashes.is_empty() {
+            let message = format!("Every track in {} is downloaded", pl.name);
+            self.toast(message, Severity::Info);
+            return;
+        }
+        self.download_in_background(hashes, false);
+    }
+
+    /// Called as the tracks downloading with `id` finish, with how many are done out of the total
+    pub(super) fn download_progress(&mut self, id: u64, done: usize, total: usize) {
+        if let Some(download) = self.downloading.get_mut(&id) {
+            download.done = done;
+            download.total = Some(total);
+        }
+    }
+
+    /// How many of the downloads started from the player are done, out of how many (if any are running)
+    pub(super) fn downloads_progress(&self) -> Option<(usize, usize)> {
+        if self.downloading.is_empty() {
+            return None;
+        }
+        Some(
+            self.downloading
+                .values()
+                .fold((0, 0), |(done, total), download| {
+                    let len = download.total.unwrap_or(download.hashes.len());
+                    (done + download.done, total + len)
+                }),
+        )
+    }
+
+    /// Called once the tracks downloading with `id` are done (or failed)
+    pub(super) fn download_done(&mut self, id: u64) {
+        self.downloading.remove(&id);
+        self.refresh_downloads();
+    }
+}
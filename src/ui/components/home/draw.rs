@@ -1,41 +1,113 @@
-use std::iter;
+use std::time::Instant;
 
 use color_eyre::eyre::Result;
 use ratatui::{prelude::*, widgets::*};
 
-use super::{PlaylistID, Repeat, TrackSelectionMethod};
+use super::{
+    help, AbRepeat, DownloadStatus, PlaylistRow, Repeat, TrackID, TrackRow, TrackSelectionMethod,
+    CLIP_SHOWN,
+};
 use crate::{
-    cfg,
+    duration,
     player2::{self},
-    ui::{action::Action, mode::Mode, symbol},
+    ui::{mode::Mode, symbol},
+    waveform,
 };
 
 impl super::Home {
     pub(super) fn draw_titlebar(&mut self, f: &mut Frame<'_>, area: Rect) -> Result<()> {
+        if self.player.clipped() != 0 {
+            self.clipped_at = Some(Instant::now());
+        }
+        let clipping = self.clipped_at.is_some_and(|at| at.elapsed() < CLIP_SHOWN);
         // Title bar
-        let titlebar = Block::new()
+        let mut titlebar = Block::new()
             .title(Line::from(vec![Span::styled(
-                "DMM".to_string() + " " + symbol::MUSIC_NOTES + " ",
+                if self.cfg.accessibility.enabled {
+                    "DMM ".to_string()
+                } else {
+                    "DMM".to_string() + " " + symbol::MUSIC_NOTES + " "
+                },
                 Style::default().add_modifier(Modifier::BOLD),
             )]))
+            .title_bottom(match &self.output_error {
+                Some(e) => {
+                    format!(" audio output failed: {e} (play to retry, or pick another device) ")
+                        .fg(Color::LightRed)
+                }
+                None => match (&self.player_error, self.resolver.out().load_summary()) {
+                    (Some(e), _) => format!(" failed to play the track: {e} ").fg(Color::LightRed),
+                    (None, Some(failed)) => {
+                        format!(" {failed} (run `dmm check` for details) ").fg(Color::LightYellow)
+                    }
+                    (None, None) => "".into(),
+                },
+            })
             .borders(Borders::TOP | Borders::BOTTOM)
-            .border_style(Style::new().fg(Color::Yellow));
+            .border_style(Style::new().fg(self.cfg.theme.border));
+        if clipping {
+            let clip = match self.cfg.accessibility.enabled {
+                true => " clipping (lower the pre-amp) ".bold(),
+                false => " CLIP ".bold().fg(Color::White).bg(Color::Red),
+            };
+            titlebar = titlebar.title(block::Title::from(clip).alignment(Alignment::Right));
+        }
         // .title_position(block::Position::Bottom);
         let titlebar_content_area = titlebar.inner(area);
         f.render_widget(titlebar, area);
 
-        let titlebar_content = Paragraph::new(Line::from(vec![
-            symbol::SHUFFLE
-                .fg(match self.sel_method {
-                    TrackSelectionMethod::Random => Color::LightGreen,
-                    TrackSelectionMethod::Sequential => Color::DarkGray,
-                })
-                .add_modifier(Modifier::BOLD),
+        if self.cfg.accessibility.enabled {
+            let state = match self.player.state() {
+                player2::State::Playing => "playing",
+                player2::State::Paused => "paused",
+                player2::State::Stopped => "stopped",
+            };
+            let ab_repeat = match self.ab_repeat {
+                Some(ab_repeat) => format!(" | {}", ab_repeat.label()),
+                None => String::new(),
+            };
+            let volume = match self.volume {
+                100 => String::new(),
+                volume => format!(" | volume {volume}%"),
+            };
+            let cast = match &self.cast {
+                Some(cast) => format!(" | casting to {}", cast.target().url()),
+                None => String::new(),
+            };
+            let length = match self.playing_length() {
+                Some(length) => format!("{}:{:0>2}", length / 60, length % 60),
+                None => "unknown length".to_string(),
+            };
+            let titlebar_content = Paragraph::new(format!(
+                "mode: {mode} | repeat: {repeat}{ab_repeat}{volume}{cast} | {state} | {t_min}:{t_sec:0>2} of {length} | track {n} of {num} | {name}",
+                mode = self.sel_method.label(),
+                repeat = self.repeat.label(),
+                t_min = self.player.timestamp() / 60,
+                t_sec = self.player.timestamp() % 60,
+                n = self.current.track + 1,
+                num = self.get_playlist(self.current.playlist).tracks.len(),
+                name = self.get_track(self.current).meta.name,
+            ));
+            f.render_widget(titlebar_content, titlebar_content_area);
+            return Ok(());
+        }
+
+        let length = match self.playing_length() {
+            Some(length) => format!("{}:{:0>2}", length / 60, length % 60),
+            None => "--:--".to_string(),
+        };
+        let mut titlebar_content = vec![
+            match self.sel_method {
+                TrackSelectionMethod::Random => symbol::SHUFFLE.fg(self.cfg.theme.playing),
+                TrackSelectionMethod::Sequential => symbol::SHUFFLE.fg(self.cfg.theme.dim),
+                TrackSelectionMethod::Radio => symbol::RADIO.fg(Color::LightBlue),
+            }
+            .add_modifier(Modifier::BOLD),
             " ".into(),
             {
                 let (color, sym) = match self.repeat {
                     Repeat::Never => (Color::LightRed, symbol::REPEAT_OFF),
-                    Repeat::RepeatPlaylist => (Color::LightGreen, symbol::REPEAT),
+                    Repeat::RepeatPlaylist => (self.cfg.theme.playing, symbol::REPEAT),
                     Repeat::RepeatTrack => (Color::LightBlue, symbol::REPEAT_ONE),
                 };
                 sym.fg(color)
@@ -46,7 +118,7 @@ impl super::Home {
                 .fg(if self.player.state() == player2::State::Stopped {
                     Color::LightRed
                 } else {
-                    Color::DarkGray
+                    self.cfg.theme.dim
                 })
                 .add_modifier(Modifier::BOLD),
             " ".into(),
@@ -54,64 +126,159 @@ impl super::Home {
                 .fg(if self.player.state() == player2::State::Paused {
                     Color::LightRed
                 } else {
-                    Color::DarkGray
+                    self.cfg.theme.dim
                 })
                 .add_modifier(Modifier::BOLD),
             " ".into(),
             symbol::PLAY
                 .fg(if self.player.state() == player2::State::Playing {
-                    Color::LightGreen
+                    self.cfg.theme.playing
                 } else {
-                    Color::DarkGray
+                    self.cfg.theme.dim
                 })
                 .add_modifier(Modifier::BOLD),
             " ".into(),
-            "│".fg(Color::Yellow),
+            "│".fg(self.cfg.theme.border),
             format!(
-                "{}:{:0>2}->{}:{:0>2}",
+                "{}:{:0>2}->{length}",
                 self.player.timestamp() / 60,
                 self.player.timestamp() % 60,
-                self.player.duration() / 60,
-                self.player.duration() % 60,
             )
             .into(),
-            "│".fg(Color::Yellow),
+            "│".fg(self.cfg.theme.border),
+        ];
+        if let Some(ab_repeat) = self.ab_repeat {
+            titlebar_content.extend([
+                ab_repeat.label().fg(Color::LightBlue),
+                "│".fg(self.cfg.theme.border),
+            ]);
+        }
+        if self.volume != 100 {
+            titlebar_content.extend([
+                format!("vol {}%", self.volume).into(),
+                "│".fg(self.cfg.theme.border),
+            ]);
+        }
+        if let Some(cast) = &self.cast {
+            titlebar_content.extend([
+                format!("cast {}", cast.target().url()).fg(Color::LightBlue),
+                "│".fg(self.cfg.theme.border),
+            ]);
+        }
+        titlebar_content.extend([
             format!(
                 "# {n}/{num}",
                 n = self.current.track + 1,
                 num = self.get_playlist(self.current.playlist).tracks.len(),
             )
             .into(),
-            "│".fg(Color::Yellow),
+            "│".fg(self.cfg.theme.border),
             self.get_track(self.current).meta.name.clone().italic(),
-        ]))
-        .fg(Color::Gray);
+        ]);
+        let titlebar_content = Paragraph::new(Line::from(titlebar_content)).fg(Color::Gray);
         f.render_widget(titlebar_content, titlebar_content_area);
         Ok(())
     }
 
+    /// Playback progress, drawn over the track's waveform if it is available
+    fn draw_progress(&mut self, f: &mut Frame<'_>, area: Rect) -> Result<()> {
+        self.progress_area = area;
+        const LEVELS: [&str; 9] = [" ", "▁", "▂", "▃", "▄", "▅", "▆", "▇", "█"];
+        let width = area.width as usize;
+        let duration = self.progress_length();
+        let played = if duration == 0 {
+            0
+        } else {
+            (self.player.timestamp() as usize * width / duration as usize).min(width)
+        };
+        if self.cfg.accessibility.enabled {
+            // played and unplayed parts are told apart by shape, not color
+            let progress = Paragraph::new("=".repeat(played) + &"-".repeat(width - played));
+            f.render_widget(progress, area);
+            return Ok(());
+        }
+        let columns = match &self.waveform {
+            Some(peaks) => waveform::resample(peaks, width)
+                .into_iter()
+                .map(|peak| LEVELS[(peak as usize * (LEVELS.len() - 1)).div_ceil(255)])
+                .collect::<Vec<_>>(),
+            None => vec!["━"; width],
+        };
+        // columns of the part of the track marked for A-B repeat
+        let column = |secs: u64| (secs as usize * width / (duration as usize).max(1)).min(width);
+        let repeated = match self.ab_repeat {
+            Some(AbRepeat::Start(start)) => column(start)..column(start) + 1,
+            Some(AbRepeat::Looping(start, end)) => {
+                column(start)..column(end).max(column(start) + 1)
+            }
+            None => 0..0,
+        };
+        let style = |i: usize| match (i < played, repeated.contains(&i)) {
+            (true, _) => Style::new().fg(self.cfg.theme.playing),
+            (false, true) => Style::new().fg(Color::LightBlue),
+            (false, false) => Style::new().fg(self.cfg.theme.dim),
+        };
+        let mut spans = Vec::<Span>::new();
+        for (i, text) in columns.into_iter().enumerate() {
+            let style = style(i);
+            match spans.last_mut() {
+                Some(span) if span.style == style => span.content.to_mut().push_str(text),
+                _ => spans.push(Span::styled(text, style)),
+            }
+        }
+        f.render_widget(Paragraph::new(Line::from(spans)), area);
+        Ok(())
+    }
+
     fn draw_info(&mut self, f: &mut Frame<'_>, area: Rect) -> Result<()> {
+        let meter_height = if self.meter.is_some() { 5 } else { 0 };
+        // the cover is only drawn if it leaves room for the keybinds
+        let cover_height = match &self.cover {
+            Some(cover) if !self.cfg.accessibility.enabled => {
+                let height = cover.height(area.width.saturating_sub(2)) + 2;
+                if height <= area.height.saturating_sub(12 + meter_height) / 2 {
+                    height
+                } else {
+                    0
+                }
+            }
+            _ => 0,
+        };
         let info_layout = Layout::new(
             Direction::Vertical,
             [
                 Constraint::Length(6),
                 Constraint::Max(6),
+                Constraint::Length(meter_height),
+                Constraint::Length(cover_height),
                 Constraint::Min(0),
             ],
         )
         .split(area);
 
-        let selected_playlist = self.get_playlist(PlaylistID {
-            playlist: self
-                .p_list_state
-                .selected()
-                .unwrap_or(self.current.playlist.playlist),
-        });
+        let selected_id = self.selected_playlist();
+        let (mut total, mut unknown) = (0, 0);
+        for track in 0..self.get_playlist(selected_id).tracks.len() {
+            match self.track_duration(TrackID {
+                track,
+                playlist: selected_id,
+            }) {
+                Some(duration) => total += duration,
+                None => unknown += 1,
+            }
+        }
+        let selected_playlist = self.get_playlist(selected_id);
         let playlist = Paragraph::new(vec![
             Line::from(selected_playlist.name.clone().italic()),
             Line::from(vec![
                 selected_playlist.tracks.len().to_string().bold(),
-                " track(s)".into(),
+                " track(s), ".into(),
+                duration::format(total).bold(),
+                if unknown == 0 {
+                    "".into()
+                } else {
+                    format!(" (+{unknown} unknown)").into()
+                },
             ]),
             Line::from(vec![
                 selected_playlist.sources.len().to_string().bold(),
@@ -125,25 +292,83 @@ impl super::Home {
         .block(
             Block::new()
                 .title("Playlist".bold())
-                .border_style(Style::new().fg(Color::Yellow))
+                .border_style(Style::new().fg(self.cfg.theme.border))
                 .borders(Borders::ALL),
         );
         f.render_widget(playlist, info_layout[0]);
 
         let sel_track = &self.get_playlist(self.current.playlist).tracks
             [self.t_list_state.selected().unwrap_or(self.current.track)];
-        let track = Paragraph::new(vec![
+        let mut track_lines = vec![
             Line::from(sel_track.meta.name.clone().italic()),
             Line::from(vec!["by: ".bold(), sel_track.meta.artist.clone().into()]),
-        ])
-        .block(
-            Block::new()
-                .title("Track".bold())
-                .border_style(Style::new().fg(Color::Yellow))
-                .borders(Borders::ALL),
-        )
-        .wrap(Wrap { trim: false });
+        ];
+        if let Some(album) = &sel_track.meta.album {
+            track_lines.push(Line::from(vec!["album: ".bold(), album.clone().into()]));
+        }
+        let sel_id = TrackID {
+            track: self.t_list_state.selected().unwrap_or(self.current.track),
+            playlist: self.current.playlist,
+        };
+        if let Ok((hash, _, _)) = self.track_file(sel_id) {
+            let plays = self.play_counts.get(&hash).copied().unwrap_or(0);
+            track_lines.push(Line::from(vec![
+                "played: ".bold(),
+                format!("{plays} time(s)").into(),
+            ]));
+            let playlists = self.resolver.out().playlists_with(hash);
+            if playlists > 1 {
+                track_lines.push(Line::from(vec![
+                    "in: ".bold(),
+                    format!("{playlists} playlists").into(),
+                ]));
+            }
+        }
+        let tags = self
+            .get_playlist(self.current.playlist)
+            .track_tags(sel_track)
+            .cloned()
+            .collect::<Vec<_>>();
+        if !tags.is_empty() {
+            track_lines.push(Line::from(vec!["tags: ".bold(), tags.join(", ").into()]));
+        }
+        let track = Paragraph::new(track_lines)
+            .block(
+                Block::new()
+                    .title("Track".bold())
+                    .border_style(Style::new().fg(self.cfg.theme.border))
+                    .borders(Borders::ALL),
+            )
+            .wrap(Wrap { trim: false });
         f.render_widget(track, info_layout[1]);
+        if let Some(meter) = self.meter.as_mut() {
+            meter.update(self.player.levels());
+            // (the pre-amp is what could make tracks clip)
+            let title = match self.cfg.audio.preamp {
+                0.0 => "Level".to_string(),
+                preamp => format!("Level (pre-amp {preamp:+.1} dB)"),
+            };
+            let block = Block::new()
+                .title(title.bold())
+                .border_style(Style::new().fg(self.cfg.theme.border))
+                .borders(Borders::ALL);
+            let lines = match self.cfg.accessibility.enabled {
+                true => meter.text(),
+                false => meter.lines(block.inner(info_layout[2]).width),
+            };
+            f.render_widget(Paragraph::new(lines).block(block), info_layout[2]);
+        }
+        if let Some(cover) = self.cover.as_mut().filter(|_| cover_height != 0) {
+            let block = Block::new()
+                .title("Cover".bold())
+                .border_style(Style::new().fg(self.cfg.theme.border))
+                .borders(Borders::ALL);
+            let inner = block.inner(info_layout[3]);
+            f.render_widget(block, info_layout[3]);
+            let cover = Paragraph::new(cover.lines(inner.width, inner.height).to_vec())
+                .alignment(Alignment::Center);
+            f.render_widget(cover, inner);
+        }
         let mut lines = self
             .cfg
             .keybinds
@@ -152,27 +377,10 @@ impl super::Home {
             .unwrap()
             .iter()
             .map(|(keys, action)| {
-                let mut output = String::new();
-                for key in keys {
-                    output += "<";
-                    output += cfg::key_event_to_string(key).as_str();
-                    output += ">";
-                }
-                output += " ";
-                output += match action {
-                    Action::Quit => "quit",
-                    Action::PausePlay => "pause/play",
-                    Action::ChangeModeSelection => "toggle shuffle play",
-                    Action::ChangeModeRepeat => "toggle repeat",
-                    Action::NextTrack => "skip",
-                    Action::ListLeft => "select track list",
-                    Action::ListRight => "select playlist list",
-                    Action::ListSelNext => "list: next",
-                    Action::ListSelPrev => "list: prev",
-                    Action::ListChooseSelected => "list: play track/select playlist",
-                    other => panic!("Unexpected binding to key {other:?} (bound to {keys:?})"),
+                let Some(label) = help::label(action) else {
+                    panic!("Unexpected binding to key {action:?} (bound to {keys:?})");
                 };
-                output
+                format!("{} {label}", help::keys_label(keys))
             })
             .collect::<Vec<_>>();
         lines.sort();
@@ -180,28 +388,197 @@ impl super::Home {
             .block(
                 Block::new()
                     .title("Keybinds".bold())
-                    .border_style(Style::new().fg(Color::Yellow))
+                    .border_style(Style::new().fg(self.cfg.theme.border))
                     .borders(Borders::ALL),
             )
             .wrap(Wrap { trim: false });
-        f.render_widget(track, info_layout[2]);
+        f.render_widget(track, info_layout[4]);
+        Ok(())
+    }
+
+    /// Lyrics of the current track, drawn over `area`. Synced lyrics are scrolled to the line being sung
+    fn draw_lyrics(&mut self, f: &mut Frame<'_>, area: Rect) -> Result<()> {
+        let block = Block::new()
+            .title("Lyrics".bold())
+            .border_style(Style::new().fg(self.cfg.theme.border))
+            .borders(Borders::ALL);
+        let height = block.inner(area).height as usize;
+        f.render_widget(Clear, area);
+        let Some(lyrics) = &self.lyrics else {
+            let empty = Paragraph::new("no lyrics for this track".dim()).block(block);
+            f.render_widget(empty, area);
+            return Ok(());
+        };
+        if !lyrics.synced() {
+            let lines = lyrics
+                .lines
+                .iter()
+                .map(|(_, line)| Line::from(line.clone()))
+                .collect::<Vec<_>>();
+            let text = Paragraph::new(lines)
+                .block(block)
+                .wrap(Wrap { trim: false });
+            f.render_widget(text, area);
+            return Ok(());
+        }
+        let current = lyrics.current(self.player.timestamp() * 1000);
+        let lines = lyrics
+            .lines
+            .iter()
+            .enumerate()
+            .map(|(i, (_, line))| match Some(i) == current {
+                true if self.cfg.accessibility.enabled => Line::from(format!("> {line}")),
+                true => Line::from(line.clone().fg(self.cfg.theme.playing).bold()),
+                false => Line::from(line.clone()),
+            })
+            .collect::<Vec<_>>();
+        // the line being sung is kept a third of the way down
+        let scroll = current.unwrap_or(0).saturating_sub(height / 3);
+        let text = Paragraph::new(lines)
+            .block(block)
+            .scroll((scroll as u16, 0));
+        f.render_widget(text, area);
+        Ok(())
+    }
+
+    /// Search popup, drawn over `area`
+    fn draw_search(&mut self, f: &mut Frame<'_>, area: Rect) -> Result<()> {
+        let area = area.inner(Margin::new(area.width / 8, area.height / 8));
+        let search = self.search.as_ref().unwrap();
+        let items = search
+            .results
+            .iter()
+            .map(|id| {
+                let track = self.get_track(*id);
+                ListItem::new(Line::from(vec![
+                    track.meta.name.clone().italic(),
+                    " by ".dim(),
+                    track.meta.artist.clone().into(),
+                    format!(" ({})", self.get_playlist(id.playlist).name).dim(),
+                ]))
+            })
+            .collect::<Vec<_>>();
+        let block = Block::new()
+            .title("Search".bold())
+            .title_bottom(format!("{} result(s)", search.results.len()).dim())
+            .border_style(Style::new().fg(self.cfg.theme.border))
+            .borders(Borders::ALL);
+        let inner = block.inner(area);
+        let layout = Layout::new(
+            Direction::Vertical,
+            [Constraint::Length(1), Constraint::Min(0)],
+        )
+        .split(inner);
+        f.render_widget(Clear, area);
+        f.render_widget(block, area);
+        f.render_widget(
+            Paragraph::new(Line::from(vec![
+                "/ ".fg(self.cfg.theme.accent),
+                search.query.clone().into(),
+            ])),
+            layout[0],
+        );
+        let list = List::new(items)
+            .highlight_symbol(">")
+            .highlight_spacing(HighlightSpacing::Always)
+            .highlight_style(Style::new().fg(self.cfg.theme.highlight));
+        f.render_stateful_widget(
+            list,
+            layout[1],
+            &mut self.search.as_mut().unwrap().list_state,
+        );
+        Ok(())
+    }
+
+    /// Output device picker popup, drawn over `area`
+    fn draw_devices(&mut self, f: &mut Frame<'_>, area: Rect) -> Result<()> {
+        let area = area.inner(Margin::new(area.width / 8, area.height / 8));
+        let devices = self.devices.as_mut().unwrap();
+        let items = devices
+            .names
+            .iter()
+            .map(|name| {
+                let mut line = Line::from(name.clone());
+                if Some(name) == self.device_name.as_ref() {
+                    line.push_span(" (in use)".dim());
+                }
+                ListItem::new(line)
+            })
+            .collect::<Vec<_>>();
+        let list = List::new(items)
+            .block(
+                Block::new()
+                    .title("Output Device".bold())
+                    .title_bottom("<enter> to switch, <esc> to cancel".dim())
+                    .border_style(Style::new().fg(self.cfg.theme.border))
+                    .borders(Borders::ALL),
+            )
+            .highlight_symbol(">")
+            .highlight_spacing(HighlightSpacing::Always)
+            .highlight_style(Style::new().fg(self.cfg.theme.highlight));
+        f.render_widget(Clear, area);
+        f.render_stateful_widget(list, area, &mut devices.list_state);
+        Ok(())
+    }
+
+    /// Tag filter popup, drawn over `area`
+    fn draw_tags(&mut self, f: &mut Frame<'_>, area: Rect) -> Result<()> {
+        let area = area.inner(Margin::new(area.width / 8, area.height / 8));
+        let tags = self.tags.as_mut().unwrap();
+        let items = [None]
+            .into_iter()
+            .chain(tags.tags.iter().map(Some))
+            .map(|tag| {
+                let mut line = match tag {
+                    Some(tag) => Line::from(tag.clone()),
+                    None => Line::from("all tracks".italic()),
+                };
+                if tag == self.tag_filter.as_ref() {
+                    line.push_span(" (shown)".dim());
+                }
+                ListItem::new(line)
+            })
+            .collect::<Vec<_>>();
+        let list = List::new(items)
+            .block(
+                Block::new()
+                    .title("Filter by Tag".bold())
+                    .title_bottom("<enter> to filter, <esc> to cancel".dim())
+                    .border_style(Style::new().fg(self.cfg.theme.border))
+                    .borders(Borders::ALL),
+            )
+            .highlight_symbol(">")
+            .highlight_spacing(HighlightSpacing::Always)
+            .highlight_style(Style::new().fg(self.cfg.theme.highlight));
+        f.render_widget(Clear, area);
+        f.render_stateful_widget(list, area, &mut tags.list_state);
         Ok(())
     }
 
     pub(super) fn draw_inner(&mut self, f: &mut Frame<'_>, area: Rect) -> Result<()> {
         let main_layout = Layout::new(
             Direction::Vertical,
-            [Constraint::Length(3), Constraint::Min(0)],
+            [
+                Constraint::Length(3),
+                Constraint::Length(1),
+                // announcements (accessibility mode only)
+                Constraint::Length(if self.cfg.accessibility.enabled { 1 } else { 0 }),
+                Constraint::Min(0),
+            ],
         )
         .horizontal_margin(1)
         .split(area);
         self.draw_titlebar(f, main_layout[0])?;
+        self.draw_progress(f, main_layout[1])?;
+        if let Some(announcement) = &self.announcement {
+            f.render_widget(Paragraph::new(announcement.as_str()), main_layout[2]);
+        }
 
         let content_layout = Layout::new(
             Direction::Horizontal,
             [Constraint::Max(37), Constraint::Min(0)],
         )
-        .split(main_layout[1]);
+        .split(main_layout[3]);
 
         self.draw_info(f, content_layout[0])?;
 
@@ -210,28 +587,138 @@ impl super::Home {
             [Constraint::Percentage(50), Constraint::Percentage(50)],
         )
         .split(content_layout[1]);
+        let side_layout = Layout::new(
+            Direction::Vertical,
+            [Constraint::Percentage(60), Constraint::Percentage(40)],
+        )
+        .split(lists_layout[1]);
+        let rows = self.track_rows();
+        self.follow_playing(&rows, lists_layout[0].height.saturating_sub(2) as usize);
+        self.t_list_view.select(
+            self.t_list_state
+                .selected()
+                .and_then(|track| rows.iter().position(|row| row.selects() == Some(track))),
+        );
+        let durations = rows
+            .iter()
+            .map(|row| match row {
+                TrackRow::Track(track) => self.track_duration(TrackID {
+                    track: *track,
+                    playlist: self.current.playlist,
+                }),
+                TrackRow::Section { .. } => None,
+            })
+            .collect::<Vec<_>>();
+        let downloads = rows
+            .iter()
+            .map(|row| match row {
+                TrackRow::Track(track) => self.download_status(TrackID {
+                    track: *track,
+                    playlist: self.current.playlist,
+                }),
+                // (not shown)
+                TrackRow::Section { .. } => DownloadStatus::Downloaded,
+            })
+            .collect::<Vec<_>>();
+        let download_progress = match self.downloads_progress() {
+            Some((done, total)) => format!("downloading {done}/{total}").fg(self.cfg.theme.accent),
+            None => "".into(),
+        };
         f.render_stateful_widget(
             List::new(
-                self.get_playlist(self.current.playlist)
-                    .tracks
-                    .iter()
-                    .enumerate()
-                    .map(|(i, track)| {
+                rows.iter()
+                    .zip(durations)
+                    .zip(downloads)
+                    .map(|((row, duration), download)| {
+                        let i = match row {
+                            TrackRow::Track(i) => *i,
+                            TrackRow::Section {
+                                name,
+                                collapsed,
+                                tracks,
+                            } => {
+                                let item = ListItem::new(Line::from(vec![
+                                    if *collapsed { "▸ " } else { "▾ " }.into(),
+                                    name.clone()
+                                        .unwrap_or_else(|| "Other tracks".to_string())
+                                        .bold(),
+                                    format!(" ({})", tracks.len()).dim(),
+                                    if self.cfg.accessibility.enabled {
+                                        if *collapsed {
+                                            " collapsed"
+                                        } else {
+                                            " expanded"
+                                        }
+                                        .into()
+                                    } else {
+                                        "".into()
+                                    },
+                                ]));
+                                // a collapsed section is highlighted if the current track is hidden in it
+                                return if *collapsed && tracks.contains(&self.current.track) {
+                                    item.fg(self.cfg.theme.playing)
+                                } else {
+                                    item
+                                };
+                            }
+                        };
+                        let track = &self.get_playlist(self.current.playlist).tracks[i];
                         let is_now_playing = i == self.current.track;
+                        let is_marked = self.marked.contains(&i);
                         let i = i + 1;
                         let item = ListItem::new(Line::from(vec![
+                            match download {
+                                DownloadStatus::Downloaded => {
+                                    download.icon().fg(self.cfg.theme.dim)
+                                }
+                                DownloadStatus::Missing => download.icon().fg(Color::LightRed),
+                                DownloadStatus::Downloading => {
+                                    download.icon().fg(self.cfg.theme.accent)
+                                }
+                            },
+                            " ".into(),
                             {
                                 let fmt = i.to_string();
                                 let n_zeroes = 3usize.saturating_sub(fmt.len());
-                                let zeroes = iter::repeat('0').take(n_zeroes).collect::<String>();
+                                let zeroes = "0".repeat(n_zeroes);
                                 zeroes.dim()
                             },
                             i.to_string().into(),
                             ": ".into(),
                             track.meta.name.clone().italic(),
+                            match duration {
+                                Some(duration) => format!(" {}", duration::format(duration)).dim(),
+                                None => "".into(),
+                            },
+                            if self.cfg.accessibility.enabled {
+                                download.label().into()
+                            } else {
+                                "".into()
+                            },
+                            if is_now_playing && self.cfg.accessibility.enabled {
+                                " (playing)".into()
+                            } else {
+                                "".into()
+                            },
+                            if track.disabled && self.cfg.accessibility.enabled {
+                                " (disabled)".into()
+                            } else {
+                                "".into()
+                            },
+                            if is_marked && self.cfg.accessibility.enabled {
+                                " (marked)".into()
+                            } else {
+                                "".into()
+                            },
                         ]));
                         if is_now_playing {
-                            item.light_green()
+                            item.fg(self.cfg.theme.playing)
+                        } else if is_marked {
+                            item.fg(self.cfg.theme.accent).bold()
+                        } else if track.disabled {
+                            item.dim().crossed_out()
+                        } else if download == DownloadStatus::Missing {
+                            item.dim()
                         } else {
                             item
                         }
@@ -240,35 +727,89 @@ impl super::Home {
             )
             .block(
                 Block::new()
-                    .title("Track Selection".bold())
-                    .border_style(Style::new().fg(Color::Yellow))
+                    .title(match (&self.edit, &self.tag_filter) {
+                        (Some(_), _) => "Track Order (editing)".bold(),
+                        (None, Some(tag)) => format!("Track Selection (tagged {tag})").bold(),
+                        (None, None) => "Track Selection".bold(),
+                    })
+                    .title(match self.marked.len() {
+                        0 => "".into(),
+                        n => format!("{n} marked").fg(self.cfg.theme.accent),
+                    })
+                    .title(block::Title::from(download_progress).alignment(Alignment::Right))
+                    .title_bottom(match self.edit {
+                        Some(_) => "<shift-j>/<shift-k> to move, <enter> to save, <esc> to cancel",
+                        None => "",
+                    })
+                    .border_style(Style::new().fg(self.cfg.theme.border))
                     .borders(Borders::ALL),
             )
             .highlight_symbol(">")
             .highlight_spacing(HighlightSpacing::Always)
-            .highlight_style(Style::new().fg(Color::LightCyan)),
+            .highlight_style(Style::new().fg(self.cfg.theme.highlight)),
             lists_layout[0],
-            &mut self.t_list_state,
+            &mut self.t_list_view,
         );
 
         f.render_stateful_widget(
             List::new(
-                self.resolver
-                    .out()
-                    .playlists
-                    .iter()
+                self.playlist_rows()
+                    .into_iter()
                     .enumerate()
-                    .map(|(i, pl)| {
-                        let is_now_playing = i == self.current.playlist.playlist;
-                        let item = if self.p_list_state.selected().is_some_and(|x| x == i) {
-                            ListItem::new(Line::from(vec!["> ".into(), pl.name.clone().into()]))
-                        } else {
-                            ListItem::new(Line::from(vec!["- ".into(), pl.name.clone().into()]))
-                        };
-                        if is_now_playing {
-                            item.light_green()
-                        } else {
-                            item
+                    .map(|(i, row)| {
+                        let selected = self.p_list_state.selected().is_some_and(|x| x == i);
+                        match row {
+                            PlaylistRow::Group {
+                                name,
+                                collapsed,
+                                len,
+                            } => {
+                                // a collapsed group is highlighted if the current playlist is hidden in it
+                                let is_now_playing = collapsed
+                                    && self.get_playlist(self.current.playlist).group.as_ref()
+                                        == Some(&name);
+                                let marker = match (selected, collapsed) {
+                                    (true, _) => "> ",
+                                    (false, true) => "▸ ",
+                                    (false, false) => "▾ ",
+                                };
+                                let item = ListItem::new(Line::from(vec![
+                                    marker.into(),
+                                    name.bold(),
+                                    format!(" ({len})").dim(),
+                                    if self.cfg.accessibility.enabled {
+                                        if collapsed { " collapsed" } else { " expanded" }.into()
+                                    } else {
+                                        "".into()
+                                    },
+                                ]));
+                                if is_now_playing {
+                                    item.fg(self.cfg.theme.playing)
+                                } else {
+                                    item
+                                }
+                            }
+                            PlaylistRow::Playlist(id) => {
+                                let pl = self.get_playlist(id);
+                                let is_now_playing = id == self.current.playlist;
+                                let indent = if pl.group.is_some() { "  " } else { "" };
+                                let marker = if selected { "> " } else { "- " };
+                                let item = ListItem::new(Line::from(vec![
+                                    indent.into(),
+                                    marker.into(),
+                                    pl.name.clone().into(),
+                                    if is_now_playing && self.cfg.accessibility.enabled {
+                                        " (playing)".into()
+                                    } else {
+                                        "".into()
+                                    },
+                                ]));
+                                if is_now_playing {
+                                    item.fg(self.cfg.theme.playing)
+                                } else {
+                                    item
+                                }
+                            }
                         }
                     })
                     .collect::<Vec<_>>(),
@@ -276,14 +817,63 @@ impl super::Home {
             .block(
                 Block::new()
                     .title("Playlist Selection".bold())
-                    .border_style(Style::new().fg(Color::Yellow))
+                    .border_style(Style::new().fg(self.cfg.theme.border))
                     .borders(Borders::ALL),
             )
-            .highlight_style(Style::new().fg(Color::LightCyan)),
-            lists_layout[1],
+            .highlight_style(Style::new().fg(self.cfg.theme.highlight)),
+            side_layout[0],
             &mut self.p_list_state,
         );
 
+        f.render_stateful_widget(
+            List::new(
+                self.queue
+                    .iter()
+                    .enumerate()
+                    .map(|(i, id)| {
+                        let mut spans = vec![
+                            format!("{}: ", i + 1).into(),
+                            self.get_track(*id).meta.name.clone().italic(),
+                        ];
+                        if id.playlist != self.current.playlist {
+                            spans.push(format!(" ({})", self.get_playlist(id.playlist).name).dim());
+                        }
+                        ListItem::new(Line::from(spans))
+                    })
+                    .collect::<Vec<_>>(),
+            )
+            .block(
+                Block::new()
+                    .title(format!("Queue ({})", self.queue.len()).bold())
+                    .border_style(Style::new().fg(self.cfg.theme.border))
+                    .borders(Borders::ALL),
+            )
+            .highlight_symbol(">")
+            .highlight_spacing(HighlightSpacing::Always)
+            .highlight_style(Style::new().fg(self.cfg.theme.highlight)),
+            side_layout[1],
+            &mut self.q_list_state,
+        );
+
+        if self.show_lyrics {
+            self.draw_lyrics(f, lists_layout[1])?;
+        }
+        if self.search.is_some() {
+            self.draw_search(f, content_layout[1])?;
+        }
+        if self.devices.is_some() {
+            self.draw_devices(f, content_layout[1])?;
+        }
+        if self.tags.is_some() {
+            self.draw_tags(f, content_layout[1])?;
+        }
+        if self.transfer.is_some() {
+            self.draw_transfer(f, content_layout[1])?;
+        }
+        if self.help.is_some() {
+            self.draw_help(f, main_layout[3])?;
+        }
+
         Ok(())
     }
 }
@@ -0,0 +1,71 @@
+use color_eyre::eyre::Result;
+
+use super::PlaylistID;
+use crate::ui::action::Severity;
+
+/// Track order of a playlist, while it is being edited
+#[derive(Debug, Clone)]
+pub(super) struct Edit {
+    pub playlist: PlaylistID,
+    /// tracks of the playlist in their new order
+    pub order: Vec<usize>,
+}
+
+impl super::Home {
+    /// Starts editing the track order of the current playlist (with the track list selected),
+    /// returning false if it can not be edited
+    pub(super) fn start_edit(&mut self) -> bool {
+        let playlist = self.get_playlist(self.current.playlist);
+        if playlist.is_virtual() {
+            let name = playlist.name.clone();
+            warn!("{name} is not a playlist file, and its track order can not be edited");
+            self.toast(
+                format!("The order of {name} can not be edited"),
+                Severity::Warning,
+            );
+            return false;
+        }
+        let len = playlist.tracks.len();
+        self.edit = Some(Edit {
+            playlist: self.current.playlist,
+            order: (0..len).collect(),
+        });
+        if self.t_list_state.selected().is_none() {
+            self.t_list_state.select(Some(self.current.track));
+        }
+        self.p_list_state.select(None);
+        self.q_list_state.select(None);
+        true
+    }
+
+    /// Moves the selected track one place towards the start (or end) of the playlist
+    pub(super) fn move_edited(&mut self, up: bool) {
+        let (Some(edit), Some(track)) = (self.edit.as_mut(), self.t_list_state.selected()) else {
+            return;
+        };
+        let Some(i) = edit.order.iter().position(|&t| t == track) else {
+            return;
+        };
+        if up && i > 0 {
+            edit.order.swap(i, i - 1);
+        } else if !up && i + 1 < edit.order.len() {
+            edit.order.swap(i, i + 1);
+        }
+    }
+
+    /// Writes the new track order to the playlist file. The playlist is then reloaded along with the file
+    pub(super) fn save_edit(&mut self) -> Result<()> {
+        let Some(edit) = self.edit.as_ref() else {
+            return Ok(());
+        };
+        if edit.order.iter().enumerate().any(|(i, &track)| i != track) {
+            crate::edit::reorder_tracks(
+                &self.resolver,
+                self.get_playlist(edit.playlist),
+                &edit.order,
+            )?;
+        }
+        self.edit = None;
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+//! Keeping the playing track in view, and remembering where each playlist's track list was scrolled to
+
+use super::{PlaylistID, TrackID, TrackRow};
+
+/// Where the track list of a playlist was, when another playlist was switched to
+#[derive(Clone, Copy, Debug)]
+pub(super) struct TrackView {
+    /// first row shown
+    offset: usize,
+    /// selected track (if the track list was selected)
+    selected: Option<usize>,
+}
+
+impl super::Home {
+    /// Remembers where the track list of `from` is scrolled to, and scrolls to where `to`'s was.
+    /// Returns the track that was selected in `to`'s track list (if there was one)
+    pub(super) fn switch_track_view(&mut self, from: PlaylistID, to: PlaylistID) -> Option<usize> {
+        self.track_views.insert(
+            from,
+            TrackView {
+                offset: self.t_list_view.offset(),
+                selected: self.t_list_state.selected(),
+            },
+        );
+        let view = self.track_views.get(&to).copied();
+        *self.t_list_view.offset_mut() = view.map_or(0, |view| view.offset);
+        view.and_then(|view| view.selected)
+    }
+
+    /// With follow enabled, centers the track list on the playing track when it changes
+    /// (`rows` are the rows of the list, and `height` is how many of them fit).
+    ///
+    /// The selection is moved along with it only if it was on the track that played before,
+    /// so browsing the list is not interrupted
+    pub(super) fn follow_playing(&mut self, rows: &[TrackRow], height: usize) {
+        if !self.follow || self.followed == Some(self.current) || self.edit.is_some() {
+            return;
+        }
+        let previous = self.followed.replace(self.current);
+        let Some(row) = rows
+            .iter()
+            .position(|row| *row == TrackRow::Track(self.current.track))
+        else {
+            return;
+        };
+        let selected = self.t_list_state.selected();
+        let on_previous = previous.is_some_and(|previous: TrackID| {
+            previous.playlist == self.current.playlist && selected == Some(previous.track)
+        });
+        match selected {
+            Some(_) if on_previous => self.t_list_state.select(Some(self.current.track)),
+            // the list keeps the selection in view, which would undo the scrolling
+            Some(_) => return,
+            None => {}
+        }
+        let offset = row.saturating_sub(height / 2);
+        *self.t_list_view.offset_mut() = offset.min(rows.len().saturating_sub(height));
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::BTreeMap;
+
+use super::PlaylistID;
+
+/// A row in the playlist selection list
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) enum PlaylistRow {
+    /// header of a group of playlists
+    Group {
+        name: String,
+        collapsed: bool,
+        /// number of playlists in the group
+        len: usize,
+    },
+    Playlist(PlaylistID),
+}
+
+impl super::Home {
+    /// Rows of the playlist selection list: the playlist of all tracks and ungrouped playlists first,
+    /// then each group (sorted by name) followed by its playlists (unless it is collapsed)
+    pub(super) fn playlist_rows(&self) -> Vec<PlaylistRow> {
+        let mut rows = vec![];
+        let mut groups = BTreeMap::<&str, Vec<PlaylistID>>::new();
+        for (i, pl) in self.resolver.out().playlists.iter().enumerate() {
+            let id = PlaylistID { playlist: i };
+            if pl.archived {
+                continue;
+            }
+            match &pl.group {
+                Some(group) => groups.entry(group).or_default().push(id),
+                None if pl.is_all_tracks() => rows.insert(0, PlaylistRow::Playlist(id)),
+                None => rows.push(PlaylistRow::Playlist(id)),
+            }
+        }
+        for (name, ids) in groups {
+            let collapsed = self.collapsed_groups.contains(name);
+            rows.push(PlaylistRow::Group {
+                name: name.to_string(),
+                collapsed,
+                len: ids.len(),
+            });
+            if !collapsed {
+                rows.extend(ids.into_iter().map(PlaylistRow::Playlist));
+            }
+        }
+        rows
+    }
+
+    /// Playlist under the cursor in the playlist list (the current one if the cursor is on a group header)
+    pub(super) fn selected_playlist(&self) -> PlaylistID {
+        match self
+            .p_list_state
+            .selected()
+            .and_then(|row| self.playlist_rows().get(row).cloned())
+        {
+            Some(PlaylistRow::Playlist(id)) => id,
+            _ => self.current.playlist,
+        }
+    }
+
+    /// Row of the given playlist (or the header of its group, if that is collapsed)
+    pub(super) fn playlist_row_of(&self, playlist: PlaylistID) -> usize {
+        let group = self.get_playlist(playlist).group.as_deref();
+        self.playlist_rows()
+            .iter()
+            .position(|row| match row {
+                PlaylistRow::Playlist(id) => *id == playlist,
+                PlaylistRow::Group { name, .. } => Some(name.as_str()) == group,
+            })
+            .unwrap_or(0)
+    }
+
+    /// Expands or collapses the group under the cursor in the playlist list.
+    /// If the cursor is on a playlist in a group, that group is collapsed
+    pub(super) fn toggle_selected_group(&mut self) {
+        let Some(row) = self.p_list_state.selected() else {
+            return;
+        };
+        match self.playlist_rows().get(row) {
+            Some(PlaylistRow::Group {
+                name, collapsed, ..
+            }) => {
+                if *collapsed {
+                    self.collapsed_groups.remove(name);
+                } else {
+                    self.collapsed_groups.insert(name.clone());
+                }
+            }
+            Some(PlaylistRow::Playlist(id)) => {
+                if let Some(group) = self.get_playlist(*id).group.clone() {
+                    self.collapsed_groups.insert(group);
+                    self.p_list_state.select(Some(self.playlist_row_of(*id)));
+                }
+            }
+            None => {}
+        }
+    }
+}
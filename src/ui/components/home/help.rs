@@ -0,0 +1,247 @@
+//! The help popup, listing the keybindings of every mode
+
+use std::collections::BTreeMap;
+
+use color_eyre::eyre::Result;
+use crossterm::event::KeyEvent;
+use ratatui::{prelude::*, widgets::*};
+
+use crate::{
+    cfg,
+    ui::{action::Action, mode::Mode},
+};
+
+/// Categories of the help popup, in the order they are listed
+const CATEGORIES: &[&str] = &[
+    "General",
+    "Playback",
+    "Lists",
+    "Queue",
+    "Popups",
+    "View",
+    "Search",
+    "Output Devices",
+    "Tag Filter",
+    "Move/Copy Tracks",
+    "Editing Track Order",
+    "Help",
+    "Other",
+];
+
+/// State of the help popup
+#[derive(Debug, Default)]
+pub(super) struct Help {
+    /// how many lines are scrolled past
+    pub scroll: u16,
+}
+
+/// What a keybinding does
+pub(super) fn label(action: &Action) -> Option<&'static str> {
+    Some(match action {
+        Action::Quit => "quit",
+        Action::PausePlay => "pause/play",
+        Action::ChangeModeSelection => "change mode (sequential/shuffle/radio)",
+        Action::ChangeModeRepeat => "toggle repeat",
+        Action::NextTrack => "skip",
+        Action::PrevTrack => "previous track",
+        Action::VolumeUp => "volume up",
+        Action::VolumeDown => "volume down",
+        Action::LoopMarkStart => "A-B repeat: mark start (A)",
+        Action::LoopMarkEnd => "A-B repeat: mark end (B), and start repeating",
+        Action::LoopClear => "A-B repeat: stop",
+        Action::ListLeft => "select track list",
+        Action::ListRight => "select playlist list",
+        Action::ListQueue => "select queue",
+        Action::ListSelNext => "list: next",
+        Action::ListSelPrev => "list: prev",
+        Action::ListChooseSelected => "list: play track/select playlist",
+        Action::ListToggleGroup => "list: expand/collapse group or section",
+        Action::PreviewSelected => "list: preview track",
+        Action::QueueAdd => "list: add track (or marked tracks) to queue",
+        Action::ListToggleMark => "list: mark/unmark track",
+        Action::ListClearMarks => "list: unmark all tracks",
+        Action::ListDownload => "list: download track (or marked tracks)",
+        Action::PlaylistDownload => "list: download every track in the selected playlist",
+        Action::ListRedownload => "list: download track (or marked tracks) again",
+        Action::RefreshDownloads => "list: check which tracks are downloaded",
+        Action::ListRemoveTracks => "list: remove track (or marked tracks) from playlist",
+        Action::ListMoveTracks => "list: move track (or marked tracks) to another playlist",
+        Action::ListCopyTracks => "list: copy track (or marked tracks) to another playlist",
+        Action::QueueRemove => "queue: remove",
+        Action::QueueMoveUp => "queue: move up",
+        Action::QueueMoveDown => "queue: move down",
+        Action::OpenSearch => "search all playlists",
+        Action::OpenDevices => "choose output device",
+        Action::OpenTags => "filter tracks by tag",
+        Action::EditStart => "edit track order",
+        Action::ToggleMeter => "show/hide level meter",
+        Action::ToggleLyrics => "show/hide lyrics",
+        Action::ToggleFollow => "follow playing track on/off",
+        Action::ToggleHelp => "show/hide all keybinds",
+        Action::Reload => "reload config and playlists",
+        Action::HelpScrollDown => "scroll down",
+        Action::HelpScrollUp => "scroll up",
+        Action::CloseSearch => "close search",
+        Action::SearchChoose => "play result",
+        Action::SearchSelNext => "next result",
+        Action::SearchSelPrev => "previous result",
+        Action::CloseDevices => "close without switching",
+        Action::DeviceChoose => "switch to device",
+        Action::DeviceSelNext => "next device",
+        Action::DeviceSelPrev => "previous device",
+        Action::CloseTags => "close without filtering",
+        Action::TagChoose => "filter by tag",
+        Action::TagSelNext => "next tag",
+        Action::TagSelPrev => "previous tag",
+        Action::CloseTransfer => "close without moving",
+        Action::TransferChoose => "move/copy to playlist",
+        Action::TransferSelNext => "next playlist",
+        Action::TransferSelPrev => "previous playlist",
+        Action::EditMoveUp => "move track up",
+        Action::EditMoveDown => "move track down",
+        Action::EditSave => "save track order",
+        Action::EditCancel => "discard track order",
+        _ => return None,
+    })
+}
+
+/// Which category of the help popup a keybinding of `mode` is listed under
+fn category(mode: Mode, action: &Action) -> &'static str {
+    match (mode, action) {
+        (_, Action::Quit) => "General",
+        (Mode::Search, _) => "Search",
+        (Mode::Devices, _) => "Output Devices",
+        (Mode::Tags, _) => "Tag Filter",
+        (Mode::Transfer, _) => "Move/Copy Tracks",
+        (Mode::Edit, _) => "Editing Track Order",
+        (Mode::Help, _) => "Help",
+        (Mode::Home, Action::ToggleHelp) => "General",
+        (
+            Mode::Home,
+            Action::PausePlay
+            | Action::ChangeModeSelection
+            | Action::ChangeModeRepeat
+            | Action::NextTrack
+            | Action::PrevTrack
+            | Action::VolumeUp
+            | Action::VolumeDown
+            | Action::LoopMarkStart
+            | Action::LoopMarkEnd
+            | Action::LoopClear
+            | Action::PreviewSelected,
+        ) => "Playback",
+        (
+            Mode::Home,
+            Action::ListLeft
+            | Action::ListRight
+            | Action::ListSelNext
+            | Action::ListSelPrev
+            | Action::ListChooseSelected
+            | Action::ListToggleGroup
+            | Action::ListToggleMark
+            | Action::ListClearMarks
+            | Action::ListDownload
+            | Action::PlaylistDownload
+            | Action::ListRedownload
+            | Action::RefreshDownloads
+            | Action::ListRemoveTracks
+            | Action::ListMoveTracks
+            | Action::ListCopyTracks,
+        ) => "Lists",
+        (
+            Mode::Home,
+            Action::ListQueue
+            | Action::QueueAdd
+            | Action::QueueRemove
+            | Action::QueueMoveUp
+            | Action::QueueMoveDown,
+        ) => "Queue",
+        (
+            Mode::Home,
+            Action::OpenSearch | Action::OpenDevices | Action::OpenTags | Action::EditStart,
+        ) => "Popups",
+        (Mode::Home, Action::ToggleMeter | Action::ToggleLyrics | Action::ToggleFollow) => "View",
+        (Mode::Home, _) => "Other",
+    }
+}
+
+/// `<key><key>` for a key sequence
+pub(super) fn keys_label(keys: &[KeyEvent]) -> String {
+    keys.iter()
+        .map(|key| format!("<{}>", cfg::key_event_to_string(key)))
+        .collect()
+}
+
+impl super::Home {
+    /// Lines of the help popup: the keybindings of every mode, grouped by category.
+    /// Keys doing the same thing are listed together
+    fn help_lines(&self) -> Vec<Line<'static>> {
+        // category -> label -> keys
+        let mut groups = BTreeMap::<usize, BTreeMap<String, Vec<String>>>::new();
+        for (mode, bindings) in self.cfg.keybinds.0.iter() {
+            for (keys, action) in bindings {
+                let category = category(*mode, action);
+                let label = match label(action) {
+                    Some(label) => label.to_string(),
+                    None => format!("{action:?}"),
+                };
+                let order = CATEGORIES.iter().position(|c| *c == category).unwrap();
+                let keys_list = groups.entry(order).or_default().entry(label).or_default();
+                let keys = keys_label(keys);
+                if !keys_list.contains(&keys) {
+                    keys_list.push(keys);
+                }
+            }
+        }
+
+        let mut lines = vec![];
+        for (order, entries) in groups {
+            if !lines.is_empty() {
+                lines.push(Line::default());
+            }
+            lines.push(Line::from(
+                CATEGORIES[order].bold().fg(self.cfg.theme.accent),
+            ));
+            let mut entries = entries
+                .into_iter()
+                .map(|(label, mut keys)| {
+                    keys.sort();
+                    (keys.join(" "), label)
+                })
+                .collect::<Vec<_>>();
+            entries.sort();
+            let width = entries
+                .iter()
+                .map(|(keys, _)| keys.len())
+                .max()
+                .unwrap_or(0);
+            for (keys, label) in entries {
+                lines.push(Line::from(vec![
+                    format!("  {keys:<width$}  ").fg(self.cfg.theme.highlight),
+                    label.into(),
+                ]));
+            }
+        }
+        lines
+    }
+
+    /// Help popup, drawn over `area`
+    pub(super) fn draw_help(&mut self, f: &mut Frame<'_>, area: Rect) -> Result<()> {
+        let lines = self.help_lines();
+        let block = Block::new()
+            .title("Keybinds".bold())
+            .title_bottom("<j>/<k> to scroll, <?> or <esc> to close".dim())
+            .border_style(Style::new().fg(self.cfg.theme.border))
+            .borders(Borders::ALL);
+        let height = block.inner(area).height as usize;
+        let max_scroll = lines.len().saturating_sub(height) as u16;
+        let help = self.help.as_mut().unwrap();
+        help.scroll = help.scroll.min(max_scroll);
+        f.render_widget(Clear, area);
+        f.render_widget(
+            Paragraph::new(lines).block(block).scroll((help.scroll, 0)),
+            area,
+        );
+        Ok(())
+    }
+}
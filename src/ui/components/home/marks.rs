@@ -0,0 +1,120 @@
+//! Marking several tracks in the track list, to queue, download, or remove them all at once
+
+use std::collections::HashSet;
+
+use super::TrackID;
+use crate::{cache, ui::action::Severity};
+
+impl super::Home {
+    /// Marks (or unmarks) the selected track, and moves the selection on to the next one
+    pub(super) fn toggle_mark(&mut self) {
+        let Some(track) = self.t_list_state.selected() else {
+            return;
+        };
+        if !self.marked.remove(&track) {
+            self.marked.insert(track);
+        }
+        self.select_visible_track(true);
+        self.announce(format!("{} marked", self.marked.len()));
+    }
+
+    /// Tracks (of the current playlist) that batch actions apply to: the marked ones in playlist order,
+    /// or the selected one if none are marked
+    pub(super) fn chosen_tracks(&self) -> Vec<usize> {
+        if self.marked.is_empty() {
+            return self.t_list_state.selected().into_iter().collect();
+        }
+        let mut tracks = self.marked.iter().copied().collect::<Vec<_>>();
+        tracks.sort();
+        tracks
+    }
+
+    /// Adds the marked tracks to the end of the queue
+    pub(super) fn queue_marked(&mut self) {
+        let tracks = self.chosen_tracks();
+        for &track in &tracks {
+            self.queue.push(TrackID {
+                track,
+                playlist: self.current.playlist,
+            });
+        }
+        self.marked.clear();
+        self.preload_outdated = true;
+        let message = format!("Queued {} track(s)", tracks.len());
+        self.toast(message.clone(), Severity::Info);
+        self.announce(message);
+    }
+
+    /// Store hashes of the chosen tracks, which are downloaded by the download actions
+    fn chosen_hashes(&self) -> HashSet<cache::Hash> {
+        let mut hashes = HashSet::new();
+        for track in self.chosen_tracks() {
+            match self.track_file(TrackID {
+                track,
+                playlist: self.current.playlist,
+            }) {
+                Ok((hash, _, _)) => {
+                    hashes.insert(hash);
+                }
+                Err(e) => warn!("Not downloading track {track}: {e}"),
+            }
+        }
+        hashes
+    }
+
+    /// Downloads the chosen tracks that are not downloaded yet, in the background
+    pub(super) fn download_chosen(&mut self) {
+        let cache = &self.resolver.out().cache;
+        let hashes = self
+            .chosen_hashes()
+            .into_iter()
+            .filter(|&hash| cache.find(hash).is_none())
+            .collect::<HashSet<_>>();
+        if hashes.is_empty() {
+            self.toast("Already downloaded".to_string(), Severity::Info);
+            return;
+        }
+        self.marked.clear();
+        self.download_in_background(hashes, false);
+    }
+
+    /// Downloads the chosen tracks again, in the background. What is already downloaded is kept if it fails
+    pub(super) fn redownload_chosen(&mut self) {
+        let hashes = self.chosen_hashes();
+        if hashes.is_empty() {
+            return;
+        }
+        self.marked.clear();
+        self.download_in_background(hashes, true);
+    }
+
+    /// Removes the chosen tracks from the playlist file. The playlist is then reloaded along with the file
+    pub(super) fn remove_chosen(&mut self) {
+        let playlist = self.get_playlist(self.current.playlist);
+        if playlist.is_virtual() {
+            let name = playlist.name.clone();
+            warn!("{name} is not a playlist file, and tracks can not be removed from it");
+            self.toast(
+                format!("Tracks can not be removed from {name}"),
+                Severity::Warning,
+            );
+            return;
+        }
+        let tracks = self.chosen_tracks();
+        if tracks.is_empty() {
+            return;
+        }
+        match crate::edit::remove_tracks(&self.resolver, playlist, &tracks) {
+            Ok(()) => {
+                self.marked.clear();
+                let message = format!("Removed {} track(s) from the playlist", tracks.len());
+                self.toast(message.clone(), Severity::Info);
+                self.announce(message);
+            }
+            Err(e) => {
+                error!("Failed to remove tracks from the playlist: {e}");
+                self.toast(format!("Removing tracks failed: {e}"), Severity::Error);
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use std::time::{Duration, Instant};
+
+use ratatui::prelude::*;
+
+use crate::player2::LevelReading;
+
+/// Quietest level shown on the meter (in dB), anything below is shown as silence
+const FLOOR_DB: f32 = -48.0;
+/// How fast the meter falls back after a peak (in meter widths per second)
+const FALL_RATE: f32 = 1.5;
+const PARTIAL: [&str; 8] = ["", "▏", "▎", "▍", "▌", "▋", "▊", "▉"];
+/// How often the peak/RMS readout changes (it would be too fast to read otherwise)
+const READOUT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Level (linear) in dB, or None for silence
+fn db(level: f32) -> Option<f32> {
+    (level > 0.0).then(|| 20.0 * level.log10())
+}
+
+/// `-12.3 dB`, or `silent`
+fn db_text(level: f32) -> String {
+    match db(level) {
+        Some(db) => format!("{db:.1} dB"),
+        None => "silent".to_string(),
+    }
+}
+
+/// Level meter of the left and right channels of what is playing
+pub(super) struct LevelMeter {
+    /// levels shown (from 0 at [`FLOOR_DB`] to 1 at 0 dB), which fall back slowly instead of following every peak
+    shown: [f32; 2],
+    last_update: Instant,
+    /// peak and RMS levels (of both channels) shown in the readout
+    readout: (f32, f32),
+    /// highest peak, summed squared RMS levels, and number of readings since the readout last changed
+    window: (f32, f32, u32),
+    window_start: Instant,
+}
+
+impl LevelMeter {
+    pub fn new() -> Self {
+        Self {
+            shown: [0.0; 2],
+            last_update: Instant::now(),
+            readout: (0.0, 0.0),
+            window: (0.0, 0.0, 0),
+            window_start: Instant::now(),
+        }
+    }
+
+    /// Takes in the levels played since the last update
+    pub fn update(&mut self, reading: LevelReading) {
+        let fall = self.last_update.elapsed().as_secs_f32() * FALL_RATE;
+        self.last_update = Instant::now();
+        for (shown, peak) in self.shown.iter_mut().zip(reading.peaks) {
+            let level = match db(peak) {
+                Some(db) => ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0),
+                None => 0.0,
+            };
+            *shown = level.max(*shown - fall);
+        }
+        let (peak, squares, readings) = &mut self.window;
+        *peak = reading.peaks.into_iter().fold(*peak, f32::max);
+        *squares += reading.rms.iter().map(|rms| rms * rms).sum::<f32>() / 2.0;
+        *readings += 1;
+        if self.window_start.elapsed() >= READOUT_INTERVAL {
+            self.readout = (*peak, (*squares / *readings as f32).sqrt());
+            self.window = (0.0, 0.0, 0);
+            self.window_start = Instant::now();
+        }
+    }
+
+    /// Peak and RMS level, as a line of text
+    pub fn readout(&self) -> Line<'static> {
+        let (peak, rms) = self.readout;
+        Line::from(format!("peak {}, rms {}", db_text(peak), db_text(rms)))
+    }
+
+    /// The meter as one line per channel, `width` cells wide, followed by the readout
+    pub fn lines(&self, width: u16) -> Vec<Line<'static>> {
+        let cells = width.saturating_sub(2) as usize;
+        ["L ", "R "]
+            .into_iter()
+            .zip(self.shown)
+            .map(|(label, level)| {
+                let eighths = (level * (cells * 8) as f32) as usize;
+                let mut spans = vec![label.dark_gray()];
+                for cell in 0..eighths.div_ceil(8) {
+                    let symbol = match eighths - cell * 8 {
+                        8.. => "█",
+                        partial => PARTIAL[partial],
+                    };
+                    // the last quarter of the meter is above -12 dB, and the last 16th above -3 dB
+                    let color = match (cell * 16) / cells.max(1) {
+                        ..12 => Color::LightGreen,
+                        12..15 => Color::Yellow,
+                        _ => Color::LightRed,
+                    };
+                    spans.push(symbol.fg(color));
+                }
+                Line::from(spans)
+            })
+            .chain([self.readout().dark_gray()])
+            .collect()
+    }
+
+    /// The levels as text (and the readout), for accessibility mode
+    pub fn text(&self) -> Vec<Line<'static>> {
+        ["left", "right"]
+            .into_iter()
+            .zip(self.shown)
+            .map(|(channel, level)| match level > 0.0 {
+                true => {
+                    let db = (FLOOR_DB * (1.0 - level)).round() as i32;
+                    Line::from(format!("{channel}: {db} dB"))
+                }
+                false => Line::from(format!("{channel}: silent")),
+            })
+            .chain([self.readout()])
+            .collect()
+    }
+}
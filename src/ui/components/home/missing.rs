@@ -0,0 +1,53 @@
+//! Tracks that are not downloaded yet: marking them in the track list, and skipping over them while playing
+
+use color_eyre::eyre::Result;
+use log::{info, warn};
+
+use super::{DownloadStatus, Repeat};
+use crate::ui::action::Severity;
+
+impl super::Home {
+    /// Called when the current track can not be played because it is not downloaded.
+    /// With `audio.skip_not_downloaded`, this moves on to the next track that is (trying each track at most once),
+    /// otherwise (or when repeating the track) playback stops
+    pub(super) fn skip_not_downloaded(&mut self) -> Result<()> {
+        let name = self.get_track(self.current).meta.name.clone();
+        warn!("Could not find file for track {name:?}. It is probably not downloaded");
+        info!("Try downloading the playlist with `dmm download`");
+        self.downloads.insert(self.current, DownloadStatus::Missing);
+        if !self.cfg.audio.skip_not_downloaded
+            || !self.autoplay
+            || self.preview.is_some()
+            || self.repeat == Repeat::RepeatTrack
+        {
+            self.toast(format!("{name} is not downloaded"), Severity::Error);
+            self.autoplay = false;
+            self.player.stop()?;
+            return Ok(());
+        }
+        self.toast(
+            format!("{name} is not downloaded, skipping it"),
+            Severity::Warning,
+        );
+        for _ in 0..self.playable_tracks(self.current.playlist).len() {
+            // so shuffling does not pick it again
+            self.shuffle_played();
+            self.select_next_track()?;
+            if !self.autoplay {
+                // reached the end of the playlist
+                return Ok(());
+            }
+            if self.is_downloaded(self.current) {
+                return self.play_c_track();
+            }
+        }
+        warn!("None of the tracks in the playlist are downloaded");
+        self.toast(
+            "None of the tracks in this playlist are downloaded".to_string(),
+            Severity::Error,
+        );
+        self.autoplay = false;
+        self.player.stop()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,64 @@
+use std::collections::VecDeque;
+
+use super::TrackID;
+
+/// Tracks to play next, before going back to the track selection method
+#[derive(Debug, Clone, Default)]
+pub(super) struct Queue {
+    tracks: VecDeque<TrackID>,
+}
+
+impl Queue {
+    pub fn push(&mut self, track: TrackID) {
+        self.tracks.push_back(track);
+    }
+
+    /// Takes the next track to play
+    pub fn pop(&mut self) -> Option<TrackID> {
+        self.tracks.pop_front()
+    }
+
+    /// The track that will be played next
+    pub fn front(&self) -> Option<TrackID> {
+        self.tracks.front().copied()
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<TrackID> {
+        self.tracks.remove(index)
+    }
+
+    /// Moves the entry at `index` one place towards the front, returning its new index
+    pub fn move_up(&mut self, index: usize) -> usize {
+        if index == 0 || index >= self.tracks.len() {
+            return index;
+        }
+        self.tracks.swap(index, index - 1);
+        index - 1
+    }
+
+    /// Moves the entry at `index` one place towards the back, returning its new index
+    pub fn move_down(&mut self, index: usize) -> usize {
+        if index + 1 >= self.tracks.len() {
+            return index;
+        }
+        self.tracks.swap(index, index + 1);
+        index + 1
+    }
+
+    pub fn len(&self) -> usize {
+        self.tracks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &TrackID> {
+        self.tracks.iter()
+    }
+
+    /// Replaces every entry with `f(entry)`, removing the entries it returns `None` for
+    pub fn remap(&mut self, f: impl FnMut(TrackID) -> Option<TrackID>) {
+        self.tracks = self.tracks.drain(..).filter_map(f).collect();
+    }
+}
@@ -0,0 +1,101 @@
+use std::collections::BTreeSet;
+
+use color_eyre::eyre::Result;
+use rand::{distributions::WeightedIndex, prelude::*};
+
+use super::{PlaylistID, TrackID};
+use crate::{
+    cache::Hash,
+    cfg::RadioHeuristic,
+    schema::{Playlist, Track},
+};
+
+fn track_hash(playlist: &Playlist, track: &Track) -> Option<Hash> {
+    Some(Hash::track(playlist.find_source(&track.src)?, track))
+}
+
+impl super::Home {
+    /// Remembers that a track was played, so radio mode does not pick it again too soon
+    pub(super) fn radio_played(&mut self, hash: Hash) {
+        self.radio_history.retain(|h| *h != hash);
+        self.radio_history.push_front(hash);
+        self.radio_history.truncate(self.cfg.radio.avoid_recent);
+    }
+
+    /// Picks a track similar to the current one, from any playlist.
+    ///
+    /// Candidates are scored using the configured heuristic, and one is picked at random (weighted by score).
+    /// If nothing is similar at all, any downloaded track may be picked. Only downloaded tracks are considered.
+    pub(super) fn radio_next(&self) -> Result<TrackID> {
+        let playlists = &self.resolver.out().playlists;
+        let current = self.get_track(self.current);
+        let Some(current_hash) = track_hash(self.get_playlist(self.current.playlist), current)
+        else {
+            return Ok(self.current);
+        };
+        // (every track shares the playlist of all tracks, smart playlists are made from the others,
+        // and archived playlists are hidden)
+        let contents = playlists
+            .iter()
+            .filter(|pl| !pl.is_virtual() && !pl.archived)
+            .map(|pl| {
+                pl.tracks
+                    .iter()
+                    .filter_map(|track| track_hash(pl, track))
+                    .collect::<BTreeSet<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut seen = BTreeSet::new();
+        let mut candidates = vec![];
+        for (p, pl) in playlists.iter().enumerate() {
+            if pl.archived {
+                continue;
+            }
+            for (t, track) in pl.tracks.iter().enumerate() {
+                let Some(hash) = track_hash(pl, track).filter(|_| !track.disabled) else {
+                    continue;
+                };
+                if hash == current_hash
+                    || !seen.insert(hash)
+                    || self.resolver.out().cache.find(hash).is_none()
+                {
+                    continue;
+                }
+                let same_artist =
+                    u32::from(track.meta.artist.eq_ignore_ascii_case(&current.meta.artist));
+                let shared_playlists = contents
+                    .iter()
+                    .filter(|set| set.contains(&current_hash) && set.contains(&hash))
+                    .count() as u32;
+                let score = match self.cfg.radio.heuristic {
+                    RadioHeuristic::Artist => same_artist,
+                    RadioHeuristic::Playlists => shared_playlists,
+                    RadioHeuristic::Mixed => same_artist * 2 + shared_playlists,
+                };
+                let id = TrackID {
+                    track: t,
+                    playlist: PlaylistID { playlist: p },
+                };
+                candidates.push((id, score, self.radio_history.contains(&hash)));
+            }
+        }
+        if candidates.is_empty() {
+            warn!("radio: no other tracks are available [repeating current track]");
+            return Ok(self.current);
+        }
+        // recently played tracks are only picked if there is nothing else
+        if candidates.iter().any(|(_, _, recent)| !recent) {
+            candidates.retain(|(_, _, recent)| !recent);
+        }
+
+        let mut rng = thread_rng();
+        let weights = candidates.iter().map(|(_, score, _)| *score);
+        let pick = match WeightedIndex::new(weights) {
+            Ok(dist) => dist.sample(&mut rng),
+            // every score is zero
+            Err(_) => rng.gen_range(0..candidates.len()),
+        };
+        Ok(candidates[pick].0)
+    }
+}
@@ -0,0 +1,164 @@
+use std::sync::Arc;
+
+use super::{PlaylistID, Resume, TrackID};
+use crate::{
+    resolver::Resolver,
+    schema::Track,
+    ui::{
+        action::{Action, Severity},
+        mode::Mode,
+    },
+};
+
+/// Finds the playlist `id` (of `old`) in `new`, by the file it was loaded from
+fn find_playlist(old: &Resolver, new: &Resolver, id: PlaylistID) -> Option<PlaylistID> {
+    let path = &old.out().playlists[id.playlist].file_path;
+    new.out()
+        .playlists
+        .iter()
+        .position(|pl| &pl.file_path == path)
+        .map(|playlist| PlaylistID { playlist })
+}
+
+/// Finds the track `id` (of `old`) in `new`, by its source and input
+fn find_track(old: &Resolver, new: &Resolver, id: TrackID) -> Option<TrackID> {
+    let playlist = find_playlist(old, new, id.playlist)?;
+    let old_track = &old.out().playlists[id.playlist.playlist].tracks[id.track];
+    let tracks = &new.out().playlists[playlist.playlist].tracks;
+    let same = |t: &Track| t.src == old_track.src && t.input == old_track.input;
+    // the same position is preferred, in case the track is in the playlist more than once
+    let track = if tracks.get(id.track).is_some_and(same) {
+        id.track
+    } else {
+        tracks.iter().position(same)?
+    };
+    Some(TrackID { track, playlist })
+}
+
+impl super::Home {
+    /// Switches to a reloaded resolver, updating everything that refers to playlists and tracks by index
+    pub(super) fn reload(&mut self, res: Arc<Resolver>) {
+        let old = std::mem::replace(&mut self.resolver, res);
+        // playlists may have moved, so the remembered scroll positions would end up on the wrong ones
+        self.track_views.clear();
+        self.marked.clear();
+        let new = self.resolver.clone();
+        let remap = |id| find_track(&old, &new, id);
+        let selected = self.t_list_state.selected().map(|track| TrackID {
+            track,
+            playlist: self.current.playlist,
+        });
+
+        if let Some(edit) = self.edit.take() {
+            // the new order is kept only if the playlist's tracks did not change under it
+            let unchanged = find_playlist(&old, &new, edit.playlist).filter(|pl| {
+                new.out().playlists[pl.playlist].tracks
+                    == old.out().playlists[edit.playlist.playlist].tracks
+            });
+            match unchanged {
+                Some(playlist) => self.edit = Some(super::Edit { playlist, ..edit }),
+                None => {
+                    warn!("The playlist was changed while editing its track order, discarding the new order");
+                    self.toast(
+                        "The playlist changed, discarded the new track order".to_string(),
+                        Severity::Warning,
+                    );
+                    if let Some(tx) = &self.command_tx {
+                        let _ = tx.send(Action::ChangeMode(Mode::Home));
+                    }
+                }
+            }
+        }
+
+        self.current = match remap(self.current) {
+            Some(current) => current,
+            None => {
+                // whatever is playing keeps playing, but what plays next starts over from a playlist that still exists
+                warn!("The current track was removed, going back to the start of the playlist");
+                self.toast(
+                    "The current track was removed".to_string(),
+                    Severity::Warning,
+                );
+                let playlist = find_playlist(&old, &new, self.current.playlist)
+                    .filter(|pl| !new.out().playlists[pl.playlist].tracks.is_empty())
+                    .or_else(|| {
+                        new.out()
+                            .playlists
+                            .iter()
+                            .position(|pl| !pl.tracks.is_empty() && !pl.archived)
+                            .map(|playlist| PlaylistID { playlist })
+                    })
+                    .unwrap_or(PlaylistID { playlist: 0 });
+                TrackID { track: 0, playlist }
+            }
+        };
+        self.queue.remap(remap);
+        self.jump_on_track_complete = self.jump_on_track_complete.and_then(remap);
+        self.resume_jump = self.resume_jump.and_then(|resume| {
+            Some(Resume {
+                track: remap(resume.track)?,
+                ..resume
+            })
+        });
+        self.history = self.history.drain(..).filter_map(remap).collect();
+        if let Some(preview) = self.preview.as_mut() {
+            match remap(preview.track) {
+                Some(track) => preview.track = track,
+                // a preview that is playing is the current track
+                None if preview.started => preview.track = self.current,
+                None => self.preview = None,
+            }
+        }
+        if let Some(preview) = self.preview.as_mut() {
+            preview.resume = preview.resume.and_then(|resume| {
+                Some(Resume {
+                    track: remap(resume.track)?,
+                    ..resume
+                })
+            });
+        }
+        self.resume_playlist = self.resume_playlist.and_then(|resume| {
+            Some(Resume {
+                track: remap(resume.track)?,
+                ..resume
+            })
+        });
+        if let Some(preloaded) = self.preloaded.as_mut() {
+            match remap(preloaded.track) {
+                Some(track) => preloaded.track = track,
+                None => self.preloaded = None,
+            }
+        }
+        // tracks may have moved, so the shuffle order starts over
+        self.shuffle = None;
+        self.shuffle_played();
+        // the next track (or how it is trimmed) may have changed
+        self.preload_outdated = true;
+        // track lengths are looked up again, as tracks may have been downloaded since
+        self.durations.clear();
+        self.refresh_downloads();
+        self.load_lyrics();
+        // (the titlebar says this too, while files are failing to load)
+        let failed = new.out().load_summary();
+        if let Some(failed) =
+            failed.filter(|failed| old.out().load_summary().as_ref() != Some(failed))
+        {
+            self.announce(failed);
+        }
+
+        if selected.is_some() {
+            // the selected track stays selected, if it is still in the playlist
+            let track = selected
+                .and_then(remap)
+                .filter(|track| track.playlist == self.current.playlist)
+                .map_or(self.current.track, |track| track.track);
+            self.t_list_state.select(Some(track));
+        }
+        if self.p_list_state.selected().is_some() {
+            self.p_list_state
+                .select(Some(self.playlist_row_of(self.current.playlist)));
+        }
+        self.clamp_queue_selection();
+        self.update_search_results();
+    }
+}
@@ -0,0 +1,204 @@
+//! Playback settings of each playlist (selection mode, repeat mode, and the last played track and position), kept in
+//! `run/playback-state.ron`, so a playlist picks up where it left off when it is selected again.
+//!
+//! The playlist that was playing last is remembered as well, so with `resume_on_start` the player can continue from it
+//! the next time it is opened. The position is written every [`SAVE_INTERVAL`] while playing, and when quitting
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use color_eyre::eyre::Result;
+use serde::{Deserialize, Serialize};
+
+use super::{PlaylistID, Repeat, Resume, TrackID, TrackSelectionMethod};
+use crate::{cache::Hash, player2, resolver::Resolver, schema};
+
+/// Name of the playback state file (in the `run` directory)
+const STATE_FILE: &str = "playback-state.ron";
+
+/// How often the position of the playing track is written to the state file
+const SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(super) struct PlaybackState {
+    /// name of the playlist that was playing last
+    #[serde(default)]
+    last_playlist: Option<String>,
+    /// by playlist name
+    playlists: BTreeMap<String, PlaylistState>,
+    /// when the state file was last written
+    #[serde(skip)]
+    saved_at: Option<Instant>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct PlaylistState {
+    mode: TrackSelectionMethod,
+    repeat: Repeat,
+    /// store hash of the last played track (it is looked for by this first, as the playlist may have changed since)
+    hash: Option<String>,
+    /// position of the last played track in the playlist
+    track: usize,
+    /// seconds into the last played track
+    position: u64,
+}
+
+impl PlaybackState {
+    fn path(res: &Resolver) -> PathBuf {
+        res.dirs().run.join(STATE_FILE)
+    }
+
+    /// Reads the state file (starting over if it can not be read)
+    pub fn load(res: &Resolver) -> Self {
+        let path = Self::path(res);
+        match fs::read_to_string(&path) {
+            Ok(text) => ron::from_str(&text).unwrap_or_else(|e| {
+                warn!("Failed to read the playback state ({path:?}): {e} [starting over]");
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    fn save(&mut self, res: &Resolver) -> Result<()> {
+        fs::write(Self::path(res), schema::to_ron_pretty(self)?)?;
+        self.saved_at = Some(Instant::now());
+        Ok(())
+    }
+}
+
+impl super::Home {
+    /// Remembers the playback settings of the current playlist (and the current track and position, if it is playing).
+    /// They are written to the state file if anything but the position changed, if the position changed and it was not
+    /// written in the last [`SAVE_INTERVAL`], or with `write`
+    pub(super) fn remember_playback(&mut self, write: bool) {
+        let name = self.get_playlist(self.current.playlist).name.clone();
+        let mut state = match self.playback_state.playlists.get(&name) {
+            Some(state) => state.clone(),
+            None => PlaylistState {
+                mode: self.sel_method,
+                repeat: self.repeat,
+                hash: None,
+                track: self.current.track,
+                position: 0,
+            },
+        };
+        state.mode = self.sel_method;
+        state.repeat = self.repeat;
+        let mut changed = false;
+        if self.player.state() != player2::State::Stopped && self.preview.is_none() {
+            if self.playback_state.last_playlist.as_ref() != Some(&name) {
+                self.playback_state.last_playlist = Some(name.clone());
+                changed = true;
+            }
+            state.hash = self
+                .track_file(self.current)
+                .ok()
+                .map(|(hash, ..)| hash.to_string());
+            state.track = self.current.track;
+            state.position = self.player.timestamp();
+        }
+        let old = self.playback_state.playlists.insert(name, state.clone());
+        let moved = old
+            .as_ref()
+            .is_some_and(|old| old.position != state.position)
+            && self
+                .playback_state
+                .saved_at
+                .is_none_or(|at| at.elapsed() >= SAVE_INTERVAL);
+        changed |= old.is_none_or(|old| {
+            PlaylistState {
+                position: state.position,
+                ..old
+            } != state
+        });
+        if changed || moved || write {
+            if let Err(e) = self.playback_state.save(&self.resolver) {
+                warn!("Failed to save the playback state: {e}");
+            }
+        }
+    }
+
+    /// Restores the saved playback settings of the current playlist (after it was selected): the selection and repeat
+    /// modes, and the last played track, which continues from where it was once playback is started
+    pub(super) fn restore_playback(&mut self) {
+        let playlist = self.current.playlist;
+        let Some(state) = self
+            .playback_state
+            .playlists
+            .get(&self.get_playlist(playlist).name)
+            .cloned()
+        else {
+            return;
+        };
+        self.sel_method = state.mode;
+        self.repeat = state.repeat;
+        self.shuffle = None;
+        self.preload_outdated = true;
+        let tracks = &self.get_playlist(playlist).tracks;
+        let by_hash = state.hash.and_then(|hash| {
+            let hash = hash.parse::<Hash>().ok()?;
+            (0..tracks.len()).find(|&track| {
+                self.track_file(TrackID { track, playlist })
+                    .is_ok_and(|(h, ..)| h == hash)
+            })
+        });
+        // (if the track is not found by its hash, the one at its old place is selected, but from the start,
+        // as it may be a different track now)
+        let (track, position) = match by_hash {
+            Some(track) => (track, state.position),
+            None if state.track < tracks.len() => (state.track, 0),
+            None => return,
+        };
+        self.current.track = track;
+        self.t_list_state
+            .select(Some(self.visible_track_near(track)));
+        self.resume_playlist = Some(Resume {
+            track: self.current,
+            position,
+            paused: false,
+        });
+    }
+
+    /// Makes the playlist that was playing last (when the player was closed) the current one, and restores its
+    /// playback settings, so starting playback continues from where it was (for `resume_on_start`)
+    pub(super) fn resume_last_playlist(&mut self) {
+        let Some(name) = &self.playback_state.last_playlist else {
+            return;
+        };
+        let Some(playlist) = self
+            .resolver
+            .out()
+            .playlists
+            .iter()
+            .position(|pl| &pl.name == name)
+        else {
+            debug!("The last played playlist ({name}) no longer exists, not resuming it");
+            return;
+        };
+        self.set_current(TrackID {
+            track: 0,
+            playlist: PlaylistID { playlist },
+        });
+        self.restore_playback();
+    }
+
+    /// Plays the track the current playlist left off at (from the position it was at), if it was restored with
+    /// [`Self::restore_playback`]. Returns false if there was nothing to continue from
+    pub(super) fn continue_playlist(&mut self) -> Result<bool> {
+        let Some(resume) = self
+            .resume_playlist
+            .take()
+            .filter(|resume| resume.track.playlist == self.current.playlist)
+        else {
+            return Ok(false);
+        };
+        self.set_current(resume.track);
+        self.play_c_track_from(resume.position, resume.paused)?;
+        Ok(true)
+    }
+}
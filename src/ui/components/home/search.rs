@@ -0,0 +1,95 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use ratatui::widgets::ListState;
+
+use super::{PlaylistID, TrackID};
+
+/// Maximum number of results shown
+const MAX_RESULTS: usize = 100;
+
+/// State of the search popup
+#[derive(Debug, Default)]
+pub(super) struct Search {
+    pub query: String,
+    /// matching tracks, best match first
+    pub results: Vec<TrackID>,
+    pub list_state: ListState,
+}
+
+impl Search {
+    pub fn select_next(&mut self) {
+        if let Some(i) = self.list_state.selected() {
+            self.list_state
+                .select(Some((i + 1).min(self.results.len().saturating_sub(1))));
+        }
+    }
+
+    pub fn select_prev(&mut self) {
+        if let Some(i) = self.list_state.selected() {
+            self.list_state.select(Some(i.saturating_sub(1)));
+        }
+    }
+
+    pub fn selected(&self) -> Option<TrackID> {
+        self.results.get(self.list_state.selected()?).copied()
+    }
+}
+
+impl super::Home {
+    /// Edits the search query (typing and backspace). Other keys are left to the keybinds of the search mode
+    pub(super) fn search_key(&mut self, key: KeyEvent) {
+        let Some(search) = self.search.as_mut() else {
+            return;
+        };
+        match key.code {
+            KeyCode::Char(c)
+                if !key
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                search.query.push(c);
+            }
+            KeyCode::Backspace => {
+                search.query.pop();
+            }
+            _ => return,
+        }
+        self.update_search_results();
+    }
+
+    /// Fuzzy-searches the name and artist of every track in every playlist for the query
+    pub(super) fn update_search_results(&mut self) {
+        let Some(search) = self.search.as_ref() else {
+            return;
+        };
+        let matcher = SkimMatcherV2::default().ignore_case();
+        let mut scores = vec![];
+        if !search.query.is_empty() {
+            for (p, pl) in self.resolver.out().playlists.iter().enumerate() {
+                // (their tracks are all in other playlists too, and archived playlists are hidden)
+                if pl.is_virtual() || pl.archived {
+                    continue;
+                }
+                for (t, track) in pl.tracks.iter().enumerate() {
+                    let text = format!("{} {}", track.meta.name, track.meta.artist);
+                    if let Some(score) = matcher.fuzzy_match(&text, &search.query) {
+                        let id = TrackID {
+                            track: t,
+                            playlist: PlaylistID { playlist: p },
+                        };
+                        scores.push((score, id));
+                    }
+                }
+            }
+        }
+        // best match first
+        scores.sort_by_key(|(score, _)| -score);
+        scores.truncate(MAX_RESULTS);
+
+        let search = self.search.as_mut().unwrap();
+        search.results = scores.into_iter().map(|(_, id)| id).collect();
+        search
+            .list_state
+            .select((!search.results.is_empty()).then_some(0));
+    }
+}
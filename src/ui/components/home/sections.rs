@@ -0,0 +1,119 @@
+//! Sections of a playlist's track list: runs of tracks with the same `group`, shown under a header that can be collapsed
+
+/// A row in the track list
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) enum TrackRow {
+    /// header of a section (None for the tracks without a `group` after the other sections)
+    Section {
+        name: Option<String>,
+        collapsed: bool,
+        /// the tracks in the section, in the order they are shown
+        tracks: Vec<usize>,
+    },
+    Track(usize),
+}
+
+impl TrackRow {
+    /// The track the track list selection is on when it is on this row (None for the header of an expanded section,
+    /// which cannot be selected)
+    pub fn selects(&self) -> Option<usize> {
+        match self {
+            Self::Section {
+                collapsed: true,
+                tracks,
+                ..
+            } => tracks.first().copied(),
+            Self::Section { .. } => None,
+            Self::Track(track) => Some(*track),
+        }
+    }
+}
+
+impl super::Home {
+    /// Rows of the track list: the shown tracks (see [`Self::visible_tracks`]), with a header before each section.
+    /// Tracks before the first section have no header, and the tracks of collapsed sections are left out.
+    /// While editing the track order, there are no headers
+    pub(super) fn track_rows(&self) -> Vec<TrackRow> {
+        let visible = self.visible_tracks();
+        let tracks = &self.get_playlist(self.current.playlist).tracks;
+        let editing = self
+            .edit
+            .as_ref()
+            .is_some_and(|edit| edit.playlist == self.current.playlist);
+        if editing || tracks.iter().all(|track| track.group.is_none()) {
+            return visible.into_iter().map(TrackRow::Track).collect();
+        }
+        let mut rows = vec![];
+        for run in visible.chunk_by(|&a, &b| tracks[a].group == tracks[b].group) {
+            let name = tracks[run[0]].group.clone();
+            if name.is_none() && rows.is_empty() {
+                rows.extend(run.iter().copied().map(TrackRow::Track));
+                continue;
+            }
+            let collapsed = self
+                .collapsed_sections
+                .contains(&(self.current.playlist, name.clone()));
+            rows.push(TrackRow::Section {
+                name,
+                collapsed,
+                tracks: run.to_vec(),
+            });
+            if !collapsed {
+                rows.extend(run.iter().copied().map(TrackRow::Track));
+            }
+        }
+        rows
+    }
+
+    /// Tracks that the track list selection can be on, in the order they are shown.
+    /// The header of a collapsed section is selected as the first track in it
+    pub(super) fn selectable_tracks(&self) -> Vec<usize> {
+        self.track_rows()
+            .iter()
+            .filter_map(TrackRow::selects)
+            .collect()
+    }
+
+    /// The collapsed section `track` is hidden in (as its header's row), if it is in one.
+    /// While the selection is on a collapsed section's header, this is that section
+    pub(super) fn collapsed_section_of(&self, track: usize) -> Option<TrackRow> {
+        self.track_rows().into_iter().find(|row| match row {
+            TrackRow::Section {
+                collapsed: true,
+                tracks,
+                ..
+            } => tracks.contains(&track),
+            _ => false,
+        })
+    }
+
+    /// Expands the section whose header the track list selection is on, or collapses the section of the selected track
+    /// (moving the selection to its header)
+    pub(super) fn toggle_selected_section(&mut self) {
+        let Some(selected) = self.t_list_state.selected() else {
+            return;
+        };
+        let rows = self.track_rows();
+        let section = rows.iter().find_map(|row| match row {
+            TrackRow::Section {
+                name,
+                collapsed,
+                tracks,
+            } if tracks.contains(&selected) => Some((name.clone(), *collapsed, tracks[0])),
+            _ => None,
+        });
+        let Some((name, collapsed, first)) = section else {
+            return;
+        };
+        let key = (self.current.playlist, name.clone());
+        let name = name.unwrap_or_else(|| "other tracks".to_string());
+        if collapsed {
+            self.collapsed_sections.remove(&key);
+            self.announce(format!("Expanded {name}"));
+        } else {
+            self.collapsed_sections.insert(key);
+            self.t_list_state.select(Some(first));
+            self.announce(format!("Collapsed {name}"));
+        }
+    }
+}
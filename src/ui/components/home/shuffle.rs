@@ -0,0 +1,80 @@
+use std::mem;
+
+use rand::prelude::*;
+
+use super::{PlaylistID, TrackSelectionMethod};
+
+fn permutation(tracks: &[usize]) -> Vec<usize> {
+    let mut order = tracks.to_vec();
+    order.shuffle(&mut thread_rng());
+    order
+}
+
+/// Order tracks are played in when shuffling: every track of the playlist plays once (in a random order)
+/// before any of them repeats
+#[derive(Debug, Clone)]
+pub(super) struct Shuffle {
+    playlist: PlaylistID,
+    /// tracks of the playlist that could be played when it was shuffled (the ones that are not disabled)
+    tracks: Vec<usize>,
+    /// tracks that have not been played yet this round, in the order they will be played
+    remaining: Vec<usize>,
+    /// order of the next round, decided in advance so the track after the end of this round is known for preloading
+    next_round: Vec<usize>,
+}
+
+impl Shuffle {
+    pub fn new(playlist: PlaylistID, tracks: Vec<usize>) -> Self {
+        Self {
+            playlist,
+            remaining: permutation(&tracks),
+            next_round: permutation(&tracks),
+            tracks,
+        }
+    }
+
+    /// If this is the order of `playlist` (which has `tracks` to play)
+    pub fn is_for(&self, playlist: PlaylistID, tracks: &[usize]) -> bool {
+        self.playlist == playlist && self.tracks == tracks
+    }
+
+    /// The track to play after the current one, or None at the end of a round if the playlist does not `repeat`
+    pub fn peek(&self, repeat: bool) -> Option<usize> {
+        match self.remaining.first() {
+            Some(&track) => Some(track),
+            None if repeat => self.next_round.first().copied(),
+            None => None,
+        }
+    }
+
+    /// Marks `track` as played, starting the next round if this one is over
+    pub fn played(&mut self, track: usize) {
+        if self.remaining.is_empty() {
+            self.remaining = mem::replace(&mut self.next_round, permutation(&self.tracks));
+        }
+        self.remaining.retain(|&t| t != track);
+        // the end of one round and the start of the next should not be the same track
+        let last = self.remaining.last().copied().unwrap_or(track);
+        if self.next_round.len() > 1 && self.next_round.first() == Some(&last) {
+            let other = thread_rng().gen_range(1..self.next_round.len());
+            self.next_round.swap(0, other);
+        }
+    }
+}
+
+impl super::Home {
+    /// Keeps the shuffle order up to date with the current track, which has just started playing
+    /// (or was current when shuffling was turned on)
+    pub(super) fn shuffle_played(&mut self) {
+        if self.sel_method != TrackSelectionMethod::Random {
+            return;
+        }
+        let playlist = self.current.playlist;
+        let tracks = self.playable_tracks(playlist);
+        let shuffle = match self.shuffle.as_mut() {
+            Some(shuffle) if shuffle.is_for(playlist, &tracks) => shuffle,
+            _ => self.shuffle.insert(Shuffle::new(playlist, tracks)),
+        };
+        shuffle.played(self.current.track);
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::BTreeSet;
+
+use ratatui::widgets::ListState;
+
+use crate::schema::Playlist;
+
+/// State of the tag filter popup
+#[derive(Debug, Default)]
+pub(super) struct TagPicker {
+    /// tags used in any playlist. the first row of the popup (showing every track) is not included
+    pub tags: Vec<String>,
+    pub list_state: ListState,
+}
+
+impl TagPicker {
+    /// Lists the tags used in `playlists`, selecting `current` (or the "all tracks" row)
+    pub fn new(playlists: &[Playlist], current: Option<&str>) -> Self {
+        let tags = playlists
+            .iter()
+            .flat_map(|pl| pl.tracks.iter().flat_map(|track| pl.track_tags(track)))
+            .cloned()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let selected = current
+            .and_then(|current| tags.iter().position(|tag| tag == current))
+            .map_or(0, |i| i + 1);
+        Self {
+            tags,
+            list_state: ListState::default().with_selected(Some(selected)),
+        }
+    }
+
+    pub fn select_next(&mut self) {
+        if let Some(i) = self.list_state.selected() {
+            self.list_state.select(Some((i + 1).min(self.tags.len())));
+        }
+    }
+
+    pub fn select_prev(&mut self) {
+        if let Some(i) = self.list_state.selected() {
+            self.list_state.select(Some(i.saturating_sub(1)));
+        }
+    }
+
+    /// The chosen filter (None for every track)
+    pub fn selected(&self) -> Option<&str> {
+        self.tags
+            .get(self.list_state.selected()?.checked_sub(1)?)
+            .map(String::as_str)
+    }
+}
+
+impl super::Home {
+    /// Tracks of the current playlist shown in the track list, in the order they are shown
+    /// (all of them, unless filtering by tag. while editing the track order, all of them in the new order)
+    pub(super) fn visible_tracks(&self) -> Vec<usize> {
+        if let Some(edit) = self
+            .edit
+            .as_ref()
+            .filter(|edit| edit.playlist == self.current.playlist)
+        {
+            return edit.order.clone();
+        }
+        let playlist = self.get_playlist(self.current.playlist);
+        let filter = self.tag_filter.as_slice();
+        (0..playlist.tracks.len())
+            .filter(|&i| playlist.track_has_tag(&playlist.tracks[i], filter))
+            .collect()
+    }
+
+    /// The shown track closest to `track` (the next one after it, or the last one if there are none after it).
+    /// A track in a collapsed section is shown as the section's header.
+    /// If no tracks are shown, this is `track`, so that the track list stays selected
+    pub(super) fn visible_track_near(&self, track: usize) -> usize {
+        if let Some(section) = self.collapsed_section_of(track) {
+            return section.selects().unwrap_or(track);
+        }
+        let visible = self.selectable_tracks();
+        visible
+            .iter()
+            .copied()
+            .find(|&i| i >= track)
+            .or(visible.last().copied())
+            .unwrap_or(track)
+    }
+
+    /// Moves the track list selection to the next (or previous) shown track
+    pub(super) fn select_visible_track(&mut self, forward: bool) {
+        let Some(selected) = self.t_list_state.selected() else {
+            return;
+        };
+        let visible = self.selectable_tracks();
+        // the shown tracks are not in order while editing the track order
+        let next = match visible.iter().position(|&i| i == selected) {
+            Some(row) if forward => visible.get(row + 1).copied(),
+            Some(row) => row.checked_sub(1).map(|row| visible[row]),
+            None if forward => visible.iter().copied().find(|&i| i > selected),
+            None => visible.iter().copied().rev().find(|&i| i < selected),
+        };
+        if let Some(next) = next {
+            self.t_list_state.select(Some(next));
+        }
+    }
+
+    /// Filters the track list to tracks with `tag` (or shows every track)
+    pub(super) fn set_tag_filter(&mut self, tag: Option<String>) {
+        self.tag_filter = tag;
+        if let Some(selected) = self.t_list_state.selected() {
+            self.t_list_state
+                .select(Some(self.visible_track_near(selected)));
+        }
+        let message = match &self.tag_filter {
+            Some(tag) => format!("Showing tracks tagged {tag}"),
+            None => "Showing all tracks".to_string(),
+        };
+        self.announce(message);
+    }
+}
@@ -0,0 +1,139 @@
+//! Moving or copying tracks to another playlist, which is picked in a popup
+
+use color_eyre::eyre::Result;
+use ratatui::{prelude::*, widgets::*};
+
+use super::PlaylistID;
+use crate::{edit, ui::action::Severity};
+
+/// State of the popup picking the playlist to move (or copy) tracks to
+#[derive(Debug)]
+pub(super) struct TransferPicker {
+    from: PlaylistID,
+    /// tracks of `from` to move or copy
+    tracks: Vec<usize>,
+    /// if the tracks are removed from `from` (otherwise they are copied)
+    remove: bool,
+    /// playlists the tracks can go to
+    targets: Vec<PlaylistID>,
+    list_state: ListState,
+}
+
+impl TransferPicker {
+    pub fn select_next(&mut self) {
+        if let Some(i) = self.list_state.selected() {
+            self.list_state
+                .select(Some((i + 1).min(self.targets.len().saturating_sub(1))));
+        }
+    }
+
+    pub fn select_prev(&mut self) {
+        if let Some(i) = self.list_state.selected() {
+            self.list_state.select(Some(i.saturating_sub(1)));
+        }
+    }
+}
+
+impl super::Home {
+    /// Opens the popup to move (or copy) the chosen tracks to another playlist,
+    /// returning false if they can not be moved
+    pub(super) fn open_transfer(&mut self, remove: bool) -> bool {
+        let from = self.current.playlist;
+        let playlist = self.get_playlist(from);
+        if playlist.is_virtual() {
+            let name = playlist.name.clone();
+            warn!("{name} is not a playlist file, and tracks can not be moved or copied from it");
+            self.toast(
+                format!("Tracks can not be moved or copied from {name}"),
+                Severity::Warning,
+            );
+            return false;
+        }
+        let tracks = self.chosen_tracks();
+        let targets = (0..self.resolver.out().playlists.len())
+            .map(|playlist| PlaylistID { playlist })
+            .filter(|&id| id != from && !self.get_playlist(id).is_virtual())
+            .collect::<Vec<_>>();
+        if tracks.is_empty() || targets.is_empty() {
+            return false;
+        }
+        self.transfer = Some(TransferPicker {
+            from,
+            tracks,
+            remove,
+            targets,
+            list_state: ListState::default().with_selected(Some(0)),
+        });
+        true
+    }
+
+    /// Moves (or copies) the tracks to the playlist selected in the popup.
+    /// The playlists are then reloaded along with their files
+    pub(super) fn transfer_chosen(&mut self) {
+        let Some(picker) = self.transfer.take() else {
+            return;
+        };
+        let Some(&to) = picker
+            .list_state
+            .selected()
+            .and_then(|i| picker.targets.get(i))
+        else {
+            return;
+        };
+        let (from, to) = (self.get_playlist(picker.from), self.get_playlist(to));
+        let to_name = to.name.clone();
+        match edit::copy_tracks(&self.resolver, from, &picker.tracks, to, picker.remove) {
+            Ok(()) => {
+                if picker.remove {
+                    self.marked.clear();
+                }
+                let done = if picker.remove { "Moved" } else { "Copied" };
+                let message = format!("{done} {} track(s) to {to_name}", picker.tracks.len());
+                self.toast(message.clone(), Severity::Info);
+                self.announce(message);
+            }
+            Err(e) => {
+                error!("Failed to move or copy tracks to {to_name:?}: {e}");
+                self.toast(format!("Moving tracks failed: {e}"), Severity::Error);
+            }
+        }
+    }
+
+    /// Popup picking the playlist to move (or copy) tracks to, drawn over `area`
+    pub(super) fn draw_transfer(&mut self, f: &mut Frame<'_>, area: Rect) -> Result<()> {
+        let area = area.inner(Margin::new(area.width / 8, area.height / 8));
+        let picker = self.transfer.as_ref().unwrap();
+        let items = picker
+            .targets
+            .iter()
+            .map(|&id| {
+                let playlist = self.get_playlist(id);
+                let mut line = Line::from(playlist.name.clone());
+                if playlist.archived {
+                    line.push_span(" (archived)".dim());
+                }
+                ListItem::new(line)
+            })
+            .collect::<Vec<_>>();
+        let title = format!(
+            "{} {} track(s) to",
+            if picker.remove { "Move" } else { "Copy" },
+            picker.tracks.len()
+        );
+        let list = List::new(items)
+            .block(
+                Block::new()
+                    .title(title.bold())
+                    .title_bottom("<enter> to choose, <esc> to cancel".dim())
+                    .border_style(Style::new().fg(self.cfg.theme.border))
+                    .borders(Borders::ALL),
+            )
+            .highlight_symbol(">")
+            .highlight_spacing(HighlightSpacing::Always)
+            .highlight_style(Style::new().fg(self.cfg.theme.highlight));
+        f.render_widget(Clear, area);
+        let picker = self.transfer.as_mut().unwrap();
+        f.render_stateful_widget(list, area, &mut picker.list_state);
+        Ok(())
+    }
+}
@@ -0,0 +1,139 @@
+//! Short-lived messages drawn over the player (sent with [`Action::Toast`])
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use color_eyre::eyre::Result;
+use ratatui::{prelude::*, widgets::*};
+
+use super::Component;
+use crate::{
+    cfg::{Config, Theme},
+    ui::{
+        action::{Action, Severity},
+        tui::Frame,
+    },
+};
+
+/// How many toasts are shown at once (older ones are dropped first)
+const MAX_SHOWN: usize = 3;
+/// Widest a toast gets, longer messages are wrapped
+const MAX_WIDTH: u16 = 50;
+
+struct Toast {
+    message: String,
+    severity: Severity,
+    until: Instant,
+}
+
+impl Severity {
+    /// How long a toast is shown for (errors stay up longer, so they can be read)
+    fn shown_for(&self) -> Duration {
+        match self {
+            Self::Info => Duration::from_secs(2),
+            Self::Warning => Duration::from_secs(4),
+            Self::Error => Duration::from_secs(6),
+        }
+    }
+
+    fn color(&self, theme: &Theme) -> Color {
+        match self {
+            Self::Info => theme.highlight,
+            Self::Warning => Color::Yellow,
+            Self::Error => Color::Red,
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            Self::Info => "",
+            Self::Warning => "Warning",
+            Self::Error => "Error",
+        }
+    }
+}
+
+/// How many lines `message` takes up when wrapped to `width` (at whitespace, like [`Wrap`] does)
+fn wrapped_lines(message: &str, width: u16) -> u16 {
+    let width = width.max(1) as usize;
+    let (mut lines, mut used) = (1usize, 0);
+    for word in message.split_whitespace() {
+        let len = Span::raw(word).width().max(1);
+        if used > 0 && used + 1 + len > width {
+            lines += 1;
+            used = 0;
+        }
+        if used > 0 {
+            used += 1;
+        }
+        // words longer than a line are broken up
+        lines += (used + len).saturating_sub(1) / width;
+        used = (used + len - 1) % width + 1;
+    }
+    lines.min(u16::MAX as usize) as u16
+}
+
+#[derive(Default)]
+pub struct Toasts {
+    /// oldest first
+    toasts: VecDeque<Toast>,
+    theme: Theme,
+}
+
+impl Component for Toasts {
+    fn register_config_handler(&mut self, config: Config) -> Result<()> {
+        self.theme = config.theme;
+        Ok(())
+    }
+
+    fn update(&mut self, action: Action) -> Result<Option<Action>> {
+        match action {
+            Action::Toast(message, severity) => {
+                if self.toasts.len() == MAX_SHOWN {
+                    self.toasts.pop_front();
+                }
+                self.toasts.push_back(Toast {
+                    message,
+                    severity,
+                    until: Instant::now() + severity.shown_for(),
+                });
+            }
+            Action::Render => {
+                let now = Instant::now();
+                self.toasts.retain(|toast| toast.until > now);
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    fn draw(&mut self, f: &mut Frame<'_>, rect: Rect) -> Result<()> {
+        // stacked upwards from the bottom right corner, newest at the bottom
+        let width = MAX_WIDTH.min(rect.width.saturating_sub(2));
+        let mut bottom = rect.bottom().saturating_sub(1);
+        for toast in self.toasts.iter().rev() {
+            let block = Block::new()
+                .title(toast.severity.title().bold())
+                .borders(Borders::ALL)
+                .border_style(Style::new().fg(toast.severity.color(&self.theme)));
+            let text = Paragraph::new(toast.message.as_str()).wrap(Wrap { trim: true });
+            let lines = wrapped_lines(&toast.message, width.saturating_sub(2));
+            let height = (lines + 2).min(bottom.saturating_sub(rect.y));
+            if height < 3 {
+                break;
+            }
+            let area = Rect {
+                x: rect.right().saturating_sub(width + 1),
+                y: bottom - height,
+                width,
+                height,
+            };
+            f.render_widget(Clear, area);
+            f.render_widget(text.block(block), area);
+            bottom -= height;
+        }
+        Ok(())
+    }
+}
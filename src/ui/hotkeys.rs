@@ -0,0 +1,135 @@
+//! System-wide hotkeys, which work even when the terminal running DMM is not focused.
+//!
+//! Key events are read directly from the kernel's input devices (`/dev/input/event*`), so this works
+//! under X11, Wayland, and the console alike. It does require read access to those devices,
+//! which usually means being in the `input` group.
+
+use color_eyre::eyre::Result;
+use flume::Sender;
+
+use crate::{cfg::GlobalHotkeys, ui::action::Action};
+
+#[cfg(feature = "global-hotkeys")]
+pub fn listen(hotkeys: &GlobalHotkeys, tx: Sender<Action>) -> Result<()> {
+    use std::{collections::HashMap, str::FromStr, sync::Arc, thread};
+
+    use crossterm::event::{KeyCode, KeyModifiers, MediaKeyCode};
+    use evdev::{InputEventKind, Key};
+
+    fn evdev_key(code: KeyCode) -> Option<Key> {
+        Some(match code {
+            KeyCode::Char(' ') => Key::KEY_SPACE,
+            KeyCode::Char('-') => Key::KEY_MINUS,
+            KeyCode::Char(c) if c.is_ascii_alphanumeric() => {
+                return Key::from_str(&format!("KEY_{}", c.to_ascii_uppercase())).ok()
+            }
+            KeyCode::F(n) => return Key::from_str(&format!("KEY_F{n}")).ok(),
+            KeyCode::Backspace => Key::KEY_BACKSPACE,
+            KeyCode::Enter => Key::KEY_ENTER,
+            KeyCode::Left => Key::KEY_LEFT,
+            KeyCode::Right => Key::KEY_RIGHT,
+            KeyCode::Up => Key::KEY_UP,
+            KeyCode::Down => Key::KEY_DOWN,
+            KeyCode::Home => Key::KEY_HOME,
+            KeyCode::End => Key::KEY_END,
+            KeyCode::PageUp => Key::KEY_PAGEUP,
+            KeyCode::PageDown => Key::KEY_PAGEDOWN,
+            KeyCode::Tab => Key::KEY_TAB,
+            KeyCode::Delete => Key::KEY_DELETE,
+            KeyCode::Insert => Key::KEY_INSERT,
+            KeyCode::Esc => Key::KEY_ESC,
+            KeyCode::Media(MediaKeyCode::PlayPause) => Key::KEY_PLAYPAUSE,
+            KeyCode::Media(MediaKeyCode::Play) => Key::KEY_PLAYCD,
+            KeyCode::Media(MediaKeyCode::Pause) => Key::KEY_PAUSECD,
+            KeyCode::Media(MediaKeyCode::Stop) => Key::KEY_STOPCD,
+            KeyCode::Media(MediaKeyCode::TrackNext) => Key::KEY_NEXTSONG,
+            KeyCode::Media(MediaKeyCode::TrackPrevious) => Key::KEY_PREVIOUSSONG,
+            _ => return None,
+        })
+    }
+
+    fn modifier(key: Key) -> Option<KeyModifiers> {
+        match key {
+            Key::KEY_LEFTCTRL | Key::KEY_RIGHTCTRL => Some(KeyModifiers::CONTROL),
+            Key::KEY_LEFTALT | Key::KEY_RIGHTALT => Some(KeyModifiers::ALT),
+            Key::KEY_LEFTSHIFT | Key::KEY_RIGHTSHIFT => Some(KeyModifiers::SHIFT),
+            _ => None,
+        }
+    }
+
+    if hotkeys.is_empty() {
+        return Ok(());
+    }
+    let bindings = Arc::new(
+        hotkeys
+            .iter()
+            .filter_map(|(key, action)| match evdev_key(key.code) {
+                Some(code) => Some(((code, key.modifiers), action.clone())),
+                None => {
+                    warn!("global hotkey {key:?} is not supported [ignoring]");
+                    None
+                }
+            })
+            .collect::<HashMap<_, _>>(),
+    );
+
+    let mut listening = 0usize;
+    for (path, mut device) in evdev::enumerate() {
+        // only listen to devices that can produce at least one of the bound keys
+        if !device
+            .supported_keys()
+            .is_some_and(|keys| bindings.keys().any(|(key, _)| keys.contains(*key)))
+        {
+            continue;
+        }
+        debug!(
+            "listening for global hotkeys on {path:?} ({})",
+            device.name().unwrap_or("unknown device")
+        );
+        listening += 1;
+        let bindings = bindings.clone();
+        let tx = tx.clone();
+        thread::Builder::new()
+            .name("global-hotkeys".to_string())
+            .spawn(move || {
+                let mut modifiers = KeyModifiers::empty();
+                loop {
+                    let events = match device.fetch_events() {
+                        Ok(events) => events,
+                        Err(e) => {
+                            warn!("stopped listening for global hotkeys on {path:?}: {e}");
+                            return;
+                        }
+                    };
+                    for event in events {
+                        let InputEventKind::Key(key) = event.kind() else {
+                            continue;
+                        };
+                        // 0 = release, 1 = press, 2 = repeat
+                        if let Some(modifier) = modifier(key) {
+                            modifiers.set(modifier, event.value() != 0);
+                        } else if event.value() == 1 {
+                            if let Some(action) = bindings.get(&(key, modifiers)) {
+                                debug!("Got global hotkey action: {action:?}");
+                                if tx.send(action.clone()).is_err() {
+                                    return;
+                                }
+                            }
+                        }
+                    }
+                }
+            })?;
+    }
+    if listening == 0 {
+        warn!("global hotkeys are configured, but no input devices could be opened (is this user in the `input` group?)");
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "global-hotkeys"))]
+pub fn listen(hotkeys: &GlobalHotkeys, _tx: Sender<Action>) -> Result<()> {
+    if !hotkeys.is_empty() {
+        warn!("global hotkeys are configured, but DMM was built without the `global-hotkeys` feature [ignoring]");
+    }
+    Ok(())
+}
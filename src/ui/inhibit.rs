@@ -0,0 +1,142 @@
+//! Keeping the system from going to sleep while music is playing (with `prevent_sleep: true` in the config)
+//!
+//! On linux, a "sleep" and "idle" inhibitor lock is taken from systemd-logind over D-Bus (like `systemd-inhibit` does),
+//! falling back to asking the desktop over `org.freedesktop.ScreenSaver`. On macOS, `caffeinate` is run while playing.
+
+/// Holds an inhibitor lock while the player is playing, see [`Inhibitor::set`]
+#[derive(Default)]
+pub struct Inhibitor {
+    lock: Option<imp::Lock>,
+    /// taking the lock failed, so it is not tried again
+    failed: bool,
+}
+
+impl Inhibitor {
+    /// Takes the lock if `inhibit` is true (and it is not held already), or releases it if it is false.
+    /// If taking it fails, this is logged, and it is not tried again
+    pub fn set(&mut self, inhibit: bool) {
+        match (inhibit, self.lock.is_some()) {
+            (true, false) if !self.failed => match imp::Lock::take() {
+                Ok(lock) => {
+                    debug!("preventing the system from sleeping while playing");
+                    self.lock = Some(lock);
+                }
+                Err(e) => {
+                    warn!("Failed to prevent the system from sleeping while playing: {e}");
+                    self.failed = true;
+                }
+            },
+            (false, true) => {
+                debug!("allowing the system to sleep");
+                self.lock = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use color_eyre::eyre::Result;
+    use zbus::{blocking::Connection, zvariant::OwnedFd};
+
+    /// shown by tools that list what is preventing sleep
+    const WHY: &str = "Playing music";
+    const SCREENSAVER: &str = "org.freedesktop.ScreenSaver";
+
+    pub enum Lock {
+        /// logind releases the lock once this is closed
+        Logind { _fd: OwnedFd },
+        /// the cookie to give back to the desktop to release it
+        ScreenSaver(Connection, u32),
+    }
+
+    impl Lock {
+        pub fn take() -> Result<Self> {
+            let logind = || {
+                let reply = Connection::system()?.call_method(
+                    Some("org.freedesktop.login1"),
+                    "/org/freedesktop/login1",
+                    Some("org.freedesktop.login1.Manager"),
+                    "Inhibit",
+                    &("sleep:idle", "DMM", WHY, "block"),
+                )?;
+                reply.body().deserialize::<OwnedFd>()
+            };
+            match logind() {
+                Ok(fd) => Ok(Self::Logind { _fd: fd }),
+                Err(e) => {
+                    debug!("Failed to take a logind inhibitor lock: {e} [trying {SCREENSAVER}]");
+                    let conn = Connection::session()?;
+                    let reply = conn.call_method(
+                        Some(SCREENSAVER),
+                        "/org/freedesktop/ScreenSaver",
+                        Some(SCREENSAVER),
+                        "Inhibit",
+                        &("DMM", WHY),
+                    )?;
+                    let cookie = reply.body().deserialize::<u32>()?;
+                    Ok(Self::ScreenSaver(conn, cookie))
+                }
+            }
+        }
+    }
+
+    impl Drop for Lock {
+        fn drop(&mut self) {
+            if let Self::ScreenSaver(conn, cookie) = self {
+                if let Err(e) = conn.call_method(
+                    Some(SCREENSAVER),
+                    "/org/freedesktop/ScreenSaver",
+                    Some(SCREENSAVER),
+                    "UnInhibit",
+                    &(*cookie,),
+                ) {
+                    warn!("Failed to allow the system to sleep again: {e}");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use std::process::{Child, Command, Stdio};
+
+    use color_eyre::eyre::Result;
+
+    /// `caffeinate`, which prevents idle sleep until it is killed (or DMM exits)
+    pub struct Lock(Child);
+
+    impl Lock {
+        pub fn take() -> Result<Self> {
+            let child = Command::new("caffeinate")
+                .args(["-i", "-w", &std::process::id().to_string()])
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()?;
+            Ok(Self(child))
+        }
+    }
+
+    impl Drop for Lock {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+mod imp {
+    use color_eyre::eyre::{bail, Result};
+
+    pub struct Lock;
+
+    impl Lock {
+        pub fn take() -> Result<Self> {
+            bail!("this is only supported on linux and macOS")
+        }
+    }
+}
@@ -4,4 +4,16 @@ use serde::{Deserialize, Serialize};
 pub enum Mode {
     #[default]
     Home,
+    /// the search popup is open (typed text goes to the search query)
+    Search,
+    /// the output device picker is open
+    Devices,
+    /// the tag filter popup is open
+    Tags,
+    /// the popup picking the playlist to move or copy tracks to is open
+    Transfer,
+    /// the order of the tracks in the current playlist is being edited
+    Edit,
+    /// the help popup is open
+    Help,
 }
@@ -0,0 +1,332 @@
+//! MPRIS support, so DMM can be controlled by `playerctl`, desktop media keys, and media widgets
+//!
+//! DMM is exposed on the D-Bus session bus as `org.mpris.MediaPlayer2.dmm.instance<pid>`. Commands received over D-Bus
+//! are turned into [`Action`]s, and the player reports its state with [`Mpris::update`].
+
+use color_eyre::eyre::Result;
+use flume::Sender;
+
+use crate::ui::action::Action;
+
+/// State of the player, as shown over MPRIS
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Status {
+    pub playback: Playback,
+    pub shuffle: bool,
+    /// "None", "Track", or "Playlist"
+    pub loop_status: &'static str,
+    /// index of the track in its playlist, used as the MPRIS track id
+    pub track_index: usize,
+    pub title: String,
+    pub artist: String,
+    pub playlist: String,
+    /// seconds (None if it is not known)
+    pub length: Option<u64>,
+    /// seconds
+    pub position: u64,
+    /// percent
+    pub volume: u8,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Playback {
+    Playing,
+    Paused,
+    #[default]
+    Stopped,
+}
+
+#[cfg(target_os = "linux")]
+pub use imp::Mpris;
+
+#[cfg(target_os = "linux")]
+pub fn serve(tx: Sender<Action>) -> Result<Option<Mpris>> {
+    Mpris::serve(tx).map(Some)
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::collections::HashMap;
+
+    use color_eyre::eyre::Result;
+    use flume::Sender;
+    use zbus::{
+        blocking::{connection, Connection},
+        interface,
+        zvariant::{ObjectPath, Value},
+    };
+
+    use super::{Playback, Status};
+    use crate::ui::action::Action;
+
+    const PATH: &str = "/org/mpris/MediaPlayer2";
+
+    fn send(tx: &Sender<Action>, action: Action) {
+        debug!("Got MPRIS action: {action:?}");
+        let _ = tx.send(action);
+    }
+
+    struct Root {
+        tx: Sender<Action>,
+    }
+
+    #[interface(name = "org.mpris.MediaPlayer2")]
+    impl Root {
+        fn raise(&self) {}
+
+        fn quit(&self) {
+            send(&self.tx, Action::Quit);
+        }
+
+        #[zbus(property)]
+        fn can_quit(&self) -> bool {
+            true
+        }
+
+        #[zbus(property)]
+        fn can_raise(&self) -> bool {
+            false
+        }
+
+        #[zbus(property)]
+        fn has_track_list(&self) -> bool {
+            false
+        }
+
+        #[zbus(property)]
+        fn identity(&self) -> &str {
+            "DMM"
+        }
+
+        #[zbus(property)]
+        fn supported_uri_schemes(&self) -> Vec<String> {
+            vec![]
+        }
+
+        #[zbus(property)]
+        fn supported_mime_types(&self) -> Vec<String> {
+            vec![]
+        }
+    }
+
+    struct Player {
+        tx: Sender<Action>,
+        status: Status,
+    }
+
+    #[interface(name = "org.mpris.MediaPlayer2.Player")]
+    impl Player {
+        fn next(&self) {
+            send(&self.tx, Action::NextTrack);
+        }
+
+        fn previous(&self) {
+            send(&self.tx, Action::PrevTrack);
+        }
+
+        fn pause(&self) {
+            if self.status.playback == Playback::Playing {
+                send(&self.tx, Action::PausePlay);
+            }
+        }
+
+        fn play_pause(&self) {
+            send(&self.tx, Action::PausePlay);
+        }
+
+        fn stop(&self) {
+            self.pause();
+        }
+
+        fn play(&self) {
+            if self.status.playback != Playback::Playing {
+                send(&self.tx, Action::PausePlay);
+            }
+        }
+
+        fn seek(&self, _offset: i64) {}
+
+        fn set_position(&self, _track_id: ObjectPath<'_>, _position: i64) {}
+
+        fn open_uri(&self, _uri: &str) {}
+
+        #[zbus(property)]
+        fn playback_status(&self) -> &str {
+            match self.status.playback {
+                Playback::Playing => "Playing",
+                Playback::Paused => "Paused",
+                Playback::Stopped => "Stopped",
+            }
+        }
+
+        #[zbus(property)]
+        fn loop_status(&self) -> &str {
+            self.status.loop_status
+        }
+
+        #[zbus(property)]
+        fn shuffle(&self) -> bool {
+            self.status.shuffle
+        }
+
+        #[zbus(property)]
+        fn metadata(&self) -> HashMap<&str, Value<'_>> {
+            let mut metadata = HashMap::new();
+            if self.status.playback == Playback::Stopped {
+                return metadata;
+            }
+            let track_id = format!("/org/dmm/track/{}", self.status.track_index);
+            if let Ok(path) = ObjectPath::try_from(track_id) {
+                metadata.insert("mpris:trackid", Value::from(path));
+            }
+            if let Some(length) = self.status.length {
+                metadata.insert("mpris:length", Value::from(length as i64 * 1_000_000));
+            }
+            metadata.insert("xesam:title", Value::from(self.status.title.as_str()));
+            metadata.insert(
+                "xesam:artist",
+                Value::from(vec![self.status.artist.as_str()]),
+            );
+            metadata.insert("xesam:album", Value::from(self.status.playlist.as_str()));
+            metadata
+        }
+
+        #[zbus(property)]
+        fn position(&self) -> i64 {
+            self.status.position as i64 * 1_000_000
+        }
+
+        #[zbus(property)]
+        fn rate(&self) -> f64 {
+            1.0
+        }
+
+        #[zbus(property)]
+        fn minimum_rate(&self) -> f64 {
+            1.0
+        }
+
+        #[zbus(property)]
+        fn maximum_rate(&self) -> f64 {
+            1.0
+        }
+
+        #[zbus(property)]
+        fn volume(&self) -> f64 {
+            self.status.volume as f64 / 100.0
+        }
+
+        #[zbus(property)]
+        fn set_volume(&self, volume: f64) {
+            // (MPRIS allows volumes above 1.0, but the player does not go above 100%)
+            let percent = (volume.clamp(0.0, 1.0) * 100.0).round() as u8;
+            send(&self.tx, Action::SetVolume(percent));
+        }
+
+        #[zbus(property)]
+        fn can_go_next(&self) -> bool {
+            true
+        }
+
+        #[zbus(property)]
+        fn can_go_previous(&self) -> bool {
+            true
+        }
+
+        #[zbus(property)]
+        fn can_play(&self) -> bool {
+            true
+        }
+
+        #[zbus(property)]
+        fn can_pause(&self) -> bool {
+            true
+        }
+
+        #[zbus(property)]
+        fn can_seek(&self) -> bool {
+            false
+        }
+
+        #[zbus(property)]
+        fn can_control(&self) -> bool {
+            true
+        }
+    }
+
+    pub struct Mpris {
+        conn: Connection,
+    }
+
+    impl Mpris {
+        pub fn serve(tx: Sender<Action>) -> Result<Self> {
+            let conn = connection::Builder::session()?
+                .name(format!(
+                    "org.mpris.MediaPlayer2.dmm.instance{}",
+                    std::process::id()
+                ))?
+                .serve_at(PATH, Root { tx: tx.clone() })?
+                .serve_at(
+                    PATH,
+                    Player {
+                        tx,
+                        status: Status::default(),
+                    },
+                )?
+                .build()?;
+            debug!("serving MPRIS");
+            Ok(Self { conn })
+        }
+
+        /// Updates the state shown over MPRIS, notifying clients of anything that changed
+        pub fn update(&self, status: Status) -> Result<()> {
+            let iface_ref = self.conn.object_server().interface::<_, Player>(PATH)?;
+            let mut iface = iface_ref.get_mut();
+            if iface.status == status {
+                return Ok(());
+            }
+            let old = std::mem::replace(&mut iface.status, status);
+            let ctxt = iface_ref.signal_context();
+            // position is not signaled (clients calculate it from the playback status)
+            zbus::block_on(async {
+                if old.playback != iface.status.playback {
+                    iface.playback_status_changed(ctxt).await?;
+                }
+                if old.shuffle != iface.status.shuffle {
+                    iface.shuffle_changed(ctxt).await?;
+                }
+                if old.loop_status != iface.status.loop_status {
+                    iface.loop_status_changed(ctxt).await?;
+                }
+                if old.volume != iface.status.volume {
+                    iface.volume_changed(ctxt).await?;
+                }
+                if (old.playback == Playback::Stopped)
+                    != (iface.status.playback == Playback::Stopped)
+                    || old.track_index != iface.status.track_index
+                    || old.title != iface.status.title
+                    || old.length != iface.status.length
+                {
+                    iface.metadata_changed(ctxt).await?;
+                }
+                Ok::<_, zbus::Error>(())
+            })?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub struct Mpris;
+
+#[cfg(not(target_os = "linux"))]
+impl Mpris {
+    pub fn update(&self, _status: Status) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn serve(_tx: Sender<Action>) -> Result<Option<Mpris>> {
+    Ok(None)
+}
@@ -0,0 +1,323 @@
+//! Remote control over HTTP, so DMM can be controlled from a browser (such as a phone on the same network)
+//!
+//! When `remote.enabled` is set in the config, the player serves a small web page at `remote.address`, along with a
+//! JSON API it uses:
+//! - `GET /api/status`: what is playing (the same fields as `dmm status --output json`, and the volume)
+//! - `POST /api/play-pause`, `POST /api/next`, `POST /api/previous`
+//! - `POST /api/volume?value=<0-100>`
+//!
+//! For casting to this player from another one (see [`super::cast`]):
+//! - `GET /api/store/<name>`: if the store has the download (or sidecar file) `<name>` (404 if not)
+//! - `PUT /api/store/<name>`: adds it to the store (with a `Content-Length`), if it is not there yet. This is only
+//!   allowed with `remote.token` set, as anything uploaded is played
+//! - `POST /api/play?hash=<hash>&position=<seconds>&paused=<true/false>`: plays the track downloaded as `<hash>`
+//!
+//! Commands are turned into [`Action`]s, and the player reports its state with [`Remote::update`].
+//! With `remote.token` set, every request has to pass it as `?token=<token>` (URL-encoded).
+
+use std::{
+    ffi::OsStr,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use color_eyre::eyre::{bail, Result};
+use flume::Sender;
+use serde::Serialize;
+
+use crate::{
+    cache::{self, Hash},
+    cfg::RemoteConfig,
+    status::Status,
+    ui::action::Action,
+};
+
+const PAGE: &str = include_str!("../../assets/remote.html");
+
+/// How long a client may take to send its request
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest line of a request that is read
+const MAX_LINE: u64 = 8192;
+
+/// Longest name of a store entry that can be uploaded (a hash, and the extension of a sidecar file)
+const MAX_NAME: usize = 64;
+
+/// Largest store entry that can be uploaded (in bytes)
+const MAX_UPLOAD: u64 = 1 << 30;
+
+/// State of the player, as shown by the remote control
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NowPlaying {
+    #[serde(flatten)]
+    pub status: Status,
+    /// percent
+    pub volume: u8,
+}
+
+pub struct Remote {
+    now: Arc<Mutex<Option<NowPlaying>>>,
+}
+
+impl Remote {
+    /// Starts serving the remote control (in the background). `store` is the directory of the store, which tracks are
+    /// uploaded to when casting
+    pub fn serve(config: &RemoteConfig, store: PathBuf, tx: Sender<Action>) -> Result<Self> {
+        let listener = TcpListener::bind(&config.address)?;
+        info!(
+            "Serving the remote control at http://{}/",
+            listener.local_addr()?
+        );
+        let now = Arc::new(Mutex::new(None));
+        let token = config.token.clone();
+        thread::Builder::new().name("remote".to_string()).spawn({
+            let now = now.clone();
+            move || {
+                for stream in listener.incoming() {
+                    let result = stream
+                        .map_err(Into::into)
+                        .and_then(|stream| handle(stream, &now, token.as_deref(), &store, &tx));
+                    if let Err(e) = result {
+                        debug!("remote control request failed: {e}");
+                    }
+                    if tx.is_disconnected() {
+                        return;
+                    }
+                }
+            }
+        })?;
+        Ok(Self { now })
+    }
+
+    /// Updates the state shown by the remote control
+    pub fn update(&self, now: NowPlaying) {
+        *self.now.lock().unwrap() = Some(now);
+    }
+}
+
+/// Answers one request
+fn handle(
+    mut stream: TcpStream,
+    now: &Mutex<Option<NowPlaying>>,
+    token: Option<&str>,
+    store: &Path,
+    tx: &Sender<Action>,
+) -> Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader
+        .by_ref()
+        .take(MAX_LINE)
+        .read_line(&mut request_line)?;
+    // (only the length of the body is needed from the headers, which only uploads have)
+    let mut length = None::<u64>;
+    loop {
+        let mut header = String::new();
+        if reader.by_ref().take(MAX_LINE).read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let param = |name: &str| {
+        query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+            .map(decode)
+    };
+    if token.is_some() && param("token").as_deref() != token {
+        return respond(
+            &mut stream,
+            "403 Forbidden",
+            "text/plain",
+            "wrong or missing token",
+        );
+    }
+    if let Some(name) = path.strip_prefix("/api/store/") {
+        return store_entry(
+            &mut stream,
+            &mut reader,
+            method,
+            name,
+            length,
+            store,
+            token.is_some(),
+        );
+    }
+    let action = match (method, path) {
+        ("GET", "/") => {
+            return respond(&mut stream, "200 OK", "text/html; charset=utf-8", PAGE);
+        }
+        ("GET", "/api/status") => None,
+        ("POST", "/api/play-pause") => Some(Action::PausePlay),
+        ("POST", "/api/next") => Some(Action::NextTrack),
+        ("POST", "/api/previous") => Some(Action::PrevTrack),
+        ("POST", "/api/volume") => {
+            match param("value").and_then(|value| value.parse::<u8>().ok()) {
+                Some(volume) => Some(Action::SetVolume(volume.min(100))),
+                None => {
+                    return respond(
+                        &mut stream,
+                        "400 Bad Request",
+                        "text/plain",
+                        "expected ?value=<0-100>",
+                    );
+                }
+            }
+        }
+        ("POST", "/api/play") => {
+            let hash = param("hash").and_then(|hash| hash.parse::<Hash>().ok());
+            let position = param("position").map_or(Some(0), |position| position.parse().ok());
+            let paused = param("paused").map_or(Some(false), |paused| paused.parse().ok());
+            match (hash, position, paused) {
+                (Some(hash), Some(position), Some(paused)) => Some(Action::PlayStored {
+                    hash: hash.to_string(),
+                    position,
+                    paused,
+                }),
+                _ => {
+                    return respond(
+                        &mut stream,
+                        "400 Bad Request",
+                        "text/plain",
+                        "expected ?hash=<store hash>[&position=<seconds>][&paused=<true/false>]",
+                    );
+                }
+            }
+        }
+        _ => return respond(&mut stream, "404 Not Found", "text/plain", "not found"),
+    };
+    if let Some(action) = action {
+        debug!("Got remote control action: {action:?}");
+        tx.send(action)?;
+    }
+    // (this is from before the action is handled, the page asks again shortly after)
+    let body = serde_json::to_string(&*now.lock().unwrap())?;
+    respond(&mut stream, "200 OK", "application/json", &body)
+}
+
+/// Answers `GET` (is it in the store) and `PUT` (add it to the store) requests for the store entry `name`. Uploads
+/// are only accepted with a token (`protected`), and never replace what is already in the store
+fn store_entry(
+    stream: &mut TcpStream,
+    reader: &mut BufReader<TcpStream>,
+    method: &str,
+    name: &str,
+    length: Option<u64>,
+    store: &Path,
+    protected: bool,
+) -> Result<()> {
+    // (only names of entries, so nothing outside of the store, or other than downloads and their sidecars, can be
+    // written to)
+    let valid = name.len() <= MAX_NAME
+        && cache::entry_hash(OsStr::new(name)).is_some()
+        && !name.ends_with(".upload")
+        && !name.ends_with(".tmp")
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-_=.".contains(&b));
+    if !valid {
+        return respond(stream, "400 Bad Request", "text/plain", "not a store entry");
+    }
+    let path = store.join(name);
+    match method {
+        "GET" if path.exists() => respond(stream, "200 OK", "text/plain", "stored"),
+        "GET" => respond(stream, "404 Not Found", "text/plain", "not stored"),
+        "PUT" if !protected => respond(
+            stream,
+            "403 Forbidden",
+            "text/plain",
+            "uploading requires remote.token to be set",
+        ),
+        "PUT" if path.exists() => respond(stream, "409 Conflict", "text/plain", "already stored"),
+        "PUT" => {
+            let Some(length) = length else {
+                return respond(
+                    stream,
+                    "411 Length Required",
+                    "text/plain",
+                    "expected a Content-Length",
+                );
+            };
+            if length > MAX_UPLOAD {
+                return respond(
+                    stream,
+                    "413 Content Too Large",
+                    "text/plain",
+                    &format!("uploads can be at most {MAX_UPLOAD} bytes"),
+                );
+            }
+            // (written next to it first, so a partial upload is never taken for the whole file)
+            let partial = store.join(format!("{name}.upload"));
+            let result = File::create(&partial)
+                .and_then(|mut file| io::copy(&mut reader.take(length), &mut file))
+                .map_err(Into::into)
+                .and_then(|copied| {
+                    if copied != length {
+                        bail!("the upload of {name} ended early ({copied} of {length} bytes)");
+                    }
+                    Ok(fs::rename(&partial, &path)?)
+                });
+            if let Err(e) = result {
+                let _ = fs::remove_file(&partial);
+                return Err(e);
+            }
+            info!("Received {name} from a player casting to this one");
+            respond(stream, "200 OK", "text/plain", "stored")
+        }
+        _ => respond(
+            stream,
+            "405 Method Not Allowed",
+            "text/plain",
+            "expected GET or PUT",
+        ),
+    }
+}
+
+/// Decodes a query value (as encoded by browsers: `+` for spaces, and `%XX` for anything else that is not allowed)
+fn decode(value: &str) -> String {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+    while let Some((&byte, after)) = rest.split_first() {
+        rest = after;
+        match byte {
+            b'+' => bytes.push(b' '),
+            b'%' => match rest
+                .get(..2)
+                .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok())
+            {
+                Some(decoded) => {
+                    bytes.push(decoded);
+                    rest = &rest[2..];
+                }
+                // (not an escape, kept as it is)
+                None => bytes.push(b'%'),
+            },
+            _ => bytes.push(byte),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) -> Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()?;
+    Ok(())
+}
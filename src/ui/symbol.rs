@@ -8,6 +8,7 @@ macro_rules! sym {
 sym!(PAUSE, "󰏤");
 sym!(PLAY, "󰐊");
 sym!(SHUFFLE, "󰒟");
+sym!(RADIO, "󰐹");
 sym!(REPEAT, "󰑖");
 sym!(REPEAT_ONE, "󰑘");
 sym!(REPEAT_OFF, "󰑗");
@@ -15,3 +16,6 @@ sym!(MUSIC_NOTES, "󰝚");
 sym!(DIAL_INDICATOR_LOW, "󰾆");
 sym!(DIAL_INDICATOR_HIGH, "󰓅");
 sym!(OCTAGON, "󰏃");
+sym!(CHECK, "󰄬");
+sym!(CLOSE, "󰅖");
+sym!(DOWNLOAD, "󰇚");
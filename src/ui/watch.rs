@@ -0,0 +1,25 @@
+//! Reloading the player when the config, playlists, or sources change (see [`crate::watch`])
+
+use std::thread;
+
+use color_eyre::eyre::Result;
+use flume::Sender;
+use notify::RecommendedWatcher;
+
+use crate::{resolver::Directories, ui::action::Action};
+
+/// Starts watching for changes, sending [`Action::ReloadFiles`] when something changed.
+///
+/// Watching stops when the returned watcher is dropped
+pub fn watch(dirs: &Directories, tx: Sender<Action>) -> Result<RecommendedWatcher> {
+    let (watcher, changes) = crate::watch::watch(dirs)?;
+    thread::spawn(move || {
+        while changes.recv().is_ok() {
+            debug!("files changed, reloading");
+            if tx.send(Action::ReloadFiles).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(watcher)
+}
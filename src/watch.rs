@@ -0,0 +1,57 @@
+//! Watching the music directory for changes to the config, playlists, and sources
+//! (for reloading the player, and `dmm download watch`)
+//!
+//! Editors often save a file in several steps (write a temporary file, rename it, ...), so changes are
+//! collected until things have been quiet for a moment before they are reported.
+
+use std::{path::Path, thread, time::Duration};
+
+use color_eyre::eyre::Result;
+use flume::Receiver;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::resolver::Directories;
+
+/// How long to wait for more changes before reporting them
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// If a change to `path` could affect the resolved config, playlists, or sources
+fn is_relevant(path: &Path) -> bool {
+    // skips editor swap files, and the temporary files DMM writes before renaming them into place
+    path.extension().is_some_and(|ext| ext == "ron")
+        && !path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+/// Starts watching for changes. The receiver gets a message once things are quiet after something changed.
+///
+/// Watching stops when the returned watcher is dropped
+pub fn watch(dirs: &Directories) -> Result<(RecommendedWatcher, Receiver<()>)> {
+    let (change_tx, change_rx) = flume::unbounded::<()>();
+    let mut watcher =
+        notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+            Ok(event) if event.kind.is_access() => {}
+            Ok(event) => {
+                if event.paths.iter().any(|path| is_relevant(path)) {
+                    let _ = change_tx.send(());
+                }
+            }
+            Err(e) => warn!("Error watching for changes: {e}"),
+        })?;
+    // dmm.ron is watched through its directory, as editors may replace the file instead of writing to it
+    watcher.watch(&dirs.root, RecursiveMode::NonRecursive)?;
+    watcher.watch(&dirs.sources, RecursiveMode::NonRecursive)?;
+    watcher.watch(&dirs.playlists, RecursiveMode::Recursive)?;
+
+    let (tx, rx) = flume::unbounded::<()>();
+    thread::spawn(move || {
+        while change_rx.recv().is_ok() {
+            while change_rx.recv_timeout(DEBOUNCE).is_ok() {}
+            if tx.send(()).is_err() {
+                break;
+            }
+        }
+    });
+    Ok((watcher, rx))
+}
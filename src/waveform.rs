@@ -0,0 +1,97 @@
+//! Generation of the downsampled waveform ("peaks") shown behind the progress bar
+//!
+//! Peaks are stored next to the download in the store, as a `<hash>.peaks` sidecar file.
+//! Each byte of the file is the peak amplitude of one slice of the track (0-255).
+
+use std::{fs, path::Path};
+
+use color_eyre::eyre::Result;
+use symphonia::core::{audio::SampleBuffer, probe};
+
+use crate::player2::{AudioDecoder, Decoded};
+
+/// Extension of the sidecar files in the store
+pub const SIDECAR_EXT: &str = "peaks";
+/// Number of slices the track is divided into
+const RESOLUTION: usize = 512;
+
+/// Decodes the track at `path`, and computes its peaks
+pub fn generate(path: &Path, format: &str) -> Result<Vec<u8>> {
+    let mut hint = probe::Hint::new();
+    hint.with_extension(format);
+    let mut decoder = AudioDecoder::new(fs::File::open(path)?, hint)?;
+
+    // peak amplitude of each decoded packet, reduced to RESOLUTION slices at the end
+    let mut packet_peaks = vec![];
+    let mut sample_buf = None::<SampleBuffer<f32>>;
+    loop {
+        match decoder.decode_next()? {
+            Decoded::StreamEnd => break,
+            Decoded::Retry => continue,
+            Decoded::Decoded(_, buffer) => {
+                if buffer.frames() == 0 {
+                    continue;
+                }
+                let buf = sample_buf.get_or_insert_with(|| {
+                    SampleBuffer::new(buffer.capacity() as u64, *buffer.spec())
+                });
+                if buf.capacity() < buffer.capacity() * buffer.spec().channels.count() {
+                    *buf = SampleBuffer::new(buffer.capacity() as u64, *buffer.spec());
+                }
+                buf.copy_interleaved_ref(buffer);
+                let peak = buf
+                    .samples()
+                    .iter()
+                    .fold(0.0f32, |peak, s| peak.max(s.abs()));
+                packet_peaks.push(peak);
+            }
+        }
+    }
+
+    if packet_peaks.is_empty() {
+        return Ok(vec![]);
+    }
+    let slices = RESOLUTION.min(packet_peaks.len());
+    Ok((0..slices)
+        .map(|i| {
+            let start = i * packet_peaks.len() / slices;
+            let end = ((i + 1) * packet_peaks.len() / slices).max(start + 1);
+            let peak = packet_peaks[start..end]
+                .iter()
+                .fold(0.0f32, |peak, s| peak.max(*s));
+            (peak.clamp(0.0, 1.0) * u8::MAX as f32) as u8
+        })
+        .collect())
+}
+
+/// Generates peaks for the track at `path` and writes them to `sidecar`
+pub fn write(path: &Path, format: &str, sidecar: &Path) -> Result<()> {
+    let peaks = generate(path, format)?;
+    fs::write(sidecar, peaks)?;
+    Ok(())
+}
+
+/// Reads previously generated peaks, if they exist
+pub fn read(sidecar: &Path) -> Option<Vec<u8>> {
+    fs::read(sidecar).ok().filter(|peaks| !peaks.is_empty())
+}
+
+/// Resamples `peaks` to `width` columns
+pub fn resample(peaks: &[u8], width: usize) -> Vec<u8> {
+    if peaks.is_empty() {
+        return vec![0; width];
+    }
+    (0..width)
+        .map(|i| {
+            let start = i * peaks.len() / width;
+            let end = ((i + 1) * peaks.len() / width)
+                .max(start + 1)
+                .min(peaks.len());
+            peaks[start.min(peaks.len() - 1)..end]
+                .iter()
+                .copied()
+                .max()
+                .unwrap_or(0)
+        })
+        .collect()
+}
@@ -0,0 +1,177 @@
+//! Downloading with yt-dlp, for the `YtDlp` source kind
+//!
+//! yt-dlp is run directly (not through a shell), so inputs and arguments never need quoting.
+//! Its progress is printed as JSON (one object per line) using `--progress-template`, and parsed to report download progress.
+
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read},
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+};
+
+use color_eyre::eyre::{anyhow, bail, Result};
+use serde::Deserialize;
+
+use crate::schema::Progress;
+
+const BINARY: &str = "yt-dlp";
+/// Prefix of the progress lines printed by yt-dlp, to tell them apart from other output
+const PROGRESS_PREFIX: &str = "dmm-progress ";
+/// Format selection used if a source does not set one
+const DEFAULT_FORMAT: &str = "bestaudio/best";
+
+pub struct Options<'a> {
+    /// yt-dlp format selection
+    pub format: Option<&'a str>,
+    /// format the audio is converted to (the format of the source)
+    pub audio_format: &'a str,
+    pub extra_args: &'a [String],
+}
+
+/// The parts of yt-dlp's progress info that are used
+#[derive(Debug, Deserialize)]
+struct ProgressInfo {
+    downloaded_bytes: Option<u64>,
+    total_bytes: Option<u64>,
+    total_bytes_estimate: Option<f64>,
+}
+
+/// Version of yt-dlp that is installed
+pub fn version() -> Result<String> {
+    let out = Command::new(BINARY)
+        .arg("--version")
+        .output()
+        .map_err(|e| anyhow!("failed to run {BINARY}: {e}"))?;
+    if !out.status.success() {
+        bail!("`{BINARY} --version` failed ({})", out.status);
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).trim().to_string())
+}
+
+/// Checks that yt-dlp is installed, and can be run
+pub fn check_installed() -> Result<()> {
+    match Command::new(BINARY).arg("--version").output() {
+        Ok(out) if out.status.success() => {
+            debug!(
+                "found {BINARY} version {}",
+                String::from_utf8_lossy(&out.stdout).trim()
+            );
+            Ok(())
+        }
+        Ok(out) => {
+            error!("`{BINARY} --version` failed ({})", out.status);
+            info!("Your {BINARY} installation may be broken, try reinstalling or updating it");
+            bail!("{BINARY} is not working");
+        }
+        Err(e) => {
+            error!("Failed to run {BINARY}: {e}");
+            info!("Sources using the YtDlp kind need yt-dlp to be installed, and in your PATH");
+            info!("See https://github.com/yt-dlp/yt-dlp#installation for how to install it");
+            bail!("{BINARY} not found");
+        }
+    }
+}
+
+/// Inputs that are not URLs are taken to be YouTube video ids
+fn input_url(input: &str) -> String {
+    if input.contains("://") {
+        input.to_string()
+    } else {
+        format!("https://youtube.com/watch?v={input}")
+    }
+}
+
+/// Downloads `input` to `output`, converting it to `opts.audio_format`
+pub fn download(
+    opts: &Options,
+    input: &str,
+    output: &Path,
+    show_output: bool,
+    progress: &dyn Fn(Progress),
+) -> Result<()> {
+    // yt-dlp is run in the directory of `output`, and only given the file name, so the rest of the path
+    // does not have to be valid UTF-8 (or be escaped for yt-dlp)
+    let dir = output
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let name = output
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or(anyhow!("output file name not valid UTF-8"))?;
+    // yt-dlp picks the file extension itself, the file is renamed to `output` once it is done
+    // (`%` starts a field in yt-dlp's output templates, so it must be escaped in the name)
+    let template = format!("{}.%(ext)s", name.replace('%', "%%"));
+    let converted = output.with_file_name(format!("{name}.{}", opts.audio_format));
+
+    let mut command = Command::new(BINARY);
+    command
+        .current_dir(dir)
+        .args(["--no-playlist", "--quiet", "--no-warnings"])
+        .args(["--progress", "--newline", "--progress-template"])
+        .arg(format!("download:{PROGRESS_PREFIX}%(progress)j"))
+        .args(["--extract-audio", "--audio-quality", "0", "--audio-format"])
+        .arg(opts.audio_format)
+        .arg("--format")
+        .arg(opts.format.unwrap_or(DEFAULT_FORMAT))
+        .arg("--output")
+        .arg(template)
+        .args(opts.extra_args)
+        .arg("--")
+        .arg(input_url(input))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(if show_output {
+            Stdio::inherit()
+        } else {
+            Stdio::piped()
+        });
+    let mut child = command
+        .spawn()
+        .map_err(|e| anyhow!("failed to run {BINARY}: {e}"))?;
+
+    // stderr is read on another thread, so that yt-dlp does not block on a full pipe while stdout is being read
+    let stderr = child.stderr.take().map(|mut stderr| {
+        thread::spawn(move || {
+            let mut buf = String::new();
+            let _ = stderr.read_to_string(&mut buf);
+            buf
+        })
+    });
+    for line in BufReader::new(child.stdout.take().unwrap()).lines() {
+        let line = line?;
+        if let Some(json) = line.strip_prefix(PROGRESS_PREFIX) {
+            match serde_json::from_str::<ProgressInfo>(json) {
+                Ok(info) => progress(Progress {
+                    downloaded: info.downloaded_bytes.unwrap_or(0),
+                    total: info
+                        .total_bytes
+                        .or(info.total_bytes_estimate.map(|t| t as u64)),
+                }),
+                Err(e) => debug!("invalid progress from {BINARY}: {e}"),
+            }
+        } else if show_output {
+            println!("{line}");
+        }
+    }
+    let status = child.wait()?;
+    let stderr = stderr
+        .and_then(|handle| handle.join().ok())
+        .unwrap_or_default();
+
+    if !status.success() {
+        let _ = fs::remove_file(&converted);
+        let mut err = format!("{BINARY} failed ({status})");
+        if !stderr.trim().is_empty() {
+            err += &format!("\n{}", stderr.trim_end());
+        }
+        bail!(err);
+    }
+    if !converted.exists() {
+        bail!("{BINARY} did not create the expected file {converted:?}");
+    }
+    fs::rename(&converted, output)?;
+    Ok(())
+}